@@ -2,6 +2,9 @@ use std::convert::TryInto;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+use bindle::client::layout::{
+    extraction_path, ByAnnotationPath, ByName, BySha, DefaultLayout, LayoutStrategy,
+};
 use bindle::client::{Client, ClientError, Result};
 use bindle::invoice::signature::{
     KeyRing, SecretKeyEntry, SecretKeyFile, SecretKeyStorage, SignatureRole,
@@ -64,8 +67,8 @@ async fn run() -> std::result::Result<(), ClientError> {
     .await;
     let cache = DumbCache::new(bindle_client.clone(), local);
 
-    // We don't verify locally yet, but we will need the keyring to do so
-    let _keyring = load_keyring(opts.keyring)
+    // The keyring is currently only used for verifying standalone bindles locally
+    let keyring = load_keyring(opts.keyring)
         .await
         .unwrap_or_else(|_| KeyRing::default());
 
@@ -101,7 +104,13 @@ async fn run() -> std::result::Result<(), ClientError> {
         }
         SubCommand::GetParcel(gp_opts) => get_parcel(cache, gp_opts).await?,
         SubCommand::Yank(yank_opts) => {
-            bindle_client.yank_invoice(&yank_opts.bindle_id).await?;
+            if yank_opts.force {
+                bindle_client
+                    .force_yank_invoice(&yank_opts.bindle_id)
+                    .await?;
+            } else {
+                bindle_client.yank_invoice(&yank_opts.bindle_id).await?;
+            }
             println!("Bindle {} yanked", yank_opts.bindle_id);
         }
         SubCommand::Search(search_opts) => {
@@ -213,6 +222,31 @@ async fn run() -> std::result::Result<(), ClientError> {
             let out = toml::to_string(&keyring).map_err(|e| ClientError::Other(e.to_string()))?;
             println!("{}", out);
         }
+        SubCommand::VerifyStandalone(verify_opts) => {
+            verify_standalone(&keyring, verify_opts).await?
+        }
+        SubCommand::FetchDeferred(fetch_opts) => fetch_deferred(bindle_client, fetch_opts).await?,
+        SubCommand::Schema => {
+            let schema = serde_json::to_string_pretty(&bindle::invoice_schema())
+                .map_err(|e| ClientError::Other(e.to_string()))?;
+            println!("{}", schema);
+        }
+        SubCommand::Template(template_opts) => match template_opts.subcmd {
+            TemplateCommand::Apply(apply_opts) => {
+                let template = tokio::fs::read_to_string(&apply_opts.template).await?;
+                let vars = apply_opts.set.into_iter().collect();
+                let inv = bindle::render_invoice_template(&template, &vars)
+                    .map_err(|e| ClientError::Other(e.to_string()))?;
+                let out = toml::to_vec(&inv)?;
+                match apply_opts.output {
+                    Some(path) => {
+                        tokio::fs::write(&path, out).await?;
+                        println!("Wrote invoice {} to {}", inv.bindle.id, path.display());
+                    }
+                    None => tokio::io::stdout().write_all(&out).await?,
+                }
+            }
+        },
         SubCommand::CreateKey(create_opts) => {
             let dir = match create_opts.secret_file {
                 Some(dir) => dir,
@@ -333,27 +367,74 @@ async fn get_all<C: Cache + Send + Sync + Clone>(cache: C, opts: Get) -> Result<
 
     println!("Fetched invoice. Starting fetch of parcels");
 
-    let parcels = Arc::new(Mutex::new(std::collections::HashMap::new()));
     let zero_vec = Vec::with_capacity(0);
-    let is_export = opts.export.is_some();
-    let parcel_fetch = inv
+    // Work out every extraction path before fetching anything so an unsafe path doesn't leave a
+    // partially extracted bindle behind
+    let extract_paths = match opts.extract.as_ref() {
+        Some(dest) => {
+            let layout = layout_from_name(&opts.layout)?;
+            inv.parcel
+                .as_ref()
+                .unwrap_or(&zero_vec)
+                .iter()
+                .map(|p| extraction_path(layout.as_ref(), dest, &p.label).map(Some))
+                .collect::<Result<Vec<_>>>()?
+        }
+        None => vec![None; inv.parcel.as_ref().map(|p| p.len()).unwrap_or_default()],
+    };
+
+    // Lazy parcels are skipped unless asked for, keeping track of where they would have gone so
+    // they can be fetched later
+    let mut deferred = Vec::new();
+    let mut to_fetch = Vec::new();
+    for (p, extract_path) in inv
         .parcel
         .as_ref()
         .unwrap_or(&zero_vec)
         .iter()
-        .map(|p| {
+        .zip(extract_paths)
+    {
+        if p.is_lazy() && !opts.include_lazy {
+            deferred.push((p.label.sha256.clone(), extract_path));
+        } else {
+            to_fetch.push((p, extract_path));
+        }
+    }
+
+    let parcels = Arc::new(Mutex::new(std::collections::HashMap::new()));
+    let is_export = opts.export.is_some();
+    let parcel_fetch = to_fetch
+        .into_iter()
+        .map(|(p, extract_path)| {
             (
                 p.label.sha256.clone(),
                 inv.bindle.id.clone(),
                 cache.clone(),
                 parcels.clone(),
+                extract_path,
             )
         })
-        .map(|(sha, bindle_id, c, parcels)| async move {
+        .map(|(sha, bindle_id, c, parcels, extract_path)| async move {
             match c.get_parcel(bindle_id, &sha).await {
                 Ok(p) => {
-                    println!("Fetched parcel {}", sha);
-                    if is_export {
+                    println!("Fetched parcel {}", bindle::short_sha(&sha));
+                    if let Some(path) = extract_path {
+                        if let Some(parent) = path.parent() {
+                            tokio::fs::create_dir_all(parent).await?;
+                        }
+                        let mut file = tokio::fs::File::create(&path).await?;
+                        tokio::io::copy(
+                            &mut StreamReader::new(p.map(|res| res.map_err(std::io::Error::other))),
+                            &mut file,
+                        )
+                        .await?;
+                        file.flush().await?;
+                        println!(
+                            "Extracted parcel {} to {}",
+                            bindle::short_sha(&sha),
+                            path.display()
+                        );
+                    } else if is_export {
                         parcels.lock().await.insert(
                             sha,
                             StreamReader::new(p.map(|res| {
@@ -388,8 +469,28 @@ async fn get_all<C: Cache + Send + Sync + Clone>(cache: C, opts: Get) -> Result<
         .await
         .into_iter()
         .collect::<Result<Vec<_>>>()?;
+    if let Some(dest) = opts.extract.as_ref() {
+        let parcels = deferred
+            .iter()
+            .filter_map(|(sha, path)| {
+                // Extraction paths are always inside of dest, so this only strips the prefix
+                let path = path.as_ref()?.strip_prefix(dest).ok()?;
+                Some(DeferredParcel {
+                    sha256: sha.clone(),
+                    path: path.to_owned(),
+                })
+            })
+            .collect();
+        write_deferred(dest, &inv.bindle.id, parcels).await?;
+    } else if opts.export.is_none() && !deferred.is_empty() {
+        println!(
+            "Skipped {} lazy parcels, use get-parcel to fetch them when needed",
+            deferred.len()
+        );
+    }
     if let Some(p) = opts.export {
         let standalone = StandaloneWrite::new(p, &inv.bindle.id)?;
+        let inv_id = inv.bindle.id.clone();
         standalone
             .write(
                 inv,
@@ -401,8 +502,149 @@ async fn get_all<C: Cache + Send + Sync + Clone>(cache: C, opts: Get) -> Result<
                     .into_inner(),
             )
             .await?;
+        let parcels = deferred
+            .iter()
+            .map(|(sha, _)| DeferredParcel {
+                sha256: sha.clone(),
+                path: Path::new(bindle::standalone::PARCEL_DIR).join(format!("{}.dat", sha)),
+            })
+            .collect();
+        write_deferred(standalone.path(), &inv_id, parcels).await?;
+
+        if !opts.sign_detached.is_empty() {
+            let keyfile = match opts.secret_file {
+                Some(f) => f,
+                None => ensure_config_dir().await?.join("secret_keys.toml"),
+            };
+            let mut keys = Vec::with_capacity(opts.sign_detached.len());
+            for name in opts.sign_detached {
+                let role = role_from_name(name)?;
+                let key = first_matching_key(keyfile.clone(), &role).await?;
+                keys.push((role, key));
+            }
+            standalone
+                .write_detached_signatures(
+                    &keys.iter().map(|(r, k)| (r.clone(), k)).collect::<Vec<_>>(),
+                )
+                .await?;
+            println!("Wrote detached signatures for invoice {}", inv_id);
+        }
+    }
+
+    Ok(())
+}
+
+/// The name of the file that records the lazy parcels skipped by `get`
+const DEFERRED_FILE: &str = "deferred.toml";
+
+/// The lazy parcels of a bindle that were skipped when it was fetched, along with the paths they
+/// should be written to relative to the directory containing the deferred file
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DeferredParcels {
+    bindle: String,
+    parcel: Vec<DeferredParcel>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DeferredParcel {
+    sha256: String,
+    path: PathBuf,
+}
+
+async fn write_deferred(
+    dir: &Path,
+    bindle_id: &bindle::Id,
+    parcels: Vec<DeferredParcel>,
+) -> Result<()> {
+    if parcels.is_empty() {
+        return Ok(());
+    }
+    let count = parcels.len();
+    let deferred = DeferredParcels {
+        bindle: bindle_id.to_string(),
+        parcel: parcels,
+    };
+    tokio::fs::write(dir.join(DEFERRED_FILE), toml::to_vec(&deferred)?).await?;
+    println!(
+        "Skipped {} lazy parcels, use fetch-deferred {} to fetch them when needed",
+        count,
+        dir.display()
+    );
+    Ok(())
+}
+
+async fn fetch_deferred(client: Client, opts: FetchDeferred) -> Result<()> {
+    let manifest_path = opts.path.join(DEFERRED_FILE);
+    let mut deferred: DeferredParcels = bindle::client::load::toml(&manifest_path).await?;
+    let mut remaining = Vec::new();
+    for parcel in deferred.parcel {
+        if !opts.shas.is_empty() && !opts.shas.contains(&parcel.sha256) {
+            remaining.push(parcel);
+            continue;
+        }
+        // The deferred file could have been edited, so make sure it can't write outside of the
+        // directory
+        if !parcel
+            .path
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+        {
+            return Err(ClientError::InvalidExtractPath(parcel.sha256));
+        }
+        let data = client
+            .fetch_deferred(deferred.bindle.as_str(), &parcel.sha256)
+            .await?;
+        let path = opts.path.join(&parcel.path);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, data).await?;
+        println!(
+            "Fetched parcel {} to {}",
+            bindle::short_sha(&parcel.sha256),
+            path.display()
+        );
+    }
+
+    // Only keep track of the parcels that still need to be fetched
+    if remaining.is_empty() {
+        tokio::fs::remove_file(&manifest_path).await?;
+    } else {
+        deferred.parcel = remaining;
+        tokio::fs::write(&manifest_path, toml::to_vec(&deferred)?).await?;
+    }
+    Ok(())
+}
+
+async fn verify_standalone(keyring: &KeyRing, opts: VerifyStandalone) -> Result<()> {
+    let standalone = StandaloneRead::new(&opts.path, &opts.bindle_id).await?;
+    for s in standalone.verify_detached_signatures(keyring).await? {
+        println!(
+            "Verified detached signature by {} with role {}",
+            s.by, s.role
+        );
     }
 
+    // The signatures only cover the invoice, so check every parcel that was exported against it
+    let inv = standalone.get_invoice().await?;
+    for parcel in inv.parcel.iter().flatten() {
+        let path = standalone.parcel_data_path(&parcel.label.sha256);
+        let data = match tokio::fs::read(&path).await {
+            Ok(d) => d,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                warn!(
+                    "Parcel {} is not in the standalone bindle",
+                    parcel.label.sha256
+                );
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if format!("{:x}", sha2::Sha256::digest(&data)) != parcel.label.sha256 {
+            return Err(ClientError::ParcelShaMismatch(parcel.label.sha256.clone()));
+        }
+    }
+    println!("Standalone bindle {} verified", opts.bindle_id);
     Ok(())
 }
 
@@ -416,6 +658,16 @@ async fn load_keyring(keyring: Option<PathBuf>) -> anyhow::Result<KeyRing> {
     Ok(kr)
 }
 
+fn layout_from_name(name: &str) -> Result<Box<dyn LayoutStrategy + Send + Sync>> {
+    match name {
+        "default" => Ok(Box::new(DefaultLayout::default())),
+        "annotation" => Ok(Box::new(ByAnnotationPath::default())),
+        "name" => Ok(Box::new(ByName)),
+        "sha" => Ok(Box::new(BySha)),
+        _ => Err(ClientError::Other(format!("Unknown layout {}", name))),
+    }
+}
+
 fn map_storage_error(e: ProviderError) -> ClientError {
     match e {
         ProviderError::Io(e) => ClientError::Io(e),
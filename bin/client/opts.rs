@@ -89,6 +89,23 @@ pub enum SubCommand {
         about = "Print the public key entries for keys from the secret key file. If no '--label' is supplied, public keys for all secret keys are returned."
     )]
     PrintKey(PrintKey),
+    #[clap(name = "template", about = "Work with invoice templates")]
+    Template(Template),
+    #[clap(
+        name = "verify-standalone",
+        about = "Verify the detached signatures and parcels of a standalone bindle against the keyring. This does not contact the server"
+    )]
+    VerifyStandalone(VerifyStandalone),
+    #[clap(
+        name = "fetch-deferred",
+        about = "Fetch the lazy parcels that were skipped by 'get --extract' or 'get --export' into the directory they were skipped from"
+    )]
+    FetchDeferred(FetchDeferred),
+    #[clap(
+        name = "schema",
+        about = "Print the JSON Schema of the invoice format, for validating invoices with other tools or editors"
+    )]
+    Schema,
 }
 
 #[derive(Clap)]
@@ -144,6 +161,39 @@ pub struct Get {
         about = "If specified, export the bindle as a standlone bindle in the given directory"
     )]
     pub export: Option<PathBuf>,
+    #[clap(
+        short = 'x',
+        long = "extract",
+        conflicts_with = "export",
+        about = "If specified, extract all parcels of the bindle into the given directory, placing them according to --layout"
+    )]
+    pub extract: Option<PathBuf>,
+    #[clap(
+        long = "layout",
+        default_value = "default",
+        possible_values = &["default", "annotation", "name", "sha"],
+        about = "The layout used with --extract. 'annotation' places parcels at the path in their 'bindle.dev/path' annotation, 'name' uses the name of the parcel, and 'sha' uses the SHA of the parcel. 'default' uses 'annotation' and falls back to 'name'"
+    )]
+    pub layout: String,
+    #[clap(
+        long = "sign-detached",
+        value_name = "ROLE",
+        requires = "export",
+        about = "Write a detached signature file (invoice.toml.sig) next to the exported invoice, signed with the first secret key with the given role. Values are: c[reator], a[pprover], h[ost], p[roxy]. Can be given multiple times to sign with multiple roles"
+    )]
+    pub sign_detached: Vec<String>,
+    #[clap(
+        short = 'f',
+        long = "secrets-file",
+        requires = "sign-detached",
+        about = "the path to the file where secret keys are stored for --sign-detached. Use 'create-key' to create a new key"
+    )]
+    pub secret_file: Option<PathBuf>,
+    #[clap(
+        long = "include-lazy",
+        about = "Also fetch parcels marked as lazy. By default, lazy parcels are skipped and recorded in a deferred.toml file in the --extract or --export directory so they can be fetched later with 'fetch-deferred'"
+    )]
+    pub include_lazy: bool,
 }
 
 #[derive(Clap)]
@@ -154,6 +204,11 @@ pub struct Yank {
         about = "The name of the bindle, e.g. example.com/mybindle/1.2.3"
     )]
     pub bindle_id: String,
+    #[clap(
+        long = "force",
+        about = "Yank the bindle even if the server is limiting yanks because too many bindles have been yanked recently"
+    )]
+    pub force: bool,
 }
 
 const VERSION_QUERY: &str = r#"version constraint of the bindle to search for. This is a semver range modifier that can either denote an exact version, or a range of versions.
@@ -197,6 +252,16 @@ pub struct Search {
         about = "Whether or not to include yanked bindles in the search result"
     )]
     pub yanked: Option<bool>,
+    #[clap(
+        long = "annotation",
+        about = "Only return bindles with this annotation, as KEY or KEY=VALUE"
+    )]
+    pub annotation: Option<String>,
+    #[clap(
+        long = "sort",
+        about = "The order of the results: name (the default), version, or annotation:KEY. Prefix with - to sort in descending order"
+    )]
+    pub sort: Option<String>,
 }
 
 impl From<Search> for bindle::QueryOptions {
@@ -208,6 +273,8 @@ impl From<Search> for bindle::QueryOptions {
             limit: s.limit,
             strict: s.strict,
             yanked: s.yanked,
+            annotation: s.annotation,
+            sort: s.sort,
         }
     }
 }
@@ -376,3 +443,81 @@ pub struct PushFile {
     )]
     pub media_type: Option<String>,
 }
+
+#[derive(Clap)]
+pub struct Template {
+    #[clap(subcommand)]
+    pub subcmd: TemplateCommand,
+}
+
+#[derive(Clap)]
+pub enum TemplateCommand {
+    #[clap(
+        name = "apply",
+        about = "Render an invoice template by replacing its {{ var }} placeholders with the given values. The rendered invoice is printed to stdout unless '--output' is given"
+    )]
+    Apply(TemplateApply),
+}
+
+#[derive(Clap)]
+pub struct TemplateApply {
+    #[clap(
+        index = 1,
+        value_name = "TEMPLATE",
+        about = "The path to the invoice template"
+    )]
+    pub template: PathBuf,
+    #[clap(
+        long = "set",
+        value_name = "KEY=VALUE",
+        parse(try_from_str = parse_key_val),
+        about = "A value for a template variable, e.g. '--set version=1.2.3'. Can be given multiple times"
+    )]
+    pub set: Vec<(String, String)>,
+    #[clap(
+        short = 'o',
+        long = "output",
+        about = "The location to write the rendered invoice to"
+    )]
+    pub output: Option<PathBuf>,
+}
+
+fn parse_key_val(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) => Ok((k.to_owned(), v.to_owned())),
+        None => Err(format!("invalid KEY=VALUE: no '=' found in {:?}", s)),
+    }
+}
+
+#[derive(Clap)]
+pub struct VerifyStandalone {
+    #[clap(
+        index = 1,
+        value_name = "BINDLE",
+        about = "The name of the bindle, e.g. example.com/mybindle/1.2.3"
+    )]
+    pub bindle_id: String,
+    #[clap(
+        short = 'p',
+        long = "path",
+        default_value = "./",
+        about = "A path where the standalone bindle directory is located"
+    )]
+    pub path: PathBuf,
+}
+
+#[derive(Clap)]
+pub struct FetchDeferred {
+    #[clap(
+        index = 1,
+        value_name = "DIR",
+        about = "The directory containing the deferred.toml file. This is the --extract directory or the standalone bindle directory created by --export"
+    )]
+    pub path: PathBuf,
+    #[clap(
+        long = "sha",
+        value_name = "SHA",
+        about = "Only fetch the deferred parcel with the given SHA. Can be given multiple times. By default, all deferred parcels are fetched"
+    )]
+    pub shas: Vec<String>,
+}
@@ -1,13 +1,19 @@
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use clap::Clap;
 use tracing::warn;
 
 use bindle::{
+    audit::FileAuditSink,
+    interceptor::{unique_names::UniqueParcelNames, InterceptorChain},
     invoice::signature::{KeyRing, SignatureRole},
     provider, search,
-    server::{server, TlsConfig},
+    server::{
+        server, AuditLog, CrawlerPolicy, ServerOptions, SignatureStripping, TlsConfig, YankGuard,
+        YankedListing,
+    },
     signature::SecretKeyFile,
     SecretKeyEntry,
 };
@@ -93,6 +99,156 @@ struct Opts {
         about = "Use the new embedded database provider. This is currently experimental, but fairly stable and more efficient. In the future, this will be the default"
     )]
     use_embedded_db: bool,
+
+    #[clap(
+        name = "verify_read_sample_rate",
+        long = "verify-read-sample-rate",
+        env = "BINDLE_VERIFY_READ_SAMPLE_RATE",
+        about = "the fraction of parcel reads (between 0 and 1) that should have their SHA verified while being served. Failed verifications are logged as errors [default: 0]"
+    )]
+    verify_read_sample_rate: Option<f64>,
+
+    #[clap(
+        name = "quarantine_corrupt_parcels",
+        long = "quarantine-corrupt-parcels",
+        env = "BINDLE_QUARANTINE_CORRUPT_PARCELS",
+        about = "Refuse to serve any parcel that fails a sampled read verification until the server is restarted"
+    )]
+    quarantine_corrupt_parcels: bool,
+
+    #[clap(
+        name = "read_only",
+        long = "read-only",
+        env = "BINDLE_READ_ONLY",
+        about = "Serve bindles without allowing any writes. Requests to create or yank bindles will be rejected. This must be set if the bindle directory cannot be written to"
+    )]
+    read_only: bool,
+
+    #[clap(
+        name = "yank_limit",
+        long = "yank-limit",
+        env = "BINDLE_YANK_LIMIT",
+        about = "the maximum number of bindles a single user can yank within the --yank-window. Further yanks are rejected unless the client forces them. If not set, yanks are not limited"
+    )]
+    yank_limit: Option<usize>,
+
+    #[clap(
+        name = "yank_window",
+        long = "yank-window",
+        env = "BINDLE_YANK_WINDOW",
+        requires = "yank_limit",
+        about = "the window of time, in seconds, used for the --yank-limit [default: 60]"
+    )]
+    yank_window: Option<u64>,
+
+    #[clap(
+        name = "yank_admin_group",
+        long = "yank-admin-group",
+        env = "BINDLE_YANK_ADMIN_GROUP",
+        requires = "yank_limit",
+        about = "a group whose members are exempt from the --yank-limit"
+    )]
+    yank_admin_group: Option<String>,
+
+    #[clap(
+        name = "strip_host_signatures",
+        long = "strip-host-signatures",
+        env = "BINDLE_STRIP_HOST_SIGNATURES",
+        about = "Remove the signatures added by this server from invoices before returning them. Clients can still request the full invoice with the verbatim=true query parameter. By default, invoices are returned exactly as stored"
+    )]
+    strip_host_signatures: bool,
+
+    #[clap(
+        name = "unique_parcel_names",
+        long = "unique-parcel-names",
+        env = "BINDLE_UNIQUE_PARCEL_NAMES",
+        about = "Reject invoices in which more than one parcel has the same name. Duplicate names are valid in a bindle, but clients that map parcel names to paths need them to be unique"
+    )]
+    unique_parcel_names: bool,
+
+    #[clap(
+        name = "yanked_listing_group",
+        long = "yanked-listing-group",
+        env = "BINDLE_YANKED_LISTING_GROUP",
+        about = "a group whose members are the only users allowed to include yanked bindles in query results. If not set, any user allowed to query can list yanked bindles"
+    )]
+    yanked_listing_group: Option<String>,
+
+    #[clap(
+        name = "robots_txt",
+        long = "robots-txt",
+        env = "BINDLE_ROBOTS_TXT",
+        about = "the path to a robots.txt file to serve at /robots.txt. If not set, no robots.txt is served"
+    )]
+    robots_txt: Option<PathBuf>,
+
+    #[clap(
+        name = "honor_noindex",
+        long = "honor-noindex",
+        env = "BINDLE_HONOR_NOINDEX",
+        about = "Return an X-Robots-Tag: noindex header with invoices that have the bindle.dev/noindex annotation set to true"
+    )]
+    honor_noindex: bool,
+
+    #[clap(
+        name = "audit_log",
+        long = "audit-log",
+        env = "BINDLE_AUDIT_LOG",
+        about = "the path to a file to record every create and yank request in, along with who made it and whether it succeeded. If not set, no audit log is kept"
+    )]
+    audit_log: Option<PathBuf>,
+
+    #[clap(
+        name = "audit_admin_group",
+        long = "audit-admin-group",
+        env = "BINDLE_AUDIT_ADMIN_GROUP",
+        requires = "audit_log",
+        about = "a group whose members are allowed to query the --audit-log. If not set, the audit log can't be queried through the API"
+    )]
+    audit_admin_group: Option<String>,
+
+    #[clap(subcommand)]
+    #[serde(skip)]
+    command: Option<ServerCommand>,
+}
+
+#[derive(Clap)]
+enum ServerCommand {
+    #[clap(
+        name = "migrate",
+        about = "Migrates the bindle directory to a newer storage layout, verifying every invoice and parcel before and after. Migrations can be rerun safely if interrupted. Stop any server using the directory first, as migrating removes the part files of in-progress uploads"
+    )]
+    Migrate(MigrateOpts),
+    #[clap(
+        name = "import",
+        about = "Imports every invoice TOML file found in a directory, along with the parcels found next to each invoice, either at parcels/<SHA>.dat or named after the parcel label. Existing invoices and parcels are skipped, so an import can be rerun to complete it"
+    )]
+    Import(ImportOpts),
+}
+
+#[derive(Clap)]
+struct ImportOpts {
+    #[clap(index = 1, value_name = "DIR", about = "the directory to import from")]
+    dir: PathBuf,
+}
+
+#[derive(Clap)]
+struct MigrateOpts {
+    #[clap(
+        long = "from",
+        about = "the layout version the bindle directory is currently at. Directories created before layouts were versioned are at version 0"
+    )]
+    from: u32,
+    #[clap(
+        long = "to",
+        about = "the layout version to migrate to [default: the newest supported version]"
+    )]
+    to: Option<u32>,
+    #[clap(
+        long = "target",
+        about = "migrate a copy of the bindle directory into this directory instead of migrating it in place"
+    )]
+    target: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -139,6 +295,22 @@ async fn main() -> anyhow::Result<()> {
                 .join("bindle")
         });
 
+    if let Some(ServerCommand::Migrate(m)) = opts.command {
+        let to =
+            m.to.unwrap_or(provider::file::migrate::CURRENT_LAYOUT_VERSION);
+        let report =
+            provider::file::migrate::migrate(&bindle_directory, m.from, to, m.target.as_deref())
+                .await?;
+        println!(
+            "Migrated {} to layout version {} ({} invoices, {} parcels verified)",
+            m.target.as_ref().unwrap_or(&bindle_directory).display(),
+            to,
+            report.invoices,
+            report.parcels
+        );
+        return Ok(());
+    }
+
     // find bindle directory
     //   1. cli options if set
     //   2. config file if set
@@ -200,6 +372,109 @@ async fn main() -> anyhow::Result<()> {
 
     tracing::info!("Using verification strategy of {:?}", strategy);
 
+    if let Some(ServerCommand::Import(i)) = opts.command {
+        let index = search::StrictEngine::default();
+        let secret_store = SecretKeyFile::load_file(&signing_keys).await?;
+        return if opts.use_embedded_db {
+            let store = provider::embedded::EmbeddedProvider::new(&bindle_directory, index).await?;
+            import(store, secret_store, strategy, keyring, &i.dir).await
+        } else {
+            let store = provider::file::FileProvider::new(&bindle_directory, index).await;
+            import(store, secret_store, strategy, keyring, &i.dir).await
+        };
+    }
+
+    let sample_rate = opts
+        .verify_read_sample_rate
+        .or(config.verify_read_sample_rate)
+        .unwrap_or_default();
+    if !(0.0..=1.0).contains(&sample_rate) {
+        anyhow::bail!(
+            "--verify-read-sample-rate must be between 0 and 1, got {}",
+            sample_rate
+        );
+    }
+    let quarantine = opts.quarantine_corrupt_parcels || config.quarantine_corrupt_parcels;
+
+    let read_only = opts.read_only || config.read_only;
+    if read_only {
+        tracing::info!("Running in read-only mode, all writes will be rejected");
+    } else if let Err(e) = ensure_writable(&bindle_directory).await {
+        anyhow::bail!(
+            "The bindle directory {} is not writable: {}. HINT: Fix the permissions of the directory or use the --read-only flag to serve it as a read-only mirror",
+            bindle_directory.display(),
+            e
+        );
+    }
+
+    let yank_guard = match opts.yank_limit.or(config.yank_limit) {
+        Some(limit) => {
+            let window = Duration::from_secs(opts.yank_window.or(config.yank_window).unwrap_or(60));
+            tracing::info!(limit, ?window, "Limiting the rate of yanks");
+            YankGuard::new(limit, window).with_admin_groups(
+                opts.yank_admin_group
+                    .or(config.yank_admin_group)
+                    .into_iter()
+                    .collect(),
+            )
+        }
+        None => YankGuard::disabled(),
+    };
+
+    let signature_stripping = if opts.strip_host_signatures || config.strip_host_signatures {
+        tracing::info!("Stripping host signatures from returned invoices");
+        SignatureStripping::Host
+    } else {
+        SignatureStripping::Disabled
+    };
+
+    let yanked_listing = match opts.yanked_listing_group.or(config.yanked_listing_group) {
+        Some(group) => {
+            tracing::info!(%group, "Restricting listing of yanked bindles");
+            YankedListing::Groups(vec![group])
+        }
+        None => YankedListing::Allowed,
+    };
+
+    let mut crawler_policy = CrawlerPolicy {
+        honor_noindex: opts.honor_noindex || config.honor_noindex,
+        ..Default::default()
+    };
+    if let Some(path) = opts.robots_txt.or(config.robots_txt) {
+        let robots = tokio::fs::read_to_string(&path).await.map_err(|e| {
+            anyhow::anyhow!("Unable to read robots.txt from {}: {}", path.display(), e)
+        })?;
+        tracing::info!(path = %path.display(), "Serving robots.txt");
+        crawler_policy.robots_txt = Some(robots.into());
+    }
+
+    let audit_log = match opts.audit_log.or(config.audit_log) {
+        Some(path) => {
+            tracing::info!(path = %path.display(), "Recording changes in audit log");
+            AuditLog::new(FileAuditSink::new(path)).with_admin_groups(
+                opts.audit_admin_group
+                    .or(config.audit_admin_group)
+                    .into_iter()
+                    .collect(),
+            )
+        }
+        None => AuditLog::disabled(),
+    };
+
+    let mut interceptor = InterceptorChain::new();
+    if opts.unique_parcel_names || config.unique_parcel_names {
+        tracing::info!("Rejecting invoices with duplicate parcel names");
+        interceptor = interceptor.with(UniqueParcelNames);
+    }
+    let options = ServerOptions {
+        interceptor,
+        yank_guard,
+        signature_stripping,
+        yanked_listing,
+        crawler_policy,
+        audit_log,
+    };
+
     let index = search::StrictEngine::default();
     let secret_store = SecretKeyFile::load_file(&signing_keys).await.map_err(|e| {
         anyhow::anyhow!(
@@ -217,38 +492,158 @@ async fn main() -> anyhow::Result<()> {
 
     if opts.use_embedded_db {
         warn!("Using EmbeddedProvider. This is currently experimental");
-        let store =
-            provider::embedded::EmbeddedProvider::new(&bindle_directory, index.clone()).await?;
-
-        server(
-            store,
-            index,
-            bindle::authn::always::AlwaysAuthenticate,
-            bindle::authz::always::AlwaysAuthorize,
+        let store = provider::embedded::EmbeddedProvider::new(&bindle_directory, index.clone())
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Unable to open embedded database in {}: {}. HINT: The embedded database requires a writable directory, even with --read-only",
+                    bindle_directory.display(),
+                    e
+                )
+            })?;
+        let store = provider::sampled::SampledReadVerifier::new(store, sample_rate)
+            .with_quarantine(quarantine);
+        let opts = ServeOpts {
             addr,
             tls,
             secret_store,
             strategy,
             keyring,
-        )
-        .await
+            options,
+        };
+
+        if read_only {
+            serve(
+                provider::read_only::ReadOnlyProvider::new(store),
+                index,
+                opts,
+            )
+            .await
+        } else {
+            serve(store, index, opts).await
+        }
     } else {
         tracing::info!("Using FileProvider");
         let store = provider::file::FileProvider::new(&bindle_directory, index.clone()).await;
-
-        server(
-            store,
-            index,
-            bindle::authn::always::AlwaysAuthenticate,
-            bindle::authz::always::AlwaysAuthorize,
+        let store = provider::sampled::SampledReadVerifier::new(store, sample_rate)
+            .with_quarantine(quarantine);
+        let opts = ServeOpts {
             addr,
             tls,
             secret_store,
             strategy,
             keyring,
-        )
+            options,
+        };
+
+        if read_only {
+            serve(
+                provider::read_only::ReadOnlyProvider::new(store),
+                index,
+                opts,
+            )
+            .await
+        } else {
+            serve(store, index, opts).await
+        }
+    }
+}
+
+/// The server configuration that does not depend on the type of provider
+struct ServeOpts {
+    addr: SocketAddr,
+    tls: Option<TlsConfig>,
+    secret_store: SecretKeyFile,
+    strategy: bindle::VerificationStrategy,
+    keyring: KeyRing,
+    options: ServerOptions,
+}
+
+async fn serve<P>(store: P, index: search::StrictEngine, opts: ServeOpts) -> anyhow::Result<()>
+where
+    P: provider::Provider + Clone + Send + Sync + 'static,
+{
+    server(
+        store,
+        index,
+        bindle::authn::always::AlwaysAuthenticate,
+        bindle::authz::always::AlwaysAuthorize,
+        opts.addr,
+        opts.tls,
+        opts.secret_store,
+        opts.strategy,
+        opts.keyring,
+        opts.options,
+    )
+    .await
+}
+
+/// Imports the given directory into the store, printing the result for each invoice. Returns an
+/// error if any invoice was not fully imported
+async fn import<P>(
+    store: P,
+    secret_store: SecretKeyFile,
+    strategy: bindle::VerificationStrategy,
+    keyring: KeyRing,
+    dir: &std::path::Path,
+) -> anyhow::Result<()>
+where
+    P: provider::Provider + Sync,
+{
+    let importer = provider::import::Importer::new(store, secret_store, strategy, keyring);
+    let results = importer
+        .import_dir(dir)
         .await
+        .map_err(|e| anyhow::anyhow!("Unable to read import directory {}: {}", dir.display(), e))?;
+    let mut failed = 0;
+    for result in results.iter() {
+        match &result.outcome {
+            Ok(i) if i.missing_parcels.is_empty() => println!(
+                "{}: {} {} ({} parcels uploaded)",
+                result.path.display(),
+                if i.created {
+                    "imported"
+                } else {
+                    "already exists"
+                },
+                i.id,
+                i.parcels_uploaded
+            ),
+            Ok(i) => {
+                failed += 1;
+                println!(
+                    "{}: incomplete {} ({} parcels uploaded, missing parcels: {})",
+                    result.path.display(),
+                    i.id,
+                    i.parcels_uploaded,
+                    i.missing_parcels.join(", ")
+                )
+            }
+            Err(e) => {
+                failed += 1;
+                println!("{}: failed: {}", result.path.display(), e)
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!(
+            "{} of {} invoices were not fully imported",
+            failed,
+            results.len()
+        );
     }
+    println!("Imported {} invoices", results.len());
+    Ok(())
+}
+
+/// Checks that the given directory can be written to by creating it if it does not exist and then
+/// creating (and immediately removing) a temporary file in it
+async fn ensure_writable(dir: &std::path::Path) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+    let dir = dir.to_owned();
+    tokio::task::spawn_blocking(move || tempfile::tempfile_in(dir).map(|_| ()))
+        .await
+        .map_err(std::io::Error::other)?
 }
 
 fn default_config_file() -> Option<PathBuf> {
@@ -7,6 +7,8 @@ use std::sync::Mutex;
 use std::task::{Context, Poll};
 
 use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
 /// A wrapper to implement `AsyncWrite` on Sha256
 pub struct AsyncSha256 {
@@ -34,7 +36,7 @@ impl AsyncSha256 {
     }
 }
 
-impl tokio::io::AsyncWrite for AsyncSha256 {
+impl AsyncWrite for AsyncSha256 {
     fn poll_write(
         self: Pin<&mut Self>,
         _cx: &mut Context<'_>,
@@ -71,3 +73,193 @@ impl tokio::io::AsyncWrite for AsyncSha256 {
         self.poll_flush(cx)
     }
 }
+
+/// The error returned when data does not match the SHA it was expected to have. The message shows
+/// short SHAs, while the fields hold the full values
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("data does not match the expected SHA {}, got {}", crate::short_sha(.expected), crate::short_sha(.actual))]
+pub struct ShaMismatch {
+    pub expected: String,
+    pub actual: String,
+}
+
+fn check_sha(hasher: Sha256, expected: &str) -> Result<(), ShaMismatch> {
+    let actual = format!("{:x}", hasher.finalize());
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(ShaMismatch {
+            expected: expected.to_owned(),
+            actual,
+        })
+    }
+}
+
+/// An `AsyncRead` wrapper that computes the SHA256 of all data read through it. Once all of the data
+/// has been read, use [`finalize`](VerifyingReader::finalize) to get the SHA or
+/// [`verify`](VerifyingReader::verify) to check it against the expected SHA
+pub struct VerifyingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R> VerifyingReader<R> {
+    /// Wraps the given reader
+    pub fn new(inner: R) -> Self {
+        VerifyingReader {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Returns a reference to the wrapped reader
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns the hex encoded SHA256 of all data read so far
+    pub fn finalize(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+
+    /// Checks the SHA256 of all data read so far against the expected hex encoded SHA
+    pub fn verify(self, expected: &str) -> Result<(), ShaMismatch> {
+        check_sha(self.hasher, expected)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for VerifyingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        // Only hash the bytes that were filled by this read
+        let start = buf.filled().len();
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = res {
+            self.hasher.update(&buf.filled()[start..]);
+        }
+        res
+    }
+}
+
+/// An `AsyncWrite` wrapper that computes the SHA256 of all data written through it. Once all of
+/// the data has been written, use [`finalize`](VerifyingWriter::finalize) to get the SHA or
+/// [`verify`](VerifyingWriter::verify) to check it against the expected SHA
+pub struct VerifyingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W> VerifyingWriter<W> {
+    /// Wraps the given writer
+    pub fn new(inner: W) -> Self {
+        VerifyingWriter {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Returns a reference to the wrapped writer
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns the hex encoded SHA256 of all data written so far. Make sure to flush the writer
+    /// before calling this, as the SHA only covers data accepted by the wrapped writer
+    pub fn finalize(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+
+    /// Checks the SHA256 of all data written so far against the expected hex encoded SHA
+    pub fn verify(self, expected: &str) -> Result<(), ShaMismatch> {
+        check_sha(self.hasher, expected)
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for VerifyingWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let res = Pin::new(&mut self.inner).poll_write(cx, buf);
+        // The wrapped writer may only accept part of the buffer, so only hash what it accepted
+        if let Poll::Ready(Ok(n)) = res {
+            self.hasher.update(&buf[..n]);
+        }
+        res
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn sha(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    #[tokio::test]
+    async fn test_verifying_reader() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+
+        // Read with a buffer that doesn't evenly divide the data so reads cross chunk boundaries
+        let mut reader = VerifyingReader::new(data.as_slice());
+        let mut buf = [0u8; 7];
+        let mut read = Vec::new();
+        loop {
+            let n = reader.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            read.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(data, read);
+        reader.verify(&sha(&data)).expect("SHA should match");
+
+        let mut reader = VerifyingReader::new(data.as_slice());
+        tokio::io::copy(&mut reader, &mut tokio::io::sink())
+            .await
+            .unwrap();
+        let err = reader
+            .verify(&sha(b"other"))
+            .expect_err("SHA should not match");
+        assert_eq!(sha(&data), err.actual);
+
+        let mut reader = VerifyingReader::new(&b""[..]);
+        assert_eq!(0, reader.read(&mut buf).await.unwrap());
+        assert_eq!(sha(b""), reader.finalize());
+    }
+
+    #[tokio::test]
+    async fn test_verifying_writer() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+
+        let mut writer = VerifyingWriter::new(Vec::new());
+        for chunk in data.chunks(7) {
+            writer.write_all(chunk).await.unwrap();
+        }
+        writer.flush().await.unwrap();
+        assert_eq!(&data, writer.get_ref());
+        writer.verify(&sha(&data)).expect("SHA should match");
+
+        let mut writer = VerifyingWriter::new(Vec::new());
+        writer.write_all(&data).await.unwrap();
+        assert!(writer.verify(&sha(b"other")).is_err());
+
+        let mut writer = VerifyingWriter::new(Vec::new());
+        writer.write_all(b"").await.unwrap();
+        assert_eq!(sha(b""), writer.finalize());
+    }
+}
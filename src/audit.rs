@@ -0,0 +1,265 @@
+//! Types for recording who changed what on a bindle server, so the changes can be queried later
+//! for compliance or incident investigation.
+//!
+//! A server with an [`AuditLog`](crate::server::AuditLog) records an [`AuditEntry`] for every
+//! request that creates or yanks something, whether or not it succeeded. Entries are written to an
+//! [`AuditSink`], such as the [`FileAuditSink`], and can be fetched with
+//! [`Client::audit_log`](crate::client::Client::audit_log)
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// A single change that was requested from the server
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// When the request finished, as a UNIX timestamp in seconds
+    pub timestamp: u64,
+    /// The principal of the user who made the request
+    pub principal: String,
+    pub operation: AuditOperation,
+    /// The ID of the bindle the request was for
+    pub target: String,
+    /// The SHA of the parcel the request was for, if it was for a single parcel
+    pub parcel: Option<String>,
+    pub outcome: AuditOutcome,
+    /// The HTTP status code the server responded with
+    pub status: u16,
+}
+
+/// The kind of change recorded in an [`AuditEntry`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditOperation {
+    CreateInvoice,
+    YankInvoice,
+    CreateParcel,
+    /// An upload of several parcels in one request. The entry records the result of the request as
+    /// a whole rather than of each parcel
+    CreateParcels,
+}
+
+/// Whether the change recorded in an [`AuditEntry`] was made
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+/// Filters for an audit log query. Every filter that is set must match for an entry to be
+/// returned, and an empty query returns every entry
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct AuditQuery {
+    /// Only return entries for requests made by this principal
+    pub principal: Option<String>,
+    /// Only return entries for this kind of change
+    pub operation: Option<AuditOperation>,
+    /// Only return entries recorded at or after this UNIX timestamp
+    pub since: Option<u64>,
+    /// Only return entries recorded before this UNIX timestamp
+    pub until: Option<u64>,
+    /// Only return the most recent entries, up to this many
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    /// Returns true if the given entry matches the filters of this query. The limit is not
+    /// considered, as it depends on the other entries
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.principal
+            .as_ref()
+            .map(|p| *p == entry.principal)
+            .unwrap_or(true)
+            && self.operation.map(|o| o == entry.operation).unwrap_or(true)
+            && self.since.map(|s| entry.timestamp >= s).unwrap_or(true)
+            && self.until.map(|u| entry.timestamp < u).unwrap_or(true)
+    }
+
+    /// Returns the entries that match this query, keeping at most the last `limit` of them. The
+    /// entries should be given oldest first, and are returned in the same order
+    pub fn filter(&self, entries: impl IntoIterator<Item = AuditEntry>) -> Vec<AuditEntry> {
+        let mut found: Vec<AuditEntry> = entries.into_iter().filter(|e| self.matches(e)).collect();
+        if let Some(limit) = self.limit {
+            found.drain(..found.len().saturating_sub(limit));
+        }
+        found
+    }
+}
+
+/// The response to an audit log query
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AuditLogResponse {
+    /// The matching entries, oldest first
+    pub entries: Vec<AuditEntry>,
+}
+
+/// A place where audit entries are stored and queried from
+#[async_trait::async_trait]
+pub trait AuditSink {
+    /// Stores the given entry
+    async fn record(&self, entry: AuditEntry) -> anyhow::Result<()>;
+
+    /// Returns the stored entries that match the given query, oldest first
+    async fn query(&self, query: &AuditQuery) -> anyhow::Result<Vec<AuditEntry>>;
+}
+
+/// An [`AuditSink`] that appends entries to a file, one JSON object per line. Lines that cannot be
+/// parsed are skipped when querying, so a partially written line doesn't hide the rest of the log
+#[derive(Debug)]
+pub struct FileAuditSink {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileAuditSink {
+    /// Returns a sink that writes to the file at the given path. The file and its parent
+    /// directories are created when the first entry is recorded
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        FileAuditSink {
+            path: path.as_ref().to_owned(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditSink for FileAuditSink {
+    async fn record(&self, entry: AuditEntry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        // Appends are serialized so lines from concurrent requests are never interleaved
+        let _guard = self.lock.lock().await;
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    async fn query(&self, query: &AuditQuery) -> anyhow::Result<Vec<AuditEntry>> {
+        let data = {
+            let _guard = self.lock.lock().await;
+            match tokio::fs::read(&self.path).await {
+                Ok(d) => d,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            }
+        };
+        let entries = data
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| match serde_json::from_slice(line) {
+                Ok(e) => Some(e),
+                Err(e) => {
+                    warn!(path = %self.path.display(), error = %e, "Skipping invalid audit log entry");
+                    None
+                }
+            });
+        Ok(query.filter(entries))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(timestamp: u64, principal: &str, operation: AuditOperation) -> AuditEntry {
+        AuditEntry {
+            timestamp,
+            principal: principal.to_owned(),
+            operation,
+            target: "example.com/foo/1.0.0".to_owned(),
+            parcel: None,
+            outcome: AuditOutcome::Success,
+            status: 200,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_audit_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = FileAuditSink::new(dir.path().join("audit").join("log.jsonl"));
+        assert!(sink.query(&AuditQuery::default()).await.unwrap().is_empty());
+
+        let entries = [
+            entry(10, "alice", AuditOperation::CreateInvoice),
+            entry(20, "bob", AuditOperation::YankInvoice),
+            entry(30, "alice", AuditOperation::YankInvoice),
+            entry(40, "alice", AuditOperation::CreateParcel),
+        ];
+        for e in entries.iter() {
+            sink.record(e.clone()).await.unwrap();
+        }
+        // A truncated line shouldn't hide the entries around it
+        let mut file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("audit").join("log.jsonl"))
+            .await
+            .unwrap();
+        file.write_all(b"{\"timestamp\": 4\n").await.unwrap();
+        sink.record(entry(50, "bob", AuditOperation::CreateInvoice))
+            .await
+            .unwrap();
+
+        let all = sink.query(&AuditQuery::default()).await.unwrap();
+        assert_eq!(5, all.len());
+        assert_eq!(entries[..], all[..4]);
+
+        let query = |q: AuditQuery| {
+            let sink = &sink;
+            async move {
+                sink.query(&q)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|e| e.timestamp)
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(
+            vec![10, 30, 40],
+            query(AuditQuery {
+                principal: Some("alice".to_owned()),
+                ..Default::default()
+            })
+            .await
+        );
+        assert_eq!(
+            vec![20, 30],
+            query(AuditQuery {
+                operation: Some(AuditOperation::YankInvoice),
+                ..Default::default()
+            })
+            .await
+        );
+        assert_eq!(
+            vec![20, 30],
+            query(AuditQuery {
+                since: Some(20),
+                until: Some(40),
+                ..Default::default()
+            })
+            .await
+        );
+        assert_eq!(
+            vec![40, 50],
+            query(AuditQuery {
+                limit: Some(2),
+                ..Default::default()
+            })
+            .await
+        );
+    }
+}
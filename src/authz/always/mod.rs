@@ -14,19 +14,22 @@ impl Authorizable for Anonymous {
     fn groups(&self) -> Vec<String> {
         Vec::with_capacity(0)
     }
+
+    fn is_authenticated(&self) -> bool {
+        false
+    }
 }
 
 /// An authorizer that always returns success
 #[derive(Debug, Clone)]
 pub struct AlwaysAuthorize;
 
+#[async_trait::async_trait]
 impl Authorizer for AlwaysAuthorize {
-    fn authorize<A: Authorizable>(
-        &self,
-        _: A,
-        _: &str,
-        _: warp::http::Method,
-    ) -> anyhow::Result<()> {
+    async fn authorize<A>(&self, _: A, _: &str, _: warp::http::Method) -> anyhow::Result<()>
+    where
+        A: Authorizable + Send,
+    {
         Ok(())
     }
 }
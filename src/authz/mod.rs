@@ -2,6 +2,9 @@
 //! is enabled
 
 pub mod always;
+pub mod public;
+
+use thiserror::Error;
 
 /// A trait that can be implemented on any type (such as a custom `User` or `Token` type) so that it
 /// can be authorized by an [`Authorizer`](Authorizer)
@@ -12,18 +15,35 @@ pub trait Authorizable {
     /// Returns the groups the authenticated user is a member of, generally embedded on something
     /// like a JWT or fetched from an upstream server
     fn groups(&self) -> Vec<String>;
+
+    /// Returns whether or not the user actually authenticated, as opposed to being an anonymous
+    /// user. Defaults to `true`, so implementors that represent anonymous users should override it
+    fn is_authenticated(&self) -> bool {
+        true
+    }
 }
 
+/// An error an [`Authorizer`](Authorizer) can return to indicate that the request was denied only
+/// because the user is not authenticated. Requests rejected with this error will return a 401
+/// rather than a 403
+#[derive(Error, Debug)]
+#[error("authentication is required to access this resource")]
+pub struct Unauthenticated;
+
 /// A trait for any system that can authorize any [`Authorizable`](Authorizable) type
-// TODO: Will this need to be async?
+#[async_trait::async_trait]
 pub trait Authorizer {
     /// Checks whether or not the given item is authorized to access provided path and method,
-    /// returning a failure reason in the case where the item is not authorized
+    /// returning a failure reason in the case where the item is not authorized. Returning an
+    /// [`Unauthenticated`](Unauthenticated) error will cause the request to be rejected as
+    /// unauthenticated rather than forbidden
     // TODO: We might want to have a custom error enum down the line
-    fn authorize<A: Authorizable>(
+    async fn authorize<A>(
         &self,
         item: A,
         path: &str,
         method: warp::http::Method,
-    ) -> anyhow::Result<()>;
+    ) -> anyhow::Result<()>
+    where
+        A: Authorizable + Send;
 }
@@ -0,0 +1,188 @@
+//! An authorizer that requires authentication for reads, except for bindles that have been marked
+//! as public. This allows for running a server where bindles are private by default while still
+//! publishing a select few publicly
+use std::convert::TryFrom;
+
+use tracing::{debug, trace};
+use warp::http::Method;
+
+use super::{Authorizable, Authorizer, Unauthenticated};
+use crate::provider::Provider;
+use crate::server::filters::PARCEL_ID_SEPARATOR;
+use crate::Id;
+
+/// The annotation that can be set on an invoice to make it publicly readable
+pub const VISIBILITY_ANNOTATION: &str = "visibility";
+/// The value of the [`VISIBILITY_ANNOTATION`](VISIBILITY_ANNOTATION) that marks an invoice as
+/// publicly readable
+pub const PUBLIC_VISIBILITY: &str = "public";
+
+/// The path prefixes of the read endpoints that refer to a specific bindle
+const BINDLE_READ_PATHS: &[&str] = &["/v1/_i/", "/v1/_r/missing/"];
+
+/// An authorizer that rejects unauthenticated reads unless the bindle being read is public. A bindle
+/// is public if its name is within one of the configured public prefixes or if its invoice has a
+/// `visibility = "public"` annotation.
+///
+/// Unauthenticated reads that do not refer to a specific bindle (such as queries) are always
+/// rejected, as they could expose private bindles. Rejected reads return a 401. All requests that
+/// are not rejected by this authorizer, including all writes, are passed to the wrapped authorizer
+#[derive(Clone, Debug)]
+pub struct PublicReadAuthorizer<Authz, P> {
+    inner: Authz,
+    store: P,
+    public_prefixes: Vec<String>,
+}
+
+impl<Authz, P> PublicReadAuthorizer<Authz, P> {
+    /// Returns a new authorizer that wraps the given authorizer. The store is used to look up the
+    /// visibility annotation of invoices. Public prefixes are matched against whole path segments of
+    /// the bindle name, so a prefix of `example.com/public` allows `example.com/public/foo` but not
+    /// `example.com/publicity`
+    pub fn new(inner: Authz, store: P, public_prefixes: Vec<String>) -> Self {
+        PublicReadAuthorizer {
+            inner,
+            store,
+            public_prefixes: public_prefixes
+                .into_iter()
+                .map(|p| p.trim_end_matches('/').to_owned())
+                .collect(),
+        }
+    }
+
+    fn has_public_prefix(&self, id: &Id) -> bool {
+        let name = id.name();
+        self.public_prefixes.iter().any(|prefix| {
+            name.strip_prefix(prefix.as_str())
+                .map(|rest| rest.is_empty() || rest.starts_with('/'))
+                .unwrap_or(false)
+        })
+    }
+}
+
+impl<Authz, P> PublicReadAuthorizer<Authz, P>
+where
+    P: Provider + Send + Sync,
+{
+    async fn is_public(&self, id: Id) -> bool {
+        if self.has_public_prefix(&id) {
+            trace!(%id, "Bindle matches a public prefix");
+            return true;
+        }
+        // Any failure to load the invoice is treated as private so we don't leak whether or not a
+        // bindle exists
+        match self.store.get_yanked_invoice(&id).await {
+            Ok(inv) => inv
+                .annotations
+                .unwrap_or_default()
+                .get(VISIBILITY_ANNOTATION)
+                .map(|v| v == PUBLIC_VISIBILITY)
+                .unwrap_or(false),
+            Err(e) => {
+                debug!(%id, error = %e, "Unable to load invoice for visibility check");
+                false
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<Authz, P> Authorizer for PublicReadAuthorizer<Authz, P>
+where
+    Authz: Authorizer + Send + Sync,
+    P: Provider + Send + Sync,
+{
+    async fn authorize<A>(&self, item: A, path: &str, method: Method) -> anyhow::Result<()>
+    where
+        A: Authorizable + Send,
+    {
+        if !item.is_authenticated() && (method == Method::GET || method == Method::HEAD) {
+            let public = match bindle_id_from_path(path) {
+                Some(id) => self.is_public(id).await,
+                None => false,
+            };
+            if !public {
+                return Err(Unauthenticated.into());
+            }
+        }
+        self.inner.authorize(item, path, method).await
+    }
+}
+
+/// Parses the bindle ID out of the path of a read request, returning `None` if the path does not
+/// refer to a specific bindle
+fn bindle_id_from_path(path: &str) -> Option<Id> {
+    let tail = BINDLE_READ_PATHS
+        .iter()
+        .find_map(|prefix| path.strip_prefix(prefix))?;
+    let raw_id = tail.split(PARCEL_ID_SEPARATOR).next()?;
+    Id::try_from(raw_id).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::authz::always::{AlwaysAuthorize, Anonymous};
+    use crate::testing;
+
+    #[derive(Clone)]
+    struct User;
+
+    impl Authorizable for User {
+        fn principal(&self) -> String {
+            String::from("user")
+        }
+
+        fn groups(&self) -> Vec<String> {
+            Vec::new()
+        }
+    }
+
+    fn is_unauthenticated(res: anyhow::Result<()>) -> bool {
+        matches!(res, Err(e) if e.downcast_ref::<Unauthenticated>().is_some())
+    }
+
+    #[tokio::test]
+    async fn test_public_read_authorizer() {
+        let (store, _, _) = testing::setup().await;
+        let authz = PublicReadAuthorizer::new(
+            AlwaysAuthorize,
+            store,
+            vec!["example.com/public/".to_owned()],
+        );
+
+        assert!(authz
+            .authorize(
+                Anonymous,
+                "/v1/_i/example.com/public/foo/1.0.0",
+                Method::GET
+            )
+            .await
+            .is_ok());
+        assert!(authz
+            .authorize(
+                Anonymous,
+                "/v1/_i/example.com/public/foo/1.0.0@abcd",
+                Method::HEAD
+            )
+            .await
+            .is_ok());
+        assert!(is_unauthenticated(
+            authz
+                .authorize(Anonymous, "/v1/_i/example.com/publicity/1.0.0", Method::GET)
+                .await
+        ));
+        assert!(is_unauthenticated(
+            authz.authorize(Anonymous, "/v1/_q", Method::GET).await
+        ));
+        // Writes and authenticated requests are left to the wrapped authorizer
+        assert!(authz
+            .authorize(Anonymous, "/v1/_i", Method::POST)
+            .await
+            .is_ok());
+        assert!(authz
+            .authorize(User, "/v1/_i/example.com/private/1.0.0", Method::GET)
+            .await
+            .is_ok());
+    }
+}
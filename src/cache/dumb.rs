@@ -1,6 +1,10 @@
 //! A cache that doesn't ever expire entries, generally for use by a client storing bindles on disk
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::sync::Arc;
 
+use futures::future::{BoxFuture, FutureExt, Shared};
+use tokio::sync::Mutex;
 use tokio_stream::{Stream, StreamExt};
 use tracing::{debug, instrument, trace, warn};
 use tracing_futures::Instrument;
@@ -10,32 +14,138 @@ use crate::provider::{Provider, ProviderError, Result};
 use crate::verification::Verified;
 use crate::{Id, Signed};
 
+/// The shared result of fetching a parcel from the remote and storing it in the local cache. `true`
+/// means the parcel was stored locally and `false` means it was fetched but could not be stored
+type InFlightFetch = Shared<BoxFuture<'static, std::result::Result<bool, Arc<ProviderError>>>>;
+
 /// A cache that doesn't ever expire entries. It fills the cache by requesting bindles from a bindle
-/// server using the configured client and stores them in the given storage implementation
+/// server using the configured client and stores them in the given storage implementation.
+///
+/// Concurrent cache misses for the same parcel are coalesced so that only a single fetch is made to
+/// the remote. As parcels are content addressed, this is keyed only by the parcel SHA, so misses for
+/// a parcel shared by several bindles are coalesced too
 #[derive(Clone)]
 pub struct DumbCache<Local: Provider + Clone, Remote: Provider + Clone> {
     remote: Remote,
     local: Local,
+    in_flight: Arc<Mutex<HashMap<String, InFlightFetch>>>,
 }
 
 impl<Local: Provider + Clone, Remote: Provider + Clone> DumbCache<Local, Remote> {
     pub fn new(remote: Remote, local: Local) -> DumbCache<Local, Remote> {
-        DumbCache { remote, local }
+        DumbCache {
+            remote,
+            local,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl<Local, Remote> DumbCache<Local, Remote>
+where
+    Local: Provider + Send + Sync + Clone + 'static,
+    Remote: Provider + Send + Sync + Clone + 'static,
+{
+    /// Returns the in flight fetch for the given parcel, starting a new one if no other caller is
+    /// currently fetching it
+    async fn fetch_parcel(&self, bindle_id: &Id, parcel_id: &str) -> InFlightFetch {
+        let mut in_flight = self.in_flight.lock().await;
+        if let Some(fetch) = in_flight.get(parcel_id) {
+            trace!("Parcel is already being fetched, waiting on existing fetch");
+            return fetch.clone();
+        }
+
+        let local = self.local.clone();
+        let remote = self.remote.clone();
+        let all_fetches = self.in_flight.clone();
+        let bindle_id = bindle_id.clone();
+        let key = parcel_id.to_owned();
+        let fetch = async move {
+            let res = store_remote_parcel(&local, &remote, &bindle_id, &key).await;
+            // Remove the entry before anyone is handed the result so later cache misses start a
+            // new fetch rather than reusing this one
+            all_fetches.lock().await.remove(&key);
+            res.map_err(Arc::new)
+        }
+        .boxed()
+        .shared();
+        in_flight.insert(parcel_id.to_owned(), fetch.clone());
+        fetch
+    }
+}
+
+/// Fetches the parcel from the remote and attempts to store it in the local provider, returning
+/// whether or not it was stored
+async fn store_remote_parcel<Local: Provider, Remote: Provider>(
+    local: &Local,
+    remote: &Remote,
+    bindle_id: &Id,
+    parcel_id: &str,
+) -> Result<bool> {
+    // Another fetch may have completed between our cache check and the start of this one
+    if local
+        .parcel_exists(bindle_id, parcel_id)
+        .await
+        .unwrap_or(false)
+    {
+        return Ok(true);
+    }
+    let stream = remote
+        .get_parcel(bindle_id, parcel_id)
+        .await?
+        // This isn't my favorite. Right now we are mapping to an io error which will be mapped back to a storage error
+        .map(|res| res.map_err(|e| std::io::Error::other(e.to_string())));
+    // Attempt to insert the parcel into the store, if it fails, warn the user and return the parcel
+    // from the remote anyway
+    trace!("Attempting to store parcel in cache");
+    match local.create_parcel(bindle_id, parcel_id, stream).await {
+        Ok(_) => Ok(true),
+        Err(e) => {
+            warn!("Fetched parcel from server, but encountered error when trying to save to local store: {:?}", e);
+            Ok(false)
+        }
     }
 }
 
+/// Converts an error shared between coalesced fetches back into an owned error, keeping the
+/// variants that callers are likely to match on
+fn to_owned_error(e: &ProviderError) -> ProviderError {
+    match e {
+        ProviderError::NotFound => ProviderError::NotFound,
+        ProviderError::Yanked => ProviderError::Yanked,
+        ProviderError::SizeMismatch => ProviderError::SizeMismatch,
+        ProviderError::DigestMismatch => ProviderError::DigestMismatch,
+        _ => ProviderError::Other(e.to_string()),
+    }
+}
+
+#[async_trait::async_trait]
 impl<Local, Remote> Cache for DumbCache<Local, Remote>
 where
-    Local: Provider + Send + Sync + Clone,
-    Remote: Provider + Send + Sync + Clone,
+    Local: Provider + Send + Sync + Clone + 'static,
+    Remote: Provider + Send + Sync + Clone + 'static,
 {
+    #[instrument(level = "trace", skip(self, bindle_id))]
+    async fn get_parcel_cached_only<I>(
+        &self,
+        bindle_id: I,
+        parcel_id: &str,
+    ) -> Result<Box<dyn Stream<Item = Result<bytes::Bytes>> + Unpin + Send + Sync>>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        let parsed_id = bindle_id.try_into().map_err(|e| e.into())?;
+        into_cache_result(self.local.get_parcel(&parsed_id, parcel_id).await)?
+            .ok_or(ProviderError::NotCached)
+    }
 }
 
 #[async_trait::async_trait]
 impl<Local, Remote> Provider for DumbCache<Local, Remote>
 where
-    Local: Provider + Send + Sync + Clone,
-    Remote: Provider + Send + Sync + Clone,
+    Local: Provider + Send + Sync + Clone + 'static,
+    Remote: Provider + Send + Sync + Clone + 'static,
 {
     async fn create_invoice<I>(&self, _: I) -> Result<(crate::Invoice, Vec<crate::Label>)>
     where
@@ -126,36 +236,17 @@ where
                     debug!(
                         "Cache miss for parcel, attempting to fetch from server"
                     );
-                    let label = crate::Label {
-                        sha256: parcel_id.to_owned(),
-                        name: "".to_string(),
-                        ..crate::Label::default()
-                    };
-                    let stream = self
-                        .remote
-                        .get_parcel(&parsed_id, parcel_id)
-                        .await?
-                        // This isn't my favorite. Right now we are mapping to an io error which will be mapped back to a storage error
-                        .map(|res| {
-                            res.map_err(|e| {
-                                std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
-                            })
-                        });
-                    // Attempt to insert the parcel into the store, if it fails, warn the user and
-                    // return the parcel anyway. Either way, we need to refetch the stream, since it has
-                    // been read after we try to insert
-                    trace!("Attempting to store parcel in cache");
-                    let stream = match self
-                        .local
-                        .create_parcel(&parsed_id, &label.sha256, stream)
+                    let stored = self
+                        .fetch_parcel(&parsed_id, parcel_id)
                         .await
-                    {
-                        Ok(_) => return self.local.get_parcel(&parsed_id, parcel_id).await,
-                        Err(e) => {
-                            warn!("Fetched parcel from server, but encountered error when trying to save to local store: {:?}", e);
-                            self.remote.get_parcel(&parsed_id, parcel_id).await?
-                        }
-                    };
+                        .await
+                        .map_err(|e| to_owned_error(&e))?;
+                    // The fetched stream has already been read, so we need to refetch it, either from
+                    // the local store or from the remote if storing it failed
+                    if stored {
+                        return self.local.get_parcel(&parsed_id, parcel_id).await;
+                    }
+                    let stream = self.remote.get_parcel(&parsed_id, parcel_id).await?;
                     Ok(Box::new(stream.map(|res| res.map_err(ProviderError::from))))
                 }.instrument(tracing::trace_span!("get_parcel_cache_miss", invoice_id = %parsed_id, parcel_id)).await
             }
@@ -171,4 +262,112 @@ where
     {
         self.local.parcel_exists(bindle_id, parcel_id).await
     }
+
+    async fn parcels_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        // The cache only contains a subset of parcels, so the remote is the only source of truth
+        self.remote.parcels_with_prefix(prefix).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::test_provider::TestProvider;
+    use crate::provider::file::FileProvider;
+    use crate::search::NoopEngine;
+    use crate::testing;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_get_parcel() {
+        let remote = TestProvider {
+            get_parcel_delay: std::time::Duration::from_millis(100),
+            ..Default::default()
+        };
+        let tempdir = tempfile::tempdir().expect("Unable to create tempdir");
+        let local = FileProvider::new(tempdir.path(), NoopEngine::default()).await;
+        let scaffold = testing::Scaffold::load("valid_v1").await;
+        local
+            .create_invoice(super::super::noop_verify_and_sign(scaffold.invoice.clone()))
+            .await
+            .expect("Unable to create invoice");
+        // A second bindle containing the same parcel, whose misses should share the same fetch
+        let mut other = scaffold.invoice.clone();
+        other.bindle.id = "enterprise.com/warpcore/2.0.0".parse().unwrap();
+        local
+            .create_invoice(super::super::noop_verify_and_sign(other.clone()))
+            .await
+            .expect("Unable to create invoice");
+        let cache = DumbCache::new(remote.clone(), local);
+
+        let sha = scaffold.parcel_files.values().next().unwrap().sha.clone();
+        let ids = [scaffold.invoice.bindle.id.clone(), other.bindle.id];
+        let handles: Vec<_> = (0..10)
+            .map(|i| {
+                let cache = cache.clone();
+                let sha = sha.clone();
+                let id = ids[i % ids.len()].clone();
+                tokio::spawn(async move {
+                    let mut stream = cache
+                        .get_parcel(id, &sha)
+                        .await
+                        .expect("Should be able to get parcel");
+                    let mut data = Vec::new();
+                    while let Some(chunk) = stream.next().await {
+                        data.extend_from_slice(&chunk.expect("Unable to read parcel data"));
+                    }
+                    data
+                })
+            })
+            .collect();
+
+        let expected = &scaffold.parcel_files.values().next().unwrap().data;
+        for data in futures::future::join_all(handles).await {
+            assert_eq!(
+                expected,
+                &data.expect("Task should not panic"),
+                "Parcel data should match"
+            );
+        }
+        assert_eq!(
+            1,
+            *remote.get_parcel_count.lock().await,
+            "Remote should only be called once for concurrent cache misses"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_parcel_cached_only() {
+        let remote = TestProvider::default();
+        let tempdir = tempfile::tempdir().expect("Unable to create tempdir");
+        let local = FileProvider::new(tempdir.path(), NoopEngine::default()).await;
+        let scaffold = testing::Scaffold::load("valid_v1").await;
+        local
+            .create_invoice(super::super::noop_verify_and_sign(scaffold.invoice.clone()))
+            .await
+            .expect("Unable to create invoice");
+        let cache = DumbCache::new(remote.clone(), local);
+
+        let parcel = scaffold.parcel_files.values().next().unwrap();
+        let id = &scaffold.invoice.bindle.id;
+        assert!(matches!(
+            cache.get_parcel_cached_only(id, &parcel.sha).await,
+            Err(ProviderError::NotCached)
+        ));
+        assert_eq!(0, *remote.get_parcel_count.lock().await);
+
+        let _ = cache
+            .get_parcel(id, &parcel.sha)
+            .await
+            .expect("Should be able to get parcel");
+        let mut stream = cache
+            .get_parcel_cached_only(id, &parcel.sha)
+            .await
+            .expect("Parcel should be cached");
+        let mut data = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            data.extend_from_slice(&chunk.expect("Unable to read parcel data"));
+        }
+        assert_eq!(parcel.data, data);
+        assert_eq!(1, *remote.get_parcel_count.lock().await);
+    }
 }
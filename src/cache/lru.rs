@@ -48,7 +48,47 @@ impl<Remote: Provider + Clone> LruCache<Remote> {
     }
 }
 
-impl<Remote> Cache for LruCache<Remote> where Remote: Provider + Send + Sync + Clone {}
+#[async_trait::async_trait]
+impl<Remote> Cache for LruCache<Remote>
+where
+    Remote: Provider + Send + Sync + Clone,
+{
+    #[instrument(level = "trace", skip(self, bindle_id), fields(invoice_id))]
+    async fn get_parcel_cached_only<I>(
+        &self,
+        bindle_id: I,
+        parcel_id: &str,
+    ) -> Result<Box<dyn Stream<Item = Result<bytes::Bytes>> + Unpin + Send + Sync>>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        let parsed_id = bindle_id.try_into().map_err(|e| e.into())?;
+        tracing::span::Span::current().record("invoice_id", &tracing::field::display(&parsed_id));
+        // The parcel can only be validated against a cached invoice, as fetching the invoice
+        // would mean contacting the remote
+        match self.invoices.lock().await.get(&parsed_id) {
+            Some(inv)
+                if inv
+                    .parcel
+                    .iter()
+                    .flatten()
+                    .any(|p| p.label.sha256 == parcel_id) => {}
+            Some(_) => return Err(ProviderError::NotFound),
+            None => return Err(ProviderError::NotCached),
+        }
+
+        let mut parcels = self.parcels.lock().await;
+        let file = match parcels.get(&parcel_id.to_owned()) {
+            Some(f) => File::from_std(tokio::task::block_in_place(move || f.reopen())?),
+            None => return Err(ProviderError::NotCached),
+        };
+        Ok::<Box<dyn Stream<Item = Result<bytes::Bytes>> + Unpin + Send + Sync>, _>(Box::new(
+            FramedRead::new(file, BytesCodec::default())
+                .map(|res| res.map(|b| b.freeze()).map_err(ProviderError::from)),
+        ))
+    }
+}
 
 #[async_trait::async_trait]
 impl<Remote> Provider for LruCache<Remote>
@@ -207,122 +247,21 @@ where
             self.remote.parcel_exists(&parsed_id, parcel_id).instrument(tracing::trace_span!("parcel_exists_cache_miss", invoice_id = %parsed_id, parcel_id)).await
         }
     }
+
+    #[instrument(level = "trace", skip(self))]
+    async fn parcels_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        // The cache only contains a subset of parcels, so the remote is the only source of truth
+        self.remote.parcels_with_prefix(prefix).await
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::{
-        provider::Provider, signature::KeyRing, testing, SecretKeyEntry, SignatureRole,
-        VerificationStrategy,
+        cache::test_provider::TestProvider, provider::Provider, signature::KeyRing, testing,
+        SecretKeyEntry, SignatureRole, VerificationStrategy,
     };
-    use std::{convert::TryFrom, sync::Arc};
-
-    use tokio::sync::Mutex;
-    use tokio_stream::StreamExt;
-
-    /// A test provider that lets us make sure the cache is being called
-    #[derive(Default, Clone)]
-    struct TestProvider {
-        get_yanked_count: Arc<Mutex<u8>>,
-        get_parcel_count: Arc<Mutex<u8>>,
-        parcel_exists_count: Arc<Mutex<u8>>,
-
-        create_invoice_called: Arc<Mutex<bool>>,
-        yank_invoice_called: Arc<Mutex<bool>>,
-        create_parcel_called: Arc<Mutex<bool>>,
-    }
-
-    #[async_trait::async_trait]
-    impl Provider for TestProvider {
-        async fn create_invoice<I>(&self, _inv: I) -> Result<(crate::Invoice, Vec<crate::Label>)>
-        where
-            I: Signed + Verified + Send + Sync,
-        {
-            let mut called = self.create_invoice_called.lock().await;
-            *called = true;
-            Ok((
-                crate::Invoice::new(crate::BindleSpec {
-                    id: crate::Id::try_from("foo/bar/1.0.0").unwrap(),
-                    description: None,
-                    authors: None,
-                }),
-                Vec::new(),
-            ))
-        }
-
-        async fn get_yanked_invoice<I>(&self, _id: I) -> Result<Invoice>
-        where
-            I: TryInto<Id> + Send,
-            I::Error: Into<ProviderError>,
-        {
-            let scaffold = testing::Scaffold::load("valid_v1").await;
-            let mut count = self.get_yanked_count.lock().await;
-            *count += 1;
-            Ok(scaffold.invoice)
-        }
-
-        async fn yank_invoice<I>(&self, _id: I) -> Result<()>
-        where
-            I: TryInto<Id> + Send,
-            I::Error: Into<ProviderError>,
-        {
-            let mut called = self.yank_invoice_called.lock().await;
-            *called = true;
-            Ok(())
-        }
-
-        async fn create_parcel<I, R, B>(
-            &self,
-            _bindle_id: I,
-            _parcel_id: &str,
-            _data: R,
-        ) -> Result<()>
-        where
-            I: TryInto<Id> + Send,
-            I::Error: Into<ProviderError>,
-            R: Stream<Item = std::io::Result<B>> + Unpin + Send + Sync + 'static,
-            B: bytes::Buf + Send,
-        {
-            let mut called = self.create_parcel_called.lock().await;
-            *called = true;
-            Ok(())
-        }
-
-        async fn get_parcel<I>(
-            &self,
-            _bindle_id: I,
-            parcel_id: &str,
-        ) -> Result<Box<dyn Stream<Item = Result<bytes::Bytes>> + Unpin + Send + Sync>>
-        where
-            I: TryInto<Id> + Send,
-            I::Error: Into<ProviderError>,
-        {
-            let scaffold = testing::Scaffold::load("valid_v1").await;
-            let mut count = self.get_parcel_count.lock().await;
-            *count += 1;
-            let info = scaffold
-                .parcel_files
-                .into_iter()
-                .map(|(_, info)| info)
-                .find(|info| info.sha == parcel_id)
-                .expect("Unable to find parcel");
-            Ok(Box::new(
-                FramedRead::new(std::io::Cursor::new(info.data), BytesCodec::default())
-                    .map(|res| res.map(|b| b.freeze()).map_err(ProviderError::from)),
-            ))
-        }
-
-        async fn parcel_exists<I>(&self, _bindle_id: I, _parcel_id: &str) -> Result<bool>
-        where
-            I: TryInto<Id> + Send,
-            I::Error: Into<ProviderError>,
-        {
-            let mut count = self.parcel_exists_count.lock().await;
-            *count += 1;
-            Ok(true)
-        }
-    }
 
     #[tokio::test]
     async fn test_get_invoice() {
@@ -372,6 +311,47 @@ mod test {
         )
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_get_parcel_cached_only() {
+        let provider = TestProvider::default();
+        let cache = LruCache::new(10, provider.clone());
+
+        let scaffold = testing::Scaffold::load("valid_v1").await;
+        let sha = scaffold.parcel_files.get("parcel").unwrap().sha.as_str();
+        assert!(matches!(
+            cache
+                .get_parcel_cached_only(&scaffold.invoice.bindle.id, sha)
+                .await,
+            Err(ProviderError::NotCached)
+        ));
+
+        let _ = cache
+            .get_parcel(&scaffold.invoice.bindle.id, sha)
+            .await
+            .expect("Should be able to get parcel");
+        let mut stream = cache
+            .get_parcel_cached_only(&scaffold.invoice.bindle.id, sha)
+            .await
+            .expect("Parcel should be cached");
+        let mut data = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            data.extend_from_slice(&chunk.expect("Unable to read parcel data"));
+        }
+        assert_eq!(scaffold.parcel_files.get("parcel").unwrap().data, data);
+
+        assert!(matches!(
+            cache
+                .get_parcel_cached_only(&scaffold.invoice.bindle.id, "abc123")
+                .await,
+            Err(ProviderError::NotFound)
+        ));
+        assert_eq!(
+            1,
+            *provider.get_parcel_count.lock().await,
+            "Remote should only be called for the uncached read"
+        );
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn test_parcel_exists() {
         let provider = TestProvider::default();
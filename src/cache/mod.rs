@@ -1,10 +1,14 @@
 //! Caching implementations for client and server-side usage. This module is under heavy development
 //! and iteration
 
+use std::convert::TryInto;
+
+use tokio_stream::Stream;
+
 use crate::{
     provider::{Provider, ProviderError},
     verification::{NoopVerified, Verified},
-    NoopSigned, Signed,
+    Id, NoopSigned, Signed,
 };
 
 pub mod dumb;
@@ -14,8 +18,32 @@ pub use dumb::DumbCache;
 mod lru;
 pub use self::lru::LruCache;
 
-/// A marker trait that indicates this is a caching implementation (as opposed to just a provider)
-pub trait Cache: Provider {}
+#[cfg(test)]
+mod test_provider;
+
+/// A trait that indicates this is a caching implementation (as opposed to just a provider)
+#[async_trait::async_trait]
+pub trait Cache: Provider {
+    /// Returns the requested parcel if it is already in the cache, without ever contacting the
+    /// remote. If it isn't, a [`ProviderError::NotCached`] is returned instead of fetching it. This
+    /// allows content that was fetched ahead of time to be used while the remote is unreachable.
+    ///
+    /// The default implementation treats every parcel as not cached, so caches that can't look up
+    /// their local copy without the remote don't need to implement it
+    async fn get_parcel_cached_only<I>(
+        &self,
+        _bindle_id: I,
+        _parcel_id: &str,
+    ) -> crate::provider::Result<
+        Box<dyn Stream<Item = crate::provider::Result<bytes::Bytes>> + Unpin + Send + Sync>,
+    >
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        Err(ProviderError::NotCached)
+    }
+}
 
 /// A custom result type representing a possible cache miss. As all underlying caches implement
 /// `Storage`, this contains a storage error that is guaranteed not to be a cache miss (e.g.
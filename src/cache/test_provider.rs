@@ -0,0 +1,117 @@
+//! A remote provider for cache tests that counts how many times each method is called
+
+use std::convert::{TryFrom, TryInto};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+use tokio_stream::{Stream, StreamExt};
+use tokio_util::codec::{BytesCodec, FramedRead};
+
+use crate::provider::{Provider, ProviderError, Result};
+use crate::verification::Verified;
+use crate::{testing, Id, Invoice, Signed};
+
+/// A test provider that lets us make sure the cache is being called
+#[derive(Default, Clone)]
+pub(crate) struct TestProvider {
+    pub get_yanked_count: Arc<Mutex<u8>>,
+    pub get_parcel_count: Arc<Mutex<u8>>,
+    pub parcel_exists_count: Arc<Mutex<u8>>,
+
+    pub create_invoice_called: Arc<Mutex<bool>>,
+    pub yank_invoice_called: Arc<Mutex<bool>>,
+    pub create_parcel_called: Arc<Mutex<bool>>,
+
+    /// How long `get_parcel` should wait before returning, used to simulate a slow remote
+    pub get_parcel_delay: Duration,
+}
+
+#[async_trait::async_trait]
+impl Provider for TestProvider {
+    async fn create_invoice<I>(&self, _inv: I) -> Result<(crate::Invoice, Vec<crate::Label>)>
+    where
+        I: Signed + Verified + Send + Sync,
+    {
+        let mut called = self.create_invoice_called.lock().await;
+        *called = true;
+        Ok((
+            crate::Invoice::new(crate::BindleSpec {
+                id: crate::Id::try_from("foo/bar/1.0.0").unwrap(),
+                description: None,
+                authors: None,
+            }),
+            Vec::new(),
+        ))
+    }
+
+    async fn get_yanked_invoice<I>(&self, _id: I) -> Result<Invoice>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        let scaffold = testing::Scaffold::load("valid_v1").await;
+        let mut count = self.get_yanked_count.lock().await;
+        *count += 1;
+        Ok(scaffold.invoice)
+    }
+
+    async fn yank_invoice<I>(&self, _id: I) -> Result<()>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        let mut called = self.yank_invoice_called.lock().await;
+        *called = true;
+        Ok(())
+    }
+
+    async fn create_parcel<I, R, B>(&self, _bindle_id: I, _parcel_id: &str, _data: R) -> Result<()>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+        R: Stream<Item = std::io::Result<B>> + Unpin + Send + Sync + 'static,
+        B: bytes::Buf + Send,
+    {
+        let mut called = self.create_parcel_called.lock().await;
+        *called = true;
+        Ok(())
+    }
+
+    async fn get_parcel<I>(
+        &self,
+        _bindle_id: I,
+        parcel_id: &str,
+    ) -> Result<Box<dyn Stream<Item = Result<bytes::Bytes>> + Unpin + Send + Sync>>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        {
+            let mut count = self.get_parcel_count.lock().await;
+            *count += 1;
+        }
+        tokio::time::sleep(self.get_parcel_delay).await;
+        let scaffold = testing::Scaffold::load("valid_v1").await;
+        let info = scaffold
+            .parcel_files
+            .into_iter()
+            .map(|(_, info)| info)
+            .find(|info| info.sha == parcel_id)
+            .expect("Unable to find parcel");
+        Ok(Box::new(
+            FramedRead::new(std::io::Cursor::new(info.data), BytesCodec::default())
+                .map(|res| res.map(|b| b.freeze()).map_err(ProviderError::from)),
+        ))
+    }
+
+    async fn parcel_exists<I>(&self, _bindle_id: I, _parcel_id: &str) -> Result<bool>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        let mut count = self.parcel_exists_count.lock().await;
+        *count += 1;
+        Ok(true)
+    }
+}
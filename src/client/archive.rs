@@ -0,0 +1,270 @@
+//! Types and functions for archives created by
+//! [`export_many_to_tar`](super::Client::export_many_to_tar), including reassembling parcels that
+//! were split into chunks
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio_stream::StreamExt;
+use tracing::{debug, trace};
+
+use super::{ClientError, Result};
+use crate::async_util::VerifyingWriter;
+use crate::provider::file::{PARCEL_DAT, PARCEL_DIRECTORY};
+
+/// The name of the manifest at the root of an archive that describes the chunks of every parcel
+/// that was split. It is only present if at least one parcel was split
+pub const CHUNK_MANIFEST: &str = "chunks.toml";
+/// The directory in an archive containing the chunks of split parcels, stored as
+/// `chunks/<PARCEL_SHA>/<INDEX>`
+pub const CHUNK_DIRECTORY: &str = "chunks";
+
+/// Describes which archive entries make up each parcel that was split into chunks
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkManifest {
+    #[serde(default)]
+    pub parcel: Vec<ChunkedParcel>,
+}
+
+/// A parcel that was split into chunks. Concatenating the chunks in order gives the parcel data
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChunkedParcel {
+    pub sha256: String,
+    pub size: u64,
+    pub chunk: Vec<Chunk>,
+}
+
+/// A single archive entry holding the given byte range of a parcel
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// The path of the entry in the archive
+    pub path: String,
+    /// The offset of the first byte of the chunk in the parcel
+    pub offset: u64,
+    /// The number of bytes in the chunk
+    pub length: u64,
+}
+
+impl ChunkedParcel {
+    /// Splits a parcel of the given size into chunks of at most `chunk_size` bytes
+    pub(crate) fn new(sha256: &str, size: u64, chunk_size: u64) -> Self {
+        let chunk = (0..size)
+            .step_by(chunk_size.max(1) as usize)
+            .enumerate()
+            .map(|(index, offset)| Chunk {
+                path: format!("{}/{}/{}", CHUNK_DIRECTORY, sha256, index),
+                offset,
+                length: chunk_size.min(size - offset),
+            })
+            .collect();
+        ChunkedParcel {
+            sha256: sha256.to_owned(),
+            size,
+            chunk,
+        }
+    }
+}
+
+/// Unpacks an archive created by [`export_many_to_tar`](super::Client::export_many_to_tar) into the
+/// given directory, which can then be served by a
+/// [`FileProvider`](crate::provider::file::FileProvider).
+///
+/// Parcels that were split into chunks are reassembled and checked against their SHA, returning a
+/// [`ClientError::ParcelShaMismatch`] if the data doesn't match. Chunks are unpacked as-is before
+/// being reassembled, so an interrupted transfer can be resumed by unpacking the remaining entries
+/// into the same directory. Once a parcel has been reassembled, its chunks are removed
+pub async fn unpack<R, P>(reader: R, dest: P) -> Result<()>
+where
+    R: AsyncRead + Unpin + Send,
+    P: AsRef<Path>,
+{
+    let dest = dest.as_ref();
+    let mut archive = tokio_tar::Archive::new(reader);
+    let mut entries = archive.entries()?;
+    let mut manifest: Option<ChunkManifest> = None;
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        if entry.path()?.as_ref() == Path::new(CHUNK_MANIFEST) {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).await?;
+            manifest = Some(toml::from_slice(&data)?);
+            continue;
+        }
+        trace!(path = %entry.path()?.display(), "Unpacking archive entry");
+        entry.unpack_in(dest).await?;
+    }
+
+    // The manifest comes from the archive, so check every path in it before touching the
+    // filesystem. Unlike the entries themselves, nothing else keeps it inside of dest
+    let manifest = manifest.unwrap_or_default();
+    for parcel in manifest.parcel.iter() {
+        validate_chunked_parcel(parcel)?;
+    }
+    for parcel in manifest.parcel {
+        reassemble(dest, &parcel).await?;
+    }
+    Ok(())
+}
+
+/// Checks that the SHA of the parcel is a valid SHA-256 and that every chunk is at the path the
+/// export would have given it
+fn validate_chunked_parcel(parcel: &ChunkedParcel) -> Result<()> {
+    let invalid = || {
+        ClientError::Other(format!(
+            "Archive manifest contains an invalid chunked parcel {}",
+            parcel.sha256
+        ))
+    };
+    if parcel.sha256.len() != 64
+        || !parcel
+            .sha256
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    {
+        return Err(invalid());
+    }
+    for (index, chunk) in parcel.chunk.iter().enumerate() {
+        if chunk.path != format!("{}/{}/{}", CHUNK_DIRECTORY, parcel.sha256, index) {
+            return Err(invalid());
+        }
+    }
+    Ok(())
+}
+
+async fn reassemble(dest: &Path, parcel: &ChunkedParcel) -> Result<()> {
+    let parcel_dir: PathBuf = [dest, Path::new(PARCEL_DIRECTORY), Path::new(&parcel.sha256)]
+        .iter()
+        .collect();
+    let parcel_path = parcel_dir.join(PARCEL_DAT);
+    debug!(parcel_id = %parcel.sha256, chunks = parcel.chunk.len(), "Reassembling parcel");
+
+    // Chunks are checked before anything is written, so a missing chunk doesn't leave a partial
+    // parcel behind
+    let mut sizes = HashMap::new();
+    for chunk in parcel.chunk.iter() {
+        let len = tokio::fs::metadata(dest.join(&chunk.path)).await?.len();
+        sizes.insert(chunk.path.as_str(), len);
+    }
+    let mut expected_offset = 0;
+    for chunk in parcel.chunk.iter() {
+        if chunk.offset != expected_offset || sizes[chunk.path.as_str()] != chunk.length {
+            return Err(ClientError::Other(format!(
+                "Chunk {} of parcel {} does not match the archive manifest",
+                chunk.path, parcel.sha256
+            )));
+        }
+        expected_offset += chunk.length;
+    }
+
+    tokio::fs::create_dir_all(&parcel_dir).await?;
+    let mut writer = VerifyingWriter::new(tokio::fs::File::create(&parcel_path).await?);
+    for chunk in parcel.chunk.iter() {
+        let mut file = tokio::fs::File::open(dest.join(&chunk.path)).await?;
+        tokio::io::copy(&mut file, &mut writer).await?;
+    }
+    writer.flush().await?;
+    if writer.verify(&parcel.sha256).is_err() {
+        tokio::fs::remove_dir_all(&parcel_dir).await?;
+        return Err(ClientError::ParcelShaMismatch(parcel.sha256.clone()));
+    }
+
+    tokio::fs::remove_dir_all(dest.join(CHUNK_DIRECTORY).join(&parcel.sha256)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chunk_ranges() {
+        let parcel = ChunkedParcel::new("abc", 10, 4);
+        assert_eq!(
+            vec![(0, 4), (4, 4), (8, 2)],
+            parcel
+                .chunk
+                .iter()
+                .map(|c| (c.offset, c.length))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("chunks/abc/2", parcel.chunk[2].path);
+    }
+
+    #[tokio::test]
+    async fn test_unpack_hostile_manifest() {
+        let root = tempfile::tempdir().unwrap();
+        let dest = root.path().join("dest");
+        let victim = root.path().join("victim");
+        tokio::fs::create_dir_all(&victim).await.unwrap();
+        tokio::fs::write(victim.join("data"), b"precious")
+            .await
+            .unwrap();
+        let sha = "a".repeat(64);
+
+        let bad_parcels = vec![
+            // A SHA that escapes the parcel directory
+            ChunkedParcel {
+                sha256: "../../victim".to_owned(),
+                size: 8,
+                chunk: vec![Chunk {
+                    path: "chunks/../../victim/0".to_owned(),
+                    offset: 0,
+                    length: 8,
+                }],
+            },
+            // A chunk read from outside of the archive
+            ChunkedParcel {
+                sha256: sha.clone(),
+                size: 8,
+                chunk: vec![Chunk {
+                    path: victim.join("data").display().to_string(),
+                    offset: 0,
+                    length: 8,
+                }],
+            },
+            // A chunk that is out of order
+            ChunkedParcel {
+                sha256: sha.clone(),
+                size: 8,
+                chunk: vec![Chunk {
+                    path: format!("{}/{}/1", CHUNK_DIRECTORY, sha),
+                    offset: 0,
+                    length: 8,
+                }],
+            },
+        ];
+        for parcel in bad_parcels {
+            let data = toml::to_vec(&ChunkManifest {
+                parcel: vec![parcel],
+            })
+            .unwrap();
+            let mut builder = tokio_tar::Builder::new(Vec::new());
+            let mut header = tokio_tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, CHUNK_MANIFEST, data.as_slice())
+                .await
+                .unwrap();
+            let archive = builder.into_inner().await.unwrap();
+
+            assert!(matches!(
+                unpack(archive.as_slice(), &dest).await,
+                Err(ClientError::Other(_))
+            ));
+            assert_eq!(
+                b"precious".to_vec(),
+                tokio::fs::read(victim.join("data")).await.unwrap(),
+                "Files outside of the destination should not be touched"
+            );
+            assert!(
+                tokio::fs::metadata(dest.join(PARCEL_DIRECTORY))
+                    .await
+                    .is_err(),
+                "Nothing should be reassembled from a hostile manifest"
+            );
+        }
+    }
+}
@@ -15,6 +15,20 @@ pub enum ClientError {
     /// Invalid TOML parsing that can occur when loading an invoice or label from disk
     #[error("Invalid toml")]
     InvalidToml(#[from] toml::de::Error),
+    /// The server responded successfully, but the body could not be parsed as an invoice. This
+    /// usually means the server speaks an incompatible version of the spec, rather than that it
+    /// could not be reached. Contains the parse error and the start of the body, lossily decoded as
+    /// UTF-8
+    #[error("Server returned an invalid invoice response: {source}")]
+    InvalidInvoiceResponse {
+        source: toml::de::Error,
+        body: String,
+    },
+    /// A [`ParcelTransform`](crate::client::transform::ParcelTransform) could not be applied to
+    /// the parcel data, such as when it is not in the expected format. Contains a description of
+    /// the problem
+    #[error("Unable to transform parcel: {0}")]
+    TransformFailed(String),
     /// Invalid TOML serialization that can occur when serializing an object to a request
     #[error("Invalid toml")]
     TomlSerializationError(#[from] toml::ser::Error),
@@ -25,6 +39,14 @@ pub enum ClientError {
     /// An invalid ID was given. Returns the underlying parse error
     #[error("Invalid id")]
     InvalidId(#[from] crate::id::ParseError),
+    /// The request did not complete within the configured timeout. Contains a description of the
+    /// operation that timed out
+    #[error("Timed out while performing operation: {operation}")]
+    Timeout { operation: String },
+    /// The operation was cancelled with the client's
+    /// [`CancellationToken`](tokio_util::sync::CancellationToken) before it completed
+    #[error("Operation was cancelled")]
+    Cancelled,
 
     // API errors
     /// The invoice was not found. Note that this does not necessarily mean it doesn't exist. It
@@ -43,6 +65,39 @@ pub enum ClientError {
     /// The parcel already exists.
     #[error("Parcel already exists")]
     ParcelAlreadyExists,
+    /// The parcel is not in the local cache, returned by
+    /// [`get_parcel_cached_only`](super::Client::get_parcel_cached_only). Contains the SHA of the
+    /// parcel
+    #[error("Parcel {} is not in the local cache", crate::short_sha(.0))]
+    NotCached(String),
+    /// The given SHA prefix matches more than one parcel. Contains the prefix and the full SHA of
+    /// every matching parcel
+    #[error("SHA prefix {prefix} is ambiguous and matches {} parcels", .matches.len())]
+    AmbiguousSha {
+        prefix: String,
+        matches: Vec<String>,
+    },
+    /// More than one parcel in an invoice has the same name, which was rejected because the client
+    /// requires unique parcel names. Contains the duplicated name
+    #[error("Multiple parcels are named {0}")]
+    DuplicateParcelName(String),
+    /// More than one parcel resolved to the same install path when building an overlay manifest.
+    /// Contains the duplicated path
+    #[error("Multiple parcels resolve to the install path {0}")]
+    DuplicateInstallPath(String),
+    /// The downloaded parcel data does not hash to the expected SHA, so it is not the parcel that
+    /// was requested (and none of the signatures over the invoice apply to it). Contains the full
+    /// expected SHA, though the message shows a short form
+    #[error("Parcel data does not match the expected SHA {}", crate::short_sha(.0))]
+    ParcelShaMismatch(String),
+    /// The server returned a different amount of parcel data than the size in its label. Contains
+    /// the SHA of the parcel
+    #[error("Parcel data for {} does not match the size in its label", crate::short_sha(.0))]
+    ParcelSizeMismatch(String),
+    /// A layout strategy could not place a parcel, or placed it outside of the extraction
+    /// directory. Contains the SHA of the parcel
+    #[error("Unable to safely choose an extraction path for parcel {}", crate::short_sha(.0))]
+    InvalidExtractPath(String),
     /// The error returned when the request is invalid. Contains the underlying HTTP status code and
     /// any message returned from the API
     #[error("Invalid request (status code {status_code:?}): {message:?}")]
@@ -57,9 +112,41 @@ pub enum ClientError {
     /// is only valid if the server supports authentication and/or permissions
     #[error("User has invalid credentials or is not authorized to access the requested resource")]
     Unauthorized,
+    /// The server is read-only and does not allow invoices or parcels to be created or yanked. Only
+    /// returned when the server marks the rejection with the
+    /// [`READ_ONLY_HEADER`](crate::provider::read_only::READ_ONLY_HEADER)
+    #[error("Registry is read-only and does not accept writes")]
+    ReadOnlyRegistry,
+    /// The server rejected a yank because too many bindles have recently been yanked by the same
+    /// user. The yank can be retried later or forced with
+    /// [`Client::force_yank_invoice`](crate::client::Client::force_yank_invoice). Contains the error
+    /// message from the server, if any
+    #[error("Yank was rejected because too many bindles have been yanked recently: {0:?}")]
+    YankThrottled(Option<String>),
+
+    /// The server responded with more data than the client is configured to read, see
+    /// [`ClientOptions::max_invoice_size`](crate::client::ClientOptions::max_invoice_size).
+    /// Contains the limit in bytes
+    #[error("Response exceeded the size limit of {0} bytes")]
+    ResponseTooLarge(usize),
+    /// A stream produced more data than the given size limit allowed, see
+    /// [`collect_stream`](crate::util::collect_stream). Contains the limit in bytes
+    #[error("Stream exceeded the size limit of {0} bytes")]
+    StreamTooLarge(usize),
 
     #[error("Signature error")]
     SignatureError(#[from] crate::invoice::signature::SignatureError),
+    /// An invoice from the server was signed with a different host key than the one pinned for it
+    /// in the [`tofu_store`](crate::client::ClientOptions::tofu_store). This could mean the server
+    /// was compromised or swapped. If the new key is expected, it can be trusted with
+    /// [`Client::accept_host_key`](crate::client::Client::accept_host_key). Contains the base URL
+    /// of the server and both keys
+    #[error("Host key for {host} has changed from {pinned} to {presented}")]
+    HostKeyChanged {
+        host: String,
+        pinned: String,
+        presented: String,
+    },
 
     /// A catch-all for uncategorized errors. Contains an error message describing the underlying
     /// issue
@@ -0,0 +1,79 @@
+//! Types and traits for customizing every request sent by a [`Client`](super::Client)
+//!
+//! Interceptors are the extension point for per-deployment client behavior, such as injecting
+//! tracing or tenant headers, logging, or rewriting URLs, without forking the client
+
+use reqwest::header::HeaderMap;
+use reqwest::Request;
+use tracing::debug;
+
+/// A trait for anything that needs to inspect or modify a request before the client sends it.
+/// Interceptors are run in the order they were added to the
+/// [`ClientOptions`](super::ClientOptions)
+pub trait RequestInterceptor {
+    /// Called with every request right before it is sent. The method, URL, and headers of the
+    /// request can all be read and modified
+    fn intercept(&self, request: &mut Request);
+}
+
+/// An interceptor that adds a static set of headers to every request, overwriting any header of
+/// the same name that was already set
+#[derive(Clone, Debug, Default)]
+pub struct HeaderInterceptor {
+    headers: HeaderMap,
+}
+
+impl HeaderInterceptor {
+    /// Returns a new interceptor that adds all of the given headers to each request
+    pub fn new(headers: HeaderMap) -> Self {
+        HeaderInterceptor { headers }
+    }
+}
+
+impl RequestInterceptor for HeaderInterceptor {
+    fn intercept(&self, request: &mut Request) {
+        let headers = request.headers_mut();
+        for (name, value) in self.headers.iter() {
+            headers.insert(name, value.clone());
+        }
+    }
+}
+
+/// An interceptor that logs the method and URL of every request at the `debug` level
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoggingInterceptor;
+
+impl RequestInterceptor for LoggingInterceptor {
+    fn intercept(&self, request: &mut Request) {
+        debug!(method = %request.method(), url = %request.url(), "Sending request");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_header_interceptor() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant-id", HeaderValue::from_static("enterprise"));
+        let interceptor = HeaderInterceptor::new(headers);
+
+        let mut req = Request::new(
+            reqwest::Method::GET,
+            "http://localhost:8080/v1/_i/enterprise.com/warpcore/1.0.0"
+                .parse()
+                .unwrap(),
+        );
+        req.headers_mut()
+            .insert("x-tenant-id", HeaderValue::from_static("other"));
+        interceptor.intercept(&mut req);
+
+        assert_eq!(
+            req.headers().get("x-tenant-id").unwrap(),
+            "enterprise",
+            "Static header should overwrite any existing value"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Types for deciding where each parcel of a bindle is written when it is extracted to disk
+//!
+//! Different ecosystems expect different on-disk layouts, so the path of each parcel is chosen by a
+//! [`LayoutStrategy`](LayoutStrategy). Custom strategies can be created by implementing the trait.
+//! No matter which strategy is used, [`extraction_path`](extraction_path) rejects any path that
+//! would be written outside of the extraction directory
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::client::{ClientError, Result};
+use crate::Label;
+
+/// The label annotation used by [`ByAnnotationPath`](ByAnnotationPath) to find the path of a parcel
+pub const PATH_ANNOTATION: &str = "bindle.dev/path";
+
+/// A trait for deciding where a parcel should be written when a bindle is extracted
+pub trait LayoutStrategy {
+    /// Returns the path, relative to the extraction directory, that the parcel with the given label
+    /// should be written to. Returns `None` if this strategy cannot place the parcel
+    fn path_for(&self, label: &Label) -> Option<PathBuf>;
+}
+
+/// Places every parcel at a path named after its SHA. This never conflicts, but loses the names of
+/// the parcels
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BySha;
+
+impl LayoutStrategy for BySha {
+    fn path_for(&self, label: &Label) -> Option<PathBuf> {
+        Some(PathBuf::from(&label.sha256))
+    }
+}
+
+/// Places every parcel at a path given by the name in its label. Parcels with an empty name are
+/// not placed
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ByName;
+
+impl LayoutStrategy for ByName {
+    fn path_for(&self, label: &Label) -> Option<PathBuf> {
+        if label.name.is_empty() {
+            return None;
+        }
+        Some(PathBuf::from(&label.name))
+    }
+}
+
+/// Places parcels at the path given by an annotation on their label. Parcels without the
+/// annotation are not placed
+#[derive(Clone, Debug)]
+pub struct ByAnnotationPath {
+    annotation: String,
+}
+
+impl ByAnnotationPath {
+    /// Returns a strategy that reads the path from the given annotation
+    pub fn new(annotation: &str) -> Self {
+        ByAnnotationPath {
+            annotation: annotation.to_owned(),
+        }
+    }
+}
+
+impl Default for ByAnnotationPath {
+    /// Returns a strategy that reads the path from the [`PATH_ANNOTATION`](PATH_ANNOTATION)
+    fn default() -> Self {
+        Self::new(PATH_ANNOTATION)
+    }
+}
+
+impl LayoutStrategy for ByAnnotationPath {
+    fn path_for(&self, label: &Label) -> Option<PathBuf> {
+        label
+            .annotations
+            .as_ref()
+            .and_then(|a| a.get(&self.annotation))
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+    }
+}
+
+/// The default layout, which places parcels using the [`ByAnnotationPath`](ByAnnotationPath)
+/// strategy and falls back to [`ByName`](ByName) for parcels without a path annotation
+#[derive(Clone, Debug, Default)]
+pub struct DefaultLayout {
+    annotation: ByAnnotationPath,
+}
+
+impl LayoutStrategy for DefaultLayout {
+    fn path_for(&self, label: &Label) -> Option<PathBuf> {
+        self.annotation
+            .path_for(label)
+            .or_else(|| ByName.path_for(label))
+    }
+}
+
+/// Returns the full path the parcel with the given label should be extracted to inside of `dest`,
+/// as decided by the given layout strategy.
+///
+/// Returns a [`ClientError::InvalidExtractPath`] if the strategy could not place the parcel or if
+/// the path it chose is absolute or would escape `dest` (such as with `../`)
+pub fn extraction_path<L>(layout: &L, dest: &Path, label: &Label) -> Result<PathBuf>
+where
+    L: LayoutStrategy + ?Sized,
+{
+    let relative = layout
+        .path_for(label)
+        .ok_or_else(|| ClientError::InvalidExtractPath(label.sha256.clone()))?;
+    // Only allow plain path segments (and `.`), so the path is guaranteed to stay inside of dest
+    let mut has_segment = false;
+    for component in relative.components() {
+        match component {
+            Component::Normal(_) => has_segment = true,
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(ClientError::InvalidExtractPath(label.sha256.clone()))
+            }
+        }
+    }
+    if !has_segment {
+        return Err(ClientError::InvalidExtractPath(label.sha256.clone()));
+    }
+    Ok(dest.join(relative))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn label(name: &str, path: Option<&str>) -> Label {
+        let mut label = Label::new(name.to_owned(), "abc123".to_owned());
+        if let Some(p) = path {
+            label.annotations = Some(
+                vec![(PATH_ANNOTATION.to_owned(), p.to_owned())]
+                    .into_iter()
+                    .collect(),
+            );
+        }
+        label
+    }
+
+    #[test]
+    fn test_default_layout() {
+        let dest = Path::new("/tmp/extract");
+        assert_eq!(
+            dest.join("lib/foo.wasm"),
+            extraction_path(
+                &DefaultLayout::default(),
+                dest,
+                &label("foo.wasm", Some("lib/foo.wasm"))
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            dest.join("foo.wasm"),
+            extraction_path(&DefaultLayout::default(), dest, &label("foo.wasm", None)).unwrap()
+        );
+        assert_eq!(
+            dest.join("abc123"),
+            extraction_path(&BySha, dest, &label("foo.wasm", Some("lib/foo.wasm"))).unwrap()
+        );
+        assert!(matches!(
+            extraction_path(&ByAnnotationPath::default(), dest, &label("foo.wasm", None)),
+            Err(ClientError::InvalidExtractPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_path_traversal() {
+        let dest = Path::new("/tmp/extract");
+        for bad in &["../foo.wasm", "lib/../../foo.wasm", "/etc/passwd", ".", ""] {
+            assert!(
+                matches!(
+                    extraction_path(&ByName, dest, &label(bad, None)),
+                    Err(ClientError::InvalidExtractPath(_))
+                ),
+                "Path {} should be rejected",
+                bad
+            );
+        }
+        assert!(matches!(
+            extraction_path(
+                &DefaultLayout::default(),
+                dest,
+                &label("ok", Some("../escape"))
+            ),
+            Err(ClientError::InvalidExtractPath(_))
+        ));
+        assert_eq!(
+            dest.join("./lib/foo.wasm"),
+            extraction_path(&ByName, dest, &label("./lib/foo.wasm", None)).unwrap()
+        );
+    }
+}
@@ -19,6 +19,25 @@ pub async fn raw<P: AsRef<Path>>(
     Ok(FramedRead::new(file, BytesCodec::new()))
 }
 
+/// Same as [`raw`], but also returns the size of the file in bytes. The size is `None` if it
+/// cannot be known ahead of time, such as when the path points to a pipe rather than a regular file
+pub async fn raw_with_len<P: AsRef<Path>>(
+    file_path: P,
+) -> Result<(
+    impl Stream<Item = std::result::Result<bytes::BytesMut, Error>>,
+    Option<u64>,
+)> {
+    let file = File::open(file_path).await?;
+    // Stat the opened file rather than the path so the size matches the data that is streamed
+    let len = file
+        .metadata()
+        .await
+        .ok()
+        .filter(|m| m.is_file())
+        .map(|m| m.len());
+    Ok((FramedRead::new(file, BytesCodec::new()), len))
+}
+
 /// Loads a file and deserializes it from TOML to an arbirary type. Turbofish may be required to
 /// specify the type: `bindle::client::load::toml::<bindle::Label>("/my/path.toml").await;`
 #[instrument(level = "trace", skip(file_path), fields(path = %file_path.as_ref().display()))]
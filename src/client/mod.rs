@@ -1,25 +1,46 @@
 //! Client implementation for consuming a Bindle API. Although written in Rust, it is not specific
 //! to the Rust implementation. It is meant to consume any spec-compliant bindle implementation.
 
+pub mod archive;
 mod error;
+pub mod interceptor;
+pub mod layout;
 pub mod load;
+mod overlay;
+pub mod tofu;
+pub mod transform;
 
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryInto;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
+use futures::future::{BoxFuture, FutureExt, Shared};
 use reqwest::header;
 use reqwest::Client as HttpClient;
 use reqwest::ClientBuilder;
 use reqwest::{Body, RequestBuilder, StatusCode};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
 use tokio_stream::{Stream, StreamExt};
-use tracing::{debug, info, instrument, trace};
+use tokio_util::io::StreamReader;
+use tracing::{debug, info, instrument, trace, warn};
 use url::Url;
 
+use crate::async_util::VerifyingReader;
+use crate::invoice::signature::KeyRing;
 use crate::provider::{Provider, ProviderError};
 use crate::verification::Verified;
+use crate::VerificationStrategy;
 use crate::{Id, Signed};
 
 pub use error::ClientError;
+pub use interceptor::RequestInterceptor;
+pub use overlay::{OverlayEntry, OverlayManifest};
+/// Re-exported so callers can cancel client operations without depending on the same version of
+/// `tokio-util`. See [`Client::with_cancellation`]
+pub use tokio_util::sync::CancellationToken;
 
 /// A shorthand `Result` type that always uses `ClientError` as its error variant
 pub type Result<T> = std::result::Result<T, ClientError>;
@@ -27,13 +48,38 @@ pub type Result<T> = std::result::Result<T, ClientError>;
 pub const INVOICE_ENDPOINT: &str = "_i";
 pub const QUERY_ENDPOINT: &str = "_q";
 pub const RELATIONSHIP_ENDPOINT: &str = "_r";
+pub const PARCEL_PREFIX_ENDPOINT: &str = "_p";
+pub const HEALTH_ENDPOINT: &str = "_health";
+pub const USAGE_ENDPOINT: &str = "_usage";
+pub const BATCH_PARCEL_ENDPOINT: &str = "_batch";
+pub const AUDIT_ENDPOINT: &str = "_audit";
 const TOML_MIME_TYPE: &str = "application/toml";
+/// The number of bytes of an invalid response body included in errors
+const INVALID_RESPONSE_SNIPPET_LEN: usize = 512;
+/// The default for [`ClientOptions::max_invoice_size`]
+pub const DEFAULT_MAX_INVOICE_SIZE: usize = 16 * 1024 * 1024;
+/// The default for [`ClientOptions::max_concurrency`]
+pub const DEFAULT_MAX_CONCURRENCY: usize = 8;
 
 /// A client type for interacting with a Bindle server
 #[derive(Clone)]
 pub struct Client {
     client: HttpClient,
     base_url: Url,
+    metadata_timeout: Option<Duration>,
+    bulk_timeout: Option<Duration>,
+    skip_existing_parcels: bool,
+    require_unique_parcel_names: bool,
+    define_missing_groups: bool,
+    max_invoice_size: usize,
+    max_concurrency: usize,
+    preflight_keyring: Option<Arc<KeyRing>>,
+    preflight_strategy: VerificationStrategy,
+    interceptors: Vec<Arc<dyn RequestInterceptor + Send + Sync>>,
+    known_hosts: Option<Arc<tofu::KnownHosts>>,
+    cancellation: Option<CancellationToken>,
+    warm_up: Option<Shared<BoxFuture<'static, ()>>>,
+    cache_dir: Option<PathBuf>,
 }
 
 /// The operation being performed against a Bindle server.
@@ -44,6 +90,15 @@ enum Operation {
     Query,
 }
 
+/// The kind of request being made, used to select the timeout that should apply to it
+#[derive(Clone, Copy)]
+enum RequestKind {
+    /// Requests for invoices and other metadata that are expected to be fast
+    Metadata,
+    /// Parcel uploads and downloads that are expected to be slow
+    Bulk,
+}
+
 /// Options for setting up a `Client`
 pub struct ClientOptions {
     /// Controls whether the client assumes HTTP/2 or attempts to negotiate it.
@@ -53,6 +108,67 @@ pub struct ClientOptions {
     /// option in dev-test situations where you may be working with self-signed
     /// certificates or the like.
     pub danger_accept_invalid_certs: bool,
+    /// The timeout for metadata operations, such as fetching, creating, or querying invoices.
+    /// These operations are expected to be fast. Defaults to no timeout
+    pub metadata_timeout: Option<Duration>,
+    /// The timeout for bulk operations, such as uploading or downloading parcels. This includes
+    /// the time spent streaming the body, so it should be set much higher than the metadata
+    /// timeout. Defaults to no timeout
+    pub bulk_timeout: Option<Duration>,
+    /// Controls whether the client checks if a parcel already exists on the server (with a HEAD
+    /// request) before uploading it. As parcels are content addressed, an existing parcel is never
+    /// uploaded again and a [`ClientError::ParcelAlreadyExists`] is returned instead. Defaults to
+    /// `true`
+    pub skip_existing_parcels: bool,
+    /// Controls whether the client rejects invoices in which more than one parcel has the same
+    /// name before creating them, returning a [`ClientError::DuplicateParcelName`]. Duplicate names
+    /// are valid, but tools that map parcel names to paths (such as
+    /// [`get_overlay_manifest`](Client::get_overlay_manifest)) need them to be unique. Defaults to
+    /// `false`
+    pub require_unique_parcel_names: bool,
+    /// Controls whether the client defines the groups that parcels reference but the invoice does
+    /// not, using [`Invoice::define_missing_groups`](crate::Invoice::define_missing_groups), before
+    /// creating it. This is meant for tools that build invoices from simple inputs. When disabled,
+    /// the invoice is sent as is and a warning is logged for each undefined group, so typos in
+    /// group names are not hidden. Defaults to `false`
+    pub define_missing_groups: bool,
+    /// The largest invoice, in bytes, the client will read from a server. Reading stops with a
+    /// [`ClientError::ResponseTooLarge`] as soon as a response is larger than this, so an untrusted
+    /// server cannot make the client buffer or parse an unbounded invoice. Defaults to
+    /// [`DEFAULT_MAX_INVOICE_SIZE`] (16 MiB)
+    pub max_invoice_size: usize,
+    /// The most requests a batch operation, such as [`get_invoices`](Client::get_invoices), has in
+    /// flight at once. Values of 0 are treated as 1. Defaults to [`DEFAULT_MAX_CONCURRENCY`]
+    pub max_concurrency: usize,
+    /// If set, the signatures on an invoice are verified against this keyring with the
+    /// [`preflight_strategy`](ClientOptions::preflight_strategy) before it is created, returning a
+    /// [`ClientError::SignatureError`] without sending the invoice if they are invalid. Invoices
+    /// without any signatures are not checked, as the server decides whether to accept them.
+    /// Defaults to `None`
+    pub preflight_keyring: Option<Arc<KeyRing>>,
+    /// The strategy used to verify invoices when a
+    /// [`preflight_keyring`](ClientOptions::preflight_keyring) is set. This should match the
+    /// strategy used by the server. Defaults to [`VerificationStrategy::GreedyVerification`], the
+    /// default strategy of the server
+    pub preflight_strategy: VerificationStrategy,
+    /// The interceptors applied to every request sent by the client, in the order they were added.
+    /// Use [`with_interceptor`](ClientOptions::with_interceptor) to add one
+    pub interceptors: Vec<Arc<dyn RequestInterceptor + Send + Sync>>,
+    /// If set, the key the server signs invoices with is pinned in this file the first time an
+    /// invoice is received, and every later invoice must be signed with the same key. See the
+    /// [`tofu`] module for details. Defaults to `None`
+    pub tofu_store: Option<PathBuf>,
+    /// Controls whether the client connects to the server as soon as it is created, by fetching its
+    /// [health](Client::health) in the background, so the first real request doesn't pay for
+    /// connection and TLS setup. Requests made while the warm-up is still running wait for it and
+    /// then reuse its connection. If the warm-up fails, or the client is not created inside a Tokio
+    /// runtime, it is skipped and requests connect as usual. Defaults to `false`
+    pub warm_up: bool,
+    /// A local bindle directory, in the [`FileProvider`](crate::provider::file::FileProvider)
+    /// layout, that [`get_parcel_cached_only`](Client::get_parcel_cached_only) reads parcels from.
+    /// This is usually the directory of a cache that was warmed ahead of time, such as the one used
+    /// by the `bindle` CLI. Defaults to `None`
+    pub cache_dir: Option<PathBuf>,
 }
 
 impl Default for ClientOptions {
@@ -60,10 +176,48 @@ impl Default for ClientOptions {
         Self {
             http2_prior_knowledge: false,
             danger_accept_invalid_certs: false,
+            metadata_timeout: None,
+            bulk_timeout: None,
+            skip_existing_parcels: true,
+            require_unique_parcel_names: false,
+            define_missing_groups: false,
+            max_invoice_size: DEFAULT_MAX_INVOICE_SIZE,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            preflight_keyring: None,
+            preflight_strategy: VerificationStrategy::default(),
+            interceptors: Vec::new(),
+            tofu_store: None,
+            warm_up: false,
+            cache_dir: None,
         }
     }
 }
 
+impl ClientOptions {
+    /// Adds the given interceptor after any interceptors that have already been added
+    pub fn with_interceptor<T>(mut self, interceptor: T) -> Self
+    where
+        T: RequestInterceptor + Send + Sync + 'static,
+    {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Pins the server's host key in the file at the given path on first use, see
+    /// [`tofu_store`](ClientOptions::tofu_store)
+    pub fn with_tofu_store(mut self, path: impl Into<PathBuf>) -> Self {
+        self.tofu_store = Some(path.into());
+        self
+    }
+
+    /// Reads cached parcels from the bindle directory at the given path, see
+    /// [`cache_dir`](ClientOptions::cache_dir)
+    pub fn with_cache_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(path.into());
+        self
+    }
+}
+
 impl Client {
     /// Returns a new Client with the given URL, configured using the default options.
     /// This URL should be the FQDN plus any namespacing
@@ -90,8 +244,6 @@ impl Client {
         let base_parsed = Url::parse(&base)?;
         let mut headers = header::HeaderMap::new();
         headers.insert(header::ACCEPT, "application/toml".parse().unwrap());
-        // TODO: As this evolves, we might want to allow for setting time outs and accepting
-        // self-signed certs
         let client = HttpClient::builder()
             .and_if(options.http2_prior_knowledge, |b| b.http2_prior_knowledge())
             .and_if(options.danger_accept_invalid_certs, |b| {
@@ -100,10 +252,79 @@ impl Client {
             .default_headers(headers)
             .build()
             .map_err(|e| ClientError::Other(e.to_string()))?;
-        Ok(Client {
+        let mut client = Client {
             client,
             base_url: base_parsed,
-        })
+            metadata_timeout: options.metadata_timeout,
+            bulk_timeout: options.bulk_timeout,
+            skip_existing_parcels: options.skip_existing_parcels,
+            require_unique_parcel_names: options.require_unique_parcel_names,
+            define_missing_groups: options.define_missing_groups,
+            max_invoice_size: options.max_invoice_size,
+            max_concurrency: options.max_concurrency.max(1),
+            preflight_keyring: options.preflight_keyring,
+            preflight_strategy: options.preflight_strategy,
+            interceptors: options.interceptors,
+            known_hosts: options
+                .tofu_store
+                .map(|p| Arc::new(tofu::KnownHosts::new(p))),
+            cancellation: None,
+            warm_up: None,
+            cache_dir: options.cache_dir,
+        };
+        if options.warm_up {
+            client.warm_up = client.start_warm_up();
+        }
+        Ok(client)
+    }
+
+    /// Starts fetching the server health in the background so that a connection to the server is
+    /// open and pooled before the first request. Returns a future that completes when the warm-up
+    /// is done, whether or not it succeeded, or `None` if there is no runtime to run it on
+    fn start_warm_up(&self) -> Option<Shared<BoxFuture<'static, ()>>> {
+        let handle = match tokio::runtime::Handle::try_current() {
+            Ok(h) => h,
+            Err(_) => {
+                debug!("Not running in a Tokio runtime, skipping warm-up");
+                return None;
+            }
+        };
+        // This client has no warm-up of its own, so its request doesn't wait on itself
+        let client = self.clone();
+        let task = handle.spawn(async move {
+            match client.health().await {
+                Ok(_) => debug!(url = %client.base_url, "Warmed up connection to server"),
+                Err(e) => {
+                    warn!(url = %client.base_url, error = %e, "Unable to warm up connection to server, skipping")
+                }
+            }
+        });
+        Some(task.map(|_| ()).boxed().shared())
+    }
+
+    /// Waits for the warm-up started when the client was created, if it is still running, so the
+    /// caller can reuse its connection rather than opening another one alongside it
+    async fn wait_for_warm_up(&self) {
+        if let Some(warm_up) = self.warm_up.as_ref() {
+            warm_up.clone().await;
+        }
+    }
+
+    /// Returns a copy of this client whose operations are cancelled when the given token is. A
+    /// cancelled operation stops sending or receiving data straight away, closing its connection,
+    /// and returns a [`ClientError::Cancelled`]. This includes streams returned by the client, such
+    /// as from [`get_parcel_stream`](Client::get_parcel_stream), which return the error as their
+    /// last item. Operations started after the token is cancelled fail without sending anything.
+    ///
+    /// Dropping the future of an operation also cancels it, so a token is only needed to cancel
+    /// operations that are owned elsewhere, such as when a user hits Ctrl-C or a deadline passes.
+    /// A parcel upload that is cancelled part way through is discarded by the server, so it can be
+    /// retried from the start
+    pub fn with_cancellation(&self, token: CancellationToken) -> Self {
+        Client {
+            cancellation: Some(token),
+            ..self.clone()
+        }
     }
 
     /// Performs a raw request using the underlying HTTP client and returns the raw response. The
@@ -124,24 +345,102 @@ impl Client {
             Some(b) => req.body(b),
             None => req,
         };
-        req.send().await.map_err(|e| e.into())
+        let req = self.intercept(req.build()?);
+        self.wait_for_warm_up().await;
+        self.client.execute(req).await.map_err(|e| e.into())
+    }
+
+    /// Runs all configured interceptors on the given request
+    fn intercept(&self, mut req: reqwest::Request) -> reqwest::Request {
+        for interceptor in self.interceptors.iter() {
+            interceptor.intercept(&mut req);
+        }
+        req
+    }
+
+    /// Applies the configured timeout for the given kind of request and sends it. Any timeout is
+    /// returned as a [`ClientError::Timeout`] for the given operation
+    async fn send(
+        &self,
+        req: RequestBuilder,
+        kind: RequestKind,
+        operation: &str,
+    ) -> Result<reqwest::Response> {
+        let timeout = match kind {
+            RequestKind::Metadata => self.metadata_timeout,
+            RequestKind::Bulk => self.bulk_timeout,
+        };
+        let req = match timeout {
+            Some(t) => req.timeout(t),
+            None => req,
+        };
+        let req = self.intercept(req.build().map_err(|e| map_request_error(e, operation))?);
+        trace!(?req);
+        let execute = async {
+            self.wait_for_warm_up().await;
+            self.client.execute(req).await
+        };
+        let resp = match &self.cancellation {
+            Some(token) => tokio::select! {
+                biased;
+                _ = token.cancelled() => {
+                    debug!(operation, "Request cancelled");
+                    return Err(ClientError::Cancelled);
+                }
+                resp = execute => resp,
+            },
+            None => execute.await,
+        };
+        resp.map_err(|e| map_request_error(e, operation))
+    }
+
+    /// Returns the body of the response as a stream of bytes. If the client has a cancellation
+    /// token, the stream ends with a [`ClientError::Cancelled`] as soon as the token is cancelled
+    fn body_stream(
+        &self,
+        resp: reqwest::Response,
+        operation: &'static str,
+    ) -> impl Stream<Item = Result<bytes::Bytes>> + Unpin {
+        let body = resp
+            .bytes_stream()
+            .map(move |r| r.map_err(|e| map_request_error(e, operation)));
+        // A token that is never cancelled keeps the stream the same type either way. The future is
+        // shared so that the stream is Sync, which the future returned by the token is not
+        let token = self.cancellation.clone().unwrap_or_default();
+        let cancelled = futures::FutureExt::shared(futures::FutureExt::boxed(async move {
+            token.cancelled().await
+        }));
+        Box::pin(futures::stream::unfold(
+            Some((body, cancelled)),
+            |state| async move {
+                let (mut body, mut cancelled) = state?;
+                tokio::select! {
+                    biased;
+                    _ = &mut cancelled => Some((Err(ClientError::Cancelled), None)),
+                    item = body.next() => item.map(|item| (item, Some((body, cancelled)))),
+                }
+            },
+        ))
     }
 
     //////////////// Create Invoice ////////////////
 
-    /// Creates the given invoice, returns a response containing the created invoice and a list of
-    /// missing parcels (that have not yet been uploaded)
+    /// Creates the given invoice, returns a response containing the created invoice, a list of
+    /// missing parcels (that have not yet been uploaded), and whether the bindle is already complete
     #[instrument(level = "trace", skip(self, inv), fields(id = %inv.bindle.id))]
     pub async fn create_invoice(
         &self,
-        inv: crate::Invoice,
+        mut inv: crate::Invoice,
     ) -> Result<crate::InvoiceCreateResponse> {
+        self.check_invoice(&mut inv)?;
         let req = self.create_invoice_builder().body(toml::to_vec(&inv)?);
         self.create_invoice_request(req).await
     }
 
     /// Same as [`create_invoice`](Client::create_invoice), but takes a path to an invoice file
-    /// instead. This will load the invoice file directly into the request, skipping serialization
+    /// instead. This will load the invoice file directly into the request, skipping serialization.
+    /// The request has an accurate `Content-Length` unless the size of the file cannot be known
+    /// ahead of time, in which case it falls back to chunked encoding
     #[instrument(level = "trace", skip(self, file_path), fields(path = %file_path.as_ref().display()))]
     pub async fn create_invoice_from_file<P: AsRef<Path>>(
         &self,
@@ -149,15 +448,77 @@ impl Client {
     ) -> Result<crate::InvoiceCreateResponse> {
         // Create an owned version of the path to avoid worrying about lifetimes here for the stream
         let path = file_path.as_ref().to_owned();
+        // The file is streamed without being serialized again, but it is still parsed so it gets the
+        // same checks as any other invoice
+        let mut inv = load::toml(&path).await?;
+        self.check_invoice(&mut inv)?;
+        if self.define_missing_groups {
+            // Groups can only be added to a parsed invoice, so it can't be streamed as is
+            let req = self.create_invoice_builder().body(toml::to_vec(&inv)?);
+            return self.create_invoice_request(req).await;
+        }
         debug!("Loading invoice from file");
-        let inv_stream = load::raw(path).await?;
-        debug!("Successfully loaded invoice stream");
+        let (inv_stream, len) = load::raw_with_len(path).await?;
+        debug!(?len, "Successfully loaded invoice stream");
         let req = self
             .create_invoice_builder()
             .body(Body::wrap_stream(inv_stream));
+        // Setting the length means the body is still streamed, but without chunked encoding, which
+        // some proxies do not handle well
+        let req = match len {
+            Some(l) => req.header(header::CONTENT_LENGTH, l),
+            None => req,
+        };
         self.create_invoice_request(req).await
     }
 
+    /// Runs the checks done on every invoice before it is uploaded, defining any missing groups if
+    /// configured to
+    fn check_invoice(&self, inv: &mut crate::Invoice) -> Result<()> {
+        self.check_groups(inv);
+        self.check_parcel_names(inv)?;
+        self.check_signatures(inv)?;
+        for warning in inv.license_warnings() {
+            warn!(%warning, "Invoice has an invalid license");
+        }
+        Ok(())
+    }
+
+    fn check_groups(&self, inv: &mut crate::Invoice) {
+        if self.define_missing_groups {
+            for group in inv.define_missing_groups() {
+                debug!(%group, "Defining group referenced by parcels");
+            }
+        } else {
+            for group in inv.undefined_groups() {
+                warn!(%group, "Parcels reference a group that is not defined in the invoice");
+            }
+        }
+    }
+
+    fn check_parcel_names(&self, inv: &crate::Invoice) -> Result<()> {
+        if !self.require_unique_parcel_names {
+            return Ok(());
+        }
+        match inv.duplicate_parcel_names().into_iter().next() {
+            Some(name) => Err(ClientError::DuplicateParcelName(name)),
+            None => Ok(()),
+        }
+    }
+
+    fn check_signatures(&self, inv: &crate::Invoice) -> Result<()> {
+        let keyring = match self.preflight_keyring.as_ref() {
+            Some(k) => k,
+            None => return Ok(()),
+        };
+        if inv.signature.iter().flatten().next().is_none() {
+            trace!("Invoice is not signed, skipping preflight verification");
+            return Ok(());
+        }
+        self.preflight_strategy.verify(inv.clone(), keyring)?;
+        Ok(())
+    }
+
     fn create_invoice_builder(&self) -> RequestBuilder {
         // We can unwrap here because any URL error would be programmers fault
         self.client
@@ -169,10 +530,40 @@ impl Client {
         &self,
         req: RequestBuilder,
     ) -> Result<crate::InvoiceCreateResponse> {
-        trace!(?req);
-        let resp = req.send().await?;
+        let resp = self
+            .send(req, RequestKind::Metadata, "create invoice")
+            .await?;
         let resp = unwrap_status(resp, Endpoint::Invoice, Operation::Create).await?;
-        Ok(toml::from_slice(&resp.bytes().await?)?)
+        let complete = resp.status() == StatusCode::CREATED;
+        let mut res: crate::InvoiceCreateResponse = parse_invoice_response(
+            &resp
+                .bytes()
+                .await
+                .map_err(|e| map_request_error(e, "create invoice"))?,
+        )?;
+        // Older servers don't send the complete flag, but always indicate it with the status code
+        res.complete = res.complete || complete;
+        self.check_host_key(&res.invoice).await?;
+        Ok(res)
+    }
+
+    async fn check_host_key(&self, inv: &crate::Invoice) -> Result<()> {
+        match self.known_hosts.as_ref() {
+            Some(known) => known.check(self.base_url.as_str(), inv).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Trusts the given host key for this server, replacing any key that was pinned before. Use this
+    /// after a [`ClientError::HostKeyChanged`] when the server's key is known to have changed. The
+    /// key is base64 encoded, as in the error. Returns an error if no
+    /// [`tofu_store`](ClientOptions::tofu_store) is configured
+    pub async fn accept_host_key(&self, key: &str) -> Result<()> {
+        let known = self
+            .known_hosts
+            .as_ref()
+            .ok_or_else(|| ClientError::InvalidConfig("no TOFU store is configured".to_owned()))?;
+        known.pin(self.base_url.as_str(), key).await
     }
 
     //////////////// Get Invoice ////////////////
@@ -180,7 +571,10 @@ impl Client {
     /// Returns the requested invoice from the bindle server if it exists. This can take any form
     /// that can convert into the `Id` type, but generally speaking, this is the canonical name of
     /// the bindle (e.g. `example.com/foo/1.0.0`). If you want to fetch a yanked invoice, use the
-    /// [`get_yanked_invoice`](Client::get_yanked_invoice) function
+    /// [`get_yanked_invoice`](Client::get_yanked_invoice) function.
+    ///
+    /// The invoice is always requested verbatim, so it contains every signature even if the server
+    /// is configured to strip its own signatures from returned invoices
     #[instrument(level = "trace", skip(self, id), fields(invoice_id))]
     pub async fn get_invoice<I>(&self, id: I) -> Result<crate::Invoice>
     where
@@ -212,12 +606,41 @@ impl Client {
         self.get_invoice_request(url).await
     }
 
-    async fn get_invoice_request(&self, url: Url) -> Result<crate::Invoice> {
+    /// Fetches each of the given invoices, returning the result for every ID in the same order as
+    /// `ids`. Unlike [`get_invoice`](Client::get_invoice), a missing invoice or other failure only
+    /// affects the result for that ID, so the rest of the batch is still returned. Up to
+    /// [`max_concurrency`](ClientOptions::max_concurrency) invoices are fetched at once
+    #[instrument(level = "trace", skip(self, ids), fields(invoice_count = ids.len()))]
+    pub async fn get_invoices(&self, ids: &[Id]) -> Vec<(Id, Result<crate::Invoice>)> {
+        let fetches = futures::stream::iter(ids.iter().cloned()).map(|id| async move {
+            let res = self.get_invoice(&id).await;
+            (id, res)
+        });
+        futures::StreamExt::buffered(fetches, self.max_concurrency)
+            .collect()
+            .await
+    }
+
+    async fn get_invoice_request(&self, mut url: Url) -> Result<crate::Invoice> {
+        // Always ask for every signature so verification is not affected by server configuration
+        url.query_pairs_mut().append_pair("verbatim", "true");
         let req = self.client.get(url);
-        trace!(?req);
-        let resp = req.send().await?;
+        let resp = self.send(req, RequestKind::Metadata, "get invoice").await?;
         let resp = unwrap_status(resp, Endpoint::Invoice, Operation::Get).await?;
-        Ok(toml::from_slice(&resp.bytes().await?)?)
+        let limit = self.max_invoice_size;
+        // Fail before reading anything if the server already told us the body is too large
+        if resp.content_length().unwrap_or_default() > limit as u64 {
+            return Err(ClientError::ResponseTooLarge(limit));
+        }
+        let body = crate::util::collect_stream(self.body_stream(resp, "get invoice"), Some(limit))
+            .await
+            .map_err(|e| match e {
+                ClientError::StreamTooLarge(limit) => ClientError::ResponseTooLarge(limit),
+                e => e,
+            })?;
+        let inv = parse_invoice_response(&body)?;
+        self.check_host_key(&inv).await?;
+        Ok(inv)
     }
 
     //////////////// Query Invoice ////////////////
@@ -232,10 +655,16 @@ impl Client {
             .client
             .get(self.base_url.join(QUERY_ENDPOINT).unwrap())
             .query(&query_opts);
-        trace!(?req);
-        let resp = req.send().await?;
+        let resp = self
+            .send(req, RequestKind::Metadata, "query invoices")
+            .await?;
         let resp = unwrap_status(resp, Endpoint::Query, Operation::Query).await?;
-        Ok(toml::from_slice(&resp.bytes().await?)?)
+        Ok(toml::from_slice(
+            &resp
+                .bytes()
+                .await
+                .map_err(|e| map_request_error(e, "query invoices"))?,
+        )?)
     }
 
     //////////////// Yank Invoice ////////////////
@@ -243,21 +672,47 @@ impl Client {
     /// Yanks the invoice from availability on the bindle server. This can take any form that can
     /// convert into the `Id` type, but generally speaking, this is the canonical name of the bindle
     /// (e.g. `example.com/foo/1.0.0`)
+    ///
+    /// Servers may limit how many bindles can be yanked in a short time, in which case this returns
+    /// a [`ClientError::YankThrottled`]
     #[instrument(level = "trace", skip(self, id), fields(invoice_id))]
     pub async fn yank_invoice<I>(&self, id: I) -> Result<()>
+    where
+        I: TryInto<Id>,
+        I::Error: Into<ClientError>,
+    {
+        self.yank_invoice_request(id, false).await
+    }
+
+    /// Same as [`yank_invoice`](Client::yank_invoice), but overrides any limit the server has on
+    /// how many bindles can be yanked in a short time. This should only be used once a person has
+    /// confirmed the yanks are intended
+    #[instrument(level = "trace", skip(self, id), fields(invoice_id))]
+    pub async fn force_yank_invoice<I>(&self, id: I) -> Result<()>
+    where
+        I: TryInto<Id>,
+        I::Error: Into<ClientError>,
+    {
+        self.yank_invoice_request(id, true).await
+    }
+
+    async fn yank_invoice_request<I>(&self, id: I, force: bool) -> Result<()>
     where
         I: TryInto<Id>,
         I::Error: Into<ClientError>,
     {
         let parsed_id = id.try_into().map_err(|e| e.into())?;
         tracing::span::Span::current().record("invoice_id", &tracing::field::display(&parsed_id));
-        let req = self.client.delete(self.base_url.join(&format!(
-            "{}/{}",
-            INVOICE_ENDPOINT,
-            parsed_id.to_string()
-        ))?);
-        trace!(?req);
-        let resp = req.send().await?;
+        let req = self
+            .client
+            .delete(
+                self.base_url
+                    .join(&format!("{}/{}", INVOICE_ENDPOINT, parsed_id))?,
+            )
+            .and_if(force, |req| req.query(&[("force", "true")]));
+        let resp = self
+            .send(req, RequestKind::Metadata, "yank invoice")
+            .await?;
         unwrap_status(resp, Endpoint::Invoice, Operation::Yank).await?;
         Ok(())
     }
@@ -266,7 +721,15 @@ impl Client {
 
     /// Creates the given parcel using the SHA and the raw parcel data to upload to the server.
     ///
-    /// Parcels are only accessible through a bindle, so the Bindle ID is required as well
+    /// Parcels are only accessible through a bindle, so the Bindle ID is required as well.
+    ///
+    /// Because parcels are content addressed, uploading a parcel is idempotent and safe to retry.
+    /// Repeated or concurrent uploads of the same parcel either succeed or return a
+    /// [`ClientError::ParcelAlreadyExists`], which callers can treat as success. Data that doesn't
+    /// match the SHA is always rejected, so a failed upload never blocks a retry with the right
+    /// data. Servers using the file provider also check right before storing an upload that no
+    /// other upload of the same parcel finished first, so stored data is not replaced by uploads
+    /// to the same server
     #[instrument(level = "trace", skip(self, bindle_id, data), fields(invoice_id, data_len = data.len()))]
     pub async fn create_parcel<I>(
         &self,
@@ -280,6 +743,7 @@ impl Client {
     {
         let parsed_id = bindle_id.try_into().map_err(|e| e.into())?;
         tracing::span::Span::current().record("invoice_id", &tracing::field::display(&parsed_id));
+        self.check_parcel_not_exists(&parsed_id, parcel_sha).await?;
         self.create_parcel_request(
             self.create_parcel_builder(&parsed_id, parcel_sha)
                 .body(data),
@@ -287,6 +751,50 @@ impl Client {
         .await
     }
 
+    /// Uploads several parcels of a bindle in a single request, which is much faster than
+    /// [`create_parcel`](Client::create_parcel) for bindles with many small parcels. The parcels
+    /// are keyed by SHA.
+    ///
+    /// The server stores each parcel independently and reports the result of each one, so this only
+    /// returns an error if the request itself failed. Use
+    /// [`BatchParcelResponse::failed`](crate::BatchParcelResponse::failed) to find the parcels that
+    /// were not stored. Unlike `create_parcel`, existing parcels are not skipped ahead of time.
+    /// The Bindle server holds each parcel in memory before storing it and rejects requests larger
+    /// than 64 MiB, so larger parcels should be uploaded individually
+    #[instrument(level = "trace", skip(self, bindle_id, parcels), fields(invoice_id, parcel_count = parcels.len()))]
+    pub async fn create_parcels<I>(
+        &self,
+        bindle_id: I,
+        parcels: HashMap<String, Vec<u8>>,
+    ) -> Result<crate::BatchParcelResponse>
+    where
+        I: TryInto<Id>,
+        I::Error: Into<ClientError>,
+    {
+        let parsed_id = bindle_id.try_into().map_err(|e| e.into())?;
+        tracing::span::Span::current().record("invoice_id", &tracing::field::display(&parsed_id));
+        let form = parcels
+            .into_iter()
+            .fold(reqwest::multipart::Form::new(), |form, (sha, data)| {
+                form.part(sha, reqwest::multipart::Part::bytes(data))
+            });
+        let req = self
+            .client
+            .post(
+                self.base_url
+                    .join(&format!("{}/{}", BATCH_PARCEL_ENDPOINT, parsed_id))?,
+            )
+            .multipart(form);
+        let resp = self.send(req, RequestKind::Bulk, "create parcels").await?;
+        let resp = unwrap_status(resp, Endpoint::Parcel, Operation::Create).await?;
+        Ok(toml::from_slice::<crate::BatchParcelResponse>(
+            &resp
+                .bytes()
+                .await
+                .map_err(|e| map_request_error(e, "create parcels"))?,
+        )?)
+    }
+
     /// Same as [`create_parcel`](Client::create_parcel), but takes a path to the parcel
     /// file. This will be more efficient for large files as it will stream the data into the body
     /// rather than taking the intermediate step of loading the bytes into a `Vec`.
@@ -306,6 +814,7 @@ impl Client {
         let data = data_path.as_ref().to_owned();
         let parsed_id = bindle_id.try_into().map_err(|e| e.into())?;
         tracing::span::Span::current().record("invoice_id", &tracing::field::display(&parsed_id));
+        self.check_parcel_not_exists(&parsed_id, parcel_sha).await?;
         debug!("Loading parcel data from file");
         let stream = load::raw(data).await?;
         debug!("Successfully loaded parcel stream");
@@ -334,6 +843,7 @@ impl Client {
     {
         let parsed_id = bindle_id.try_into().map_err(|e| e.into())?;
         tracing::span::Span::current().record("invoice_id", &tracing::field::display(&parsed_id));
+        self.check_parcel_not_exists(&parsed_id, parcel_sha).await?;
         let map = stream.map(|res| res.map(|mut b| b.copy_to_bytes(b.remaining())));
         let data_body = Body::wrap_stream(map);
         self.create_parcel_request(
@@ -355,10 +865,45 @@ impl Client {
         )
     }
 
-    async fn create_parcel_request(&self, req: RequestBuilder) -> Result<()> {
+    /// If configured to skip existing parcels, checks whether the parcel already exists on the
+    /// server, returning a [`ClientError::ParcelAlreadyExists`] if it does. Any error from the check
+    /// itself is ignored so the upload can still be attempted. This is only an optimization: a
+    /// parcel deleted right after the check behaves the same as one deleted right after an upload,
+    /// and a parcel created right after the check is still rejected by the server on upload
+    async fn check_parcel_not_exists(&self, bindle_id: &Id, parcel_sha: &str) -> Result<()> {
+        if !self.skip_existing_parcels {
+            return Ok(());
+        }
         // We can unwrap here because any URL error would be programmers fault
-        trace!(?req);
-        let resp = req.send().await?;
+        let req = self.client.head(
+            self.base_url
+                .join(&format!(
+                    "{}/{}@{}",
+                    INVOICE_ENDPOINT, bindle_id, parcel_sha
+                ))
+                .unwrap(),
+        );
+        match self
+            .send(req, RequestKind::Metadata, "check parcel exists")
+            .await
+        {
+            Ok(resp) if resp.status() == StatusCode::OK => {
+                debug!("Parcel already exists on server, skipping upload");
+                Err(ClientError::ParcelAlreadyExists)
+            }
+            Ok(resp) => {
+                trace!(status = %resp.status(), "Parcel does not exist on server, uploading");
+                Ok(())
+            }
+            Err(e) => {
+                debug!(error = %e, "Unable to check if parcel exists, attempting upload anyway");
+                Ok(())
+            }
+        }
+    }
+
+    async fn create_parcel_request(&self, req: RequestBuilder) -> Result<()> {
+        let resp = self.send(req, RequestKind::Bulk, "create parcel").await?;
         unwrap_status(resp, Endpoint::Parcel, Operation::Create).await?;
         Ok(())
     }
@@ -375,7 +920,7 @@ impl Client {
         let parsed_id = bindle_id.try_into().map_err(|e| e.into())?;
         tracing::span::Span::current().record("invoice_id", &tracing::field::display(&parsed_id));
         let resp = self.get_parcel_request(&parsed_id, sha).await?;
-        Ok(resp.bytes().await?.to_vec())
+        crate::util::collect_stream(self.body_stream(resp, "get parcel"), None).await
     }
 
     /// Returns the requested parcel (identified by its Bindle ID and SHA) as a stream of bytes.
@@ -394,28 +939,347 @@ impl Client {
         let parsed_id = bindle_id.try_into().map_err(|e| e.into())?;
         tracing::span::Span::current().record("invoice_id", &tracing::field::display(&parsed_id));
         let resp = self.get_parcel_request(&parsed_id, sha).await?;
-        Ok(resp.bytes_stream().map(|r| r.map_err(|e| e.into())))
+        Ok(self.body_stream(resp, "get parcel"))
+    }
+
+    /// Fetches the parcels for the given labels in order, such as those returned by
+    /// [`Invoice::resolve_platform`](crate::Invoice::resolve_platform), downloading up to `buffer`
+    /// parcels ahead of the one the consumer is currently processing. This overlaps downloading
+    /// with whatever work is done on each parcel (such as installing it).
+    ///
+    /// Parcels are only fetched as the stream is polled, so no more than `buffer` parcels are ever
+    /// held in memory or in flight. A `buffer` of 0 is treated as 1, which fetches parcels one at a
+    /// time
+    pub fn prefetch_stream<'a>(
+        &'a self,
+        bindle_id: &'a Id,
+        labels: Vec<crate::Label>,
+        buffer: usize,
+    ) -> impl Stream<Item = Result<(crate::Label, Vec<u8>)>> + 'a {
+        // Both tokio_stream and futures have a StreamExt, so call the futures one explicitly
+        let fetches = futures::stream::iter(labels).map(move |label| async move {
+            trace!(parcel_id = %label.sha256, "Prefetching parcel");
+            let data = self.get_parcel(bindle_id, &label.sha256).await?;
+            Ok((label, data))
+        });
+        futures::StreamExt::buffered(fetches, buffer.max(1))
+    }
+
+    /// Same as [`get_parcel`](Client::get_parcel), but only returns the parcel if its label has a
+    /// valid parcel signature made with a key in the given keyring. The signature is checked before
+    /// downloading, and the downloaded data is then checked against the SHA in the label, as the
+    /// signature only covers the SHA. See [`verify_parcel_signature`](crate::verify_parcel_signature)
+    /// for details on how signatures are checked
+    #[instrument(level = "trace", skip(self, bindle_id, label, keyring), fields(invoice_id, parcel_id = %label.sha256))]
+    pub async fn get_verified_parcel<I>(
+        &self,
+        bindle_id: I,
+        label: &crate::Label,
+        keyring: &KeyRing,
+    ) -> Result<Vec<u8>>
+    where
+        I: TryInto<Id>,
+        I::Error: Into<ClientError>,
+    {
+        let parsed_id = bindle_id.try_into().map_err(|e| e.into())?;
+        tracing::span::Span::current().record("invoice_id", &tracing::field::display(&parsed_id));
+        crate::verify_parcel_signature(label, keyring)?;
+        debug!("Parcel signature verified");
+        let data = self.get_parcel(&parsed_id, &label.sha256).await?;
+        if format!("{:x}", Sha256::digest(&data)) != label.sha256 {
+            return Err(ClientError::ParcelShaMismatch(label.sha256.clone()));
+        }
+        Ok(data)
+    }
+
+    /// Downloads the requested parcel, checks it against its SHA, and then applies the given
+    /// transform to it, such as decompressing it. The SHA is always checked against the original
+    /// data, so a [`ClientError::ParcelShaMismatch`] is returned before the transform ever sees data
+    /// that doesn't match. A [`TransformRegistry`](transform::TransformRegistry) can be used to
+    /// choose the transform from the media type in the parcel label
+    #[instrument(level = "trace", skip(self, bindle_id, transform), fields(invoice_id))]
+    pub async fn get_parcel_transformed<I, T>(
+        &self,
+        bindle_id: I,
+        sha: &str,
+        transform: &T,
+    ) -> Result<Vec<u8>>
+    where
+        I: TryInto<Id>,
+        I::Error: Into<ClientError>,
+        T: transform::ParcelTransform + ?Sized,
+    {
+        let parsed_id = bindle_id.try_into().map_err(|e| e.into())?;
+        tracing::span::Span::current().record("invoice_id", &tracing::field::display(&parsed_id));
+        let data = self.get_parcel(&parsed_id, sha).await?;
+        if format!("{:x}", Sha256::digest(&data)) != sha {
+            return Err(ClientError::ParcelShaMismatch(sha.to_owned()));
+        }
+        transform.transform(data).await
+    }
+
+    /// Fetches a parcel that was deferred because it is marked as `lazy` (see
+    /// [`BindleFilter::resolve`](crate::filters::BindleFilter::resolve)), such as when it is needed
+    /// some time after the bindle was installed. As a long time may have passed since the invoice
+    /// was fetched, this checks that the parcel is still part of the bindle and that the fetched
+    /// data matches its SHA. Parcels that are not lazy can still be fetched this way
+    #[instrument(level = "trace", skip(self, bindle_id), fields(invoice_id))]
+    pub async fn fetch_deferred<I>(&self, bindle_id: I, sha: &str) -> Result<Vec<u8>>
+    where
+        I: TryInto<Id>,
+        I::Error: Into<ClientError>,
+    {
+        let parsed_id = bindle_id.try_into().map_err(|e| e.into())?;
+        tracing::span::Span::current().record("invoice_id", &tracing::field::display(&parsed_id));
+        let inv = self.get_invoice(&parsed_id).await?;
+        if !inv.parcel.iter().flatten().any(|p| p.label.sha256 == sha) {
+            debug!("Deferred parcel is no longer part of the bindle");
+            return Err(ClientError::ParcelNotFound);
+        }
+        let data = self.get_parcel(&parsed_id, sha).await?;
+        if format!("{:x}", Sha256::digest(&data)) != sha {
+            return Err(ClientError::ParcelShaMismatch(sha.to_owned()));
+        }
+        Ok(data)
+    }
+
+    /// Returns the parcel with the given SHA from the configured
+    /// [`cache_dir`](ClientOptions::cache_dir) without ever contacting the server. As parcels are
+    /// content addressed, the lookup is keyed only by the SHA, so no bindle ID is needed.
+    ///
+    /// Returns [`ClientError::NotCached`] if no cache directory is configured, if the parcel is not
+    /// in it, or if `sha` is not a valid SHA-256 digest. If the cached data doesn't match its SHA, a
+    /// [`ClientError::ParcelShaMismatch`] is returned instead
+    #[instrument(level = "trace", skip(self))]
+    pub async fn get_parcel_cached_only(&self, sha: &str) -> Result<Vec<u8>> {
+        let cache_dir = match self.cache_dir.as_ref() {
+            Some(dir) => dir,
+            None => return Err(ClientError::NotCached(sha.to_owned())),
+        };
+        // The SHA is used as a path segment, so anything other than a hex digest is rejected rather
+        // than looked up
+        if sha.len() != 64 || !sha.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+            return Err(ClientError::NotCached(sha.to_owned()));
+        }
+        let path = cache_dir
+            .join(crate::provider::file::PARCEL_DIRECTORY)
+            .join(sha)
+            .join(crate::provider::file::PARCEL_DAT);
+        let data = match tokio::fs::read(&path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                debug!(path = %path.display(), "Parcel is not in the local cache");
+                return Err(ClientError::NotCached(sha.to_owned()));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if format!("{:x}", Sha256::digest(&data)) != sha {
+            return Err(ClientError::ParcelShaMismatch(sha.to_owned()));
+        }
+        Ok(data)
+    }
+
+    /// Downloads the requested parcel to the file at `dest`, splitting it into the given number of
+    /// byte ranges that are downloaded in parallel and written to their offsets in the file. For
+    /// large parcels on high latency links, this is much faster than downloading the parcel as a
+    /// single stream.
+    ///
+    /// The parcel is downloaded to a temporary file in the same directory as `dest`, and only moved
+    /// to `dest` once its SHA has been checked. If the download fails for any reason, including a
+    /// SHA mismatch or being cancelled, the temporary file is removed and `dest` is left untouched,
+    /// so an existing file there is only ever replaced by a complete, verified parcel.
+    ///
+    /// If the server does not support range requests, or fewer than 2 chunks are requested, the
+    /// parcel is downloaded sequentially instead. Note that bindle-server itself does not currently
+    /// support range requests, so against it this always takes the sequential path; the parallel
+    /// path only applies to servers (or proxies and caches in front of them) that do
+    #[instrument(level = "trace", skip(self, bindle_id, dest), fields(invoice_id, dest = %dest.as_ref().display()))]
+    pub async fn get_parcel_parallel<I, P>(
+        &self,
+        bindle_id: I,
+        sha: &str,
+        dest: P,
+        chunks: usize,
+    ) -> Result<()>
+    where
+        I: TryInto<Id>,
+        I::Error: Into<ClientError>,
+        P: AsRef<Path>,
+    {
+        let parsed_id = bindle_id.try_into().map_err(|e| e.into())?;
+        tracing::span::Span::current().record("invoice_id", &tracing::field::display(&parsed_id));
+        let dest = dest.as_ref().to_owned();
+
+        // A sibling file so the final rename stays on the same filesystem. It is removed when
+        // dropped, so any early return below cleans it up
+        let dir = match dest.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_owned(),
+            _ => PathBuf::from("."),
+        };
+        let tmp = tokio::task::spawn_blocking(move || {
+            tempfile::Builder::new()
+                .prefix(".bindle-download-")
+                .tempfile_in(dir)
+                .map(|f| f.into_temp_path())
+        })
+        .await
+        .map_err(|e| ClientError::Other(format!("Unable to create temporary file: {}", e)))??;
+
+        self.download_parcel(&parsed_id, sha, &tmp, chunks).await?;
+        if file_sha(&tmp).await? != sha {
+            return Err(ClientError::ParcelShaMismatch(sha.to_owned()));
+        }
+        tokio::task::spawn_blocking(move || tmp.persist(dest))
+            .await
+            .map_err(|e| ClientError::Other(format!("Unable to move downloaded parcel: {}", e)))?
+            .map_err(|e| e.error)?;
+        Ok(())
+    }
+
+    /// Downloads the parcel to `dest` in parallel if the server supports it, or sequentially if not
+    async fn download_parcel(
+        &self,
+        bindle_id: &Id,
+        sha: &str,
+        dest: &Path,
+        chunks: usize,
+    ) -> Result<()> {
+        let downloaded = match self.parcel_range_len(bindle_id, sha, chunks).await? {
+            Some(len) => {
+                self.get_parcel_ranges(bindle_id, sha, dest, len, chunks)
+                    .await?
+            }
+            None => false,
+        };
+        if !downloaded {
+            debug!("Server does not support range requests, downloading parcel sequentially");
+            let mut file = tokio::fs::File::create(dest).await?;
+            let resp = self.get_parcel_request(bindle_id, sha).await?;
+            let mut stream = self.body_stream(resp, "get parcel");
+            while let Some(data) = stream.next().await {
+                file.write_all(&data?).await?;
+            }
+            file.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the length of the parcel if the server supports range requests for it and it is
+    /// large enough to be split into the given number of chunks
+    async fn parcel_range_len(
+        &self,
+        bindle_id: &Id,
+        sha: &str,
+        chunks: usize,
+    ) -> Result<Option<u64>> {
+        if chunks < 2 {
+            return Ok(None);
+        }
+        let req = self
+            .client
+            .head(self.parcel_url(bindle_id, sha))
+            .header(header::ACCEPT, "*/*");
+        let resp = self
+            .send(req, RequestKind::Metadata, "check parcel ranges")
+            .await?;
+        let resp = unwrap_status(resp, Endpoint::Parcel, Operation::Get).await?;
+        let headers = resp.headers();
+        let supports_ranges = headers
+            .get(header::ACCEPT_RANGES)
+            .map(|v| v.as_bytes() == b"bytes")
+            .unwrap_or(false);
+        // A HEAD response has no body, so the length has to come from the header itself
+        let len = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        trace!(supports_ranges, ?len, "Checked parcel for range support");
+        Ok(len.filter(|l| supports_ranges && *l >= chunks as u64))
+    }
+
+    /// Downloads every chunk of the parcel in parallel. Returns false if the server ignored the
+    /// range requests, in which case the contents of the file should not be used
+    async fn get_parcel_ranges(
+        &self,
+        bindle_id: &Id,
+        sha: &str,
+        dest: &Path,
+        len: u64,
+        chunks: usize,
+    ) -> Result<bool> {
+        let file = tokio::fs::File::create(dest).await?;
+        file.set_len(len).await?;
+        drop(file);
+
+        let ranges = chunk_ranges(len, chunks);
+        debug!(chunks = ranges.len(), len, "Downloading parcel in parallel");
+        let results = futures::future::try_join_all(
+            ranges
+                .into_iter()
+                .map(|(start, end)| self.get_parcel_range(bindle_id, sha, dest, start, end)),
+        )
+        .await?;
+        Ok(results.into_iter().all(|ranged| ranged))
+    }
+
+    /// Downloads the given inclusive byte range of a parcel and writes it to the same offset in
+    /// the file at `dest`. Returns false without writing anything if the server ignored the range
+    async fn get_parcel_range(
+        &self,
+        bindle_id: &Id,
+        sha: &str,
+        dest: &Path,
+        start: u64,
+        end: u64,
+    ) -> Result<bool> {
+        let req = self
+            .client
+            .get(self.parcel_url(bindle_id, sha))
+            .header(header::ACCEPT, "*/*")
+            .header(header::RANGE, format!("bytes={}-{}", start, end));
+        let resp = self
+            .send(req, RequestKind::Bulk, "get parcel range")
+            .await?;
+        if resp.status() == StatusCode::OK {
+            trace!(start, end, "Server ignored range request");
+            return Ok(false);
+        }
+        let resp = unwrap_status(resp, Endpoint::Parcel, Operation::Get).await?;
+
+        let mut file = tokio::fs::OpenOptions::new().write(true).open(dest).await?;
+        file.seek(std::io::SeekFrom::Start(start)).await?;
+        let mut stream = self.body_stream(resp, "get parcel range");
+        while let Some(data) = stream.next().await {
+            file.write_all(&data?).await?;
+        }
+        file.flush().await?;
+        Ok(true)
+    }
+
+    fn parcel_url(&self, bindle_id: &Id, sha: &str) -> Url {
+        // We can unwrap here because any URL error would be programmers fault
+        self.base_url
+            .join(&format!("{}/{}@{}", INVOICE_ENDPOINT, bindle_id, sha))
+            .unwrap()
     }
 
     async fn get_parcel_request(&self, bindle_id: &Id, sha: &str) -> Result<reqwest::Response> {
         // Override the default accept header
         let req = self
             .client
-            .get(
-                self.base_url
-                    .join(&format!("{}/{}@{}", INVOICE_ENDPOINT, bindle_id, sha))
-                    .unwrap(),
-            )
+            .get(self.parcel_url(bindle_id, sha))
             .header(header::ACCEPT, "*/*");
-        trace!(?req);
-        let resp = req.send().await?;
+        let resp = self.send(req, RequestKind::Bulk, "get parcel").await?;
         unwrap_status(resp, Endpoint::Parcel, Operation::Get).await
     }
 
     //////////////// Relationship Endpoints ////////////////
 
     /// Gets the labels of missing parcels, if any, of the specified bindle. If the bindle is
-    /// yanked, this will fail
+    /// yanked, this will fail.
+    ///
+    /// The response from [`create_invoice`](Client::create_invoice) already contains the missing
+    /// parcels, so this is only needed to check on the progress of a bindle later on
     #[instrument(level = "trace", skip(self, id), fields(invoice_id))]
     pub async fn get_missing_parcels<I>(&self, id: I) -> Result<Vec<crate::Label>>
     where
@@ -430,10 +1294,331 @@ impl Client {
             "missing",
             parsed_id.to_string()
         ))?);
-        trace!(?req);
-        let resp = req.send().await?;
+        let resp = self
+            .send(req, RequestKind::Metadata, "get missing parcels")
+            .await?;
         let resp = unwrap_status(resp, Endpoint::Invoice, Operation::Get).await?;
-        Ok(toml::from_slice::<crate::MissingParcelsResponse>(&resp.bytes().await?)?.missing)
+        Ok(toml::from_slice::<crate::MissingParcelsResponse>(
+            &resp
+                .bytes()
+                .await
+                .map_err(|e| map_request_error(e, "get missing parcels"))?,
+        )?
+        .missing)
+    }
+
+    //////////////// Parcel Prefix ////////////////
+
+    /// Returns the full SHA of every parcel on the server that starts with the given prefix. The
+    /// server may reject prefixes that are too short
+    #[instrument(level = "trace", skip(self))]
+    pub async fn parcels_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let req = self.client.get(
+            self.base_url
+                .join(&format!("{}/{}", PARCEL_PREFIX_ENDPOINT, prefix))?,
+        );
+        let resp = self
+            .send(req, RequestKind::Metadata, "get parcels with prefix")
+            .await?;
+        let resp = unwrap_status(resp, Endpoint::Parcel, Operation::Query).await?;
+        Ok(toml::from_slice::<crate::ParcelPrefixResponse>(
+            &resp
+                .bytes()
+                .await
+                .map_err(|e| map_request_error(e, "get parcels with prefix"))?,
+        )?
+        .matches)
+    }
+
+    /// Resolves a SHA prefix (like the short hashes used by git) to the full SHA of a parcel on the
+    /// server. Returns [`ClientError::ParcelNotFound`] if no parcel matches and
+    /// [`ClientError::AmbiguousSha`] if more than one parcel matches
+    #[instrument(level = "trace", skip(self))]
+    pub async fn resolve_sha_prefix(&self, prefix: &str) -> Result<String> {
+        let mut matches = self.parcels_with_prefix(prefix).await?;
+        match matches.len() {
+            0 => Err(ClientError::ParcelNotFound),
+            1 => Ok(matches.remove(0)),
+            _ => Err(ClientError::AmbiguousSha {
+                prefix: prefix.to_owned(),
+                matches,
+            }),
+        }
+    }
+
+    //////////////// Health ////////////////
+
+    /// Returns the aggregate health of the server, including whether its storage is ready and the
+    /// versions it is running. A server whose storage is not ready still returns `Ok`, so check
+    /// [`Health::is_healthy`](crate::Health::is_healthy) to see if the server can serve requests
+    #[instrument(level = "trace", skip(self))]
+    pub async fn health(&self) -> Result<crate::Health> {
+        let req = self.client.get(self.base_url.join(HEALTH_ENDPOINT)?);
+        let resp = self.send(req, RequestKind::Metadata, "get health").await?;
+        // The body is still a health report when the storage isn't ready
+        let resp = if resp.status() == StatusCode::SERVICE_UNAVAILABLE {
+            resp
+        } else {
+            unwrap_status(resp, Endpoint::Query, Operation::Get).await?
+        };
+        Ok(toml::from_slice::<crate::Health>(
+            &resp
+                .bytes()
+                .await
+                .map_err(|e| map_request_error(e, "get health"))?,
+        )?)
+    }
+
+    //////////////// Audit Log ////////////////
+
+    /// Returns the entries in the server's audit log that match the given query, oldest first. Only
+    /// users in one of the server's audit admin groups can query the log, and servers without an
+    /// audit log return a not found error
+    #[instrument(level = "trace", skip(self))]
+    pub async fn audit_log(
+        &self,
+        query: &crate::audit::AuditQuery,
+    ) -> Result<Vec<crate::audit::AuditEntry>> {
+        let req = self
+            .client
+            .get(self.base_url.join(AUDIT_ENDPOINT)?)
+            .query(query);
+        let resp = self
+            .send(req, RequestKind::Metadata, "query audit log")
+            .await?;
+        let resp = unwrap_status(resp, Endpoint::Query, Operation::Query).await?;
+        let log = toml::from_slice::<crate::audit::AuditLogResponse>(
+            &resp
+                .bytes()
+                .await
+                .map_err(|e| map_request_error(e, "query audit log"))?,
+        )?;
+        Ok(log.entries)
+    }
+
+    //////////////// Usage ////////////////
+
+    /// Returns the storage used by each namespace on the server, keyed by namespace. See
+    /// [`UsageStats`](crate::UsageStats) for how parcels shared between namespaces are counted
+    #[instrument(level = "trace", skip(self))]
+    pub async fn usage_by_namespace(&self) -> Result<HashMap<String, crate::UsageStats>> {
+        let req = self.client.get(self.base_url.join(USAGE_ENDPOINT)?);
+        let resp = self.send(req, RequestKind::Metadata, "get usage").await?;
+        let resp = unwrap_status(resp, Endpoint::Query, Operation::Get).await?;
+        let usage = toml::from_slice::<crate::UsageResponse>(
+            &resp
+                .bytes()
+                .await
+                .map_err(|e| map_request_error(e, "get usage"))?,
+        )?;
+        Ok(usage.into_map())
+    }
+
+    //////////////// Export ////////////////
+
+    /// Exports all of the given invoices, along with their parcels, into a single tar archive
+    /// written to the given writer. The writer is returned once the archive has been finished.
+    ///
+    /// The archive uses the same layout as the [`FileProvider`](crate::provider::file::FileProvider),
+    /// with each invoice stored under `invoices/<INVOICE_SHA>/invoice.toml` and all parcels stored
+    /// in a common `parcels/<PARCEL_SHA>/parcel.dat` directory. Because parcels are content
+    /// addressed, a parcel shared by multiple invoices is only downloaded and stored once. This
+    /// means an unpacked archive can be served directly by a `FileProvider`.
+    ///
+    /// All invoices are fetched concurrently before any data is written, so a missing invoice
+    /// will cause an error before the archive is started. A [`ClientError::ParcelSizeMismatch`] is
+    /// returned if the server sends a different amount of data for a parcel than its label says
+    #[instrument(level = "trace", skip(self, ids, writer), fields(invoice_count = ids.len()))]
+    pub async fn export_many_to_tar<W>(&self, ids: &[Id], writer: W) -> Result<W>
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        self.export_tar(ids, writer, None).await
+    }
+
+    /// Same as [`export_many_to_tar`](Client::export_many_to_tar), but any parcel larger than
+    /// `chunk_size` bytes is split across multiple archive entries, so a transfer of an archive
+    /// with very large parcels can be resumed at entry granularity. Smaller parcels are stored as
+    /// a single entry as usual.
+    ///
+    /// The chunks are stored under [`CHUNK_DIRECTORY`](archive::CHUNK_DIRECTORY) and described by
+    /// a [`ChunkManifest`](archive::ChunkManifest) at the start of the archive. Use
+    /// [`archive::unpack`] to unpack the archive and reassemble the parcels
+    #[instrument(level = "trace", skip(self, ids, writer), fields(invoice_count = ids.len()))]
+    pub async fn export_many_to_tar_chunked<W>(
+        &self,
+        ids: &[Id],
+        writer: W,
+        chunk_size: u64,
+    ) -> Result<W>
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        if chunk_size == 0 {
+            return Err(ClientError::InvalidConfig(
+                "chunk size must be greater than 0".to_owned(),
+            ));
+        }
+        self.export_tar(ids, writer, Some(chunk_size)).await
+    }
+
+    async fn export_tar<W>(&self, ids: &[Id], writer: W, chunk_size: Option<u64>) -> Result<W>
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let invoices =
+            futures::future::try_join_all(ids.iter().map(|id| self.get_invoice(id))).await?;
+
+        // Work out which parcels need to be split up front so the manifest can be written first
+        let mut chunked: BTreeMap<String, archive::ChunkedParcel> = BTreeMap::new();
+        if let Some(chunk_size) = chunk_size {
+            for label in invoices
+                .iter()
+                .flat_map(|inv| inv.parcel.iter().flatten())
+                .map(|p| &p.label)
+                .filter(|l| l.size > chunk_size)
+            {
+                chunked.insert(
+                    label.sha256.clone(),
+                    archive::ChunkedParcel::new(&label.sha256, label.size, chunk_size),
+                );
+            }
+        }
+
+        let mut builder = tokio_tar::Builder::new(writer);
+        if !chunked.is_empty() {
+            let manifest = archive::ChunkManifest {
+                parcel: chunked.values().cloned().collect(),
+            };
+            let data = toml::to_vec(&manifest)?;
+            builder
+                .append_data(
+                    &mut tar_header(data.len() as u64),
+                    archive::CHUNK_MANIFEST,
+                    data.as_slice(),
+                )
+                .await?;
+        }
+        let mut seen: HashSet<String> = HashSet::new();
+        for inv in invoices.iter() {
+            let data = toml::to_vec(inv)?;
+            let path: PathBuf = [
+                crate::provider::file::INVOICE_DIRECTORY,
+                &inv.canonical_name(),
+                crate::provider::file::INVOICE_TOML,
+            ]
+            .iter()
+            .collect();
+            debug!(invoice_id = %inv.bindle.id, path = %path.display(), "Adding invoice to archive");
+            builder
+                .append_data(&mut tar_header(data.len() as u64), path, data.as_slice())
+                .await?;
+
+            for parcel in inv.parcel.iter().flatten() {
+                if !seen.insert(parcel.label.sha256.clone()) {
+                    trace!(parcel_id = %parcel.label.sha256, "Parcel already in archive, skipping");
+                    continue;
+                }
+                let stream = self
+                    .get_parcel_stream(&inv.bindle.id, &parcel.label.sha256)
+                    .await?
+                    .map(|res| res.map_err(std::io::Error::other));
+                let mut reader = StreamReader::new(Box::pin(stream));
+                let sha = &parcel.label.sha256;
+                if let Some(chunked) = chunked.get(sha) {
+                    debug!(parcel_id = %sha, chunks = chunked.chunk.len(), "Adding chunked parcel to archive");
+                    for chunk in chunked.chunk.iter() {
+                        append_exact(&mut builder, &chunk.path, chunk.length, &mut reader, sha)
+                            .await?;
+                    }
+                } else {
+                    let path: PathBuf = [
+                        crate::provider::file::PARCEL_DIRECTORY,
+                        sha,
+                        crate::provider::file::PARCEL_DAT,
+                    ]
+                    .iter()
+                    .collect();
+                    debug!(parcel_id = %sha, path = %path.display(), "Adding parcel to archive");
+                    append_exact(&mut builder, path, parcel.label.size, &mut reader, sha).await?;
+                }
+                // The header sizes come from the label, so any data past it can't be archived
+                if reader.read(&mut [0u8; 1]).await? != 0 {
+                    return Err(ClientError::ParcelSizeMismatch(sha.clone()));
+                }
+            }
+        }
+
+        builder.finish().await?;
+        Ok(builder.into_inner().await?)
+    }
+
+    //////////////// Overlay Manifest ////////////////
+
+    /// Returns a manifest mapping the install path of every parcel in the bindle to the URL it can
+    /// be fetched from. Group conditions are resolved using [`BindleFilter`](crate::filters::BindleFilter)
+    /// with each of the given features activated, so the manifest only contains the parcels that
+    /// apply. No parcel data is downloaded.
+    ///
+    /// The URLs are built from the base URL of this client rather than by the server, so they point
+    /// at the same address the client used, even when the server is behind a reverse proxy.
+    ///
+    /// There is no server endpoint for overlay manifests. The manifest is derived entirely from the
+    /// invoice, which the client already fetches, and the server does not know the address clients
+    /// reach it at, so it could not build these URLs correctly itself.
+    ///
+    /// Returns a [`ClientError::DuplicateInstallPath`] if more than one of the resolved parcels has
+    /// the same name. Bindles created with
+    /// [`require_unique_parcel_names`](ClientOptions::require_unique_parcel_names) set (or on a
+    /// server that enforces unique names) never have this problem, no matter which features are
+    /// activated
+    #[instrument(level = "trace", skip(self, id, features), fields(invoice_id))]
+    pub async fn get_overlay_manifest<I>(
+        &self,
+        id: I,
+        features: &crate::invoice::FeatureMap,
+    ) -> Result<OverlayManifest>
+    where
+        I: TryInto<Id>,
+        I::Error: Into<ClientError>,
+    {
+        let parsed_id = id.try_into().map_err(|e| e.into())?;
+        tracing::span::Span::current().record("invoice_id", &tracing::field::display(&parsed_id));
+        let inv = self.get_invoice(&parsed_id).await?;
+
+        let mut filter = crate::filters::BindleFilter::new(&inv);
+        for (group, feature) in features {
+            for (name, value) in feature {
+                filter.activate_feature(group, name, value);
+            }
+        }
+
+        let mut entries = BTreeMap::new();
+        for parcel in filter.filter() {
+            let url = self.base_url.join(&format!(
+                "{}/{}@{}",
+                INVOICE_ENDPOINT, parsed_id, parcel.label.sha256
+            ))?;
+            let label = parcel.label;
+            if entries.contains_key(&label.name) {
+                return Err(ClientError::DuplicateInstallPath(label.name));
+            }
+            entries.insert(
+                label.name,
+                OverlayEntry {
+                    sha256: label.sha256,
+                    url: url.to_string(),
+                    size: label.size,
+                    media_type: label.media_type,
+                },
+            );
+        }
+        trace!(entry_count = entries.len(), "Resolved overlay manifest");
+
+        Ok(OverlayManifest {
+            id: parsed_id,
+            entries,
+        })
     }
 }
 
@@ -538,6 +1723,10 @@ impl Provider for Client {
             })),
         }
     }
+
+    async fn parcels_with_prefix(&self, prefix: &str) -> crate::provider::Result<Vec<String>> {
+        self.parcels_with_prefix(prefix).await.map_err(|e| e.into())
+    }
 }
 
 // A helper function and related enum to make some reusable code for unwrapping a status code and returning the right error
@@ -555,6 +1744,7 @@ async fn unwrap_status(
 ) -> Result<reqwest::Response> {
     match (resp.status(), endpoint) {
         (StatusCode::OK, _) => Ok(resp),
+        (StatusCode::PARTIAL_CONTENT, Endpoint::Parcel) => Ok(resp),
         (StatusCode::ACCEPTED, Endpoint::Invoice) => Ok(resp),
         (StatusCode::CREATED, Endpoint::Invoice) => Ok(resp),
         (StatusCode::NOT_FOUND, Endpoint::Invoice) | (StatusCode::FORBIDDEN, Endpoint::Invoice) => {
@@ -570,6 +1760,17 @@ async fn unwrap_status(
         (StatusCode::CONFLICT, Endpoint::Invoice) => Err(ClientError::InvoiceAlreadyExists),
         (StatusCode::CONFLICT, Endpoint::Parcel) => Err(ClientError::ParcelAlreadyExists),
         (StatusCode::UNAUTHORIZED, _) => Err(ClientError::Unauthorized),
+        (StatusCode::TOO_MANY_REQUESTS, _) if matches!(operation, Operation::Yank) => Err(
+            ClientError::YankThrottled(parse_error_from_body(resp).await),
+        ),
+        (StatusCode::METHOD_NOT_ALLOWED, _)
+            if matches!(operation, Operation::Create | Operation::Yank)
+                && resp
+                    .headers()
+                    .contains_key(crate::provider::read_only::READ_ONLY_HEADER) =>
+        {
+            Err(ClientError::ReadOnlyRegistry)
+        }
         // You can't range match on u16 so we use a guard
         (_, _) if resp.status().is_server_error() => {
             Err(ClientError::ServerError(parse_error_from_body(resp).await))
@@ -585,6 +1786,74 @@ async fn unwrap_status(
     }
 }
 
+/// Splits `len` bytes into at most `chunks` contiguous, inclusive byte ranges of nearly equal size
+fn chunk_ranges(len: u64, chunks: usize) -> Vec<(u64, u64)> {
+    let size = len.div_ceil(chunks as u64);
+    (0..len)
+        .step_by(size.max(1) as usize)
+        .map(|start| (start, (start + size).min(len) - 1))
+        .collect()
+}
+
+/// Returns the hex encoded SHA256 of the file at the given path
+async fn file_sha(path: &Path) -> Result<String> {
+    let mut reader = VerifyingReader::new(tokio::fs::File::open(path).await?);
+    tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+    Ok(reader.finalize())
+}
+
+/// Appends exactly `len` bytes from `reader` to the archive as an entry at `path`. Returns a
+/// [`ClientError::ParcelSizeMismatch`] if the reader runs out first, as the entry's header would
+/// no longer match its data
+async fn append_exact<W, P, R>(
+    builder: &mut tokio_tar::Builder<W>,
+    path: P,
+    len: u64,
+    reader: &mut R,
+    sha: &str,
+) -> Result<()>
+where
+    W: AsyncWrite + Unpin + Send,
+    P: AsRef<Path>,
+    R: AsyncRead + Unpin,
+{
+    let mut limited = reader.take(len);
+    builder
+        .append_data(&mut tar_header(len), path, &mut limited)
+        .await?;
+    if limited.limit() != 0 {
+        return Err(ClientError::ParcelSizeMismatch(sha.to_owned()));
+    }
+    Ok(())
+}
+
+fn tar_header(size: u64) -> tokio_tar::Header {
+    let mut header = tokio_tar::Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o644);
+    header
+}
+
+/// Parses a response body containing an invoice, returning a
+/// [`ClientError::InvalidInvoiceResponse`] with the start of the body if it is not valid
+fn parse_invoice_response<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T> {
+    toml::from_slice(body).map_err(|source| ClientError::InvalidInvoiceResponse {
+        source,
+        body: String::from_utf8_lossy(&body[..body.len().min(INVALID_RESPONSE_SNIPPET_LEN)])
+            .into_owned(),
+    })
+}
+
+fn map_request_error(e: reqwest::Error, operation: &str) -> ClientError {
+    if e.is_timeout() {
+        ClientError::Timeout {
+            operation: operation.to_owned(),
+        }
+    } else {
+        e.into()
+    }
+}
+
 async fn parse_error_from_body(resp: reqwest::Response) -> Option<String> {
     let bytes = match resp.bytes().await {
         Ok(b) => b,
@@ -611,3 +1880,5 @@ trait ConditionalBuilder {
 }
 
 impl ConditionalBuilder for ClientBuilder {}
+
+impl ConditionalBuilder for RequestBuilder {}
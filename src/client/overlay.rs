@@ -0,0 +1,35 @@
+//! Types for describing a bindle as a manifest of install paths to fetchable parcels, for use by
+//! runtimes that lazily mount bindle content
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Id;
+
+/// A single file in an [`OverlayManifest`](OverlayManifest), describing where the content of the
+/// file can be fetched from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OverlayEntry {
+    /// The SHA256 of the parcel
+    pub sha256: String,
+    /// The URL the parcel can be fetched from
+    pub url: String,
+    /// The size of the parcel, in bytes
+    pub size: u64,
+    /// The media type of the parcel
+    pub media_type: String,
+}
+
+/// A manifest mapping install paths to the parcels that should be mounted at them. This allows a
+/// runtime (such as a FUSE or overlay filesystem) to fetch parcels only when they are accessed
+/// rather than downloading the whole bindle up front
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OverlayManifest {
+    /// The ID of the bindle this manifest was generated from
+    pub id: Id,
+    /// The parcels to mount, keyed by the install path (the name of the parcel's label)
+    pub entries: BTreeMap<String, OverlayEntry>,
+}
@@ -0,0 +1,187 @@
+//! Trust on first use (TOFU) pinning of the key a server signs invoices with, similar to SSH's
+//! `known_hosts`. Set [`ClientOptions::tofu_store`](super::ClientOptions::tofu_store) to enable it.
+//!
+//! The first time a client receives an invoice signed with the `host` role from a server, the key
+//! that made the signature is recorded for that server's base URL. Every later invoice from the
+//! server must have a valid host signature from the same key, otherwise a
+//! [`ClientError::HostKeyChanged`] is returned. A new key can be trusted with
+//! [`Client::accept_host_key`](super::Client::accept_host_key). Invoices without any host
+//! signature are not checked, as there is no key to compare
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use super::{ClientError, Result};
+use crate::SignatureRole;
+
+/// The contents of a known hosts file
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct KnownHostsFile {
+    /// The base64 encoded public key pinned for each server, keyed by base URL
+    #[serde(default)]
+    hosts: BTreeMap<String, String>,
+}
+
+/// A file of pinned host keys. Changes are written to disk straight away, and reads and writes
+/// from the same store are serialized so that concurrent requests don't lose updates
+#[derive(Debug)]
+pub struct KnownHosts {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl KnownHosts {
+    /// Returns a store backed by the file at the given path. The file is created when the first key
+    /// is pinned, so it doesn't need to exist yet
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        KnownHosts {
+            path: path.as_ref().to_owned(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Returns the key pinned for the given server, if there is one
+    pub async fn pinned(&self, host: &str) -> Result<Option<String>> {
+        let _guard = self.lock.lock().await;
+        Ok(self.load().await?.hosts.remove(host))
+    }
+
+    /// Pins the given key for the server, replacing any key that was pinned before
+    pub async fn pin(&self, host: &str, key: &str) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut known = self.load().await?;
+        known.hosts.insert(host.to_owned(), key.to_owned());
+        self.save(&known).await
+    }
+
+    /// Checks the host signatures on an invoice received from the given server against its pinned
+    /// key, pinning the key of the first valid host signature if the server has not been seen before
+    pub async fn check(&self, host: &str, inv: &crate::Invoice) -> Result<()> {
+        let host_sigs: Vec<_> = inv
+            .signature
+            .iter()
+            .flatten()
+            .filter(|s| s.role == SignatureRole::Host)
+            .collect();
+        if host_sigs.is_empty() {
+            debug!(%host, "Invoice has no host signature, skipping host key check");
+            return Ok(());
+        }
+        // A key is only trusted if it actually signed the invoice, otherwise anyone could copy the
+        // pinned key into a signature
+        for sig in host_sigs.iter() {
+            sig.verify(inv, &sig.public_key()?)?;
+        }
+
+        let _guard = self.lock.lock().await;
+        let mut known = self.load().await?;
+        match known.hosts.get(host) {
+            Some(pinned) if host_sigs.iter().any(|s| &s.key == pinned) => Ok(()),
+            Some(pinned) => Err(ClientError::HostKeyChanged {
+                host: host.to_owned(),
+                pinned: pinned.clone(),
+                presented: host_sigs[0].key.clone(),
+            }),
+            None => {
+                info!(%host, key = %host_sigs[0].key, "Pinning host key on first use");
+                known
+                    .hosts
+                    .insert(host.to_owned(), host_sigs[0].key.clone());
+                self.save(&known).await
+            }
+        }
+    }
+
+    async fn load(&self) -> Result<KnownHostsFile> {
+        match tokio::fs::read(&self.path).await {
+            Ok(data) => Ok(toml::from_slice(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(KnownHostsFile::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save(&self, known: &KnownHostsFile) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Write to a temporary file first so a crash never leaves a truncated store behind
+        let tmp = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp, toml::to_vec(known)?).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::invoice::signature::SecretKeyEntry;
+    use crate::testing;
+
+    #[tokio::test]
+    async fn test_known_hosts() {
+        let dir = tempfile::tempdir().unwrap();
+        let known = KnownHosts::new(dir.path().join("nested").join("known_hosts.toml"));
+        let host = "https://bindle.example.com/v1/";
+        let scaffold = testing::Scaffold::load("valid_v1").await;
+
+        let sign = |key: &SecretKeyEntry| {
+            let mut inv = scaffold.invoice.clone();
+            inv.signature = None;
+            inv.sign(SignatureRole::Host, key).unwrap();
+            inv
+        };
+        let first = SecretKeyEntry::new("first".to_owned(), vec![SignatureRole::Host]);
+        let second = SecretKeyEntry::new("second".to_owned(), vec![SignatureRole::Host]);
+
+        // Unsigned invoices can't be checked, so they don't pin anything
+        let mut unsigned = scaffold.invoice.clone();
+        unsigned.signature = None;
+        known.check(host, &unsigned).await.unwrap();
+        assert_eq!(None, known.pinned(host).await.unwrap());
+
+        let inv = sign(&first);
+        known.check(host, &inv).await.unwrap();
+        let pinned = known
+            .pinned(host)
+            .await
+            .unwrap()
+            .expect("key should be pinned");
+        known.check(host, &inv).await.unwrap();
+
+        let swapped = sign(&second);
+        match known.check(host, &swapped).await {
+            Err(ClientError::HostKeyChanged {
+                pinned: p,
+                presented,
+                ..
+            }) => {
+                assert_eq!(pinned, p);
+                assert_ne!(pinned, presented);
+            }
+            res => panic!("Expected a changed host key, got {:?}", res),
+        }
+        // Other servers are pinned separately
+        known
+            .check("https://other.example.com/v1/", &swapped)
+            .await
+            .unwrap();
+
+        // A host signature that was tampered with is rejected rather than pinned or matched
+        let mut forged = swapped.clone();
+        forged.signature.as_mut().unwrap()[0].key = pinned.clone();
+        assert!(matches!(
+            known.check(host, &forged).await,
+            Err(ClientError::SignatureError(_))
+        ));
+
+        let presented = swapped.signature.as_ref().unwrap()[0].key.clone();
+        known.pin(host, &presented).await.unwrap();
+        known.check(host, &swapped).await.unwrap();
+        assert!(known.check(host, &inv).await.is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! Transforms that are applied to parcel data after it is downloaded, such as decompressing it. Use
+//! [`get_parcel_transformed`](super::Client::get_parcel_transformed) to download and transform a
+//! parcel in one step.
+//!
+//! Transforms for common packaged formats are built in, and a [`TransformRegistry`] maps media types
+//! to the transform that unpacks them. Custom transforms can be created by implementing
+//! [`ParcelTransform`]
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+
+use tokio::io::AsyncReadExt;
+use tokio_stream::StreamExt;
+
+use super::{ClientError, Result};
+
+/// A transform applied to the data of a parcel after it is downloaded and its SHA is verified
+#[async_trait::async_trait]
+pub trait ParcelTransform: Send + Sync {
+    /// Returns the transformed parcel data
+    async fn transform(&self, data: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+/// The default maximum size of the data a [`Gunzip`] transform will decompress to, 1 GiB
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Decompresses a gzip compressed parcel. Decompression fails if the output would be larger than
+/// the maximum size, so a small parcel can't expand to exhaust memory. Use
+/// [`with_max_size`](Gunzip::with_max_size) to change the limit from
+/// [`DEFAULT_MAX_DECOMPRESSED_SIZE`]
+#[derive(Clone, Copy, Debug)]
+pub struct Gunzip {
+    max_size: u64,
+}
+
+impl Gunzip {
+    /// Returns a transform that fails if the decompressed data is larger than `max_size` bytes
+    pub fn with_max_size(max_size: u64) -> Self {
+        Gunzip { max_size }
+    }
+}
+
+impl Default for Gunzip {
+    fn default() -> Self {
+        Gunzip::with_max_size(DEFAULT_MAX_DECOMPRESSED_SIZE)
+    }
+}
+
+#[async_trait::async_trait]
+impl ParcelTransform for Gunzip {
+    async fn transform(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        let max_size = self.max_size;
+        // Decompression is CPU bound, so keep it off of the async runtime's worker threads
+        tokio::task::spawn_blocking(move || {
+            let mut out = Vec::new();
+            // Read one byte past the limit to tell data that is exactly at the limit from data
+            // that is over it
+            flate2::read::GzDecoder::new(data.as_slice())
+                .take(max_size.saturating_add(1))
+                .read_to_end(&mut out)
+                .map_err(|e| ClientError::TransformFailed(format!("invalid gzip data: {}", e)))?;
+            if out.len() as u64 > max_size {
+                return Err(ClientError::TransformFailed(format!(
+                    "decompressed data is larger than the maximum of {} bytes",
+                    max_size
+                )));
+            }
+            Ok(out)
+        })
+        .await
+        .map_err(|e| ClientError::TransformFailed(format!("gzip decompression failed: {}", e)))?
+    }
+}
+
+/// Unpacks a tar archive containing a single file, returning the contents of that file. Directory
+/// entries are ignored, but an archive with any number of files other than one is rejected
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UntarSingle;
+
+#[async_trait::async_trait]
+impl ParcelTransform for UntarSingle {
+    async fn transform(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        let invalid =
+            |e: std::io::Error| ClientError::TransformFailed(format!("invalid tar: {}", e));
+        let mut archive = tokio_tar::Archive::new(data.as_slice());
+        let mut entries = archive.entries().map_err(invalid)?;
+        let mut file = None;
+        while let Some(entry) = entries.next().await {
+            let mut entry = entry.map_err(invalid)?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            if file.is_some() {
+                return Err(ClientError::TransformFailed(
+                    "tar contains more than one file".to_owned(),
+                ));
+            }
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).await.map_err(invalid)?;
+            file = Some(contents);
+        }
+        file.ok_or_else(|| ClientError::TransformFailed("tar does not contain a file".to_owned()))
+    }
+}
+
+/// Applies each of the given transforms in order, such as [`Gunzip`] followed by [`UntarSingle`]
+/// for a compressed tar
+#[derive(Clone, Default)]
+pub struct Chain(pub Vec<Arc<dyn ParcelTransform>>);
+
+#[async_trait::async_trait]
+impl ParcelTransform for Chain {
+    async fn transform(&self, mut data: Vec<u8>) -> Result<Vec<u8>> {
+        for transform in self.0.iter() {
+            data = transform.transform(data).await?;
+        }
+        Ok(data)
+    }
+}
+
+/// A set of transforms keyed by the media type of the parcels they apply to. The default registry
+/// contains the built in transforms:
+///
+/// - `application/gzip` and `application/x-gzip`: [`Gunzip`]
+/// - `application/x-tar`: [`UntarSingle`]
+/// - `application/tar+gzip`: [`Gunzip`] followed by [`UntarSingle`]
+#[derive(Clone)]
+pub struct TransformRegistry {
+    transforms: HashMap<String, Arc<dyn ParcelTransform>>,
+}
+
+impl TransformRegistry {
+    /// Returns a registry without any transforms
+    pub fn empty() -> Self {
+        TransformRegistry {
+            transforms: HashMap::new(),
+        }
+    }
+
+    /// Adds a transform for the given media type, replacing any existing one
+    pub fn register<T>(&mut self, media_type: &str, transform: T) -> &mut Self
+    where
+        T: ParcelTransform + 'static,
+    {
+        self.transforms
+            .insert(media_type.to_ascii_lowercase(), Arc::new(transform));
+        self
+    }
+
+    /// Returns the transform for the given media type, if there is one. Media type parameters (such
+    /// as `; charset=utf-8`) are ignored
+    pub fn get(&self, media_type: &str) -> Option<&dyn ParcelTransform> {
+        let essence = media_type.split(';').next().unwrap_or_default().trim();
+        self.transforms
+            .get(&essence.to_ascii_lowercase())
+            .map(|t| t.as_ref())
+    }
+}
+
+impl Default for TransformRegistry {
+    fn default() -> Self {
+        let mut registry = TransformRegistry::empty();
+        registry
+            .register("application/gzip", Gunzip::default())
+            .register("application/x-gzip", Gunzip::default())
+            .register("application/x-tar", UntarSingle)
+            .register(
+                "application/tar+gzip",
+                Chain(vec![Arc::new(Gunzip::default()), Arc::new(UntarSingle)]),
+            );
+        registry
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    async fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tokio_tar::Builder::new(Vec::new());
+        let mut dir = tokio_tar::Header::new_gnu();
+        dir.set_entry_type(tokio_tar::EntryType::Directory);
+        dir.set_size(0);
+        dir.set_mode(0o755);
+        builder
+            .append_data(&mut dir, "dir/", tokio::io::empty())
+            .await
+            .unwrap();
+        for (name, data) in files {
+            let mut header = tokio_tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, *data).await.unwrap();
+        }
+        builder.into_inner().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_builtin_transforms() {
+        let registry = TransformRegistry::default();
+        let data = b"I'm a little teapot".to_vec();
+
+        let gunzip = registry.get("application/gzip").unwrap();
+        assert_eq!(data, gunzip.transform(gzip(&data)).await.unwrap());
+        assert!(matches!(
+            gunzip.transform(data.clone()).await,
+            Err(ClientError::TransformFailed(_))
+        ));
+
+        let limited = Gunzip::with_max_size(data.len() as u64);
+        assert_eq!(data, limited.transform(gzip(&data)).await.unwrap());
+        assert!(matches!(
+            Gunzip::with_max_size(data.len() as u64 - 1)
+                .transform(gzip(&data))
+                .await,
+            Err(ClientError::TransformFailed(_))
+        ));
+
+        let single = tar(&[("dir/teapot.txt", &data)]).await;
+        let untar = registry.get("application/x-tar").unwrap();
+        assert_eq!(data, untar.transform(single.clone()).await.unwrap());
+        assert!(matches!(
+            untar
+                .transform(tar(&[("a.txt", &data), ("b.txt", &data)]).await)
+                .await,
+            Err(ClientError::TransformFailed(_))
+        ));
+        assert!(matches!(
+            untar.transform(tar(&[]).await).await,
+            Err(ClientError::TransformFailed(_))
+        ));
+
+        let tgz = registry.get("Application/Tar+Gzip; foo=bar").unwrap();
+        assert_eq!(data, tgz.transform(gzip(&single)).await.unwrap());
+
+        assert!(registry.get("text/plain").is_none());
+    }
+}
@@ -0,0 +1,401 @@
+//! A conformance suite that checks whether a Bindle server implements the API contract from the
+//! [Bindle Spec](https://github.com/deislabs/bindle/blob/master/docs/bindle-spec.md). This module is
+//! only available if the `conformance` feature is enabled
+//!
+//! The suite only talks to the server through a [`Client`](crate::client::Client), so it can be run
+//! against any server implementation, whether it is running in the same process or is reachable at
+//! some external URL. Every run creates its own uniquely named bindles, so the suite can be run
+//! repeatedly against the same server. Note that the created bindles are yanked or left in place,
+//! as the API has no way to delete them
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), bindle::client::ClientError> {
+//! let report = bindle::conformance::run_url("http://localhost:8080/v1/").await?;
+//! println!("{}", report);
+//! assert!(report.is_success());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+use tracing::{debug, info};
+
+use crate::client::{Client, ClientError};
+use crate::{BindleSpec, Id, Invoice, Label, Parcel};
+
+/// The result of a single conformance check
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    /// A short description of the behavior that was checked
+    pub name: &'static str,
+    /// `Ok` if the server behaved as expected, otherwise an explanation of what went wrong
+    pub outcome: Result<(), String>,
+}
+
+impl CheckResult {
+    /// Returns whether or not the server passed this check
+    pub fn passed(&self) -> bool {
+        self.outcome.is_ok()
+    }
+}
+
+/// The results of every check in a conformance run, in the order they were run
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub results: Vec<CheckResult>,
+}
+
+impl Report {
+    /// Returns whether or not the server passed every check
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(CheckResult::passed)
+    }
+
+    /// Returns an iterator over all of the checks the server failed
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results.iter().filter(|r| !r.passed())
+    }
+
+    fn record(&mut self, name: &'static str, outcome: Result<(), String>) {
+        match &outcome {
+            Ok(_) => debug!(check = name, "Conformance check passed"),
+            Err(e) => info!(check = name, error = %e, "Conformance check failed"),
+        }
+        self.results.push(CheckResult { name, outcome });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in self.results.iter() {
+            match &result.outcome {
+                Ok(_) => writeln!(f, "PASS {}", result.name)?,
+                Err(e) => writeln!(f, "FAIL {}: {}", result.name, e)?,
+            }
+        }
+        let failed = self.failures().count();
+        write!(
+            f,
+            "{} passed, {} failed",
+            self.results.len() - failed,
+            failed
+        )
+    }
+}
+
+/// Runs the full conformance suite against the server the given client is configured for. Failed
+/// checks are reported in the returned [`Report`] rather than returned as an error
+pub async fn run(client: &Client) -> Report {
+    let ctx = Context::new();
+    info!(run_id = %ctx.run_id, "Starting conformance run");
+    let mut report = Report::default();
+
+    report.record(
+        "create invoice returns the invoice and its missing parcels",
+        create_invoice(client, &ctx).await,
+    );
+    report.record(
+        "get invoice returns the created invoice",
+        get_invoice(client, &ctx).await,
+    );
+    report.record(
+        "creating an existing invoice is a conflict",
+        duplicate_invoice(client, &ctx).await,
+    );
+    report.record(
+        "getting a nonexistent invoice is not found",
+        missing_invoice(client, &ctx).await,
+    );
+    report.record(
+        "missing parcels are removed once uploaded",
+        missing_parcels(client, &ctx).await,
+    );
+    report.record(
+        "uploaded parcels can be fetched unchanged",
+        parcel_round_trip(client, &ctx).await,
+    );
+    report.record(
+        "uploading an existing parcel is a conflict",
+        duplicate_parcel(client, &ctx).await,
+    );
+    report.record(
+        "parcels that do not match their label are rejected",
+        mismatched_parcel(client, &ctx).await,
+    );
+    report.record(
+        "getting a parcel that was never uploaded is not found",
+        missing_parcel(client, &ctx).await,
+    );
+    report.record(
+        "yanked invoices are only returned when requested",
+        yank_invoice(client, &ctx).await,
+    );
+
+    info!(run_id = %ctx.run_id, "{}", report);
+    report
+}
+
+/// Same as [`run`], but creates a client with the default options for the given base URL (such as
+/// `http://localhost:8080/v1/`). Returns an error only if the client cannot be created
+pub async fn run_url(base_url: &str) -> Result<Report, ClientError> {
+    let client = Client::new(base_url)?;
+    Ok(run(&client).await)
+}
+
+type CheckOutcome = Result<(), String>;
+
+/// State shared between all the checks in a single run
+struct Context {
+    run_id: String,
+}
+
+impl Context {
+    fn new() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        Context {
+            run_id: format!("run-{}", nanos),
+        }
+    }
+
+    /// Returns an invoice that is unique to this run and check, along with the data for each of its
+    /// parcels. Parcel data is unique as well, as parcels are shared between all bindles on a
+    /// server
+    fn scaffold(&self, check: &str, parcel_count: usize) -> (Invoice, Vec<Vec<u8>>) {
+        let id: Id = format!("conformance.bindle.dev/{}/{}/1.0.0", self.run_id, check)
+            .parse()
+            .expect("conformance IDs should always be valid");
+        let mut invoice = Invoice::new(BindleSpec {
+            id,
+            description: Some("Created by the bindle conformance suite".to_owned()),
+            authors: None,
+        });
+        let mut data = Vec::with_capacity(parcel_count);
+        let mut parcels = Vec::with_capacity(parcel_count);
+        for i in 0..parcel_count {
+            let bytes = format!("{} {} parcel {}", self.run_id, check, i).into_bytes();
+            let mut label = Label::new(
+                format!("parcel-{}.txt", i),
+                format!("{:x}", Sha256::digest(&bytes)),
+            );
+            label.media_type = "text/plain".to_owned();
+            label.size = bytes.len() as u64;
+            parcels.push(Parcel {
+                label,
+                conditions: None,
+            });
+            data.push(bytes);
+        }
+        if !parcels.is_empty() {
+            invoice.parcel = Some(parcels);
+        }
+        (invoice, data)
+    }
+}
+
+fn parcel_shas(inv: &Invoice) -> Vec<String> {
+    inv.parcel
+        .iter()
+        .flatten()
+        .map(|p| p.label.sha256.clone())
+        .collect()
+}
+
+/// Creates the invoice and uploads all of its parcels
+async fn create_all(client: &Client, inv: &Invoice, data: &[Vec<u8>]) -> CheckOutcome {
+    client
+        .create_invoice(inv.clone())
+        .await
+        .map_err(|e| format!("unable to create invoice: {}", e))?;
+    for (sha, bytes) in parcel_shas(inv).iter().zip(data) {
+        client
+            .create_parcel(&inv.bindle.id, sha, bytes.clone())
+            .await
+            .map_err(|e| format!("unable to create parcel {}: {}", sha, e))?;
+    }
+    Ok(())
+}
+
+async fn create_invoice(client: &Client, ctx: &Context) -> CheckOutcome {
+    let (inv, _) = ctx.scaffold("create", 2);
+    let resp = client
+        .create_invoice(inv.clone())
+        .await
+        .map_err(|e| format!("unable to create invoice: {}", e))?;
+    if resp.invoice.bindle.id != inv.bindle.id {
+        return Err(format!(
+            "expected invoice {} in response, got {}",
+            inv.bindle.id, resp.invoice.bindle.id
+        ));
+    }
+    let mut missing: Vec<String> = resp
+        .missing
+        .unwrap_or_default()
+        .into_iter()
+        .map(|l| l.sha256)
+        .collect();
+    missing.sort();
+    let mut expected = parcel_shas(&inv);
+    expected.sort();
+    if missing != expected {
+        return Err(format!(
+            "expected all parcels {:?} to be missing, got {:?}",
+            expected, missing
+        ));
+    }
+    Ok(())
+}
+
+async fn get_invoice(client: &Client, ctx: &Context) -> CheckOutcome {
+    let (inv, data) = ctx.scaffold("get", 1);
+    create_all(client, &inv, &data).await?;
+    let fetched = client
+        .get_invoice(&inv.bindle.id)
+        .await
+        .map_err(|e| format!("unable to get invoice: {}", e))?;
+    if fetched.bindle.id != inv.bindle.id || parcel_shas(&fetched) != parcel_shas(&inv) {
+        return Err("fetched invoice does not match the created invoice".to_owned());
+    }
+    Ok(())
+}
+
+async fn duplicate_invoice(client: &Client, ctx: &Context) -> CheckOutcome {
+    let (inv, _) = ctx.scaffold("duplicate-invoice", 0);
+    client
+        .create_invoice(inv.clone())
+        .await
+        .map_err(|e| format!("unable to create invoice: {}", e))?;
+    match client.create_invoice(inv).await {
+        Err(ClientError::InvoiceAlreadyExists) => Ok(()),
+        res => Err(format!("expected a conflict, got {:?}", res)),
+    }
+}
+
+async fn missing_invoice(client: &Client, ctx: &Context) -> CheckOutcome {
+    let (inv, _) = ctx.scaffold("never-created", 0);
+    match client.get_invoice(&inv.bindle.id).await {
+        Err(ClientError::InvoiceNotFound) => Ok(()),
+        res => Err(format!("expected invoice not found, got {:?}", res)),
+    }
+}
+
+async fn missing_parcels(client: &Client, ctx: &Context) -> CheckOutcome {
+    let (inv, data) = ctx.scaffold("missing-parcels", 2);
+    client
+        .create_invoice(inv.clone())
+        .await
+        .map_err(|e| format!("unable to create invoice: {}", e))?;
+    let shas = parcel_shas(&inv);
+    client
+        .create_parcel(&inv.bindle.id, &shas[0], data[0].clone())
+        .await
+        .map_err(|e| format!("unable to create parcel: {}", e))?;
+    let missing: Vec<String> = client
+        .get_missing_parcels(&inv.bindle.id)
+        .await
+        .map_err(|e| format!("unable to get missing parcels: {}", e))?
+        .into_iter()
+        .map(|l| l.sha256)
+        .collect();
+    if missing != shas[1..] {
+        return Err(format!(
+            "expected only {:?} to be missing, got {:?}",
+            &shas[1..],
+            missing
+        ));
+    }
+    Ok(())
+}
+
+async fn parcel_round_trip(client: &Client, ctx: &Context) -> CheckOutcome {
+    let (inv, data) = ctx.scaffold("round-trip", 1);
+    create_all(client, &inv, &data).await?;
+    let fetched = client
+        .get_parcel(&inv.bindle.id, &parcel_shas(&inv)[0])
+        .await
+        .map_err(|e| format!("unable to get parcel: {}", e))?;
+    if fetched != data[0] {
+        return Err("fetched parcel data does not match the uploaded data".to_owned());
+    }
+    Ok(())
+}
+
+async fn duplicate_parcel(client: &Client, ctx: &Context) -> CheckOutcome {
+    let (inv, data) = ctx.scaffold("duplicate-parcel", 1);
+    create_all(client, &inv, &data).await?;
+    match client
+        .create_parcel(&inv.bindle.id, &parcel_shas(&inv)[0], data[0].clone())
+        .await
+    {
+        Err(ClientError::ParcelAlreadyExists) => Ok(()),
+        res => Err(format!("expected a conflict, got {:?}", res)),
+    }
+}
+
+async fn mismatched_parcel(client: &Client, ctx: &Context) -> CheckOutcome {
+    let (inv, _) = ctx.scaffold("mismatched-parcel", 1);
+    client
+        .create_invoice(inv.clone())
+        .await
+        .map_err(|e| format!("unable to create invoice: {}", e))?;
+    match client
+        .create_parcel(
+            &inv.bindle.id,
+            &parcel_shas(&inv)[0],
+            b"data that does not match the label".to_vec(),
+        )
+        .await
+    {
+        Err(ClientError::InvalidRequest { .. }) => Ok(()),
+        res => Err(format!("expected an invalid request, got {:?}", res)),
+    }
+}
+
+async fn missing_parcel(client: &Client, ctx: &Context) -> CheckOutcome {
+    let (inv, _) = ctx.scaffold("missing-parcel", 1);
+    client
+        .create_invoice(inv.clone())
+        .await
+        .map_err(|e| format!("unable to create invoice: {}", e))?;
+    match client
+        .get_parcel(&inv.bindle.id, &parcel_shas(&inv)[0])
+        .await
+    {
+        Err(ClientError::ParcelNotFound) => Ok(()),
+        res => Err(format!(
+            "expected parcel not found, got {:?}",
+            res.map(|d| d.len())
+        )),
+    }
+}
+
+async fn yank_invoice(client: &Client, ctx: &Context) -> CheckOutcome {
+    let (inv, data) = ctx.scaffold("yank", 1);
+    create_all(client, &inv, &data).await?;
+    client
+        .yank_invoice(&inv.bindle.id)
+        .await
+        .map_err(|e| format!("unable to yank invoice: {}", e))?;
+    match client.get_invoice(&inv.bindle.id).await {
+        Err(ClientError::InvoiceNotFound) => (),
+        res => {
+            return Err(format!(
+                "expected yanked invoice to be not found, got {:?}",
+                res.map(|i| i.bindle.id)
+            ))
+        }
+    }
+    let yanked = client
+        .get_yanked_invoice(&inv.bindle.id)
+        .await
+        .map_err(|e| format!("unable to get yanked invoice: {}", e))?;
+    if !yanked.yanked.unwrap_or_default() {
+        return Err("invoice fetched after yanking is not marked as yanked".to_owned());
+    }
+    Ok(())
+}
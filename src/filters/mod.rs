@@ -116,6 +116,15 @@ struct FeatureReference {
     value: String,
 }
 
+/// The parcels resolved by a [`BindleFilter`], split by when they should be fetched
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedParcels {
+    /// The parcels that should be fetched when the bindle is installed
+    pub parcels: Vec<Parcel>,
+    /// The `lazy` parcels, which should only be fetched on demand after the bindle is installed
+    pub deferred: Vec<Parcel>,
+}
+
 /// BindleFilter walks an invoice and resolves a list of parcels.
 ///
 /// A bindle may define many parcels, some of which are to be included by default, and
@@ -239,6 +248,13 @@ impl<'a> BindleFilter<'a> {
         }
     }
 
+    /// Same as [`filter`](BindleFilter::filter), but splits the `lazy` parcels out into a separate
+    /// set of deferred parcels that can be fetched later
+    pub fn resolve(&self) -> ResolvedParcels {
+        let (deferred, parcels) = self.filter().into_iter().partition(|p| p.is_lazy());
+        ResolvedParcels { parcels, deferred }
+    }
+
     // Do we filter media types, too?
     // Do we filter by size?
     pub fn filter(&self) -> Vec<Parcel> {
@@ -883,4 +899,65 @@ mod test {
             assert_eq!(1, filter.len());
         }
     }
+
+    #[test]
+    fn test_resolve_lazy() {
+        let inv: crate::Invoice = toml::from_str(
+            r#"
+        bindleVersion = "1.0.0"
+
+        [bindle]
+        name = "test/lazy"
+        version = "0.1.0"
+
+        [[group]]
+        name = "extras"
+
+        [[parcel]]
+        [parcel.label]
+        name = "app.wasm"
+        sha256 = "12345"
+        mediaType = "application/wasm"
+        size = 123
+
+        [[parcel]]
+        [parcel.label]
+        name = "models.bin"
+        sha256 = "4321"
+        mediaType = "application/octet-stream"
+        size = 4000000000
+        [parcel.conditions]
+        lazy = true
+
+        [[parcel]]
+        [parcel.label]
+        name = "extra-models.bin"
+        sha256 = "5432"
+        mediaType = "application/octet-stream"
+        size = 4000000000
+        [parcel.conditions]
+        memberOf = ["extras"]
+        lazy = true
+        "#,
+        )
+        .expect("test invoice parsed");
+
+        let resolved = BindleFilter::new(&inv).resolve();
+        let names = |parcels: &[Parcel]| -> Vec<String> {
+            // The filter does not guarantee any order
+            let mut names: Vec<String> = parcels.iter().map(|p| p.label.name.clone()).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(vec!["app.wasm"], names(&resolved.parcels));
+        // Lazy parcels are still only deferred if their group is enabled
+        assert_eq!(vec!["models.bin"], names(&resolved.deferred));
+
+        let resolved = BindleFilter::new(&inv).with_group("extras").resolve();
+        assert_eq!(vec!["app.wasm"], names(&resolved.parcels));
+        assert_eq!(
+            vec!["extra-models.bin", "models.bin"],
+            names(&resolved.deferred)
+        );
+    }
 }
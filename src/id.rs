@@ -20,7 +20,8 @@ type Result<T> = std::result::Result<T, ParseError>;
 const PATH_SEPARATOR: char = '/';
 
 /// A parsed representation of an ID string for a bindle. This is currently defined as an arbitrary
-/// path with a version string at the end.
+/// path with a version string at the end. Everything before the last `/` is the name, which can
+/// have any number of `/` separated segments.
 ///
 /// Examples of valid ID strings include:
 ///
@@ -45,9 +46,12 @@ const PATH_SEPARATOR: char = '/';
 ///     .try_into().expect("should parse");
 /// println!("{}", id);
 /// ```
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(
+    Clone, Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema, Hash, PartialEq, Eq,
+)]
 pub struct Id {
     name: String,
+    #[schemars(schema_with = "crate::invoice::schema::semver")]
     version: semver::Version,
 }
 
@@ -57,6 +61,16 @@ impl Id {
         &self.name
     }
 
+    /// Returns the namespace of the ID, which is everything in the name before the last `/`. For
+    /// example, the namespace of `example.com/a/foo/1.0.0` is `example.com/a`. Names without a `/`
+    /// are in the empty namespace
+    pub fn namespace(&self) -> &str {
+        self.name
+            .rsplit_once('/')
+            .map(|(ns, _)| ns)
+            .unwrap_or_default()
+    }
+
     // Returns the [`Version`](semver::Version) part of this ID
     pub fn version(&self) -> &semver::Version {
         &self.version
@@ -178,4 +192,30 @@ mod test {
             "Missing name should fail parsing"
         );
     }
+
+    #[test]
+    fn test_id_name_depth() {
+        let id = Id::from_str("foo/1.0.0").unwrap();
+        assert_eq!("foo", id.name());
+        assert_eq!("1.0.0", id.version_string());
+        assert_eq!("foo/1.0.0", id.to_string());
+
+        let id = Id::from_str("example.com/team/project/component/1.0.0-rc.1+build.5").unwrap();
+        assert_eq!("example.com/team/project/component", id.name());
+        assert_eq!("example.com/team/project", id.namespace());
+        assert_eq!("1.0.0-rc.1+build.5", id.version_string());
+        assert_eq!(
+            "example.com/team/project/component/1.0.0-rc.1+build.5",
+            id.to_string()
+        );
+        assert_eq!(id, Id::from_str(&id.to_string()).unwrap());
+    }
+
+    #[test]
+    fn test_id_namespace() {
+        let id = Id::from_str("example.com/a/foo/1.0.0").unwrap();
+        assert_eq!("example.com/a", id.namespace());
+        let id = Id::from_str("foo/1.0.0").unwrap();
+        assert_eq!("", id.namespace());
+    }
 }
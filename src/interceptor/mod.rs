@@ -0,0 +1,145 @@
+//! Types and traits for transforming or rejecting invoices before they are stored. This module is
+//! only available if the `server` feature is enabled
+//!
+//! Interceptors are the extension point for custom registry behavior, such as injecting
+//! annotations or enforcing naming policy, without patching the core handlers
+
+pub mod noop;
+pub mod unique_names;
+
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::Invoice;
+
+/// The reason an [`InvoiceInterceptor`](InvoiceInterceptor) rejected an invoice. This is returned
+/// to the client as the error message
+#[derive(Error, Debug)]
+pub enum RejectReason {
+    /// The invoice is not allowed by the configured policy. This is returned to the client as a
+    /// 403 Forbidden
+    #[error("Invoice rejected by policy: {0}")]
+    Forbidden(String),
+    /// The invoice is not valid according to the interceptor. This is returned to the client as a
+    /// 400 Bad Request
+    #[error("Invoice rejected as invalid: {0}")]
+    Invalid(String),
+}
+
+/// A trait for any system that needs to inspect, modify, or reject an invoice before it is stored
+pub trait InvoiceInterceptor {
+    /// Called with a newly uploaded invoice before it is verified, signed, and stored. The returned
+    /// invoice is the one that will be stored, so implementors are free to modify it. Any changes
+    /// are made before verification, so modifying signed fields (such as the bindle ID or parcel
+    /// list) will cause the creator's signature to fail verification
+    fn before_store(&self, invoice: Invoice) -> Result<Invoice, RejectReason>;
+}
+
+/// An interceptor that runs each of its interceptors in the order they were added, passing the
+/// output of one into the next. The first rejection stops the chain
+#[derive(Clone, Default)]
+pub struct InterceptorChain {
+    interceptors: Vec<Arc<dyn InvoiceInterceptor + Send + Sync>>,
+}
+
+impl InterceptorChain {
+    /// Returns a new, empty chain. An empty chain returns all invoices unmodified
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the given interceptor at the end of the chain
+    pub fn with<T>(mut self, interceptor: T) -> Self
+    where
+        T: InvoiceInterceptor + Send + Sync + 'static,
+    {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+}
+
+impl InvoiceInterceptor for InterceptorChain {
+    fn before_store(&self, invoice: Invoice) -> Result<Invoice, RejectReason> {
+        self.interceptors
+            .iter()
+            .try_fold(invoice, |inv, interceptor| interceptor.before_store(inv))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Annotate(&'static str);
+
+    impl InvoiceInterceptor for Annotate {
+        fn before_store(&self, mut invoice: Invoice) -> Result<Invoice, RejectReason> {
+            invoice
+                .annotations
+                .get_or_insert_with(Default::default)
+                .insert(self.0.to_owned(), "true".to_owned());
+            Ok(invoice)
+        }
+    }
+
+    struct RejectAnnotated(&'static str);
+
+    impl InvoiceInterceptor for RejectAnnotated {
+        fn before_store(&self, invoice: Invoice) -> Result<Invoice, RejectReason> {
+            match invoice.annotations.as_ref() {
+                Some(a) if a.contains_key(self.0) => Err(RejectReason::Forbidden(format!(
+                    "{} is not allowed",
+                    self.0
+                ))),
+                _ => Ok(invoice),
+            }
+        }
+    }
+
+    fn invoice() -> Invoice {
+        Invoice::new(crate::BindleSpec {
+            id: "enterprise.com/warpcore/1.0.0".parse().unwrap(),
+            description: None,
+            authors: None,
+        })
+    }
+
+    #[test]
+    fn test_chain_runs_in_order() {
+        let chain = InterceptorChain::new()
+            .with(Annotate("first"))
+            .with(Annotate("second"));
+        let inv = chain
+            .before_store(invoice())
+            .expect("invoice should not be rejected");
+        let annotations = inv.annotations.expect("annotations should be set");
+        assert!(annotations.contains_key("first"));
+        assert!(annotations.contains_key("second"));
+
+        // An interceptor should see the changes of the ones before it
+        let chain = InterceptorChain::new()
+            .with(Annotate("first"))
+            .with(RejectAnnotated("first"));
+        assert!(matches!(
+            chain.before_store(invoice()),
+            Err(RejectReason::Forbidden(_))
+        ));
+
+        // But not the changes of the ones after it
+        let chain = InterceptorChain::new()
+            .with(RejectAnnotated("first"))
+            .with(Annotate("first"));
+        chain
+            .before_store(invoice())
+            .expect("invoice should not be rejected");
+    }
+
+    #[test]
+    fn test_empty_chain_is_noop() {
+        let inv = InterceptorChain::new()
+            .before_store(invoice())
+            .expect("invoice should not be rejected");
+        assert!(inv.annotations.is_none());
+    }
+}
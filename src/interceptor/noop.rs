@@ -0,0 +1,13 @@
+//! A simple interceptor that does nothing for use when no invoice transformation is desired
+use super::{InvoiceInterceptor, RejectReason};
+use crate::Invoice;
+
+/// An interceptor that returns every invoice unmodified
+#[derive(Debug, Clone, Default)]
+pub struct NoopInterceptor;
+
+impl InvoiceInterceptor for NoopInterceptor {
+    fn before_store(&self, invoice: Invoice) -> Result<Invoice, RejectReason> {
+        Ok(invoice)
+    }
+}
@@ -0,0 +1,59 @@
+//! An interceptor that rejects invoices with duplicate parcel names
+use super::{InvoiceInterceptor, RejectReason};
+use crate::Invoice;
+
+/// An interceptor that rejects any invoice where more than one parcel has the same label name.
+///
+/// Duplicate names are valid in a bindle, so this is opt-in. It is useful for registries whose
+/// consumers map parcel names to paths, such as when building an overlay manifest
+#[derive(Debug, Clone, Default)]
+pub struct UniqueParcelNames;
+
+impl InvoiceInterceptor for UniqueParcelNames {
+    fn before_store(&self, invoice: Invoice) -> Result<Invoice, RejectReason> {
+        let duplicates = invoice.duplicate_parcel_names();
+        if duplicates.is_empty() {
+            return Ok(invoice);
+        }
+        Err(RejectReason::Invalid(format!(
+            "parcel names must be unique, found duplicates: {}",
+            duplicates.join(", ")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Label, Parcel};
+
+    fn parcel(name: &str, sha: &str) -> Parcel {
+        Parcel {
+            label: Label::new(name.to_owned(), sha.to_owned()),
+            conditions: None,
+        }
+    }
+
+    #[test]
+    fn test_unique_parcel_names() {
+        let mut invoice = Invoice::new(crate::BindleSpec {
+            id: "enterprise.com/warpcore/1.0.0".parse().unwrap(),
+            description: None,
+            authors: None,
+        });
+        invoice.parcel = Some(vec![parcel("foo", "abc123"), parcel("bar", "def456")]);
+        let mut invoice = UniqueParcelNames
+            .before_store(invoice)
+            .expect("unique names should not be rejected");
+
+        invoice
+            .parcel
+            .as_mut()
+            .unwrap()
+            .push(parcel("foo", "fff999"));
+        match UniqueParcelNames.before_store(invoice) {
+            Err(RejectReason::Invalid(msg)) => assert!(msg.contains("foo"), "{}", msg),
+            _ => panic!("duplicate names should be rejected as invalid"),
+        }
+    }
+}
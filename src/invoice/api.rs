@@ -1,10 +1,13 @@
 //! Contains various type definitions for API request and response types that leverage the Bindle
 //! objects
 
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
 use serde::{Deserialize, Serialize};
 
 use crate::invoice::{Invoice, Label};
-use crate::search::SearchOptions;
+use crate::search::{QueryError, SearchOptions};
 
 /// A custom type for responding to invoice creation requests. Because invoices can be created
 /// before parcels are uploaded, this allows the API to inform the user if there are missing parcels
@@ -12,10 +15,36 @@ use crate::search::SearchOptions;
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct InvoiceCreateResponse {
+    /// Whether every parcel in the bindle has already been uploaded. Servers that predate this
+    /// field do not send it, so clients should rely on the status code if it is not present.
+    ///
+    /// NOTE: This must come before any of the table fields so it can be serialized to TOML
+    #[serde(default)]
+    pub complete: bool,
     pub invoice: Invoice,
+    /// The parcels that still need to be uploaded. This is only set if the bindle is incomplete
     pub missing: Option<Vec<Label>>,
 }
 
+impl InvoiceCreateResponse {
+    /// Creates a response for the given invoice and the parcels it is missing, marking it as
+    /// complete if nothing is missing
+    pub fn new(invoice: Invoice, missing: Vec<Label>) -> Self {
+        let complete = missing.is_empty();
+        InvoiceCreateResponse {
+            complete,
+            invoice,
+            missing: (!complete).then_some(missing),
+        }
+    }
+
+    /// Returns the parcels that still need to be uploaded, which is empty if the bindle is
+    /// complete
+    pub fn missing_parcels(&self) -> &[Label] {
+        self.missing.as_deref().unwrap_or_default()
+    }
+}
+
 /// A response to a missing parcels request. TOML doesn't support top level arrays, so they
 /// must be embedded in a table
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,6 +53,119 @@ pub struct MissingParcelsResponse {
     pub missing: Vec<Label>,
 }
 
+/// A response to a parcel SHA prefix lookup, containing the full SHA of every stored parcel that
+/// starts with the requested prefix
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct ParcelPrefixResponse {
+    pub matches: Vec<String>,
+}
+
+/// An aggregate view of the health of a server, intended for monitoring. Servers respond with
+/// this on the health endpoint with a 200 status if the storage is ready and a 503 otherwise
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct Health {
+    /// Whether the server is running and able to respond to requests
+    pub alive: bool,
+    /// Whether the storage backing the server is ready to serve requests
+    pub storage_ready: bool,
+    /// The version of the Bindle spec the server implements
+    pub spec_version: String,
+    /// The version of the server implementation
+    pub impl_version: String,
+    /// The number of seconds since the server started
+    pub uptime_seconds: u64,
+}
+
+impl Health {
+    /// Returns true if the server is alive and its storage is ready
+    pub fn is_healthy(&self) -> bool {
+        self.alive && self.storage_ready
+    }
+}
+
+/// The storage used by each namespace on a server, intended for chargeback and capacity planning.
+/// See [`UsageStats`](crate::UsageStats) for how parcels shared between namespaces are counted
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct UsageResponse {
+    /// The usage of each namespace, sorted by namespace
+    // This is a list rather than a map because bindles without a namespace use the empty string
+    // as their key, which can't be serialized as a TOML table name
+    pub namespaces: Vec<NamespaceUsage>,
+}
+
+/// The usage of a single namespace in a [`UsageResponse`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct NamespaceUsage {
+    pub namespace: String,
+    pub usage: crate::UsageStats,
+}
+
+impl UsageResponse {
+    /// Builds a response from the usage of each namespace, keyed by namespace
+    pub fn new(usage: HashMap<String, crate::UsageStats>) -> Self {
+        let mut namespaces: Vec<NamespaceUsage> = usage
+            .into_iter()
+            .map(|(namespace, usage)| NamespaceUsage { namespace, usage })
+            .collect();
+        namespaces.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+        UsageResponse { namespaces }
+    }
+
+    /// Returns the usage of each namespace, keyed by namespace
+    pub fn into_map(self) -> HashMap<String, crate::UsageStats> {
+        self.namespaces
+            .into_iter()
+            .map(|n| (n.namespace, n.usage))
+            .collect()
+    }
+}
+
+/// A response to a batch parcel upload, with the result of every parcel in the request. Each
+/// parcel is stored independently, so some parcels may have been created even if others failed
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct BatchParcelResponse {
+    #[serde(default)]
+    pub parcel: Vec<BatchParcelResult>,
+}
+
+impl BatchParcelResponse {
+    /// Returns the results of the parcels that could not be stored
+    pub fn failed(&self) -> impl Iterator<Item = &BatchParcelResult> {
+        self.parcel
+            .iter()
+            .filter(|p| p.status == BatchParcelStatus::Failed)
+    }
+}
+
+/// The result of storing a single parcel of a [`BatchParcelResponse`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct BatchParcelResult {
+    pub sha256: String,
+    pub status: BatchParcelStatus,
+    /// Why the parcel could not be stored. Only set if the status is `failed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Whether a parcel in a batch upload was stored
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum BatchParcelStatus {
+    /// The parcel was stored
+    Created,
+    /// The parcel was already stored, so the data was discarded
+    AlreadyExists,
+    /// The parcel could not be stored, such as when it is not part of the bindle or the data does
+    /// not match its SHA
+    Failed,
+}
+
 /// A string error message returned from the server
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
@@ -43,17 +185,33 @@ pub struct QueryOptions {
     #[serde(alias = "l")]
     pub limit: Option<u8>,
     pub strict: Option<bool>,
+    /// Whether to include yanked invoices in the results. Each returned invoice has its `yanked`
+    /// field set if it is yanked
+    #[serde(alias = "include_yanked")]
     pub yanked: Option<bool>,
+    /// Only return invoices with this annotation, given as `KEY` to match any value or
+    /// `KEY=VALUE` to match an exact value
+    pub annotation: Option<String>,
+    /// The order to return invoices in: `name` (the default), `version`, or `annotation:KEY`.
+    /// Prefix with `-` to sort in descending order
+    pub sort: Option<String>,
 }
 
-impl From<QueryOptions> for SearchOptions {
-    fn from(qo: QueryOptions) -> Self {
+impl TryFrom<QueryOptions> for SearchOptions {
+    type Error = QueryError;
+
+    fn try_from(qo: QueryOptions) -> Result<Self, Self::Error> {
         let defaults = SearchOptions::default();
-        SearchOptions {
+        Ok(SearchOptions {
             limit: qo.limit.unwrap_or(defaults.limit),
             offset: qo.offset.unwrap_or(defaults.offset),
             strict: qo.strict.unwrap_or(defaults.strict),
             yanked: qo.yanked.unwrap_or(defaults.yanked),
-        }
+            annotation: qo.annotation.as_deref().map(str::parse).transpose()?,
+            sort: match qo.sort.as_deref() {
+                Some(s) => s.parse()?,
+                None => defaults.sort,
+            },
+        })
     }
 }
@@ -1,12 +1,13 @@
 //! The specification for a bindle
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::id::Id;
 
 /// The specification for a bindle, that uniquely identifies the Bindle and provides additional
 /// optional metadata
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct BindleSpec {
     #[serde(flatten)]
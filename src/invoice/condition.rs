@@ -1,11 +1,15 @@
 //! Definition of the `Condition` type
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// Conditions associate parcels to [`Group`](crate::Group)s
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Eq, PartialEq, Hash)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct Condition {
     pub member_of: Option<Vec<String>>,
     pub requires: Option<Vec<String>>,
+    /// If true, the parcel is an optional resource that should not be fetched when the bindle is
+    /// installed, but on demand afterwards
+    pub lazy: Option<bool>,
 }
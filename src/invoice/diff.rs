@@ -0,0 +1,266 @@
+//! Definition of the `ResolveDiff` type, the change in resolved parcels between two feature
+//! selections
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::filters::BindleFilter;
+use crate::invoice::{
+    Group, Invoice, Label, Parcel, SatisfiedBy, PLATFORM_FEATURE_GROUP, PLATFORM_FEATURE_NAME,
+};
+
+/// The parcels that are added and removed when switching from one set of features to another
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolveDiff {
+    /// The labels of parcels that are only resolved with the new features, sorted by name
+    pub added: Vec<Label>,
+    /// The labels of parcels that are only resolved with the old features, sorted by name
+    pub removed: Vec<Label>,
+}
+
+impl ResolveDiff {
+    /// Returns true if switching features does not change the resolved parcels
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    pub(crate) fn between(
+        invoice: &Invoice,
+        from_features: &[String],
+        to_features: &[String],
+    ) -> Result<Self, ResolveError> {
+        let from = resolve(invoice, from_features)?;
+        let to = resolve(invoice, to_features)?;
+
+        let mut added: Vec<Label> = to.difference(&from).cloned().collect();
+        let mut removed: Vec<Label> = from.difference(&to).cloned().collect();
+        added.sort_by(|a, b| a.name.cmp(&b.name));
+        removed.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(ResolveDiff { added, removed })
+    }
+}
+
+/// The reasons a set of features cannot be resolved against an invoice
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ResolveError {
+    /// The feature is not of the form `group.name=value`
+    #[error("feature `{0}` must be of the form group.name=value")]
+    InvalidFeature(String),
+    /// The same feature was given more than one value
+    #[error("feature {group}.{name} cannot be set to more than one value")]
+    ConflictingFeature { group: String, name: String },
+    /// A resolved parcel requires a group, but the features disable the parcels that the group
+    /// needs to be satisfied
+    #[error("parcel {parcel} requires group {group}, but the parcels matching the features do not satisfy it ({satisfied_by:?})")]
+    Unsatisfiable {
+        parcel: String,
+        group: String,
+        satisfied_by: SatisfiedBy,
+    },
+    /// A group is marked as required, but the features disable the parcels that the group needs to
+    /// be satisfied
+    #[error("group {group} is required, but the parcels matching the features do not satisfy it ({satisfied_by:?})")]
+    RequiredGroupUnsatisfiable {
+        group: String,
+        satisfied_by: SatisfiedBy,
+    },
+    /// The features select a platform that the bindle does not support
+    #[error("platform {platform} is not supported, supported platforms are: [{}]", .supported.join(", "))]
+    IncompatiblePlatform {
+        platform: String,
+        supported: Vec<String>,
+    },
+}
+
+/// Resolves the labels of all parcels that apply with the given features activated, making sure
+/// every requirement of a resolved parcel is met
+pub(crate) fn resolve(
+    invoice: &Invoice,
+    features: &[String],
+) -> Result<HashSet<Label>, ResolveError> {
+    ResolveIter::new(invoice, features).collect()
+}
+
+/// Resolves parcels one at a time. The parcels are selected on the first call to `next`, which
+/// fails straight away if the features are invalid or a required group is unsatisfiable. After
+/// that, each selected parcel is yielded in invoice order once its own requirements are checked,
+/// and iteration ends after the first parcel whose requirements are not met
+pub(crate) struct ResolveIter<'a> {
+    invoice: &'a Invoice,
+    features: &'a [String],
+    state: ResolveState<'a>,
+}
+
+enum ResolveState<'a> {
+    Pending,
+    Selected(Selection<'a>),
+    Done,
+}
+
+impl<'a> ResolveIter<'a> {
+    pub(crate) fn new(invoice: &'a Invoice, features: &'a [String]) -> Self {
+        ResolveIter {
+            invoice,
+            features,
+            state: ResolveState::Pending,
+        }
+    }
+}
+
+impl<'a> Iterator for ResolveIter<'a> {
+    type Item = Result<Label, ResolveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let ResolveState::Pending = self.state {
+            match Selection::new(self.invoice, self.features) {
+                Ok(selection) => self.state = ResolveState::Selected(selection),
+                Err(e) => {
+                    self.state = ResolveState::Done;
+                    return Some(Err(e));
+                }
+            }
+        }
+        let selection = match &mut self.state {
+            ResolveState::Selected(s) => s,
+            _ => return None,
+        };
+        let parcel = match selection.parcels.get(selection.next) {
+            Some(p) => p,
+            None => {
+                self.state = ResolveState::Done;
+                return None;
+            }
+        };
+        selection.next += 1;
+        match selection.check_requires(parcel) {
+            Ok(()) => Some(Ok(parcel.label.clone())),
+            Err(e) => {
+                self.state = ResolveState::Done;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// The parcels selected by the filter for a set of features, in invoice order
+struct Selection<'a> {
+    invoice: &'a Invoice,
+    parcels: Vec<Parcel>,
+    next: usize,
+}
+
+impl<'a> Selection<'a> {
+    fn new(invoice: &'a Invoice, features: &[String]) -> Result<Self, ResolveError> {
+        let mut parsed: BTreeMap<(&str, &str), &str> = BTreeMap::new();
+        for feature in features {
+            let (group, name, value) = parse_feature(feature)?;
+            match parsed.insert((group, name), value) {
+                Some(existing) if existing != value => {
+                    return Err(ResolveError::ConflictingFeature {
+                        group: group.to_owned(),
+                        name: name.to_owned(),
+                    })
+                }
+                _ => {}
+            }
+        }
+
+        // Check the platform before anything else, as none of the parcels will be usable on it
+        if let Some(platform) = parsed.get(&(PLATFORM_FEATURE_GROUP, PLATFORM_FEATURE_NAME)) {
+            if !invoice.is_compatible(platform) {
+                return Err(ResolveError::IncompatiblePlatform {
+                    platform: (*platform).to_owned(),
+                    supported: invoice.supported_platforms(),
+                });
+            }
+        }
+
+        let mut filter = BindleFilter::new(invoice);
+        for ((group, name), value) in parsed.iter() {
+            filter.activate_feature(group, name, value);
+        }
+        // The filter doesn't return parcels in any particular order, so put them back in the order
+        // of the invoice to make resolution (and which error is returned) deterministic
+        let mut filtered: HashSet<Parcel> = filter.filter().into_iter().collect();
+        let parcels = invoice
+            .parcel
+            .iter()
+            .flatten()
+            .filter(|p| filtered.remove(p))
+            .cloned()
+            .collect();
+        let selection = Selection {
+            invoice,
+            parcels,
+            next: 0,
+        };
+
+        // The filter silently drops a group if features disable its members, so we check that every
+        // group that must be processed is still satisfied. The groups marked as required are
+        // checked here, and the groups required by a parcel are checked when it is reached
+        for group in invoice.group.iter().flatten().filter(|g| g.is_required()) {
+            if !selection.is_satisfied(group) {
+                return Err(ResolveError::RequiredGroupUnsatisfiable {
+                    group: group.name.clone(),
+                    satisfied_by: group.satisfied_by(),
+                });
+            }
+        }
+        Ok(selection)
+    }
+
+    fn is_satisfied(&self, group: &Group) -> bool {
+        let selected = self
+            .parcels
+            .iter()
+            .filter(|p| p.member_of(&group.name))
+            .count();
+        let members = self
+            .invoice
+            .parcel
+            .iter()
+            .flatten()
+            .filter(|p| p.member_of(&group.name))
+            .count();
+        group.is_satisfied(selected, members)
+    }
+
+    /// Checks that every group the parcel requires is satisfied by the selected parcels
+    fn check_requires(&self, parcel: &Parcel) -> Result<(), ResolveError> {
+        for name in parcel
+            .conditions
+            .iter()
+            .filter_map(|c| c.requires.as_ref())
+            .flatten()
+        {
+            // Groups that are required but not defined in the invoice get the default semantics
+            let group = self.invoice.group(name).cloned().unwrap_or_else(|| Group {
+                name: name.to_owned(),
+                required: None,
+                satisfied_by: None,
+            });
+            if !self.is_satisfied(&group) {
+                return Err(ResolveError::Unsatisfiable {
+                    parcel: parcel.label.name.clone(),
+                    group: name.clone(),
+                    satisfied_by: group.satisfied_by(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Splits a feature of the form `group.name=value` into its parts
+fn parse_feature(feature: &str) -> Result<(&str, &str, &str), ResolveError> {
+    let invalid = || ResolveError::InvalidFeature(feature.to_owned());
+    let (key, value) = feature.split_once('=').ok_or_else(invalid)?;
+    let (group, name) = key.split_once('.').ok_or_else(invalid)?;
+    if group.is_empty() || name.is_empty() {
+        return Err(invalid());
+    }
+    Ok((group, name, value))
+}
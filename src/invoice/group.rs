@@ -1,13 +1,84 @@
 //! Definition of the `Group` type
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// A group is a top-level organization object that may contain zero or more parcels. Every parcel
 /// belongs to at least one group, but may belong to others.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct Group {
     pub name: String,
     pub required: Option<bool>,
-    pub satisfied_by: Option<String>,
+    pub satisfied_by: Option<SatisfiedBy>,
+}
+
+/// The criterion by which a group that must be processed is considered satisfied, based on which of
+/// its member parcels are selected
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum SatisfiedBy {
+    /// All of the parcels in the group are needed. A group that doesn't need to be processed can
+    /// still be left out entirely, so this is all-or-nothing. This is the default
+    #[default]
+    AllOf,
+    /// At least one of the parcels in the group is needed
+    OneOf,
+    /// The group is satisfied no matter which of its parcels are selected, including none of them
+    #[serde(alias = "anyOf")]
+    Optional,
+}
+
+impl Group {
+    /// Returns true if the group must be processed even if no parcel requires it
+    pub fn is_required(&self) -> bool {
+        self.required.unwrap_or(false)
+    }
+
+    /// Returns the criterion for satisfying this group, which defaults to [`SatisfiedBy::AllOf`]
+    pub fn satisfied_by(&self) -> SatisfiedBy {
+        self.satisfied_by.unwrap_or_default()
+    }
+
+    /// Returns true if selecting `selected` of the group's `members` parcels satisfies the group
+    pub fn is_satisfied(&self, selected: usize, members: usize) -> bool {
+        match self.satisfied_by() {
+            SatisfiedBy::AllOf => selected >= members,
+            SatisfiedBy::OneOf => selected > 0 || members == 0,
+            SatisfiedBy::Optional => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_satisfied() {
+        let group = |satisfied_by: Option<&str>| -> Group {
+            let mut toml = "name = \"test\"\n".to_owned();
+            if let Some(s) = satisfied_by {
+                toml.push_str(&format!("satisfiedBy = \"{}\"\n", s));
+            }
+            toml::from_str(&toml).expect("group should parse")
+        };
+
+        let all = group(None);
+        assert_eq!(SatisfiedBy::AllOf, all.satisfied_by());
+        assert!(all.is_satisfied(2, 2));
+        assert!(!all.is_satisfied(1, 2));
+        assert!(all.is_satisfied(0, 0));
+
+        let one = group(Some("oneOf"));
+        assert!(one.is_satisfied(1, 2));
+        assert!(!one.is_satisfied(0, 2));
+
+        for optional in [group(Some("optional")), group(Some("anyOf"))] {
+            assert_eq!(SatisfiedBy::Optional, optional.satisfied_by());
+            assert!(optional.is_satisfied(0, 2));
+        }
+
+        assert!(toml::from_str::<Group>("name = \"test\"\nsatisfiedBy = \"someOf\"").is_err());
+    }
 }
@@ -3,14 +3,29 @@
 //! See the [Label Spec](https://github.com/deislabs/bindle/blob/master/docs/label-spec.md) for more
 //! detailed information
 
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::invoice::{AnnotationMap, FeatureMap};
+use crate::invoice::{AnnotationMap, FeatureMap, Signature, SignatureRole};
+
+/// The number of hex characters kept by [`short_sha`]
+pub const SHORT_SHA_LEN: usize = 12;
+
+/// Returns the first [`SHORT_SHA_LEN`] characters of a SHA for logs and human readable output.
+/// Strings that are already shorter are returned as is. The short form is only for display and
+/// must never be used to look up, store or verify a parcel
+pub fn short_sha(sha: &str) -> &str {
+    sha.get(..SHORT_SHA_LEN).unwrap_or(sha)
+}
 
-/// Metadata of a stored parcel
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+/// Metadata of a stored parcel. The `Display` implementation shows the name and a
+/// [short SHA](short_sha), while `Debug` keeps the full SHA
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct Label {
+    #[schemars(schema_with = "super::schema::sha256")]
     pub sha256: String,
     pub media_type: String,
     pub name: String,
@@ -18,6 +33,10 @@ pub struct Label {
     pub annotations: Option<AnnotationMap>,
     pub feature: Option<FeatureMap>,
     pub origin: Option<String>,
+    /// Signatures of this parcel's SHA, made independently of any invoice signature. This allows
+    /// the author of a parcel to be different from the author of the invoice that contains it
+    #[serde(rename = "signature")]
+    pub signatures: Option<Vec<Signature>>,
 }
 
 impl Label {
@@ -28,6 +47,27 @@ impl Label {
             ..Label::default()
         }
     }
+
+    /// Returns the SPDX license expression that applies to this parcel, as declared in the
+    /// [`LICENSE_ANNOTATION`](crate::LICENSE_ANNOTATION)
+    pub fn license(&self) -> Option<&str> {
+        self.annotations
+            .as_ref()
+            .and_then(|a| a.get(super::LICENSE_ANNOTATION))
+            .map(String::as_str)
+    }
+
+    /// Returns the text that is signed by a parcel signature, which is made up of the signer and
+    /// their role followed by the SHA of the parcel
+    pub(super) fn cleartext(&self, by: &str, role: &SignatureRole) -> String {
+        [by, &role.to_string(), "~", &self.sha256].join("\n")
+    }
+}
+
+impl Display for Label {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{} ({})", self.name, short_sha(&self.sha256))
+    }
 }
 
 impl Default for Label {
@@ -40,6 +80,25 @@ impl Default for Label {
             annotations: None,
             feature: None,
             origin: None,
+            signatures: None,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_short_sha() {
+        let sha = "e1706ab0a39ac88094b6d54a3f5cdba41fe5a901c04e8d4e0e0d2a0e3b1a6a09";
+        assert_eq!("e1706ab0a39a", short_sha(sha));
+        assert_eq!("abc", short_sha("abc"));
+        // Non-ASCII input shouldn't panic on a char boundary
+        assert_eq!("aééééééé", short_sha("aééééééé"));
+
+        let label = Label::new("foo.txt".to_owned(), sha.to_owned());
+        assert_eq!("foo.txt (e1706ab0a39a)", label.to_string());
+        assert!(format!("{:?}", label).contains(sha));
+    }
+}
@@ -0,0 +1,171 @@
+//! Helpers for the SPDX license annotations on invoices and labels
+//!
+//! See the [SPDX License List](https://spdx.org/licenses/) for the full set of identifiers
+
+/// The annotation holding the SPDX license expression that applies to a bindle (when used on an
+/// invoice) or a single parcel (when used on a label), such as `MIT` or `MIT OR Apache-2.0`. This
+/// is different from the `bindle.dev/license` label annotation, which marks a parcel as being a
+/// license document
+pub const LICENSE_ANNOTATION: &str = "bindle.dev/spdx-license";
+
+/// The SPDX license identifiers that are recognized when validating license expressions. This is
+/// not the complete SPDX list, but covers the licenses in common use. Identifiers that aren't
+/// listed here are only warned about, never rejected
+const KNOWN_LICENSES: &[&str] = &[
+    "0BSD",
+    "AFL-3.0",
+    "AGPL-3.0-only",
+    "AGPL-3.0-or-later",
+    "Apache-1.1",
+    "Apache-2.0",
+    "APSL-2.0",
+    "Artistic-2.0",
+    "BlueOak-1.0.0",
+    "BSD-1-Clause",
+    "BSD-2-Clause",
+    "BSD-2-Clause-Patent",
+    "BSD-3-Clause",
+    "BSD-3-Clause-Clear",
+    "BSD-4-Clause",
+    "BSL-1.0",
+    "BUSL-1.1",
+    "CAL-1.0",
+    "CC-BY-3.0",
+    "CC-BY-4.0",
+    "CC-BY-SA-3.0",
+    "CC-BY-SA-4.0",
+    "CC-BY-NC-4.0",
+    "CC-BY-ND-4.0",
+    "CC0-1.0",
+    "CDDL-1.0",
+    "CDDL-1.1",
+    "CECILL-2.1",
+    "CPL-1.0",
+    "ECL-2.0",
+    "EFL-2.0",
+    "EPL-1.0",
+    "EPL-2.0",
+    "EUPL-1.1",
+    "EUPL-1.2",
+    "FTL",
+    "GFDL-1.3-only",
+    "GFDL-1.3-or-later",
+    "GPL-2.0-only",
+    "GPL-2.0-or-later",
+    "GPL-3.0-only",
+    "GPL-3.0-or-later",
+    "HPND",
+    "ICU",
+    "IJG",
+    "ISC",
+    "LGPL-2.0-only",
+    "LGPL-2.0-or-later",
+    "LGPL-2.1-only",
+    "LGPL-2.1-or-later",
+    "LGPL-3.0-only",
+    "LGPL-3.0-or-later",
+    "LPPL-1.3c",
+    "MIT",
+    "MIT-0",
+    "MPL-1.1",
+    "MPL-2.0",
+    "MPL-2.0-no-copyleft-exception",
+    "MS-PL",
+    "MS-RL",
+    "MulanPSL-2.0",
+    "NCSA",
+    "ODbL-1.0",
+    "OFL-1.1",
+    "OpenSSL",
+    "OSL-3.0",
+    "PHP-3.01",
+    "PostgreSQL",
+    "PSF-2.0",
+    "Python-2.0",
+    "Ruby",
+    "SSPL-1.0",
+    "Unicode-DFS-2016",
+    "Unicode-3.0",
+    "Unlicense",
+    "UPL-1.0",
+    "Vim",
+    "W3C",
+    "WTFPL",
+    "X11",
+    "Zlib",
+    "zlib-acknowledgement",
+    "ZPL-2.1",
+];
+
+/// The SPDX license exceptions that are recognized after a `WITH` operator
+const KNOWN_EXCEPTIONS: &[&str] = &[
+    "Autoconf-exception-3.0",
+    "Bison-exception-2.2",
+    "Classpath-exception-2.0",
+    "GCC-exception-3.1",
+    "LLVM-exception",
+    "OpenJDK-assembly-exception-1.0",
+    "Qt-LGPL-exception-1.1",
+    "Swift-exception",
+];
+
+/// Returns the license identifiers and exceptions in the given SPDX license expression that are
+/// not recognized. Operators (`AND`, `OR`, `WITH`), parentheses, the `+` suffix, and custom
+/// `LicenseRef-` identifiers are always accepted. Matching is case insensitive, as in the SPDX spec
+pub fn unknown_spdx_identifiers(expression: &str) -> Vec<String> {
+    let spaced = expression.replace('(', " ( ").replace(')', " ) ");
+    let mut unknown = Vec::new();
+    let mut after_with = false;
+    for token in spaced.split_whitespace() {
+        match token.to_ascii_uppercase().as_str() {
+            "(" | ")" | "AND" | "OR" => continue,
+            "WITH" => {
+                after_with = true;
+                continue;
+            }
+            _ => {}
+        }
+        let known = if after_with {
+            contains(KNOWN_EXCEPTIONS, token)
+        } else {
+            token.starts_with("LicenseRef-")
+                || token.starts_with("DocumentRef-")
+                || contains(KNOWN_LICENSES, token.trim_end_matches('+'))
+        };
+        after_with = false;
+        if !known {
+            unknown.push(token.to_owned());
+        }
+    }
+    unknown
+}
+
+fn contains(list: &[&str], id: &str) -> bool {
+    list.iter().any(|l| l.eq_ignore_ascii_case(id))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unknown_spdx_identifiers() {
+        assert!(unknown_spdx_identifiers("MIT").is_empty());
+        assert!(unknown_spdx_identifiers("mit or apache-2.0").is_empty());
+        assert!(unknown_spdx_identifiers(
+            "(GPL-2.0-or-later WITH Classpath-exception-2.0) AND LicenseRef-Internal"
+        )
+        .is_empty());
+        assert!(unknown_spdx_identifiers("EPL-1.0+").is_empty());
+
+        assert_eq!(
+            vec!["MIT-ish".to_owned()],
+            unknown_spdx_identifiers("Apache-2.0 OR MIT-ish")
+        );
+        // Licenses aren't valid exceptions
+        assert_eq!(
+            vec!["MIT".to_owned()],
+            unknown_spdx_identifiers("GPL-3.0-only WITH MIT")
+        );
+    }
+}
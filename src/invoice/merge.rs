@@ -0,0 +1,74 @@
+//! Definition of the `MergeConflict` type and the logic for merging several invoices into one
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use thiserror::Error;
+
+use crate::invoice::{Group, Invoice, Parcel};
+
+/// The parcels and groups that could not be merged because the invoices disagree on them
+#[derive(Error, Debug, Clone, Default, PartialEq, Eq)]
+#[error("unable to merge invoices, conflicting parcels: [{}], conflicting groups: [{}]", .parcels.join(", "), .groups.join(", "))]
+pub struct MergeConflict {
+    /// The SHAs of parcels that appear in more than one invoice with different labels or
+    /// conditions, sorted
+    pub parcels: Vec<String>,
+    /// The names of groups that appear in more than one invoice with different definitions, sorted
+    pub groups: Vec<String>,
+}
+
+pub(crate) fn merge(base: &Invoice, others: &[Invoice]) -> Result<Invoice, MergeConflict> {
+    let mut parcels: BTreeMap<&str, &Parcel> = BTreeMap::new();
+    let mut parcel_order: Vec<&Parcel> = Vec::new();
+    let mut groups: BTreeMap<&str, &Group> = BTreeMap::new();
+    let mut group_order: Vec<&Group> = Vec::new();
+    let mut conflict_parcels = BTreeSet::new();
+    let mut conflict_groups = BTreeSet::new();
+
+    for inv in std::iter::once(base).chain(others.iter()) {
+        for parcel in inv.parcel.iter().flatten() {
+            match parcels.get(parcel.label.sha256.as_str()) {
+                Some(existing) if *existing != parcel => {
+                    conflict_parcels.insert(parcel.label.sha256.clone());
+                }
+                Some(_) => {}
+                None => {
+                    parcels.insert(&parcel.label.sha256, parcel);
+                    parcel_order.push(parcel);
+                }
+            }
+        }
+        for group in inv.group.iter().flatten() {
+            match groups.get(group.name.as_str()) {
+                Some(existing) if *existing != group => {
+                    conflict_groups.insert(group.name.clone());
+                }
+                Some(_) => {}
+                None => {
+                    groups.insert(&group.name, group);
+                    group_order.push(group);
+                }
+            }
+        }
+    }
+
+    if !conflict_parcels.is_empty() || !conflict_groups.is_empty() {
+        return Err(MergeConflict {
+            parcels: conflict_parcels.into_iter().collect(),
+            groups: conflict_groups.into_iter().collect(),
+        });
+    }
+
+    // Iterate in reverse so that earlier invoices overwrite the annotations of later ones
+    let mut annotations = BTreeMap::new();
+    for inv in others.iter().rev().chain(std::iter::once(base)) {
+        annotations.extend(inv.annotations.clone().unwrap_or_default());
+    }
+
+    let mut merged = Invoice::new(base.bindle.clone());
+    merged.bindle_version = base.bindle_version.clone();
+    merged.annotations = (!annotations.is_empty()).then_some(annotations);
+    merged.parcel = (!parcel_order.is_empty()).then(|| parcel_order.into_iter().cloned().collect());
+    merged.group = (!group_order.is_empty()).then(|| group_order.into_iter().cloned().collect());
+    Ok(merged)
+}
@@ -4,43 +4,94 @@
 mod api;
 mod bindle_spec;
 mod condition;
+mod diff;
 mod group;
 mod label;
+mod license;
+mod merge;
 mod parcel;
+pub(crate) mod schema;
 mod sealed;
 pub mod signature;
+mod summary;
+mod template;
+mod usage;
 pub mod verification;
 
 #[doc(inline)]
-pub use api::{ErrorResponse, InvoiceCreateResponse, MissingParcelsResponse, QueryOptions};
+pub use api::{
+    BatchParcelResponse, BatchParcelResult, BatchParcelStatus, ErrorResponse, Health,
+    InvoiceCreateResponse, MissingParcelsResponse, NamespaceUsage, ParcelPrefixResponse,
+    QueryOptions, UsageResponse,
+};
 #[doc(inline)]
 pub use bindle_spec::BindleSpec;
 #[doc(inline)]
 pub use condition::Condition;
 #[doc(inline)]
-pub use group::Group;
+pub use diff::{ResolveDiff, ResolveError};
 #[doc(inline)]
-pub use label::Label;
+pub use group::{Group, SatisfiedBy};
+#[doc(inline)]
+pub use label::{short_sha, Label, SHORT_SHA_LEN};
+#[doc(inline)]
+pub use license::{unknown_spdx_identifiers, LICENSE_ANNOTATION};
+#[doc(inline)]
+pub use merge::MergeConflict;
 #[doc(inline)]
 pub use parcel::Parcel;
 #[doc(inline)]
-pub use signature::{SecretKeyEntry, Signature, SignatureError, SignatureRole};
+pub use schema::invoice_schema;
+#[doc(inline)]
+pub use signature::{
+    SecretKeyEntry, Signature, SignatureAlgorithm, SignatureError, SignatureRole, SignatureScope,
+};
+#[doc(inline)]
+pub use summary::{InvoiceSummary, MediaTypeSummary};
+#[doc(inline)]
+pub use template::{render_invoice_template, TemplateError};
+#[doc(inline)]
+pub use usage::{usage_by_namespace, UsageStats};
 #[doc(inline)]
 pub use verification::VerificationStrategy;
 
-use ed25519_dalek::{Signature as EdSignature, Signer};
+use schemars::JsonSchema;
 use semver::{Compat, Version, VersionReq};
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use tracing::{debug, info};
 
 use std::borrow::{Borrow, BorrowMut};
 use std::collections::BTreeMap;
 use std::fmt::Debug;
-use std::time::{SystemTime, UNIX_EPOCH};
 
 use self::verification::Verified;
 use crate::BINDLE_VERSION_1;
 
+/// The invoice annotation listing the platforms a bindle supports as a comma separated list, such as
+/// `linux/amd64,windows/amd64`. A bindle without this annotation supports every platform
+pub const PLATFORMS_ANNOTATION: &str = "bindle.dev/platforms";
+
+/// The invoice annotation holding the time a host accepted the invoice, as a UNIX timestamp in
+/// seconds. It is set when an invoice is first signed with the host role, replacing any value set
+/// by the client. Only host signatures cover it, so setting it never invalidates the signatures of
+/// creators, approvers, or proxies
+pub const CREATED_ANNOTATION: &str = "bindle.dev/created";
+
+/// The invoice annotation asking crawlers not to index the bindle when it is set to `true`.
+/// Servers with a [`CrawlerPolicy`](crate::server::CrawlerPolicy) that honors it return an
+/// `X-Robots-Tag: noindex` header along with the invoice
+pub const NOINDEX_ANNOTATION: &str = "bindle.dev/noindex";
+
+/// The feature group used to mark parcels that only apply to a single platform
+pub const PLATFORM_FEATURE_GROUP: &str = "platform";
+
+/// The name of the feature in the [`PLATFORM_FEATURE_GROUP`] that holds the platform a parcel is for.
+/// For example, a parcel only for `linux/amd64` has the following feature:
+///
+/// [parcel.label.feature.platform]
+/// target = "linux/amd64"
+pub const PLATFORM_FEATURE_NAME: &str = "target";
+
 /// Alias for feature map in an Invoice's parcel
 pub type FeatureMap = BTreeMap<String, BTreeMap<String, String>>;
 
@@ -62,19 +113,30 @@ pub trait Signed: sealed::Sealed {
 ///
 /// Most fields on this struct are singular to best represent the specification. There,
 /// fields like `group` and `parcel` are singular due to the conventions of TOML.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct Invoice {
+    #[schemars(schema_with = "schema::semver")]
     pub bindle_version: String,
     pub yanked: Option<bool>,
+    #[serde(skip_serializing_if = "is_none_or_empty")]
     pub yanked_signature: Option<Vec<Signature>>,
     pub bindle: BindleSpec,
     pub annotations: Option<AnnotationMap>,
+    // Empty lists of tables are skipped, as TOML can't represent them after the `bindle` table.
+    // They deserialize back to `None`, which means the same thing
+    #[serde(skip_serializing_if = "is_none_or_empty")]
     pub parcel: Option<Vec<Parcel>>,
+    #[serde(skip_serializing_if = "is_none_or_empty")]
     pub group: Option<Vec<Group>>,
+    #[serde(skip_serializing_if = "is_none_or_empty")]
     pub signature: Option<Vec<Signature>>,
 }
 
+fn is_none_or_empty<T>(list: &Option<Vec<T>>) -> bool {
+    list.as_ref().map(Vec::is_empty).unwrap_or(true)
+}
+
 impl Invoice {
     /// Create a new Invoice with a bindle specification.
     ///
@@ -130,12 +192,12 @@ impl Invoice {
 
     /// Check whether a group by this name is present.
     pub fn has_group(&self, name: &str) -> bool {
-        let empty = Vec::with_capacity(0);
-        self.group
-            .as_ref()
-            .unwrap_or(&empty)
-            .iter()
-            .any(|g| g.name == name)
+        self.group(name).is_some()
+    }
+
+    /// Returns the group with the given name, if it is defined
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.group.iter().flatten().find(|g| g.name == name)
     }
 
     /// Get all of the parcels on the given group.
@@ -156,7 +218,235 @@ impl Invoice {
             .collect()
     }
 
-    fn cleartext(&self, by: &str, role: &SignatureRole) -> String {
+    /// Returns the label names used by more than one parcel in this invoice, sorted by name.
+    ///
+    /// Duplicate names are allowed by the spec (for example, parcels with the same name in mutually
+    /// exclusive groups), but consumers that map parcel names to paths need them to be unique
+    pub fn duplicate_parcel_names(&self) -> Vec<String> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for parcel in self.parcel.iter().flatten() {
+            *counts.entry(parcel.label.name.as_str()).or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(name, _)| name.to_owned())
+            .collect()
+    }
+
+    /// Returns the names of the groups that parcels are members of or require, but that are not
+    /// defined in the invoice, in the order they are first referenced
+    pub fn undefined_groups(&self) -> Vec<String> {
+        let mut undefined: Vec<String> = Vec::new();
+        let referenced = self
+            .parcel
+            .iter()
+            .flatten()
+            .filter_map(|p| p.conditions.as_ref())
+            .flat_map(|c| {
+                c.member_of
+                    .iter()
+                    .flatten()
+                    .chain(c.requires.iter().flatten())
+            });
+        for name in referenced {
+            if !self.has_group(name) && !undefined.contains(name) {
+                undefined.push(name.clone());
+            }
+        }
+        undefined
+    }
+
+    /// Defines every group returned by [`undefined_groups`](Invoice::undefined_groups) with the
+    /// default settings (not required and satisfied by all of its parcels), which is how
+    /// resolution already treats them. Returns the names of the groups that were added
+    pub fn define_missing_groups(&mut self) -> Vec<String> {
+        let undefined = self.undefined_groups();
+        if !undefined.is_empty() {
+            self.group
+                .get_or_insert_with(Vec::new)
+                .extend(undefined.iter().map(|name| Group {
+                    name: name.clone(),
+                    required: None,
+                    satisfied_by: None,
+                }));
+        }
+        undefined
+    }
+
+    /// Returns the SPDX license expression that applies to the whole bindle, as declared in the
+    /// [`LICENSE_ANNOTATION`]. Individual parcels can declare their own license with
+    /// [`Label::license`]
+    pub fn license(&self) -> Option<&str> {
+        self.annotations
+            .as_ref()
+            .and_then(|a| a.get(LICENSE_ANNOTATION))
+            .map(String::as_str)
+    }
+
+    /// Returns a warning for each license expression on the invoice or its parcels that contains
+    /// an identifier that is not a known SPDX identifier (see [`unknown_spdx_identifiers`])
+    pub fn license_warnings(&self) -> Vec<String> {
+        let invoice = self.license().map(|l| ("the invoice".to_owned(), l));
+        let parcels = self
+            .parcel
+            .iter()
+            .flatten()
+            .filter_map(|p| Some((format!("parcel {}", p.label.name), p.label.license()?)));
+        invoice
+            .into_iter()
+            .chain(parcels)
+            .flat_map(|(source, expression)| {
+                unknown_spdx_identifiers(expression)
+                    .into_iter()
+                    .map(move |id| {
+                        format!("license of {} has unknown SPDX identifier {}", source, id)
+                    })
+            })
+            .collect()
+    }
+
+    /// Summarize the parcels on this invoice, returning the total number of parcels and bytes as
+    /// well as a breakdown of those totals by media type.
+    pub fn summary(&self) -> InvoiceSummary {
+        InvoiceSummary::from_parcels(self.parcel.iter().flatten())
+    }
+
+    /// Returns the time a host accepted this invoice from the [`CREATED_ANNOTATION`], as a UNIX
+    /// timestamp in seconds. Returns `None` if the annotation is missing or is not a valid timestamp
+    pub fn created(&self) -> Option<u64> {
+        self.annotations
+            .as_ref()
+            .and_then(|a| a.get(CREATED_ANNOTATION))
+            .and_then(|c| c.parse().ok())
+    }
+
+    /// Returns true if the [`NOINDEX_ANNOTATION`] is set to `true`
+    pub fn is_noindex(&self) -> bool {
+        self.annotations
+            .as_ref()
+            .and_then(|a| a.get(NOINDEX_ANNOTATION))
+            .map(|v| v.trim().eq_ignore_ascii_case("true"))
+            .unwrap_or(false)
+    }
+
+    /// Returns the platforms this bindle supports, as declared in the
+    /// [`PLATFORMS_ANNOTATION`]. An empty list means the bindle does not declare any platforms and
+    /// is compatible with all of them
+    pub fn supported_platforms(&self) -> Vec<String> {
+        self.annotations
+            .as_ref()
+            .and_then(|a| a.get(PLATFORMS_ANNOTATION))
+            .map(|platforms| {
+                platforms
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|p| !p.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns true if this bindle can be used on the given platform, either because the platform
+    /// is one of its [`supported_platforms`](Invoice::supported_platforms) or because it does not
+    /// declare any platforms
+    pub fn is_compatible(&self, platform: &str) -> bool {
+        let supported = self.supported_platforms();
+        supported.is_empty() || supported.iter().any(|p| p == platform)
+    }
+
+    /// Resolve the labels of the parcels to use on the given platform, sorted by name. This
+    /// includes every parcel for that platform (see [`PLATFORM_FEATURE_NAME`]) along with any
+    /// parcels that aren't for a specific platform, and is otherwise resolved the same way as with
+    /// a [`BindleFilter`](crate::filters::BindleFilter).
+    ///
+    /// Returns a [`ResolveError::IncompatiblePlatform`] if the bindle does not support the platform
+    pub fn resolve_platform(&self, platform: &str) -> Result<Vec<Label>, ResolveError> {
+        let feature = format!(
+            "{}.{}={}",
+            PLATFORM_FEATURE_GROUP, PLATFORM_FEATURE_NAME, platform
+        );
+        let mut labels: Vec<Label> = diff::resolve(self, &[feature])?.into_iter().collect();
+        labels.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(labels)
+    }
+
+    /// Resolve the labels of the parcels that apply with the given features activated, yielding
+    /// each one as soon as it is resolved so that a consumer can start fetching parcels before the
+    /// whole bindle is resolved. Features have the same form as in
+    /// [`resolve_diff`](Invoice::resolve_diff).
+    ///
+    /// Labels are yielded in the order the parcels appear in the invoice. Errors are deterministic
+    /// and end the stream: invalid features, an unsupported platform, or an unsatisfiable required
+    /// group are returned as the first item before any label, while a parcel that requires an
+    /// unsatisfiable group is returned as an error in place of that parcel. Labels that were
+    /// already yielded before such an error should be discarded, as the bindle as a whole cannot
+    /// be resolved
+    pub fn resolve_stream<'a>(
+        &'a self,
+        activated: &'a [String],
+    ) -> impl futures::Stream<Item = Result<Label, ResolveError>> + 'a {
+        futures::stream::iter(diff::ResolveIter::new(self, activated))
+    }
+
+    /// Compare the parcels resolved with two different sets of features, returning the labels of
+    /// the parcels that are added and removed when switching from the first set to the second.
+    ///
+    /// Each feature has the form `group.name=value`, matching the TOML:
+    ///
+    /// [parcel.label.feature.GROUP]
+    /// NAME = "VALUE"
+    ///
+    /// Parcels are resolved the same way as with a [`BindleFilter`](crate::filters::BindleFilter).
+    /// If either set of features cannot be resolved, such as when the features disable every parcel
+    /// of a group that another parcel requires or select a platform the bindle does not support, an
+    /// error is returned instead of a partial diff.
+    pub fn resolve_diff(
+        &self,
+        from_features: &[String],
+        to_features: &[String],
+    ) -> Result<ResolveDiff, ResolveError> {
+        ResolveDiff::between(self, from_features, to_features)
+    }
+
+    /// Merge the parcels, groups, and annotations of several invoices into a new invoice, such as
+    /// when assembling an aggregate bindle from its components.
+    ///
+    /// The merged invoice uses the bindle spec of `base`. Parcels are matched by SHA and groups by
+    /// name, so a parcel or group that appears in more than one invoice is only included once. If
+    /// the invoices disagree on the label or conditions of a parcel, or on the definition of a
+    /// group, a [`MergeConflict`] listing every conflicting parcel and group is returned.
+    ///
+    /// When more than one invoice has the same annotation, the value from `base` takes precedence,
+    /// followed by the values from `others` in the order they are given. The merged invoice is not
+    /// yanked and has no signatures, as it is a new invoice that must be signed again
+    pub fn merge(base: &Invoice, others: &[Invoice]) -> Result<Invoice, MergeConflict> {
+        merge::merge(base, others)
+    }
+
+    fn cleartext(&self, by: &str, role: &SignatureRole, scope: SignatureScope) -> String {
+        if scope == SignatureScope::ParcelsOnly {
+            // The scope is part of the cleartext so that the signature can't be mistaken for a
+            // parcel signature when the invoice has a single parcel
+            let mut shas: Vec<&str> = self
+                .parcel
+                .iter()
+                .flatten()
+                .map(|p| p.label.sha256.as_str())
+                .collect();
+            shas.sort_unstable();
+            shas.dedup();
+            let mut buf = vec![
+                by.to_owned(),
+                role.to_string(),
+                "parcels".to_owned(),
+                "~".to_owned(),
+            ];
+            buf.extend(shas.into_iter().map(str::to_owned));
+            return buf.join("\n");
+        }
+
         let mut buf = vec![
             by.to_owned(),
             self.bindle.id.name().to_owned(),
@@ -172,9 +462,54 @@ impl Invoice {
             })
         }
 
+        // The creation time is set by the host after the other roles have signed, so only host
+        // signatures cover it. Invoices without it have the same cleartext as before it existed
+        if *role == SignatureRole::Host {
+            if let Some(created) = self
+                .annotations
+                .as_ref()
+                .and_then(|a| a.get(CREATED_ANNOTATION))
+            {
+                buf.push(format!("{}={}", CREATED_ANNOTATION, created));
+            }
+        }
+
         buf.join("\n")
     }
 
+    /// Verifies every [`SignatureScope::ParcelsOnly`] signature on the invoice and returns the ones
+    /// made with a key in the keyring. Returns an error if any of them is invalid.
+    ///
+    /// A returned signature only attests that its signer signed this set of parcels, not this
+    /// bindle, so it must not be treated as evidence of who published the invoice
+    pub fn verify_parcels_only(
+        &self,
+        keyring: &signature::KeyRing,
+    ) -> Result<Vec<&Signature>, SignatureError> {
+        let mut known = Vec::new();
+        for s in self
+            .signature
+            .iter()
+            .flatten()
+            .filter(|s| s.scope == SignatureScope::ParcelsOnly)
+        {
+            let pko = s.public_key()?;
+            s.verify(self, &pko)?;
+            if keyring.contains(&pko) {
+                known.push(s);
+            }
+        }
+        Ok(known)
+    }
+
+    /// Returns true if the invoice has a host signature that covers the creation time
+    fn has_host_signature(&self) -> bool {
+        self.signature
+            .iter()
+            .flatten()
+            .any(|s| s.role == SignatureRole::Host && s.scope == SignatureScope::Invoice)
+    }
+
     /// Sign the parcels on the current package.
     ///
     /// Note that this signature will be invalidated if any parcels are
@@ -185,16 +520,32 @@ impl Invoice {
     /// a cryptographic signature on those fields. The result is then stored in
     /// a `[[signature]]` block on the invoice. Multiple signatures can be attached
     /// to any invoice.
+    ///
+    /// Signing with the host role also sets the [`CREATED_ANNOTATION`] to the current time, which
+    /// the host signature then covers, unless the invoice already has a host signature covering it.
     pub fn sign(
         &mut self,
         signer_role: SignatureRole,
         keyfile: &SecretKeyEntry,
     ) -> Result<(), SignatureError> {
-        let signer_name = keyfile.label.clone();
-        let key = keyfile.key()?;
+        self.sign_with_scope(signer_role, keyfile, SignatureScope::default())
+    }
+
+    /// The same as [`sign`](Invoice::sign), but signs only the parts of the invoice covered by the
+    /// given scope. A [`SignatureScope::ParcelsOnly`] signature is a lightweight attestation of the
+    /// parcel list that stays valid when the rest of the invoice is amended, but it says nothing
+    /// about which bindle the parcels belong to. For that reason, a
+    /// [`VerificationStrategy`] never counts it toward a role. Use
+    /// [`verify_parcels_only`](Invoice::verify_parcels_only) to check it instead
+    pub fn sign_with_scope(
+        &mut self,
+        signer_role: SignatureRole,
+        keyfile: &SecretKeyEntry,
+        scope: SignatureScope,
+    ) -> Result<(), SignatureError> {
         // The spec says it is illegal for the a single key to sign the same invoice
         // more than once.
-        let encoded_key = base64::encode(key.public.to_bytes());
+        let encoded_key = base64::encode(keyfile.key()?.public.to_bytes());
         if let Some(sigs) = self.signature.as_ref() {
             for s in sigs {
                 if s.key == encoded_key {
@@ -203,21 +554,19 @@ impl Invoice {
             }
         }
 
-        let cleartext = self.cleartext(&signer_name, &signer_role);
-        let signature: EdSignature = key.sign(cleartext.as_bytes());
+        if signer_role == SignatureRole::Host
+            && scope == SignatureScope::Invoice
+            && !self.has_host_signature()
+        {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_err(|_| SignatureError::SigningFailed)?;
+            self.annotations
+                .get_or_insert_with(AnnotationMap::new)
+                .insert(CREATED_ANNOTATION.to_owned(), now.as_secs().to_string());
+        }
 
-        // Timestamp should be generated at this moment.
-        let ts = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map_err(|_| SignatureError::SigningFailed)?;
-
-        let signature_entry = Signature {
-            by: signer_name,
-            key: encoded_key,
-            signature: base64::encode(signature.to_bytes()),
-            role: signer_role,
-            at: ts.as_secs(),
-        };
+        let signature_entry = Signature::create_with_scope(self, keyfile, signer_role, scope)?;
 
         match self.signature.as_mut() {
             Some(signatures) => signatures.push(signature_entry),
@@ -257,43 +606,65 @@ fn sign_one(
     signer_role: SignatureRole,
     keyfile: &SecretKeyEntry,
 ) -> Result<(), SignatureError> {
-    let signer_name = keyfile.label.clone();
-    let key = keyfile.key()?;
-    // The spec says it is illegal for the a single key to sign the same invoice
-    // more than once.
-    let encoded_key = base64::encode(key.public.to_bytes());
-    if let Some(sigs) = inv.signature.as_ref() {
-        for s in sigs {
-            if s.key == encoded_key {
-                return Err(SignatureError::DuplicateSignature);
-            }
-        }
-    }
-
-    let cleartext = inv.cleartext(&signer_name, &signer_role);
-    let signature: EdSignature = key.sign(cleartext.as_bytes());
+    inv.sign(signer_role, keyfile)
+}
 
-    // Timestamp should be generated at this moment.
-    let ts = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|_| SignatureError::SigningFailed)?;
+/// Sign a single parcel by adding a signature of its SHA to the given label. Parcel signatures are
+/// independent of invoice signatures, so they can be added by a parcel author who did not create
+/// the invoice, and adding one does not invalidate any signatures on the invoice.
+///
+/// As with invoices, a single key cannot sign the same parcel more than once
+pub fn sign_parcel(
+    label: &mut Label,
+    signer_role: SignatureRole,
+    keyfile: &SecretKeyEntry,
+) -> Result<(), SignatureError> {
+    let encoded_key = base64::encode(keyfile.key()?.public.to_bytes());
+    if label
+        .signatures
+        .iter()
+        .flatten()
+        .any(|s| s.key == encoded_key)
+    {
+        return Err(SignatureError::DuplicateSignature);
+    }
 
-    let signature_entry = Signature {
-        by: signer_name,
-        key: encoded_key,
-        signature: base64::encode(signature.to_bytes()),
-        role: signer_role,
-        at: ts.as_secs(),
-    };
+    let signature_entry = Signature::create_for_parcel(label, keyfile, signer_role)?;
 
-    match inv.signature.as_mut() {
+    match label.signatures.as_mut() {
         Some(signatures) => signatures.push(signature_entry),
-        None => inv.signature = Some(vec![signature_entry]),
+        None => label.signatures = Some(vec![signature_entry]),
     };
 
     Ok(())
 }
 
+/// Verify the signatures on the given parcel label. Every signature must be valid and at least one
+/// of them must be made with a key in the keyring, so a label without any signatures fails with a
+/// [`SignatureError::NoKnownKey`].
+///
+/// Note that the signatures only cover the parcel's SHA, so any parcel data should also be checked
+/// against the SHA in the label
+pub fn verify_parcel_signature(
+    label: &Label,
+    keyring: &signature::KeyRing,
+) -> Result<(), SignatureError> {
+    let mut known_key = false;
+    for s in label.signatures.iter().flatten() {
+        debug!(by = %s.by, parcel = %label.sha256, "Checking parcel signature");
+        let pko = s.public_key()?;
+        s.verify_parcel(label, &pko)?;
+        if keyring.contains(&pko) {
+            debug!("Found key {}", s.by);
+            known_key = true;
+        }
+    }
+    if !known_key {
+        return Err(SignatureError::NoKnownKey);
+    }
+    Ok(())
+}
+
 /// An invoice that has been signed and can no longer be modified unless converted back into a
 /// normal invoice with the `signed` method
 pub struct SignedInvoice<T: Into<Invoice>>(T);
@@ -328,6 +699,7 @@ where
 
 /// A struct that fakes signing. Purely for internal usage with things such as caches and
 /// passthrough implementations (like the client)
+#[cfg_attr(not(feature = "caching"), allow(dead_code))]
 pub(crate) struct NoopSigned<T: Into<Invoice>>(pub(crate) T);
 
 impl<T: Into<Invoice>> Signed for NoopSigned<T> {
@@ -494,6 +866,160 @@ mod test {
             .verify(invoice, &keyring)
             .expect_err("missing the creator key, so verification should fail");
     }
+
+    #[tokio::test]
+    async fn test_parcels_only_signature() {
+        let mut invoice = crate::testing::Scaffold::load("valid_v1").await.invoice;
+        invoice.signature = None;
+        let creator = SecretKeyEntry::new("Creator".to_owned(), vec![SignatureRole::Creator]);
+        let keyring = KeyRing::new(vec![(&creator).try_into().expect("convert to public key")]);
+
+        invoice
+            .sign_with_scope(
+                SignatureRole::Creator,
+                &creator,
+                SignatureScope::ParcelsOnly,
+            )
+            .expect("Sign the parcels");
+        let invoice: Invoice = toml::from_str(&toml::to_string(&invoice).unwrap())
+            .expect("Invoice with a scoped signature should parse");
+        assert_eq!(
+            SignatureScope::ParcelsOnly,
+            invoice.signature.as_ref().unwrap()[0].scope
+        );
+        assert_eq!(
+            1,
+            invoice
+                .verify_parcels_only(&keyring)
+                .expect("Signed invoice should verify")
+                .len()
+        );
+        assert!(invoice
+            .verify_parcels_only(&KeyRing::default())
+            .expect("Signed invoice should verify")
+            .is_empty());
+
+        // Amending everything but the parcel list keeps the signature valid
+        let mut amended = invoice.clone();
+        amended.bindle.description = Some("A new description".to_owned());
+        amended.bindle.id = "enterprise.com/warpcore/2.0.0".parse().unwrap();
+        amended
+            .annotations
+            .get_or_insert_with(Default::default)
+            .insert("reviewed".to_owned(), "true".to_owned());
+        amended.parcel.as_mut().unwrap().reverse();
+        amended
+            .verify_parcels_only(&keyring)
+            .expect("Amended metadata should not break the signature");
+
+        // Changing a parcel does not
+        let mut tampered = invoice.clone();
+        tampered.parcel.as_mut().unwrap()[0].label.sha256 = "111aaabbbcccdddeee".to_owned();
+        tampered
+            .verify_parcels_only(&keyring)
+            .expect_err("A different SHA should break the signature");
+
+        // Nor does adding one
+        let mut extended = invoice;
+        extended.parcel.as_mut().unwrap().push(Parcel {
+            label: Label::new("extra.txt".to_owned(), "111aaabbbcccdddeee".to_owned()),
+            conditions: None,
+        });
+        extended
+            .verify_parcels_only(&keyring)
+            .expect_err("An extra parcel should break the signature");
+    }
+
+    #[tokio::test]
+    async fn test_parcels_only_signature_does_not_attest_invoice() {
+        let mut invoice = crate::testing::Scaffold::load("valid_v1").await.invoice;
+        invoice.signature = None;
+        let creator = SecretKeyEntry::new("Creator".to_owned(), vec![SignatureRole::Creator]);
+        let keyring = KeyRing::new(vec![(&creator).try_into().expect("convert to public key")]);
+        invoice
+            .sign_with_scope(
+                SignatureRole::Creator,
+                &creator,
+                SignatureScope::ParcelsOnly,
+            )
+            .expect("Sign the parcels");
+
+        // Moving the creator's signature onto a different bindle with the same parcels must not
+        // make it look like the creator published that bindle
+        let mut renamed = invoice;
+        renamed.bindle.id = "evil.com/anything/1.0.0".parse().unwrap();
+        for strategy in [
+            VerificationStrategy::CreativeIntegrity,
+            VerificationStrategy::GreedyVerification,
+            VerificationStrategy::AuthoritativeIntegrity,
+            VerificationStrategy::ExhaustiveVerification,
+            VerificationStrategy::MultipleAttestation(vec![SignatureRole::Creator]),
+            VerificationStrategy::MultipleAttestationGreedy(vec![SignatureRole::Creator]),
+        ] {
+            assert!(
+                strategy.verify(renamed.clone(), &keyring).is_err(),
+                "{:?} should not accept a parcels-only signature as creator attestation",
+                strategy
+            );
+        }
+
+        // A full signature from the real publisher of the renamed bindle still verifies
+        let publisher = SecretKeyEntry::new("Publisher".to_owned(), vec![SignatureRole::Creator]);
+        renamed
+            .sign(SignatureRole::Creator, &publisher)
+            .expect("Sign the invoice");
+        let keyring = KeyRing::new(vec![
+            (&creator).try_into().unwrap(),
+            (&publisher).try_into().unwrap(),
+        ]);
+        VerificationStrategy::CreativeIntegrity
+            .verify(renamed, &keyring)
+            .expect("The full creator signature should verify");
+    }
+
+    #[tokio::test]
+    async fn test_host_created_timestamp() {
+        let mut invoice = crate::testing::Scaffold::load("valid_v1").await.invoice;
+        invoice.annotations = None;
+        let creator = SecretKeyEntry::new("Creator".to_owned(), vec![SignatureRole::Creator]);
+        let host = SecretKeyEntry::new("Host".to_owned(), vec![SignatureRole::Host]);
+        let other_host = SecretKeyEntry::new("Other Host".to_owned(), vec![SignatureRole::Host]);
+        let check = |inv: &Invoice, key: &SecretKeyEntry, index: usize| {
+            inv.signature.as_ref().unwrap()[index].verify(inv, &key.key().unwrap().public)
+        };
+
+        invoice.sign(SignatureRole::Creator, &creator).unwrap();
+        assert_eq!(None, invoice.created());
+
+        // A value set by the client is replaced when the host signs
+        invoice.annotations = Some(
+            vec![(CREATED_ANNOTATION.to_owned(), "1".to_owned())]
+                .into_iter()
+                .collect(),
+        );
+        invoice.sign(SignatureRole::Host, &host).unwrap();
+        let created = invoice
+            .created()
+            .expect("host should set the creation time");
+        assert!(created > 1);
+        check(&invoice, &creator, 0).expect("creator signature should still be valid");
+        check(&invoice, &host, 1).expect("host signature should be valid");
+
+        // Further host signatures keep the timestamp so earlier ones stay valid
+        invoice.sign(SignatureRole::Host, &other_host).unwrap();
+        assert_eq!(Some(created), invoice.created());
+        check(&invoice, &host, 1).expect("first host signature should still be valid");
+        check(&invoice, &other_host, 2).expect("second host signature should be valid");
+
+        // Only the host signatures cover the timestamp
+        invoice
+            .annotations
+            .as_mut()
+            .unwrap()
+            .insert(CREATED_ANNOTATION.to_owned(), (created + 1).to_string());
+        check(&invoice, &creator, 0).expect("creator signature should not cover the timestamp");
+        check(&invoice, &host, 1).expect_err("host signature should cover the timestamp");
+    }
     #[test]
     fn invalid_signatures_should_fail() {
         let invoice = r#"
@@ -595,6 +1121,58 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_sign_and_verify_parcel() {
+        let mut label = Label::new("telescope.gif".to_owned(), "aaabbbcccdddeeefff".to_owned());
+        let author = SecretKeyEntry::new(
+            "Parcel Author <author@example.com>".to_owned(),
+            vec![SignatureRole::Creator],
+        );
+        let other = SecretKeyEntry::new(
+            "Someone Else <else@example.com>".to_owned(),
+            vec![SignatureRole::Creator],
+        );
+        let keyring = KeyRing::new(vec![(&author).try_into().expect("convert to public key")]);
+
+        match verify_parcel_signature(&label, &keyring) {
+            Err(SignatureError::NoKnownKey) => (),
+            res => panic!("Unsigned parcel should not verify, got {:?}", res),
+        }
+
+        sign_parcel(&mut label, SignatureRole::Creator, &author).expect("Sign the parcel");
+        sign_parcel(&mut label, SignatureRole::Approver, &author)
+            .expect_err("Should not be able to sign again with the same key");
+        verify_parcel_signature(&label, &keyring).expect("Signed parcel should verify");
+
+        // The signature should survive a round trip through the invoice format
+        let mut invoice = Invoice::new(BindleSpec {
+            id: "aricebo/1.2.3".parse().unwrap(),
+            description: None,
+            authors: None,
+        });
+        invoice.parcel = Some(vec![Parcel {
+            label: label.clone(),
+            conditions: None,
+        }]);
+        let invoice: Invoice = toml::from_str(&toml::to_string(&invoice).unwrap())
+            .expect("Invoice with parcel signatures should parse");
+        assert_eq!(label, invoice.parcel.unwrap()[0].label);
+
+        // The signature only applies to the signed SHA
+        let mut tampered = label.clone();
+        tampered.sha256 = "111aaabbbcccdddeee".to_owned();
+        verify_parcel_signature(&tampered, &keyring)
+            .expect_err("Signature should not apply to a different SHA");
+
+        // A signature from an unknown key is allowed as long as a known key also signed
+        sign_parcel(&mut label, SignatureRole::Creator, &other).expect("Sign the parcel");
+        verify_parcel_signature(&label, &keyring).expect("Known key signed the parcel");
+        let keyring = KeyRing::new(vec![other.try_into().expect("convert to public key")]);
+        verify_parcel_signature(&label, &keyring).expect("Other key signed the parcel");
+        verify_parcel_signature(&label, &KeyRing::default())
+            .expect_err("No signing key is on the keyring");
+    }
+
     #[test]
     fn test_invoice_should_serialize() {
         let label = Label {
@@ -605,6 +1183,7 @@ mod test {
             annotations: None,
             feature: None,
             origin: None,
+            signatures: None,
         };
         let parcel = Parcel {
             label,
@@ -647,6 +1226,24 @@ mod test {
         assert_eq!(lab.size, 101);
     }
 
+    #[test]
+    fn test_empty_lists_should_serialize() {
+        let mut inv = Invoice::new(BindleSpec {
+            id: "foo/1.2.3".parse().unwrap(),
+            description: None,
+            authors: None,
+        });
+        inv.parcel = Some(vec![]);
+        inv.group = Some(vec![]);
+        inv.signature = Some(vec![]);
+
+        let res = toml::to_string(&inv).expect("empty lists should serialize");
+        let inv2 = toml::from_str::<Invoice>(res.as_str()).unwrap();
+        assert!(inv2.parcel.is_none());
+        assert!(inv2.group.is_none());
+        assert!(inv2.signature.is_none());
+    }
+
     #[test]
     fn test_examples_in_spec_parse() {
         let test_files = vec![
@@ -753,4 +1350,685 @@ mod test {
         let members = invoice.group_members("telescopes");
         assert_eq!(2, members.len());
     }
+
+    #[test]
+    fn test_summary() {
+        let invoice = r#"
+        bindleVersion = "1.0.0"
+
+        [bindle]
+        name = "aricebo"
+        version = "1.2.3"
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "aaabbbcccdddeeefff"
+        name = "telescope.gif"
+        mediaType = "image/gif"
+        size = 100
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "aaabbbcccdddeeeggg"
+        name = "telescope2.gif"
+        mediaType = "image/gif"
+        size = 250
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "111aaabbbcccdddeee"
+        name = "telescope.txt"
+        mediaType = "text/plain"
+        size = 50
+        "#;
+
+        let invoice: crate::Invoice = toml::from_str(invoice).expect("a nice clean parse");
+        let summary = invoice.summary();
+        assert_eq!(3, summary.parcel_count);
+        assert_eq!(400, summary.total_size);
+        assert_eq!(2, summary.media_types.len());
+
+        let gifs = summary
+            .media_types
+            .get("image/gif")
+            .expect("gif media type should be summarized");
+        assert_eq!(2, gifs.parcel_count);
+        assert_eq!(350, gifs.total_size);
+
+        let text = summary
+            .media_types
+            .get("text/plain")
+            .expect("text media type should be summarized");
+        assert_eq!(1, text.parcel_count);
+        assert_eq!(50, text.total_size);
+
+        // An invoice with no parcels should have an empty summary
+        let empty = Invoice::new(invoice.bindle.clone()).summary();
+        assert_eq!(InvoiceSummary::default(), empty);
+    }
+
+    #[test]
+    fn test_resolve_diff() {
+        let invoice = r#"
+        bindleVersion = "1.0.0"
+
+        [bindle]
+        name = "aricebo"
+        version = "1.2.3"
+
+        [[group]]
+        name = "horn"
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "aaabbbcccdddeeefff"
+        name = "default.txt"
+        mediaType = "text/plain"
+        size = 100
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "111aaabbbcccdddeee"
+        name = "narwhal.txt"
+        mediaType = "text/plain"
+        size = 100
+        [parcel.label.feature.testing]
+        animal = "narwhal"
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "222aaabbbcccdddeee"
+        name = "unicorn.txt"
+        mediaType = "text/plain"
+        size = 100
+        [parcel.label.feature.testing]
+        animal = "unicorn"
+        [parcel.conditions]
+        requires = ["horn"]
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "333aaabbbcccdddeee"
+        name = "horn.txt"
+        mediaType = "text/plain"
+        size = 100
+        [parcel.label.feature.horns]
+        style = "spiral"
+        [parcel.conditions]
+        memberOf = ["horn"]
+        "#;
+
+        let invoice: crate::Invoice = toml::from_str(invoice).expect("a nice clean parse");
+
+        let diff = invoice
+            .resolve_diff(
+                &["testing.animal=narwhal".to_owned()],
+                &["testing.animal=unicorn".to_owned()],
+            )
+            .expect("features should resolve");
+        let names = |labels: &[Label]| labels.iter().map(|l| l.name.clone()).collect::<Vec<_>>();
+        assert_eq!(vec!["horn.txt", "unicorn.txt"], names(&diff.added));
+        assert_eq!(vec!["narwhal.txt"], names(&diff.removed));
+
+        assert!(invoice
+            .resolve_diff(&[], &[])
+            .expect("no features should resolve")
+            .is_empty());
+
+        // Disabling the only parcel in a required group cannot be satisfied
+        match invoice.resolve_diff(
+            &[],
+            &[
+                "testing.animal=unicorn".to_owned(),
+                "horns.style=straight".to_owned(),
+            ],
+        ) {
+            Err(ResolveError::Unsatisfiable {
+                parcel,
+                group,
+                satisfied_by,
+            }) => {
+                assert_eq!("unicorn.txt", parcel);
+                assert_eq!("horn", group);
+                assert_eq!(SatisfiedBy::AllOf, satisfied_by);
+            }
+            res => panic!("Expected an unsatisfiable error, got {:?}", res),
+        }
+
+        assert_eq!(
+            Err(ResolveError::InvalidFeature("testing.animal".to_owned())),
+            invoice.resolve_diff(&["testing.animal".to_owned()], &[])
+        );
+        assert_eq!(
+            Err(ResolveError::ConflictingFeature {
+                group: "testing".to_owned(),
+                name: "animal".to_owned()
+            }),
+            invoice.resolve_diff(
+                &[],
+                &[
+                    "testing.animal=narwhal".to_owned(),
+                    "testing.animal=unicorn".to_owned()
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_resolve_group_requirements() {
+        let invoice: crate::Invoice = toml::from_str(
+            r#"
+        bindleVersion = "1.0.0"
+
+        [bindle]
+        name = "groups"
+        version = "1.0.0"
+
+        [[group]]
+        name = "all"
+
+        [[group]]
+        name = "one"
+        satisfiedBy = "oneOf"
+
+        [[group]]
+        name = "optional"
+        satisfiedBy = "optional"
+
+        [[group]]
+        name = "always"
+        required = true
+        satisfiedBy = "oneOf"
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "aaabbbcccdddeeefff"
+        name = "main.txt"
+        mediaType = "text/plain"
+        size = 100
+        [parcel.conditions]
+        requires = ["all", "one", "optional"]
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "111aaabbbcccdddeee"
+        name = "all-a.txt"
+        mediaType = "text/plain"
+        size = 100
+        [parcel.label.feature.all]
+        a = "on"
+        [parcel.conditions]
+        memberOf = ["all"]
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "222aaabbbcccdddeee"
+        name = "all-b.txt"
+        mediaType = "text/plain"
+        size = 100
+        [parcel.conditions]
+        memberOf = ["all"]
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "333aaabbbcccdddeee"
+        name = "one-a.txt"
+        mediaType = "text/plain"
+        size = 100
+        [parcel.label.feature.one]
+        a = "on"
+        [parcel.conditions]
+        memberOf = ["one"]
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "444aaabbbcccdddeee"
+        name = "one-b.txt"
+        mediaType = "text/plain"
+        size = 100
+        [parcel.label.feature.other]
+        b = "on"
+        [parcel.conditions]
+        memberOf = ["one"]
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "555aaabbbcccdddeee"
+        name = "optional.txt"
+        mediaType = "text/plain"
+        size = 100
+        [parcel.label.feature.optional]
+        a = "on"
+        [parcel.conditions]
+        memberOf = ["optional"]
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "666aaabbbcccdddeee"
+        name = "always.txt"
+        mediaType = "text/plain"
+        size = 100
+        [parcel.label.feature.always]
+        a = "on"
+        [parcel.conditions]
+        memberOf = ["always"]
+        "#,
+        )
+        .expect("a nice clean parse");
+
+        assert_eq!(
+            SatisfiedBy::OneOf,
+            invoice.group("one").unwrap().satisfied_by()
+        );
+        assert!(invoice.group("always").unwrap().is_required());
+        assert!(invoice.group("nope").is_none());
+
+        let resolve = |features: &[&str]| {
+            let features: Vec<String> = features.iter().map(|f| f.to_string()).collect();
+            invoice.resolve_diff(&[], &features)
+        };
+        let unsatisfiable = |group: &str, satisfied_by| {
+            Err(ResolveError::Unsatisfiable {
+                parcel: "main.txt".to_owned(),
+                group: group.to_owned(),
+                satisfied_by,
+            })
+        };
+
+        resolve(&[]).expect("all groups should be satisfied");
+
+        // All of the parcels in an allOf group are needed
+        assert_eq!(
+            unsatisfiable("all", SatisfiedBy::AllOf),
+            resolve(&["all.a=off"])
+        );
+
+        // A oneOf group only needs one of its parcels
+        resolve(&["one.a=off"]).expect("one parcel should satisfy the group");
+        assert_eq!(
+            unsatisfiable("one", SatisfiedBy::OneOf),
+            resolve(&["one.a=off", "other.b=off"])
+        );
+
+        // An optional group is satisfied without any of its parcels
+        let diff = resolve(&["optional.a=off"]).expect("optional group should be satisfied");
+        assert_eq!(
+            vec!["optional.txt"],
+            diff.removed
+                .iter()
+                .map(|l| l.name.clone())
+                .collect::<Vec<_>>()
+        );
+
+        // A required group has to be satisfied even though no parcel requires it
+        assert_eq!(
+            Err(ResolveError::RequiredGroupUnsatisfiable {
+                group: "always".to_owned(),
+                satisfied_by: SatisfiedBy::OneOf,
+            }),
+            resolve(&["always.a=off"])
+        );
+
+        // Streaming yields the same parcels in invoice order, and ends with the same errors
+        let stream = |features: &[&str]| {
+            use futures::StreamExt;
+            let features: Vec<String> = features.iter().map(|f| f.to_string()).collect();
+            futures::executor::block_on(invoice.resolve_stream(&features).collect::<Vec<_>>())
+        };
+        let names = |items: Vec<Result<Label, ResolveError>>| -> Vec<String> {
+            items.into_iter().map(|i| i.unwrap().name).collect()
+        };
+        assert_eq!(
+            vec![
+                "main.txt",
+                "all-a.txt",
+                "all-b.txt",
+                "one-a.txt",
+                "one-b.txt",
+                "optional.txt",
+                "always.txt"
+            ],
+            names(stream(&[]))
+        );
+        assert_eq!(
+            vec![
+                "main.txt",
+                "all-a.txt",
+                "all-b.txt",
+                "one-b.txt",
+                "always.txt"
+            ],
+            names(stream(&["one.a=off", "optional.a=off"]))
+        );
+        assert_eq!(
+            vec![unsatisfiable("all", SatisfiedBy::AllOf).unwrap_err()],
+            stream(&["all.a=off"])
+                .into_iter()
+                .map(|i| i.unwrap_err())
+                .collect::<Vec<_>>()
+        );
+        let items = stream(&["always.a=off"]);
+        assert_eq!(
+            1,
+            items.len(),
+            "Required groups should fail before any label"
+        );
+        assert!(matches!(
+            items[0],
+            Err(ResolveError::RequiredGroupUnsatisfiable { .. })
+        ));
+        let items = stream(&["invalid"]);
+        assert_eq!(
+            vec![Err(ResolveError::InvalidFeature("invalid".to_owned()))],
+            items
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let base: Invoice = toml::from_str(
+            r#"
+        bindleVersion = "1.0.0"
+
+        [bindle]
+        name = "aggregate"
+        version = "1.0.0"
+
+        [annotations]
+        owner = "base"
+
+        [[group]]
+        name = "plugins"
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "aaabbbcccdddeeefff"
+        name = "shared.txt"
+        mediaType = "text/plain"
+        size = 100
+        "#,
+        )
+        .expect("base invoice should parse");
+
+        let component: Invoice = toml::from_str(
+            r#"
+        bindleVersion = "1.0.0"
+
+        [bindle]
+        name = "component"
+        version = "0.1.0"
+
+        [annotations]
+        owner = "component"
+        license = "MIT"
+
+        [[group]]
+        name = "plugins"
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "aaabbbcccdddeeefff"
+        name = "shared.txt"
+        mediaType = "text/plain"
+        size = 100
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "111aaabbbcccdddeee"
+        name = "plugin.wasm"
+        mediaType = "application/wasm"
+        size = 200
+        [parcel.conditions]
+        memberOf = ["plugins"]
+        "#,
+        )
+        .expect("component invoice should parse");
+
+        let mut other = component.clone();
+        other.annotations = Some(
+            vec![("license".to_owned(), "Apache-2.0".to_owned())]
+                .into_iter()
+                .collect(),
+        );
+
+        let merged = Invoice::merge(&base, &[component.clone(), other])
+            .expect("compatible invoices should merge");
+        assert_eq!(base.bindle.id, merged.bindle.id);
+        let names: Vec<&str> = merged
+            .parcel
+            .iter()
+            .flatten()
+            .map(|p| p.label.name.as_str())
+            .collect();
+        assert_eq!(vec!["shared.txt", "plugin.wasm"], names);
+        assert_eq!(1, merged.group.expect("should have groups").len());
+        let annotations = merged.annotations.expect("should have annotations");
+        assert_eq!("base", annotations["owner"]);
+        assert_eq!("MIT", annotations["license"]);
+        assert!(merged.signature.is_none());
+
+        // Changing the label of a shared parcel and the definition of a shared group conflicts
+        let mut conflicting = component;
+        conflicting.parcel.as_mut().unwrap()[0].label.name = "renamed.txt".to_owned();
+        conflicting.group.as_mut().unwrap()[0].required = Some(true);
+        match Invoice::merge(&base, &[conflicting]) {
+            Err(conflict) => assert_eq!(
+                MergeConflict {
+                    parcels: vec!["aaabbbcccdddeeefff".to_owned()],
+                    groups: vec!["plugins".to_owned()],
+                },
+                conflict
+            ),
+            Ok(_) => panic!("Conflicting invoices should not merge"),
+        }
+    }
+
+    #[test]
+    fn test_license_warnings() {
+        let invoice: Invoice = toml::from_str(
+            r#"
+        bindleVersion = "1.0.0"
+
+        [bindle]
+        name = "licensed"
+        version = "1.0.0"
+
+        [annotations]
+        "bindle.dev/spdx-license" = "MIT OR Apache-2.0"
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "aaabbbcccdddeeefff"
+        name = "vendored.js"
+        mediaType = "text/javascript"
+        size = 100
+        [parcel.label.annotations]
+        "bindle.dev/spdx-license" = "BSD-3-Clause AND Totally-Free"
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "111aaabbbcccdddeee"
+        name = "main.js"
+        mediaType = "text/javascript"
+        size = 100
+        "#,
+        )
+        .expect("invoice should parse");
+
+        assert_eq!(Some("MIT OR Apache-2.0"), invoice.license());
+        let parcels = invoice.parcel.as_ref().unwrap();
+        assert_eq!(
+            Some("BSD-3-Clause AND Totally-Free"),
+            parcels[0].label.license()
+        );
+        assert_eq!(None, parcels[1].label.license());
+
+        let warnings = invoice.license_warnings();
+        assert_eq!(1, warnings.len());
+        assert!(warnings[0].contains("vendored.js") && warnings[0].contains("Totally-Free"));
+    }
+
+    #[test]
+    fn test_platforms() {
+        let invoice: Invoice = toml::from_str(
+            r#"
+        bindleVersion = "1.0.0"
+
+        [bindle]
+        name = "multiplatform"
+        version = "1.0.0"
+
+        [annotations]
+        "bindle.dev/platforms" = "linux/amd64, windows/amd64"
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "aaabbbcccdddeeefff"
+        name = "config.toml"
+        mediaType = "application/toml"
+        size = 100
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "111aaabbbcccdddeee"
+        name = "server"
+        mediaType = "application/octet-stream"
+        size = 1000
+        [parcel.label.feature.platform]
+        target = "linux/amd64"
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "222aaabbbcccdddeee"
+        name = "server.exe"
+        mediaType = "application/octet-stream"
+        size = 1000
+        [parcel.label.feature.platform]
+        target = "windows/amd64"
+        "#,
+        )
+        .expect("invoice should parse");
+
+        assert_eq!(
+            vec!["linux/amd64", "windows/amd64"],
+            invoice.supported_platforms()
+        );
+        assert!(invoice.is_compatible("linux/amd64"));
+        assert!(!invoice.is_compatible("darwin/arm64"));
+
+        let names =
+            |labels: Vec<Label>| -> Vec<String> { labels.into_iter().map(|l| l.name).collect() };
+        assert_eq!(
+            vec!["config.toml", "server"],
+            names(invoice.resolve_platform("linux/amd64").unwrap())
+        );
+        assert_eq!(
+            vec!["config.toml", "server.exe"],
+            names(invoice.resolve_platform("windows/amd64").unwrap())
+        );
+
+        let incompatible = Err(ResolveError::IncompatiblePlatform {
+            platform: "darwin/arm64".to_owned(),
+            supported: vec!["linux/amd64".to_owned(), "windows/amd64".to_owned()],
+        });
+        assert_eq!(incompatible, invoice.resolve_platform("darwin/arm64"));
+        assert!(matches!(
+            invoice.resolve_diff(&[], &["platform.target=darwin/arm64".to_owned()]),
+            Err(ResolveError::IncompatiblePlatform { .. })
+        ));
+
+        // An invoice without platforms is compatible with all of them
+        let mut any = invoice.clone();
+        any.annotations = None;
+        assert!(any.supported_platforms().is_empty());
+        assert!(any.is_compatible("darwin/arm64"));
+        assert_eq!(
+            vec!["config.toml"],
+            names(any.resolve_platform("darwin/arm64").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_duplicate_parcel_names() {
+        let mut invoice: Invoice = toml::from_str(
+            r#"
+        bindleVersion = "1.0.0"
+
+        [bindle]
+        name = "duplicates"
+        version = "1.0.0"
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "111aaabbbcccdddeee"
+        name = "server"
+        mediaType = "application/octet-stream"
+        size = 1000
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "222aaabbbcccdddeee"
+        name = "config.toml"
+        mediaType = "application/toml"
+        size = 100
+        "#,
+        )
+        .expect("invoice should parse");
+        assert!(invoice.duplicate_parcel_names().is_empty());
+
+        let mut duplicate = invoice.parcel.as_ref().unwrap()[0].clone();
+        duplicate.label.sha256 = "333aaabbbcccdddeee".to_owned();
+        invoice.parcel.as_mut().unwrap().push(duplicate.clone());
+        invoice.parcel.as_mut().unwrap().push(duplicate);
+        assert_eq!(vec!["server"], invoice.duplicate_parcel_names());
+    }
+
+    #[test]
+    fn test_define_missing_groups() {
+        let mut invoice: Invoice = toml::from_str(
+            r#"
+        bindleVersion = "1.0.0"
+
+        [bindle]
+        name = "groups"
+        version = "1.0.0"
+
+        [[group]]
+        name = "server"
+        required = true
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "111aaabbbcccdddeee"
+        name = "server"
+        mediaType = "application/octet-stream"
+        size = 1000
+        [parcel.conditions]
+        memberOf = ["server", "cli"]
+        requires = ["config"]
+
+        [[parcel]]
+        [parcel.label]
+        sha256 = "222aaabbbcccdddeee"
+        name = "config.toml"
+        mediaType = "application/toml"
+        size = 100
+        [parcel.conditions]
+        memberOf = ["config", "cli"]
+        "#,
+        )
+        .expect("invoice should parse");
+        assert_eq!(vec!["cli", "config"], invoice.undefined_groups());
+        assert!(invoice.group_members("config").is_empty());
+
+        assert_eq!(vec!["cli", "config"], invoice.define_missing_groups());
+        assert!(invoice.undefined_groups().is_empty());
+        assert!(invoice.define_missing_groups().is_empty());
+        let groups: Vec<_> = invoice.group.iter().flatten().map(|g| &g.name).collect();
+        assert_eq!(vec!["server", "cli", "config"], groups);
+        assert!(invoice.group("server").unwrap().is_required());
+        assert!(!invoice.group("config").unwrap().is_required());
+        assert_eq!(1, invoice.group_members("config").len());
+        assert_eq!(2, invoice.group_members("cli").len());
+    }
 }
@@ -1,5 +1,6 @@
 //! Definition and implementation of the `Parcel` type
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::invoice::{Condition, Label};
@@ -9,7 +10,7 @@ use crate::invoice::{Condition, Label};
 /// A parcel file can be an arbitrary "blob" of data. This could be binary or text files. This
 /// object contains the metadata and associated conditions for using a parcel. For more information,
 /// see the [Bindle Spec](https://github.com/deislabs/bindle/blob/master/docs/bindle-spec.md)
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Eq, PartialEq, Hash)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct Parcel {
     pub label: Label,
@@ -26,6 +27,14 @@ impl Parcel {
             None => false,
         }
     }
+    /// Returns true if this parcel is marked as `lazy` in its conditions, meaning it should be
+    /// deferred until it is needed instead of being fetched when the bindle is installed
+    pub fn is_lazy(&self) -> bool {
+        self.conditions
+            .as_ref()
+            .and_then(|c| c.lazy)
+            .unwrap_or(false)
+    }
     /// returns true if this parcel is a member of the "global" group (default).
     ///
     /// The spec says: "An implicit global group exists. It has no name, and includes
@@ -0,0 +1,79 @@
+//! JSON Schema generation for the invoice format, so invoices can be validated by tools that do not
+//! use this crate
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject, StringValidation};
+
+use crate::invoice::Invoice;
+
+/// The pattern from the SemVer 2.0.0 spec for matching a full version
+const SEMVER_PATTERN: &str = r"^(0|[1-9]\d*)\.(0|[1-9]\d*)\.(0|[1-9]\d*)(?:-((?:0|[1-9]\d*|\d*[a-zA-Z-][0-9a-zA-Z-]*)(?:\.(?:0|[1-9]\d*|\d*[a-zA-Z-][0-9a-zA-Z-]*))*))?(?:\+([0-9a-zA-Z-]+(?:\.[0-9a-zA-Z-]+)*))?$";
+
+/// Matches a hex encoded SHA256
+const SHA256_PATTERN: &str = "^[0-9a-fA-F]{64}$";
+
+/// Returns a JSON Schema describing the invoice format. The schema is generated from the `Invoice`
+/// type, so it always matches what this crate accepts, including the patterns that versions and
+/// SHAs must match
+pub fn invoice_schema() -> serde_json::Value {
+    // A schema only contains plain JSON values, so serializing it cannot fail
+    serde_json::to_value(schemars::schema_for!(Invoice))
+        .expect("invoice schema should serialize to JSON")
+}
+
+pub(crate) fn semver(_: &mut SchemaGenerator) -> Schema {
+    pattern_string(SEMVER_PATTERN)
+}
+
+pub(crate) fn sha256(_: &mut SchemaGenerator) -> Schema {
+    pattern_string(SHA256_PATTERN)
+}
+
+fn pattern_string(pattern: &str) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        string: Some(Box::new(StringValidation {
+            pattern: Some(pattern.to_owned()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_invoice_schema() {
+        let schema = invoice_schema();
+
+        let required: Vec<&str> = schema["required"]
+            .as_array()
+            .expect("schema should have required fields")
+            .iter()
+            .map(|v| v.as_str().unwrap())
+            .collect();
+        assert!(required.contains(&"bindleVersion"));
+        assert!(required.contains(&"bindle"));
+        assert!(!required.contains(&"parcel"), "Parcels should be optional");
+        assert_eq!(false, schema["additionalProperties"]);
+        assert_eq!(
+            SEMVER_PATTERN,
+            schema["properties"]["bindleVersion"]["pattern"]
+        );
+
+        let definitions = &schema["definitions"];
+        assert_eq!(
+            SHA256_PATTERN,
+            definitions["Label"]["properties"]["sha256"]["pattern"]
+        );
+        // The bindle ID is flattened into the bindle spec
+        assert_eq!(
+            SEMVER_PATTERN,
+            definitions["BindleSpec"]["properties"]["version"]["pattern"]
+        );
+        assert!(definitions["Condition"]["properties"]["lazy"].is_object());
+    }
+}
@@ -1,6 +1,7 @@
 //! Contains the Signature type along with associated types and Roles
 
 pub use ed25519_dalek::{Keypair, PublicKey, Signature as EdSignature, Signer};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::fs::OpenOptions;
@@ -11,6 +12,9 @@ use std::convert::{TryFrom, TryInto};
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::path::Path;
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Invoice, Label};
 
 /// The latest key ring version supported by this library.
 pub const KEY_RING_VERSION: &str = "1.0";
@@ -19,20 +23,223 @@ pub const KEY_RING_VERSION: &str = "1.0";
 ///
 /// In the current implementation, a signature signs the list of parcels that belong on
 /// an invoice. The signature, in the current implementation, is an Ed25519 signature
-/// and is signed by the private counterpart of the given public key.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// and is signed by the private counterpart of the given public key. A signature can also be
+/// attached to a single parcel's [`Label`](crate::Label), in which case it signs only that
+/// parcel's SHA.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct Signature {
     // The cleartext name of the user who signed
     pub by: String,
-    // The signature block, encoded as hex chars
+    // The signature block, encoded as base64
     pub signature: String,
-    // The public key, encoded as hex chars
+    // The public key, encoded as base64. This also serves as the ID of the signing key
     pub key: String,
     // The role of the signer
     pub role: SignatureRole,
     // The UNIX timestamp, expressed as an unsigned 64-bit integer
     pub at: u64,
+    // The algorithm used to create the signature. This is not serialized when it is the default
+    // so that invoices remain readable by implementations that do not know about this field
+    #[serde(default, skip_serializing_if = "SignatureAlgorithm::is_default")]
+    pub algorithm: SignatureAlgorithm,
+    // The parts of the invoice covered by the signature. Like the algorithm, this is not
+    // serialized when it is the default
+    #[serde(default, skip_serializing_if = "SignatureScope::is_default")]
+    pub scope: SignatureScope,
+}
+
+impl Signature {
+    /// Creates a new signature of the given invoice using the signing key with the given role. The
+    /// signature is not attached to the invoice. In most cases you should use
+    /// [`sign`](crate::sign) instead, which also checks for duplicate signatures
+    pub fn create(
+        invoice: &Invoice,
+        signing_key: &SecretKeyEntry,
+        role: SignatureRole,
+    ) -> Result<Self, SignatureError> {
+        Self::create_with_scope(invoice, signing_key, role, SignatureScope::default())
+    }
+
+    /// The same as [`create`](Signature::create), but signs only the parts of the invoice covered
+    /// by the given scope
+    pub fn create_with_scope(
+        invoice: &Invoice,
+        signing_key: &SecretKeyEntry,
+        role: SignatureRole,
+        scope: SignatureScope,
+    ) -> Result<Self, SignatureError> {
+        let mut sig = Self::create_for_cleartext(
+            invoice
+                .cleartext(&signing_key.label, &role, scope)
+                .as_bytes(),
+            signing_key,
+            role,
+        )?;
+        sig.scope = scope;
+        Ok(sig)
+    }
+
+    /// Creates a new signature of the parcel with the given label using the signing key with the
+    /// given role. The signature is not attached to the label. In most cases you should use
+    /// [`sign_parcel`](crate::sign_parcel) instead, which also checks for duplicate signatures
+    pub fn create_for_parcel(
+        label: &Label,
+        signing_key: &SecretKeyEntry,
+        role: SignatureRole,
+    ) -> Result<Self, SignatureError> {
+        Self::create_for_cleartext(
+            label.cleartext(&signing_key.label, &role).as_bytes(),
+            signing_key,
+            role,
+        )
+    }
+
+    /// Creates a detached signature of the given data, such as the raw bytes of an invoice file,
+    /// using the signing key with the given role. Unlike an invoice signature, this covers every
+    /// byte of the data, so it can be checked without parsing the data
+    pub fn create_detached(
+        data: &[u8],
+        signing_key: &SecretKeyEntry,
+        role: SignatureRole,
+    ) -> Result<Self, SignatureError> {
+        Self::create_for_cleartext(
+            &detached_cleartext(&signing_key.label, &role, data),
+            signing_key,
+            role,
+        )
+    }
+
+    fn create_for_cleartext(
+        cleartext: &[u8],
+        signing_key: &SecretKeyEntry,
+        role: SignatureRole,
+    ) -> Result<Self, SignatureError> {
+        let by = signing_key.label.clone();
+        let key = signing_key.key()?;
+        let signature: EdSignature = key.sign(cleartext);
+
+        // Timestamp should be generated at this moment.
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| SignatureError::SigningFailed)?;
+
+        Ok(Signature {
+            by,
+            signature: base64::encode(signature.to_bytes()),
+            key: base64::encode(key.public.to_bytes()),
+            role,
+            at: ts.as_secs(),
+            algorithm: SignatureAlgorithm::Ed25519,
+            scope: SignatureScope::default(),
+        })
+    }
+
+    /// Verifies that this signature is a valid signature of the given invoice made by the given
+    /// public key. Only the parts of the invoice in the signature's [scope](SignatureScope) are
+    /// checked
+    pub fn verify(&self, invoice: &Invoice, public_key: &PublicKey) -> Result<(), SignatureError> {
+        self.verify_cleartext(
+            invoice
+                .cleartext(&self.by, &self.role, self.scope)
+                .as_bytes(),
+            public_key,
+        )
+    }
+
+    /// Verifies that this signature is a valid signature of the parcel with the given label made by
+    /// the given public key
+    pub fn verify_parcel(
+        &self,
+        label: &Label,
+        public_key: &PublicKey,
+    ) -> Result<(), SignatureError> {
+        self.verify_cleartext(label.cleartext(&self.by, &self.role).as_bytes(), public_key)
+    }
+
+    /// Verifies that this is a valid detached signature of the given data made by the given public
+    /// key
+    pub fn verify_detached(
+        &self,
+        data: &[u8],
+        public_key: &PublicKey,
+    ) -> Result<(), SignatureError> {
+        self.verify_cleartext(&detached_cleartext(&self.by, &self.role, data), public_key)
+    }
+
+    fn verify_cleartext(
+        &self,
+        cleartext: &[u8],
+        public_key: &PublicKey,
+    ) -> Result<(), SignatureError> {
+        if self.public_key()? != *public_key {
+            return Err(SignatureError::Unverified(self.key.clone()));
+        }
+        let ed_sig = EdSignature::new(
+            self.signature_bytes()?
+                .as_slice()
+                .try_into()
+                .map_err(|_| SignatureError::CorruptSignature(self.key.clone()))?,
+        );
+        public_key
+            .verify_strict(cleartext, &ed_sig)
+            .map_err(|_| SignatureError::Unverified(self.key.clone()))
+    }
+
+    /// Returns the decoded public key that made this signature
+    pub fn public_key(&self) -> Result<PublicKey, SignatureError> {
+        let pk = base64::decode(self.key.as_bytes())
+            .map_err(|_| SignatureError::CorruptKey(self.key.clone()))?;
+        PublicKey::from_bytes(&pk).map_err(|_| SignatureError::CorruptKey(self.key.clone()))
+    }
+
+    /// Returns the decoded bytes of the signature
+    pub fn signature_bytes(&self) -> Result<Vec<u8>, SignatureError> {
+        base64::decode(self.signature.as_bytes())
+            .map_err(|_| SignatureError::CorruptSignature(self.key.clone()))
+    }
+}
+
+/// Returns the bytes signed by a detached signature. The signer and role are included so they
+/// cannot be changed without invalidating the signature
+fn detached_cleartext(by: &str, role: &SignatureRole, data: &[u8]) -> Vec<u8> {
+    let mut buf = format!("{}\n{}\n~\n", by, role).into_bytes();
+    buf.extend_from_slice(data);
+    buf
+}
+
+/// The algorithm used to create a [`Signature`](Signature)
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureAlgorithm {
+    #[default]
+    Ed25519,
+}
+
+impl SignatureAlgorithm {
+    fn is_default(&self) -> bool {
+        *self == SignatureAlgorithm::default()
+    }
+}
+
+/// The parts of an invoice covered by an invoice [`Signature`](Signature)
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum SignatureScope {
+    /// The name and version of the bindle along with its parcels, in order. This is the default
+    #[default]
+    Invoice,
+    /// Only the set of parcels in the bindle, as a sorted list of SHAs. This attests that these are
+    /// exactly the signer's parcels, and stays valid if the bindle is renamed, versioned again, or
+    /// its parcels reordered. As the bindle itself is not covered, the signature is also valid on
+    /// any other invoice with the same parcels
+    ParcelsOnly,
+}
+
+impl SignatureScope {
+    fn is_default(&self) -> bool {
+        *self == SignatureScope::default()
+    }
 }
 
 /// Wrap errors related to signing
@@ -66,7 +273,7 @@ pub enum SignatureError {
 ///
 /// Signatories on a signature must have an associated role, as defined in the
 /// specification.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 pub enum SignatureRole {
     Creator,
@@ -449,4 +656,73 @@ mod test {
             .expect("Should load key from file");
         assert_eq!(newfile.key.len(), 1);
     }
+
+    #[tokio::test]
+    async fn test_signature_round_trip() {
+        let invoice = crate::testing::Scaffold::load("valid_v1").await.invoice;
+        let key = SecretKeyEntry::new("test".to_owned(), vec![SignatureRole::Creator]);
+        let public_key = key.key().unwrap().public;
+
+        let sig = Signature::create(&invoice, &key, SignatureRole::Creator)
+            .expect("Should be able to create signature");
+        assert_eq!(SignatureAlgorithm::Ed25519, sig.algorithm);
+        sig.verify(&invoice, &public_key)
+            .expect("Signature should verify");
+
+        // The algorithm field is omitted for the default
+        let serialized = toml::to_string(&sig).expect("Should serialize signature");
+        assert!(!serialized.contains("algorithm"));
+        assert_eq!(
+            serialized,
+            toml::to_string(&sig).unwrap(),
+            "Serialization should be deterministic"
+        );
+        let deserialized: Signature =
+            toml::from_str(&serialized).expect("Should deserialize signature");
+        deserialized
+            .verify(&invoice, &public_key)
+            .expect("Deserialized signature should verify");
+    }
+
+    #[tokio::test]
+    async fn test_signature_tampering() {
+        let invoice = crate::testing::Scaffold::load("valid_v1").await.invoice;
+        let key = SecretKeyEntry::new("test".to_owned(), vec![SignatureRole::Creator]);
+        let public_key = key.key().unwrap().public;
+        let sig = Signature::create(&invoice, &key, SignatureRole::Creator).unwrap();
+
+        let mut tampered_invoice = invoice.clone();
+        tampered_invoice.parcel.as_mut().unwrap()[0].label.sha256 = "abc123".to_owned();
+        assert!(matches!(
+            sig.verify(&tampered_invoice, &public_key),
+            Err(SignatureError::Unverified(_))
+        ));
+
+        let mut tampered_sig = sig.clone();
+        tampered_sig.by = "someone else".to_owned();
+        assert!(matches!(
+            tampered_sig.verify(&invoice, &public_key),
+            Err(SignatureError::Unverified(_))
+        ));
+
+        let mut tampered_sig = sig.clone();
+        tampered_sig.role = SignatureRole::Host;
+        assert!(matches!(
+            tampered_sig.verify(&invoice, &public_key),
+            Err(SignatureError::Unverified(_))
+        ));
+
+        let other_key = SecretKeyEntry::new("other".to_owned(), vec![SignatureRole::Creator]);
+        assert!(matches!(
+            sig.verify(&invoice, &other_key.key().unwrap().public),
+            Err(SignatureError::Unverified(_))
+        ));
+
+        let mut tampered_sig = sig;
+        tampered_sig.signature = "not base64!".to_owned();
+        assert!(matches!(
+            tampered_sig.verify(&invoice, &public_key),
+            Err(SignatureError::CorruptSignature(_))
+        ));
+    }
 }
@@ -0,0 +1,46 @@
+//! Definition of the `InvoiceSummary` type, an aggregate view of the parcels in an invoice
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::invoice::Parcel;
+
+/// The aggregate counts for all parcels of a single media type
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaTypeSummary {
+    /// The number of parcels with this media type
+    pub parcel_count: u64,
+    /// The sum of the sizes of all parcels with this media type, in bytes
+    pub total_size: u64,
+}
+
+/// A summary of the parcels contained in an invoice, bucketed by media type. This is commonly used
+/// for displaying an overview of a bindle.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceSummary {
+    /// The total number of parcels in the invoice
+    pub parcel_count: u64,
+    /// The sum of the sizes of all parcels in the invoice, in bytes
+    pub total_size: u64,
+    /// Per media type counts, keyed by the media type of the parcel's label
+    pub media_types: BTreeMap<String, MediaTypeSummary>,
+}
+
+impl InvoiceSummary {
+    pub(crate) fn from_parcels<'a>(parcels: impl Iterator<Item = &'a Parcel>) -> Self {
+        parcels.fold(InvoiceSummary::default(), |mut summary, p| {
+            summary.parcel_count += 1;
+            summary.total_size += p.label.size;
+            let entry = summary
+                .media_types
+                .entry(p.label.media_type.clone())
+                .or_default();
+            entry.parcel_count += 1;
+            entry.total_size += p.label.size;
+            summary
+        })
+    }
+}
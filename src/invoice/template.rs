@@ -0,0 +1,138 @@
+//! Functions for rendering an invoice from a template containing `{{ var }}` placeholders
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::Invoice;
+
+const PLACEHOLDER_START: &str = "{{";
+const PLACEHOLDER_END: &str = "}}";
+
+/// Describes the various errors that can occur when rendering an invoice template
+#[derive(Error, Debug)]
+pub enum TemplateError {
+    /// A placeholder in the template did not have a value. Contains the name of the variable
+    #[error("No value was given for template variable {0:?}")]
+    MissingVariable(String),
+    /// A placeholder was opened with `{{` but never closed. Contains the byte offset of the opening
+    /// braces
+    #[error("Unterminated placeholder starting at byte {0}")]
+    Unterminated(usize),
+    /// A placeholder did not contain a valid variable name. Names may only contain alphanumeric
+    /// characters, `_`, `-`, and `.`
+    #[error("Invalid template variable name {0:?}")]
+    InvalidVariable(String),
+    /// The rendered template was not a valid invoice
+    #[error("Rendered template is not a valid invoice")]
+    InvalidToml(#[from] toml::de::Error),
+}
+
+/// Renders the given invoice template by replacing every `{{ var }}` placeholder with the matching
+/// value from `vars`, then parses the result as an invoice.
+///
+/// Values are substituted verbatim, so placeholders for string fields should be inside of quotes in
+/// the template (e.g. `version = "{{ version }}"`). Any placeholder without a matching value is an
+/// error. Extra values that are not used in the template are ignored
+pub fn render_invoice_template(
+    template: &str,
+    vars: &HashMap<String, String>,
+) -> Result<Invoice, TemplateError> {
+    let rendered = render(template, vars)?;
+    Ok(toml::from_str(&rendered)?)
+}
+
+fn render(template: &str, vars: &HashMap<String, String>) -> Result<String, TemplateError> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find(PLACEHOLDER_START) {
+        rendered.push_str(&rest[..start]);
+        let after_start = &rest[start + PLACEHOLDER_START.len()..];
+        let end = match after_start.find(PLACEHOLDER_END) {
+            Some(e) => e,
+            None => {
+                return Err(TemplateError::Unterminated(
+                    template.len() - rest.len() + start,
+                ))
+            }
+        };
+        let name = after_start[..end].trim();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+        {
+            return Err(TemplateError::InvalidVariable(name.to_owned()));
+        }
+        match vars.get(name) {
+            Some(val) => rendered.push_str(val),
+            None => return Err(TemplateError::MissingVariable(name.to_owned())),
+        }
+        rest = &after_start[end + PLACEHOLDER_END.len()..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEMPLATE: &str = r#"
+    bindleVersion = "1.0.0"
+
+    [bindle]
+    name = "enterprise.com/warpcore"
+    version = "{{ version }}"
+
+    [annotations]
+    commit = "{{commit}}"
+    "#;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_render_invoice_template() {
+        let inv = render_invoice_template(
+            TEMPLATE,
+            &vars(&[("version", "1.2.3"), ("commit", "abc"), ("unused", "foo")]),
+        )
+        .expect("template should render");
+        assert_eq!("1.2.3", inv.bindle.id.version_string());
+        assert_eq!(
+            "abc",
+            inv.annotations
+                .expect("annotations should exist")
+                .get("commit")
+                .expect("commit annotation should exist")
+        );
+    }
+
+    #[test]
+    fn test_render_errors() {
+        match render_invoice_template(TEMPLATE, &vars(&[("version", "1.2.3")])) {
+            Err(TemplateError::MissingVariable(name)) => assert_eq!("commit", name),
+            res => panic!("Expected a missing variable error, got {:?}", res),
+        }
+
+        assert!(matches!(
+            render("version = \"{{ version\"", &vars(&[("version", "1.2.3")])),
+            Err(TemplateError::Unterminated(11))
+        ));
+
+        assert!(matches!(
+            render("version = \"{{ }}\"", &HashMap::new()),
+            Err(TemplateError::InvalidVariable(_))
+        ));
+
+        assert!(matches!(
+            render_invoice_template(TEMPLATE, &vars(&[("version", "1.2.3"), ("commit", "\"")])),
+            Err(TemplateError::InvalidToml(_))
+        ));
+    }
+}
@@ -0,0 +1,136 @@
+//! Definition of the `UsageStats` type, the storage used by all bindles in a namespace
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::invoice::Invoice;
+
+/// The storage used by the bindles in a single namespace (see [`Id::namespace`](crate::Id::namespace)).
+///
+/// Parcels are content addressed, so a parcel referenced by several invoices in the same namespace
+/// is only counted once. A parcel referenced from more than one namespace is charged in full to
+/// each of them, and its size is also included in `shared_bytes`, so `bytes - shared_bytes` is the
+/// storage that would be freed if the namespace were removed. Yanked invoices still use storage, so
+/// they are included
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageStats {
+    /// The number of invoices in the namespace, including yanked invoices
+    pub invoice_count: u64,
+    /// The sum of the sizes of all distinct parcels referenced by the namespace, in bytes
+    pub bytes: u64,
+    /// The part of `bytes` used by parcels that are also referenced from another namespace
+    pub shared_bytes: u64,
+}
+
+/// Computes the storage used by each namespace of the given invoices, keyed by namespace
+pub fn usage_by_namespace<'a>(
+    invoices: impl IntoIterator<Item = &'a Invoice>,
+) -> HashMap<String, UsageStats> {
+    let mut counts: HashMap<&str, u64> = HashMap::new();
+    // The size of every distinct parcel referenced by each namespace, keyed by SHA
+    let mut parcels: HashMap<&str, HashMap<&str, u64>> = HashMap::new();
+    for invoice in invoices {
+        let namespace = invoice.bindle.id.namespace();
+        *counts.entry(namespace).or_default() += 1;
+        let referenced = parcels.entry(namespace).or_default();
+        for parcel in invoice.parcel.iter().flatten() {
+            referenced.insert(&parcel.label.sha256, parcel.label.size);
+        }
+    }
+
+    let mut referencers: HashMap<&str, u64> = HashMap::new();
+    for sha in parcels.values().flat_map(|p| p.keys()) {
+        *referencers.entry(sha).or_default() += 1;
+    }
+    let shared: HashSet<&str> = referencers
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(sha, _)| sha)
+        .collect();
+
+    counts
+        .into_iter()
+        .map(|(namespace, invoice_count)| {
+            let referenced = &parcels[namespace];
+            let stats = UsageStats {
+                invoice_count,
+                bytes: referenced.values().sum(),
+                shared_bytes: referenced
+                    .iter()
+                    .filter(|(sha, _)| shared.contains(*sha))
+                    .map(|(_, size)| size)
+                    .sum(),
+            };
+            (namespace.to_owned(), stats)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::invoice::{Label, Parcel};
+
+    fn invoice(id: &str, parcels: &[(&str, u64)]) -> Invoice {
+        let mut invoice = Invoice::new(crate::BindleSpec {
+            id: id.parse().unwrap(),
+            description: None,
+            authors: None,
+        });
+        invoice.parcel = Some(
+            parcels
+                .iter()
+                .map(|(sha, size)| Parcel {
+                    label: Label {
+                        sha256: sha.to_string(),
+                        size: *size,
+                        ..Label::default()
+                    },
+                    conditions: None,
+                })
+                .collect(),
+        );
+        invoice
+    }
+
+    #[test]
+    fn test_usage_by_namespace() {
+        let invoices = vec![
+            invoice("example.com/foo/1.0.0", &[("a", 100), ("b", 10)]),
+            // The same parcels in another invoice of the namespace are only counted once
+            invoice("example.com/foo/1.1.0", &[("a", 100), ("c", 1)]),
+            invoice("example.com/bar/1.0.0", &[]),
+            invoice("other.com/foo/1.0.0", &[("a", 100), ("d", 5)]),
+            invoice("foo/1.0.0", &[]),
+        ];
+        let usage = usage_by_namespace(&invoices);
+
+        assert_eq!(3, usage.len());
+        assert_eq!(
+            UsageStats {
+                invoice_count: 3,
+                bytes: 111,
+                shared_bytes: 100,
+            },
+            usage["example.com"]
+        );
+        assert_eq!(
+            UsageStats {
+                invoice_count: 1,
+                bytes: 105,
+                shared_bytes: 100,
+            },
+            usage["other.com"]
+        );
+        assert_eq!(
+            UsageStats {
+                invoice_count: 1,
+                bytes: 0,
+                shared_bytes: 0,
+            },
+            usage[""]
+        );
+    }
+}
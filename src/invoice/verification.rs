@@ -1,12 +1,10 @@
 use crate::invoice::Signed;
 
 use super::signature::KeyRing;
-use super::{Invoice, Signature, SignatureError, SignatureRole};
-use ed25519_dalek::{PublicKey, Signature as EdSignature};
+use super::{Invoice, SignatureError, SignatureRole, SignatureScope};
 use tracing::{debug, info};
 
 use std::borrow::{Borrow, BorrowMut};
-use std::convert::TryInto;
 use std::fmt::Debug;
 use std::str::FromStr;
 
@@ -136,24 +134,6 @@ fn parse_roles(r: Option<&&str>) -> Result<Vec<SignatureRole>, &'static str> {
 
 /// A strategy for verifying an invoice.
 impl VerificationStrategy {
-    fn verify_signature(&self, sig: &Signature, cleartext: &[u8]) -> Result<(), SignatureError> {
-        let pk = base64::decode(sig.key.as_bytes())
-            .map_err(|_| SignatureError::CorruptKey(sig.key.clone()))?;
-        let sig_block = base64::decode(sig.signature.as_bytes())
-            .map_err(|_| SignatureError::CorruptSignature(sig.key.clone()))?;
-
-        let pubkey =
-            PublicKey::from_bytes(&pk).map_err(|_| SignatureError::CorruptKey(sig.key.clone()))?;
-        let ed_sig = EdSignature::new(
-            sig_block
-                .as_slice()
-                .try_into()
-                .map_err(|_| SignatureError::CorruptSignature(sig.key.clone()))?,
-        );
-        pubkey
-            .verify_strict(cleartext, &ed_sig)
-            .map_err(|_| SignatureError::Unverified(sig.key.clone()))
-    }
     /// Verify that every signature on this invoice is correct.
     ///
     /// The verification strategy will determine how this verification is performed.
@@ -216,16 +196,24 @@ impl VerificationStrategy {
                     }
 
                     let role = s.role.clone();
-                    let cleartext = inv.cleartext(&s.by, &role);
 
                     // Verify the signature
                     // TODO: This would allow a trivial DOS attack in which an attacker
                     // would only need to attach a known-bad signature, and that would
                     // prevent the module from ever being usable. This is marginally
                     // better if we only verify signatures on known keys.
-                    self.verify_signature(&s, cleartext.as_bytes())?;
+                    let pko = s.public_key()?;
+                    s.verify(inv, &pko)?;
                     debug!("Signature verified");
 
+                    // A parcels-only signature doesn't cover the bindle name or version, so it
+                    // would still verify if copied onto a different bindle with the same parcels.
+                    // It can't attest to the invoice, so it never fills a role
+                    if s.scope != SignatureScope::Invoice {
+                        debug!("Parcels-only signature, not counting it toward any role");
+                        continue;
+                    }
+
                     if !target_role && !all_verified {
                         debug!("Not a target role, not checking for verification");
                         continue;
@@ -233,10 +221,6 @@ impl VerificationStrategy {
                         filled_roles.push(role);
                     }
                     // See if the public key is known to us
-                    let pubkey = base64::decode(&s.key)
-                        .map_err(|_| SignatureError::CorruptKey(s.key.to_string()))?;
-                    let pko = PublicKey::from_bytes(pubkey.as_slice())
-                        .map_err(|_| SignatureError::CorruptKey(s.key.to_string()))?;
 
                     debug!("Looking for key");
                     // If the keyring contains PKO, then we are successful for this round.
@@ -311,6 +295,7 @@ where
 }
 
 /// An internal only type that implementes `Verified` for use in caches and other passthroughs
+#[cfg_attr(not(feature = "caching"), allow(dead_code))]
 pub(crate) struct NoopVerified<T: Into<crate::Invoice>>(pub(crate) T);
 
 impl<T: Into<crate::Invoice>> Verified for NoopVerified<T> {}
@@ -356,6 +341,7 @@ where
 mod test {
     use super::*;
     use crate::invoice::*;
+    use std::convert::TryInto;
 
     #[test]
     fn test_parse_verification_strategies() {
@@ -12,10 +12,13 @@ mod id;
 pub mod invoice;
 
 pub mod async_util;
+pub mod audit;
 #[cfg(feature = "caching")]
 pub mod cache;
 #[cfg(feature = "client")]
 pub mod client;
+#[cfg(feature = "conformance")]
+pub mod conformance;
 pub mod provider;
 #[cfg(feature = "client")]
 pub mod proxy;
@@ -26,12 +29,16 @@ pub mod server;
 pub mod standalone;
 #[cfg(feature = "test-tools")]
 pub mod testing;
+#[cfg(feature = "client")]
+pub mod util;
 
 #[cfg(feature = "server")]
 pub mod authn;
 #[cfg(feature = "server")]
 pub mod authz;
 pub mod filters;
+#[cfg(feature = "server")]
+pub mod interceptor;
 
 #[doc(inline)]
 pub use id::Id;
@@ -382,6 +382,26 @@ impl<T: crate::search::Search + Send + Sync> Provider for EmbeddedProvider<T> {
             .await?
             .map_err(map_sled_error)
     }
+
+    #[instrument(level = "trace", skip(self))]
+    async fn parcels_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let parcels = self.parcels.clone();
+        let owned_prefix = prefix.to_owned();
+        let keys = spawn_lock(self.semaphore.clone(), move || {
+            parcels
+                .scan_prefix(owned_prefix.as_bytes())
+                .keys()
+                .collect::<std::result::Result<Vec<_>, _>>()
+        })
+        .await?
+        .map_err(map_sled_error)?;
+        let matches: Vec<String> = keys
+            .into_iter()
+            .map(|k| String::from_utf8_lossy(&k).into_owned())
+            .collect();
+        debug!(total = matches.len(), "Found parcels matching prefix");
+        Ok(matches)
+    }
 }
 
 fn map_io_error(e: std::io::Error) -> ProviderError {
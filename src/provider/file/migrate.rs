@@ -0,0 +1,479 @@
+//! Migrations between versions of the [`FileProvider`](super::FileProvider) storage layout
+//!
+//! The version of a bindle directory is recorded in a [`LAYOUT_FILE`](LAYOUT_FILE) at its root.
+//! Directories created before the layout was versioned have no such file and are treated as
+//! version 0. Each migration step moves a directory forward by exactly one version and is safe to
+//! run more than once, so an interrupted migration can be resumed by running it again. The layout
+//! file is only updated after a step has fully completed
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::fs::File;
+use tracing::{debug, info, instrument, trace};
+
+use super::{
+    validate_sha256, INVOICE_DIRECTORY, INVOICE_TOML, PARCEL_DAT, PARCEL_DIRECTORY, PART_EXTENSION,
+};
+use crate::provider::ProviderError;
+
+/// The file name of the layout file at the root of a bindle directory
+pub const LAYOUT_FILE: &str = "layout.toml";
+/// The newest layout version, which is the one written by this version of Bindle
+pub const CURRENT_LAYOUT_VERSION: u32 = 1;
+
+/// The errors that can occur while migrating a bindle directory
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    /// The directory is at a different version than the migration expected
+    #[error("expected layout version {expected}, but the directory is at version {found}")]
+    VersionMismatch { expected: u32, found: u32 },
+    /// The requested version is newer than this version of Bindle supports
+    #[error("layout version {0} is not supported, the newest supported version is {CURRENT_LAYOUT_VERSION}")]
+    UnsupportedVersion(u32),
+    /// Migrating to an older layout is not supported
+    #[error("cannot migrate from layout version {from} down to version {to}")]
+    Downgrade { from: u32, to: u32 },
+    /// A stored invoice or parcel failed its integrity check
+    #[error("integrity check failed for {}: {reason}", .path.display())]
+    Corrupt { path: PathBuf, reason: String },
+    /// The directory did not contain the same bindles after migrating
+    #[error("migration lost data: found {before} {kind} before migrating and {after} after")]
+    CountMismatch {
+        kind: &'static str,
+        before: usize,
+        after: usize,
+    },
+    #[error("Malformed layout file: {0}")]
+    MalformedLayout(#[from] toml::de::Error),
+    #[error("I/O error while migrating: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A custom shorthand result type that always has an error type of
+/// [`MigrationError`](MigrationError)
+pub type Result<T> = core::result::Result<T, MigrationError>;
+
+/// The totals found when checking the integrity of a bindle directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    pub invoices: usize,
+    pub parcels: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LayoutFile {
+    version: u32,
+}
+
+/// Returns the layout version of the given bindle directory. Directories without a layout file
+/// are version 0
+pub async fn layout_version(root: &Path) -> Result<u32> {
+    match tokio::fs::read(root.join(LAYOUT_FILE)).await {
+        Ok(raw) => Ok(toml::from_slice::<LayoutFile>(&raw)?.version),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Records the current layout version in a bindle directory that doesn't hold any invoices or
+/// parcels yet, creating the directory if needed. Returns false without changing anything if the
+/// directory already has a layout file or contains data, as that data may need to be migrated
+/// first
+pub async fn init_layout(root: &Path) -> Result<bool> {
+    if exists(&root.join(LAYOUT_FILE)).await
+        || exists(&root.join(INVOICE_DIRECTORY)).await
+        || exists(&root.join(PARCEL_DIRECTORY)).await
+    {
+        return Ok(false);
+    }
+    tokio::fs::create_dir_all(root).await?;
+    write_layout_version(root, CURRENT_LAYOUT_VERSION).await?;
+    Ok(true)
+}
+
+async fn write_layout_version(root: &Path, version: u32) -> Result<()> {
+    let data =
+        toml::to_vec(&LayoutFile { version }).expect("layout file should always serialize to TOML");
+    // Write to a temporary file first so an interruption can't leave a truncated layout file
+    let tmp = root.join(format!("{}.{}", LAYOUT_FILE, PART_EXTENSION));
+    tokio::fs::write(&tmp, data).await?;
+    tokio::fs::rename(&tmp, root.join(LAYOUT_FILE)).await?;
+    Ok(())
+}
+
+/// Migrates the bindle directory at `root` from layout version `from` to version `to`, checking
+/// the integrity of every invoice and parcel before and after.
+///
+/// If `target` is set, the directory is first copied there and only the copy is migrated, leaving
+/// `root` untouched. If the directory is already past `from` (such as when a previous run was
+/// interrupted), the migration picks up from the version it is at
+#[instrument(level = "trace", skip(root, target), fields(root = %root.display()))]
+pub async fn migrate(
+    root: &Path,
+    from: u32,
+    to: u32,
+    target: Option<&Path>,
+) -> Result<IntegrityReport> {
+    if to > CURRENT_LAYOUT_VERSION {
+        return Err(MigrationError::UnsupportedVersion(to));
+    }
+    if to < from {
+        return Err(MigrationError::Downgrade { from, to });
+    }
+
+    let before = verify_integrity(root).await?;
+    info!(
+        invoices = before.invoices,
+        parcels = before.parcels,
+        "Verified bindle directory before migrating"
+    );
+
+    let dir = match target {
+        Some(t) => {
+            info!(target = %t.display(), "Copying bindle directory");
+            copy_dir(root, t).await?;
+            t
+        }
+        None => root,
+    };
+
+    let current = layout_version(dir).await?;
+    if current < from || current > to {
+        return Err(MigrationError::VersionMismatch {
+            expected: from,
+            found: current,
+        });
+    }
+    if current > from {
+        info!(version = current, "Resuming interrupted migration");
+    }
+
+    for version in current..to {
+        info!(from = version, to = version + 1, "Running migration step");
+        step(dir, version).await?;
+        write_layout_version(dir, version + 1).await?;
+    }
+
+    let after = verify_integrity(dir).await?;
+    if after.invoices != before.invoices {
+        return Err(MigrationError::CountMismatch {
+            kind: "invoices",
+            before: before.invoices,
+            after: after.invoices,
+        });
+    }
+    if after.parcels != before.parcels {
+        return Err(MigrationError::CountMismatch {
+            kind: "parcels",
+            before: before.parcels,
+            after: after.parcels,
+        });
+    }
+    info!(version = to, "Migration complete");
+    Ok(after)
+}
+
+/// Runs the step that moves a directory from the given version to the next one
+async fn step(root: &Path, from: u32) -> Result<()> {
+    match from {
+        // Version 1 only adds the layout file, but unversioned directories may also contain part
+        // files left behind by a crashed server. These would otherwise block any future write to
+        // the same invoice or parcel, so they are cleaned up here
+        0 => remove_part_files(root).await,
+        _ => Err(MigrationError::UnsupportedVersion(from + 1)),
+    }
+}
+
+async fn remove_part_files(root: &Path) -> Result<()> {
+    for (dir, file_name) in &[
+        (INVOICE_DIRECTORY, INVOICE_TOML),
+        (PARCEL_DIRECTORY, PARCEL_DAT),
+    ] {
+        let part_name = format!("{}.{}", file_name, PART_EXTENSION);
+        for entry in entries(&root.join(dir)).await? {
+            let part = entry.join(&part_name);
+            match tokio::fs::remove_file(&part).await {
+                Ok(_) => debug!(path = %part.display(), "Removed stale part file"),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+            // If the first write never finished, there is nothing else in the directory
+            if !exists(&entry.join(file_name)).await {
+                tokio::fs::remove_dir(&entry).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that every invoice in the directory parses and is stored under its canonical name and
+/// that every parcel matches its SHA
+pub async fn verify_integrity(root: &Path) -> Result<IntegrityReport> {
+    let mut report = IntegrityReport::default();
+
+    for dir in entries(&root.join(INVOICE_DIRECTORY)).await? {
+        let path = dir.join(INVOICE_TOML);
+        if is_unfinished(&path).await {
+            continue;
+        }
+        trace!(path = %path.display(), "Checking invoice");
+        let corrupt = |reason: String| MigrationError::Corrupt {
+            path: path.clone(),
+            reason,
+        };
+        let raw = tokio::fs::read(&path)
+            .await
+            .map_err(|e| corrupt(e.to_string()))?;
+        let invoice: crate::Invoice = toml::from_slice(&raw).map_err(|e| corrupt(e.to_string()))?;
+        if Some(invoice.canonical_name().as_str()) != dir.file_name().and_then(|n| n.to_str()) {
+            return Err(corrupt(
+                "invoice is not stored under its canonical name".to_owned(),
+            ));
+        }
+        report.invoices += 1;
+    }
+
+    for dir in entries(&root.join(PARCEL_DIRECTORY)).await? {
+        let path = dir.join(PARCEL_DAT);
+        if is_unfinished(&path).await {
+            continue;
+        }
+        trace!(path = %path.display(), "Checking parcel");
+        let sha = dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let result = match File::open(&path).await {
+            Ok(mut file) => validate_sha256(&mut file, &sha).await,
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok(_) => report.parcels += 1,
+            Err(ProviderError::DigestMismatch) => {
+                return Err(MigrationError::Corrupt {
+                    path,
+                    reason: "parcel data does not match its SHA".to_owned(),
+                })
+            }
+            Err(e) => {
+                return Err(MigrationError::Corrupt {
+                    path,
+                    reason: e.to_string(),
+                })
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Returns true if the file at the given path was never written because the write of its part
+/// file was interrupted
+async fn is_unfinished(path: &Path) -> bool {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".");
+    part.push(PART_EXTENSION);
+    !exists(path).await && exists(Path::new(&part)).await
+}
+
+/// Returns true if anything exists at the given path, without blocking the runtime like
+/// [`Path::exists`] does
+async fn exists(path: &Path) -> bool {
+    tokio::fs::metadata(path).await.is_ok()
+}
+
+/// Returns the paths of all directories inside of the given directory, or an empty list if it
+/// does not exist
+async fn entries(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut readdir = match tokio::fs::read_dir(dir).await {
+        Ok(r) => r,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut paths = Vec::new();
+    while let Some(entry) = readdir.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            paths.push(entry.path());
+        }
+    }
+    Ok(paths)
+}
+
+/// Recursively copies `src` into `dest`. Files that already exist in `dest` with the same size are
+/// skipped so an interrupted copy can be resumed
+async fn copy_dir(src: &Path, dest: &Path) -> Result<()> {
+    let mut pending = vec![(src.to_owned(), dest.to_owned())];
+    while let Some((from, to)) = pending.pop() {
+        tokio::fs::create_dir_all(&to).await?;
+        let mut readdir = tokio::fs::read_dir(&from).await?;
+        while let Some(entry) = readdir.next_entry().await? {
+            let target = to.join(entry.file_name());
+            let metadata = entry.metadata().await?;
+            if metadata.is_dir() {
+                pending.push((entry.path(), target));
+                continue;
+            }
+            match tokio::fs::metadata(&target).await {
+                Ok(existing) if existing.len() == metadata.len() => {
+                    trace!(path = %target.display(), "File already copied, skipping");
+                }
+                _ => {
+                    tokio::fs::copy(entry.path(), &target).await?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::invoice::signature::{KeyRing, SecretKeyEntry, SignatureRole};
+    use crate::provider::file::FileProvider;
+    use crate::provider::Provider;
+    use crate::testing;
+    use crate::VerificationStrategy;
+    use tempfile::tempdir;
+    use tokio_util::codec::{BytesCodec, FramedRead};
+
+    async fn populated_dir() -> tempfile::TempDir {
+        let temp = tempdir().expect("Unable to create tempdir");
+        let store = FileProvider::new(temp.path(), crate::search::StrictEngine::default()).await;
+        let scaffold = testing::Scaffold::load("valid_v1").await;
+        let sk = SecretKeyEntry::new(
+            "Test <test@example.com>".to_owned(),
+            vec![SignatureRole::Creator],
+        );
+        let verified = VerificationStrategy::MultipleAttestation(vec![])
+            .verify(scaffold.invoice.clone(), &KeyRing::default())
+            .unwrap();
+        let signed = crate::invoice::sign(verified, vec![(SignatureRole::Creator, &sk)]).unwrap();
+        store
+            .create_invoice(signed)
+            .await
+            .expect("Unable to create invoice");
+        for parcel in scaffold.parcel_files.values() {
+            store
+                .create_parcel(
+                    &scaffold.invoice.bindle.id,
+                    &parcel.sha,
+                    FramedRead::new(std::io::Cursor::new(parcel.data.clone()), BytesCodec::new()),
+                )
+                .await
+                .expect("Unable to create parcel");
+        }
+        // Directories from before the layout was versioned have no layout file
+        tokio::fs::remove_file(temp.path().join(LAYOUT_FILE))
+            .await
+            .expect("Unable to remove layout file");
+        temp
+    }
+
+    #[tokio::test]
+    async fn test_init_layout() {
+        let temp = tempdir().expect("Unable to create tempdir");
+        let root = temp.path().join("bindles");
+        FileProvider::new(&root, crate::search::StrictEngine::default()).await;
+        assert_eq!(
+            CURRENT_LAYOUT_VERSION,
+            layout_version(&root).await.unwrap(),
+            "A new directory should be created at the current layout version"
+        );
+
+        // Existing data without a layout file must be migrated rather than assumed current
+        let old = populated_dir().await;
+        assert!(!init_layout(old.path()).await.unwrap());
+        FileProvider::new(old.path(), crate::search::StrictEngine::default()).await;
+        assert_eq!(0, layout_version(old.path()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_in_place() {
+        let temp = populated_dir().await;
+        let parcel_dir = entries(&temp.path().join(PARCEL_DIRECTORY))
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let stale = parcel_dir.join(format!("{}.{}", PARCEL_DAT, PART_EXTENSION));
+        tokio::fs::write(&stale, b"partial").await.unwrap();
+        // A parcel whose first write was interrupted only has a part file
+        let unfinished = temp.path().join(PARCEL_DIRECTORY).join("abc123");
+        tokio::fs::create_dir_all(&unfinished).await.unwrap();
+        tokio::fs::write(
+            unfinished.join(format!("{}.{}", PARCEL_DAT, PART_EXTENSION)),
+            b"partial",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(0, layout_version(temp.path()).await.unwrap());
+        let report = migrate(temp.path(), 0, 1, None)
+            .await
+            .expect("Migration should succeed");
+        assert_eq!(1, report.invoices);
+        assert_eq!(1, layout_version(temp.path()).await.unwrap());
+        assert!(!exists(&stale).await, "Stale part file should be removed");
+        assert!(
+            !exists(&unfinished).await,
+            "Unfinished parcel directory should be removed"
+        );
+
+        // Running it again is a no-op
+        migrate(temp.path(), 0, 1, None)
+            .await
+            .expect("Rerunning a migration should succeed");
+
+        assert!(matches!(
+            migrate(temp.path(), 1, 0, None).await,
+            Err(MigrationError::Downgrade { .. })
+        ));
+        assert!(matches!(
+            migrate(temp.path(), 1, CURRENT_LAYOUT_VERSION + 1, None).await,
+            Err(MigrationError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_migrate_to_target() {
+        let temp = populated_dir().await;
+        let target = tempdir().expect("Unable to create tempdir");
+        let target_root = target.path().join("migrated");
+
+        migrate(temp.path(), 0, 1, Some(&target_root))
+            .await
+            .expect("Migration should succeed");
+        assert_eq!(0, layout_version(temp.path()).await.unwrap());
+        assert_eq!(1, layout_version(&target_root).await.unwrap());
+        assert_eq!(
+            verify_integrity(temp.path()).await.unwrap(),
+            verify_integrity(&target_root).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_migrate_corrupt() {
+        let temp = populated_dir().await;
+        let parcel_dir = entries(&temp.path().join(PARCEL_DIRECTORY))
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        tokio::fs::write(parcel_dir.join(PARCEL_DAT), b"corrupted")
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            migrate(temp.path(), 0, 1, None).await,
+            Err(MigrationError::Corrupt { .. })
+        ));
+        assert_eq!(
+            0,
+            layout_version(temp.path()).await.unwrap(),
+            "A failed integrity check should not change the layout version"
+        );
+    }
+}
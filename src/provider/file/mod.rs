@@ -2,6 +2,8 @@
 //! [documented](https://github.com/deislabs/bindle/blob/master/docs/file-layout.md) in the main
 //! Bindle repo.
 
+pub mod migrate;
+
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
@@ -27,10 +29,11 @@ use crate::verification::Verified;
 use crate::{Id, Signed};
 
 /// The folder name for the invoices directory
-const INVOICE_DIRECTORY: &str = "invoices";
+pub const INVOICE_DIRECTORY: &str = "invoices";
 /// The folder name for the parcels directory
 pub const PARCEL_DIRECTORY: &str = "parcels";
-const INVOICE_TOML: &str = "invoice.toml";
+/// The file name of the invoice inside of an invoice directory
+pub const INVOICE_TOML: &str = "invoice.toml";
 pub const PARCEL_DAT: &str = "parcel.dat";
 const CACHE_SIZE: usize = 50;
 const PART_EXTENSION: &str = "part";
@@ -66,6 +69,27 @@ impl<T: Search + Send + Sync> FileProvider<T> {
             index,
             invoice_cache: Arc::new(TokioMutex::new(LruCache::new(CACHE_SIZE))),
         };
+        match migrate::layout_version(path.as_ref()).await {
+            Ok(v) if v > migrate::CURRENT_LAYOUT_VERSION => {
+                warn!(
+                    version = v,
+                    supported = migrate::CURRENT_LAYOUT_VERSION,
+                    "Bindle directory uses a newer layout than this version supports"
+                )
+            }
+            Ok(0) => match migrate::init_layout(path.as_ref()).await {
+                Ok(true) => debug!(
+                    version = migrate::CURRENT_LAYOUT_VERSION,
+                    "Initialized new bindle directory"
+                ),
+                Ok(false) => warn!(
+                    "Bindle directory has no layout version and should be migrated with `bindle-server migrate`"
+                ),
+                Err(e) => warn!(error = %e, "Unable to write layout version"),
+            },
+            Ok(_) => {}
+            Err(e) => warn!(error = %e, "Unable to read layout version"),
+        }
         debug!("warming index");
         if let Err(e) = fs.warm_index().await {
             warn!(error = %e, "Error warming index");
@@ -337,14 +361,17 @@ impl<T: crate::search::Search + Send + Sync> Provider for FileProvider<T> {
         tracing::Span::current().record("id", &tracing::field::display(&parsed_id));
         let label = self.validate_parcel(parsed_id, parcel_id).await?;
 
-        // Test if a dir with that SHA exists. If so, this is an error.
+        // Test if the parcel data already exists. If so, this is an error. Only the data file is
+        // checked, as the directory is left behind by uploads that were rejected, and those must
+        // not block a retry with the right data
         let par_path = self.parcel_path(parcel_id);
-        if tokio::fs::metadata(&par_path)
+        let data_path = self.parcel_data_path(parcel_id);
+        if tokio::fs::metadata(&data_path)
             .await
-            .map(|m| m.is_dir())
+            .map(|m| m.is_file())
             .unwrap_or(false)
         {
-            debug!(path = %par_path.display(), "Parcel directory already exists");
+            debug!(path = %data_path.display(), "Parcel data already exists");
             return Err(ProviderError::Exists);
         }
         // Create box dir
@@ -352,7 +379,7 @@ impl<T: crate::search::Search + Send + Sync> Provider for FileProvider<T> {
         create_dir_all(par_path).await?;
 
         // Write data
-        let mut part = PartFile::new(self.parcel_data_path(parcel_id)).await?;
+        let mut part = PartFile::new(data_path).await?;
         part.write_parcel(data, parcel_id, label.size).await?;
         part.finalize().await
     }
@@ -400,6 +427,41 @@ impl<T: crate::search::Search + Send + Sync> Provider for FileProvider<T> {
             Err(e) => Err(e.into()),
         }
     }
+
+    #[instrument(level = "trace", skip(self))]
+    async fn parcels_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut readdir = match tokio::fs::read_dir(self.parcel_path("")).await {
+            Ok(r) => r,
+            // No parcels have been stored yet
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut matches = Vec::new();
+        while let Some(entry) = readdir.next_entry().await? {
+            let sha = entry.file_name().to_string_lossy().into_owned();
+            if !sha.starts_with(prefix) {
+                continue;
+            }
+            // Only count parcels that have finished writing
+            if tokio::fs::metadata(self.parcel_data_path(&sha))
+                .await
+                .map(|m| m.is_file())
+                .unwrap_or(false)
+            {
+                matches.push(sha);
+            }
+        }
+        debug!(total = matches.len(), "Found parcels matching prefix");
+        Ok(matches)
+    }
+
+    #[instrument(level = "trace", skip(self))]
+    async fn ready(&self) -> Result<()> {
+        // The root directory is otherwise created lazily on the first write, so creating it here
+        // also catches problems like a missing mount or bad permissions before any writes happen
+        create_dir_all(&self.root).await?;
+        Ok(())
+    }
 }
 
 fn map_io_error(e: std::io::Error) -> ProviderError {
@@ -544,12 +606,22 @@ impl PartFile {
             }
         };
         #[cfg(target_family = "unix")]
-        let file = OpenOptions::new()
+        let file = match OpenOptions::new()
             .create_new(true)
             .write(true)
             .read(true)
             .open(&part)
-            .await?;
+            .await
+        {
+            Ok(f) => f,
+            // Another write created the part file after the check above
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(ProviderError::WriteInProgress)
+            }
+            Err(e) => {
+                return Err(e.into());
+            }
+        };
         Ok(PartFile {
             path: part,
             final_location,
@@ -626,6 +698,19 @@ impl PartFile {
         // Close the file handle to avoid any problems with unfinished IO operations
         self.file.shutdown().await?;
 
+        // Another write may have finished between the existence check before this write started
+        // and now. Part files are created exclusively, so any write that finished did so before
+        // this one created its part file, and checking again here keeps it from being replaced.
+        // The part file is cleaned up on drop
+        if tokio::fs::metadata(&self.final_location)
+            .await
+            .map(|m| m.is_file())
+            .unwrap_or(false)
+        {
+            debug!(path = %self.final_location.display(), "File was created by another write");
+            return Err(ProviderError::Exists);
+        }
+
         tokio::fs::rename(&self.path, &self.final_location)
             .await
             .map_err(|e| e.into())
@@ -883,6 +968,54 @@ mod test {
         )
     }
 
+    #[tokio::test]
+    async fn test_retry_after_rejected_parcel() {
+        let root = tempdir().unwrap();
+        let scaffold = testing::Scaffold::load("valid_v1").await;
+        let store = FileProvider::new(
+            root.path().to_owned(),
+            crate::search::StrictEngine::default(),
+        )
+        .await;
+
+        let sk = mock_secret_key();
+        let verified = VerificationStrategy::MultipleAttestation(vec![])
+            .verify(scaffold.invoice.clone(), &KeyRing::default())
+            .unwrap();
+        let signed = crate::invoice::sign(verified, vec![(SignatureRole::Creator, &sk)]).unwrap();
+        store
+            .create_invoice(signed)
+            .await
+            .expect("Invoice should be created");
+
+        // Different bytes of the same length must be rejected as not matching the SHA
+        let parcel = scaffold.parcel_files.get("parcel").unwrap();
+        let corrupt: Vec<u8> = parcel.data.iter().map(|b| b.wrapping_add(1)).collect();
+        let err = store
+            .create_parcel(
+                &scaffold.invoice.bindle.id,
+                &parcel.sha,
+                FramedRead::new(std::io::Cursor::new(corrupt), BytesCodec::new()),
+            )
+            .await
+            .expect_err("Creating a parcel with the wrong data should fail");
+        assert!(
+            matches!(err, ProviderError::DigestMismatch),
+            "Error should be of type DigestMismatch, got {:?}",
+            err
+        );
+
+        // The rejected upload must not block a retry with the right data
+        store
+            .create_parcel(
+                &scaffold.invoice.bindle.id,
+                &parcel.sha,
+                FramedRead::new(std::io::Cursor::new(parcel.data.clone()), BytesCodec::new()),
+            )
+            .await
+            .expect("Retrying with the right data should succeed");
+    }
+
     // Running this as multi thread to make sure both processes run simultaneously
     #[tokio::test(flavor = "multi_thread")]
     async fn test_double_write() {
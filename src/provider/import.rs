@@ -0,0 +1,275 @@
+//! Imports a directory of invoices and their parcels directly into a [`Provider`], without going
+//! through a running server
+//!
+//! Every file with a `.toml` extension found under the directory (including in subdirectories) is
+//! imported as an invoice. The parcels referenced by an invoice are looked for in the directory
+//! containing the invoice file, first as `parcels/<SHA>.dat` (the layout of a
+//! [standalone bindle](crate::standalone)) and then as a file named after the parcel label. Invoices
+//! and parcels that already exist are skipped, so an import that failed part way through can be
+//! completed by running it again
+
+use std::path::{Path, PathBuf};
+
+use tokio_stream::StreamExt;
+use tokio_util::codec::{BytesCodec, FramedRead};
+use tracing::{debug, info, instrument, trace};
+
+use super::{Provider, ProviderError, Result};
+use crate::invoice::signature::{KeyRing, SecretKeyStorage, SignatureError, SignatureRole};
+use crate::invoice::VerificationStrategy;
+use crate::{Id, Invoice};
+
+/// The result of importing a single invoice file
+#[derive(Debug)]
+pub struct ImportResult {
+    /// The path of the invoice file
+    pub path: PathBuf,
+    pub outcome: Result<ImportedInvoice>,
+}
+
+impl ImportResult {
+    /// Returns true if the invoice exists in the provider and none of its parcels are missing
+    pub fn is_complete(&self) -> bool {
+        matches!(&self.outcome, Ok(i) if i.missing_parcels.is_empty())
+    }
+}
+
+/// A summary of what was done when importing an invoice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedInvoice {
+    pub id: Id,
+    /// Whether the invoice was created. This is false if the invoice already existed
+    pub created: bool,
+    /// The number of parcels that were uploaded
+    pub parcels_uploaded: usize,
+    /// The SHAs of the parcels that do not exist in the provider and could not be found next to
+    /// the invoice file
+    pub missing_parcels: Vec<String>,
+}
+
+/// Imports invoices and parcels from disk into a provider. Invoices are verified and signed with a
+/// host key in the same way as invoices uploaded to a server
+pub struct Importer<P, S> {
+    store: P,
+    secret_store: S,
+    strategy: VerificationStrategy,
+    keyring: KeyRing,
+}
+
+impl<P: Provider + Sync, S: SecretKeyStorage + Sync> Importer<P, S> {
+    pub fn new(
+        store: P,
+        secret_store: S,
+        strategy: VerificationStrategy,
+        keyring: KeyRing,
+    ) -> Self {
+        Importer {
+            store,
+            secret_store,
+            strategy,
+            keyring,
+        }
+    }
+
+    /// Imports every invoice file found under the given directory, returning the result for each
+    /// file sorted by path. An error is only returned if the directory itself cannot be read, a
+    /// failure to import one invoice does not stop the others from being imported
+    #[instrument(level = "trace", skip(self, dir), fields(dir = %dir.as_ref().display()))]
+    pub async fn import_dir(&self, dir: impl AsRef<Path>) -> Result<Vec<ImportResult>> {
+        let mut files = Vec::new();
+        find_invoice_files(dir.as_ref(), &mut files).await?;
+        files.sort();
+        info!(invoices = files.len(), "Importing invoices");
+
+        let mut results = Vec::with_capacity(files.len());
+        for path in files {
+            let outcome = self.import_invoice(&path).await;
+            results.push(ImportResult { path, outcome });
+        }
+        Ok(results)
+    }
+
+    /// Imports a single invoice file and any of its parcels that don't exist in the provider yet
+    #[instrument(level = "trace", skip(self, path), fields(path = %path.as_ref().display()))]
+    pub async fn import_invoice(&self, path: impl AsRef<Path>) -> Result<ImportedInvoice> {
+        let path = path.as_ref();
+        let inv: Invoice = toml::from_slice(&tokio::fs::read(path).await?)?;
+        let id = inv.bindle.id.clone();
+
+        let (created, missing) = match self.create_invoice(inv).await {
+            Ok((_, missing)) => (true, missing),
+            Err(ProviderError::Exists) => {
+                debug!(%id, "Invoice already exists, checking its parcels");
+                let existing = self.store.get_yanked_invoice(&id).await?;
+                let mut missing = Vec::new();
+                for parcel in existing.parcel.unwrap_or_default() {
+                    if !self.store.parcel_exists(&id, &parcel.label.sha256).await? {
+                        missing.push(parcel.label);
+                    }
+                }
+                (false, missing)
+            }
+            Err(e) => return Err(e),
+        };
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut imported = ImportedInvoice {
+            id,
+            created,
+            parcels_uploaded: 0,
+            missing_parcels: Vec::new(),
+        };
+        for label in missing {
+            let file = match find_parcel_file(dir, &label).await {
+                Some(f) => f,
+                None => {
+                    debug!(parcel = %label.sha256, "Parcel not found next to invoice");
+                    imported.missing_parcels.push(label.sha256);
+                    continue;
+                }
+            };
+            trace!(parcel = %label.sha256, file = %file.display(), "Uploading parcel");
+            let data = FramedRead::new(tokio::fs::File::open(&file).await?, BytesCodec::new())
+                .map(|res| res.map(|b| b.freeze()));
+            match self
+                .store
+                .create_parcel(&imported.id, &label.sha256, data)
+                .await
+            {
+                // Another writer got there first, which is just as good
+                Ok(_) | Err(ProviderError::Exists) => imported.parcels_uploaded += 1,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(imported)
+    }
+
+    async fn create_invoice(&self, inv: Invoice) -> Result<(Invoice, Vec<crate::Label>)> {
+        let role = SignatureRole::Host;
+        let sk = self
+            .secret_store
+            .get_first_matching(&role)
+            .ok_or(ProviderError::FailedSigning(SignatureError::NoSuitableKey))?;
+        let verified = self.strategy.verify(inv, &self.keyring)?;
+        let signed = crate::sign(verified, vec![(role, sk)])?;
+        self.store.create_invoice(signed).await
+    }
+}
+
+async fn find_invoice_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut readdir = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = readdir.next_entry().await? {
+        let path = entry.path();
+        if entry.file_type().await?.is_dir() {
+            Box::pin(find_invoice_files(&path, files)).await?;
+        } else if path.extension().unwrap_or_default() == "toml" {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+async fn find_parcel_file(dir: &Path, label: &crate::Label) -> Option<PathBuf> {
+    let candidates = [
+        dir.join(crate::provider::file::PARCEL_DIRECTORY)
+            .join(format!("{}.dat", label.sha256)),
+        dir.join(&label.name),
+    ];
+    for candidate in candidates {
+        if let Ok(md) = tokio::fs::metadata(&candidate).await {
+            if md.is_file() {
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::provider::file::FileProvider;
+    use crate::testing;
+
+    #[tokio::test]
+    async fn test_import_dir() {
+        let scaffold = testing::RawScaffold::load("lotsa_parcels").await;
+        let parsed: Invoice = toml::from_slice(&scaffold.invoice).unwrap();
+        let src = tempfile::tempdir().unwrap();
+        let bindle_dir = src.path().join("lotsa");
+        tokio::fs::create_dir_all(bindle_dir.join("parcels"))
+            .await
+            .unwrap();
+        tokio::fs::write(bindle_dir.join("invoice.toml"), &scaffold.invoice)
+            .await
+            .unwrap();
+        // Leave one parcel out so the first import is incomplete
+        let mut parcels = scaffold.parcel_files.values();
+        let held_back = parcels.next().unwrap().clone();
+        for parcel in parcels {
+            tokio::fs::write(
+                bindle_dir
+                    .join("parcels")
+                    .join(format!("{}.dat", parcel.sha)),
+                &parcel.data,
+            )
+            .await
+            .unwrap();
+        }
+        tokio::fs::write(src.path().join("broken.toml"), "not an invoice")
+            .await
+            .unwrap();
+
+        let root = tempfile::tempdir().unwrap();
+        let store = FileProvider::new(root.path(), crate::search::StrictEngine::default()).await;
+        let importer = Importer::new(
+            store.clone(),
+            testing::MockKeyStore::new(),
+            VerificationStrategy::MultipleAttestation(vec![]),
+            KeyRing::default(),
+        );
+
+        let results = importer.import_dir(src.path()).await.unwrap();
+        assert_eq!(2, results.len());
+        assert!(results[0].path.ends_with("broken.toml"));
+        assert!(results[0].outcome.is_err());
+        let imported = results[1].outcome.as_ref().unwrap();
+        assert_eq!(parsed.bindle.id, imported.id);
+        assert!(imported.created);
+        assert_eq!(scaffold.parcel_files.len() - 1, imported.parcels_uploaded);
+        assert_eq!(vec![held_back.sha.clone()], imported.missing_parcels);
+        assert!(!results[1].is_complete());
+
+        // Running again after adding the missing parcel only uploads that parcel
+        tokio::fs::write(
+            bindle_dir
+                .join("parcels")
+                .join(format!("{}.dat", held_back.sha)),
+            &held_back.data,
+        )
+        .await
+        .unwrap();
+        let imported = importer
+            .import_invoice(bindle_dir.join("invoice.toml"))
+            .await
+            .unwrap();
+        assert!(!imported.created);
+        assert_eq!(1, imported.parcels_uploaded);
+        assert!(imported.missing_parcels.is_empty());
+
+        for parcel in scaffold.parcel_files.values() {
+            assert!(store
+                .parcel_exists(&parsed.bindle.id, &parcel.sha)
+                .await
+                .unwrap());
+        }
+
+        // A complete import is a no-op
+        let imported = importer
+            .import_invoice(bindle_dir.join("invoice.toml"))
+            .await
+            .unwrap();
+        assert!(!imported.created);
+        assert_eq!(0, imported.parcels_uploaded);
+    }
+}
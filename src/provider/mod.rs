@@ -21,8 +21,12 @@
 //! will generally contain another Provider implementation or an HTTP client to talk to another
 //! server upstream
 
+#[cfg(feature = "embedded-db")]
 pub mod embedded;
 pub mod file;
+pub mod import;
+pub mod read_only;
+pub mod sampled;
 
 use std::convert::TryInto;
 
@@ -144,6 +148,25 @@ pub trait Provider {
     where
         I: TryInto<Id> + Send,
         I::Error: Into<ProviderError>;
+
+    /// Returns the full SHA of every stored parcel whose SHA starts with the given prefix.
+    ///
+    /// Unlike the other parcel methods, this is not scoped to a bindle and searches across all
+    /// parcels in storage. It is intended for resolving short SHAs given by a user, so it should
+    /// not be used in any performance sensitive path. The default implementation returns a
+    /// [`ProviderError::Unsupported`] error, as not every backend can search its parcels
+    async fn parcels_with_prefix(&self, _prefix: &str) -> Result<Vec<String>> {
+        Err(ProviderError::Unsupported(
+            "looking up parcels by SHA prefix".to_owned(),
+        ))
+    }
+
+    /// Checks that the backing storage is ready to serve requests, returning an error describing
+    /// the problem if it is not. This is used by the server's readiness and health endpoints, so it
+    /// should be cheap to call. The default implementation always reports the storage as ready
+    async fn ready(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// ProviderError describes the possible error states when storing and retrieving bindles.
@@ -158,6 +181,10 @@ pub enum ProviderError {
     /// When the resource is not found in the store
     #[error("resource not found: if an item does not appear in our records, it does not exist!")]
     NotFound,
+    /// The resource is not in the cache, returned by caches when they were asked not to fetch it
+    /// from their remote
+    #[error("resource is not cached")]
+    NotCached,
     /// Any errors that occur due to IO issues. Contains the underlying IO `Error`
     #[error("resource could not be loaded")]
     Io(#[from] std::io::Error),
@@ -176,6 +203,14 @@ pub enum ProviderError {
         "a write operation is currently in progress for this resource and it cannot be accessed"
     )]
     WriteInProgress,
+    /// The provider does not allow writes, such as when the server is running as a read-only
+    /// mirror
+    #[error("registry is read-only")]
+    ReadOnly,
+    /// The provider does not implement the requested operation. Contains a description of the
+    /// operation
+    #[error("{0} is not supported by this registry")]
+    Unsupported(String),
     /// An error that occurs when the provider implementation uses a proxy and that proxy request
     /// encounters an error. Only available with the `client` feature enabled
     #[cfg(feature = "client")]
@@ -208,6 +243,7 @@ impl From<std::convert::Infallible> for ProviderError {
 // TODO(thomastaylor312): We should probably have a more generic form of
 // deserialization/serialization errors that aren't tied to TOML as backends can serialize how they
 // want. For now there is this workaround
+#[cfg(feature = "embedded-db")]
 impl From<serde_cbor::Error> for ProviderError {
     fn from(e: serde_cbor::Error) -> Self {
         if e.is_io() {
@@ -0,0 +1,171 @@
+//! A provider that rejects all writes. This is useful for running deliberate read-only mirrors or
+//! for serving from a data directory that cannot be written to (such as a read-only volume mount)
+use std::convert::TryInto;
+
+use tokio_stream::Stream;
+use tracing::debug;
+
+use super::{Provider, ProviderError, Result};
+use crate::verification::Verified;
+use crate::{Id, Signed};
+
+/// The header the server sets on responses to writes rejected with a [`ProviderError::ReadOnly`],
+/// so clients can tell a read-only registry apart from any other `405 Method Not Allowed`
+pub const READ_ONLY_HEADER: &str = "bindle-read-only";
+
+/// A provider that wraps another provider, forwarding all reads and rejecting all writes (creating
+/// invoices, creating parcels, and yanking) with a [`ProviderError::ReadOnly`]
+#[derive(Clone)]
+pub struct ReadOnlyProvider<P> {
+    inner: P,
+}
+
+impl<P> ReadOnlyProvider<P> {
+    /// Returns a new provider that rejects all writes to the given provider
+    pub fn new(inner: P) -> Self {
+        ReadOnlyProvider { inner }
+    }
+}
+
+#[async_trait::async_trait]
+impl<P> Provider for ReadOnlyProvider<P>
+where
+    P: Provider + Send + Sync,
+{
+    async fn create_invoice<I>(&self, _: I) -> Result<(crate::Invoice, Vec<crate::Label>)>
+    where
+        I: Signed + Verified + Send + Sync,
+    {
+        debug!("Rejecting invoice creation on read-only provider");
+        Err(ProviderError::ReadOnly)
+    }
+
+    async fn get_invoice<I>(&self, id: I) -> Result<crate::Invoice>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        self.inner.get_invoice(id).await
+    }
+
+    async fn get_yanked_invoice<I>(&self, id: I) -> Result<crate::Invoice>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        self.inner.get_yanked_invoice(id).await
+    }
+
+    async fn yank_invoice<I>(&self, _: I) -> Result<()>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        debug!("Rejecting yank on read-only provider");
+        Err(ProviderError::ReadOnly)
+    }
+
+    async fn validate_parcel<I>(&self, bindle_id: I, parcel_id: &str) -> Result<crate::Label>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        self.inner.validate_parcel(bindle_id, parcel_id).await
+    }
+
+    async fn create_parcel<I, R, B>(&self, _: I, _: &str, _: R) -> Result<()>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+        R: Stream<Item = std::io::Result<B>> + Unpin + Send + Sync + 'static,
+        B: bytes::Buf + Send,
+    {
+        debug!("Rejecting parcel creation on read-only provider");
+        Err(ProviderError::ReadOnly)
+    }
+
+    async fn get_parcel<I>(
+        &self,
+        bindle_id: I,
+        parcel_id: &str,
+    ) -> Result<Box<dyn Stream<Item = Result<bytes::Bytes>> + Unpin + Send + Sync>>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        self.inner.get_parcel(bindle_id, parcel_id).await
+    }
+
+    async fn parcel_exists<I>(&self, bindle_id: I, parcel_id: &str) -> Result<bool>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        self.inner.parcel_exists(bindle_id, parcel_id).await
+    }
+
+    async fn parcels_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        self.inner.parcels_with_prefix(prefix).await
+    }
+
+    async fn ready(&self) -> Result<()> {
+        self.inner.ready().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing;
+
+    #[tokio::test]
+    async fn test_read_only_provider() {
+        let (store, _, _) = testing::setup().await;
+        let scaffold = testing::Scaffold::load("valid_v1").await;
+        store
+            .create_invoice(crate::NoopSigned(crate::verification::NoopVerified(
+                scaffold.invoice.clone(),
+            )))
+            .await
+            .expect("Unable to create invoice");
+
+        let read_only = ReadOnlyProvider::new(store);
+        read_only
+            .get_invoice(&scaffold.invoice.bindle.id)
+            .await
+            .expect("Reads should be allowed");
+
+        let other = testing::Scaffold::load("lotsa_parcels").await;
+        assert!(matches!(
+            read_only
+                .create_invoice(crate::NoopSigned(crate::verification::NoopVerified(
+                    other.invoice.clone()
+                )))
+                .await,
+            Err(ProviderError::ReadOnly)
+        ));
+        assert!(matches!(
+            read_only.yank_invoice(&scaffold.invoice.bindle.id).await,
+            Err(ProviderError::ReadOnly)
+        ));
+        let parcel = scaffold.parcel_files.values().next().unwrap();
+        assert!(matches!(
+            read_only
+                .create_parcel(
+                    &scaffold.invoice.bindle.id,
+                    &parcel.sha,
+                    tokio_util::codec::FramedRead::new(
+                        std::io::Cursor::new(parcel.data.clone()),
+                        tokio_util::codec::BytesCodec::new(),
+                    ),
+                )
+                .await,
+            Err(ProviderError::ReadOnly)
+        ));
+        // The yank should not have gone through to the wrapped provider
+        read_only
+            .get_invoice(&scaffold.invoice.bindle.id)
+            .await
+            .expect("Invoice should not have been yanked");
+    }
+}
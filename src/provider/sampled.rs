@@ -0,0 +1,320 @@
+//! A provider that verifies the SHA of a random sample of parcel reads. This is a middle ground
+//! between verifying every read, which is expensive for large registries, and never verifying
+//! reads, which lets corrupted parcels go unnoticed
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use tokio_stream::Stream;
+use tracing::{debug, error, instrument, trace};
+
+use super::{Provider, ProviderError, Result};
+use crate::verification::Verified;
+use crate::{Id, Signed};
+
+type ParcelStream = Box<dyn Stream<Item = Result<bytes::Bytes>> + Unpin + Send + Sync>;
+
+/// A provider that wraps another provider and verifies the SHA-256 sum of a configurable fraction
+/// of parcel reads.
+///
+/// As the parcel is streamed to the caller, its data is hashed and the sum is checked once the
+/// stream ends. A sum that does not match is logged, counted (see
+/// [`verification_failures`](SampledReadVerifier::verification_failures)), and the stream ends
+/// with a [`ProviderError::DigestMismatch`] so the caller does not receive the data as valid. If
+/// quarantine is enabled, all later reads of that parcel will return an error until the process is
+/// restarted, leaving the stored data in place for inspection
+#[derive(Clone)]
+pub struct SampledReadVerifier<P> {
+    inner: P,
+    sample_rate: f64,
+    quarantine: bool,
+    failures: Arc<AtomicU64>,
+    quarantined: Arc<RwLock<HashSet<String>>>,
+}
+
+impl<P> SampledReadVerifier<P> {
+    /// Returns a new provider that verifies the given fraction of parcel reads. The sample rate is
+    /// clamped between 0 (never verify) and 1 (always verify). A NaN sample rate is treated as 0
+    pub fn new(inner: P, sample_rate: f64) -> Self {
+        SampledReadVerifier {
+            inner,
+            sample_rate: if sample_rate.is_nan() {
+                0.0
+            } else {
+                sample_rate.clamp(0.0, 1.0)
+            },
+            quarantine: false,
+            failures: Arc::new(AtomicU64::new(0)),
+            quarantined: Arc::new(RwLock::new(HashSet::new())),
+        }
+    }
+
+    /// Sets whether parcels that fail verification should be quarantined
+    pub fn with_quarantine(mut self, quarantine: bool) -> Self {
+        self.quarantine = quarantine;
+        self
+    }
+
+    /// Returns the number of sampled reads that have failed verification
+    pub fn verification_failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    fn is_quarantined(&self, parcel_id: &str) -> bool {
+        self.quarantined
+            .read()
+            .map(|q| q.contains(parcel_id))
+            .unwrap_or(false)
+    }
+
+    fn should_sample(&self) -> bool {
+        self.sample_rate > 0.0 && rand::thread_rng().gen_bool(self.sample_rate)
+    }
+}
+
+#[async_trait::async_trait]
+impl<P> Provider for SampledReadVerifier<P>
+where
+    P: Provider + Send + Sync,
+{
+    async fn create_invoice<I>(&self, inv: I) -> Result<(crate::Invoice, Vec<crate::Label>)>
+    where
+        I: Signed + Verified + Send + Sync,
+    {
+        self.inner.create_invoice(inv).await
+    }
+
+    async fn get_invoice<I>(&self, id: I) -> Result<crate::Invoice>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        self.inner.get_invoice(id).await
+    }
+
+    async fn get_yanked_invoice<I>(&self, id: I) -> Result<crate::Invoice>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        self.inner.get_yanked_invoice(id).await
+    }
+
+    async fn yank_invoice<I>(&self, id: I) -> Result<()>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        self.inner.yank_invoice(id).await
+    }
+
+    async fn validate_parcel<I>(&self, bindle_id: I, parcel_id: &str) -> Result<crate::Label>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        self.inner.validate_parcel(bindle_id, parcel_id).await
+    }
+
+    async fn create_parcel<I, R, B>(&self, bindle_id: I, parcel_id: &str, data: R) -> Result<()>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+        R: Stream<Item = std::io::Result<B>> + Unpin + Send + Sync + 'static,
+        B: bytes::Buf + Send,
+    {
+        self.inner.create_parcel(bindle_id, parcel_id, data).await
+    }
+
+    #[instrument(level = "trace", skip(self, bindle_id))]
+    async fn get_parcel<I>(&self, bindle_id: I, parcel_id: &str) -> Result<ParcelStream>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        if self.is_quarantined(parcel_id) {
+            debug!("Parcel is quarantined, refusing to serve it");
+            return Err(ProviderError::Other(format!(
+                "parcel {} is quarantined after failing verification",
+                parcel_id
+            )));
+        }
+        let stream = self.inner.get_parcel(bindle_id, parcel_id).await?;
+        if !self.should_sample() {
+            return Ok(stream);
+        }
+        trace!("Parcel read was sampled for verification");
+        Ok(Box::new(VerifyingStream {
+            inner: stream,
+            hasher: Some(Sha256::new()),
+            parcel_id: parcel_id.to_owned(),
+            quarantine: self.quarantine,
+            failures: self.failures.clone(),
+            quarantined: self.quarantined.clone(),
+        }))
+    }
+
+    async fn parcel_exists<I>(&self, bindle_id: I, parcel_id: &str) -> Result<bool>
+    where
+        I: TryInto<Id> + Send,
+        I::Error: Into<ProviderError>,
+    {
+        self.inner.parcel_exists(bindle_id, parcel_id).await
+    }
+
+    async fn parcels_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        self.inner.parcels_with_prefix(prefix).await
+    }
+
+    async fn ready(&self) -> Result<()> {
+        self.inner.ready().await
+    }
+}
+
+/// A stream that hashes all of the data passing through it and checks the sum once the wrapped
+/// stream has ended
+struct VerifyingStream {
+    inner: ParcelStream,
+    // This is taken once the sum has been checked
+    hasher: Option<Sha256>,
+    parcel_id: String,
+    quarantine: bool,
+    failures: Arc<AtomicU64>,
+    quarantined: Arc<RwLock<HashSet<String>>>,
+}
+
+impl Stream for VerifyingStream {
+    type Item = Result<bytes::Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let res = match Pin::new(&mut self.inner).poll_next(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(res) => res,
+        };
+        match res {
+            Some(Ok(data)) => {
+                if let Some(hasher) = self.hasher.as_mut() {
+                    hasher.update(&data);
+                }
+                Poll::Ready(Some(Ok(data)))
+            }
+            // Stop checking the sum if the underlying stream errored, as we didn't get all of the
+            // data
+            Some(Err(e)) => {
+                self.hasher = None;
+                Poll::Ready(Some(Err(e)))
+            }
+            None => {
+                let hasher = match self.hasher.take() {
+                    Some(h) => h,
+                    None => return Poll::Ready(None),
+                };
+                let sum = format!("{:x}", hasher.finalize());
+                if sum == self.parcel_id {
+                    trace!(parcel_id = %self.parcel_id, "Sampled parcel read passed verification");
+                    return Poll::Ready(None);
+                }
+                let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+                error!(
+                    parcel_id = %self.parcel_id,
+                    %sum,
+                    failures,
+                    "Sampled parcel read failed verification, the stored parcel may be corrupt"
+                );
+                if self.quarantine {
+                    if let Ok(mut q) = self.quarantined.write() {
+                        q.insert(self.parcel_id.clone());
+                    }
+                }
+                Poll::Ready(Some(Err(ProviderError::DigestMismatch)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::provider::file::FileProvider;
+    use crate::search::NoopEngine;
+    use crate::testing;
+
+    use tokio_stream::StreamExt;
+
+    async fn read_all(stream: ParcelStream) -> Result<Vec<u8>> {
+        let mut stream = stream;
+        let mut data = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            data.extend_from_slice(&chunk?);
+        }
+        Ok(data)
+    }
+
+    #[tokio::test]
+    async fn test_sampled_read_verification() {
+        let tempdir = tempfile::tempdir().expect("Unable to create tempdir");
+        let store = FileProvider::new(tempdir.path(), NoopEngine::default()).await;
+        let scaffold = testing::Scaffold::load("valid_v1").await;
+        store
+            .create_invoice(crate::NoopSigned(crate::verification::NoopVerified(
+                scaffold.invoice.clone(),
+            )))
+            .await
+            .expect("Unable to create invoice");
+        let parcel = scaffold.parcel_files.values().next().unwrap();
+        store
+            .create_parcel(
+                &scaffold.invoice.bindle.id,
+                &parcel.sha,
+                tokio_util::codec::FramedRead::new(
+                    std::io::Cursor::new(parcel.data.clone()),
+                    tokio_util::codec::BytesCodec::new(),
+                ),
+            )
+            .await
+            .expect("Unable to create parcel");
+
+        let verifier = SampledReadVerifier::new(store, 1.0).with_quarantine(true);
+        let data = read_all(
+            verifier
+                .get_parcel(&scaffold.invoice.bindle.id, &parcel.sha)
+                .await
+                .expect("Should be able to get parcel"),
+        )
+        .await
+        .expect("Valid parcel should pass verification");
+        assert_eq!(parcel.data, data);
+        assert_eq!(0, verifier.verification_failures());
+
+        // Corrupt the parcel on disk
+        let path = tempdir
+            .path()
+            .join(crate::provider::file::PARCEL_DIRECTORY)
+            .join(&parcel.sha)
+            .join(crate::provider::file::PARCEL_DAT);
+        tokio::fs::write(&path, b"corrupted")
+            .await
+            .expect("Unable to corrupt parcel");
+
+        let res = read_all(
+            verifier
+                .get_parcel(&scaffold.invoice.bindle.id, &parcel.sha)
+                .await
+                .expect("Should be able to get parcel"),
+        )
+        .await;
+        assert!(matches!(res, Err(ProviderError::DigestMismatch)));
+        assert_eq!(1, verifier.verification_failures());
+
+        // The parcel should now be quarantined
+        assert!(verifier
+            .get_parcel(&scaffold.invoice.bindle.id, &parcel.sha)
+            .await
+            .is_err());
+    }
+}
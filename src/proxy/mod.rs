@@ -128,4 +128,8 @@ impl Provider for Proxy {
             })),
         }
     }
+
+    async fn parcels_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        Ok(self.client.parcels_with_prefix(prefix).await?)
+    }
 }
@@ -1,6 +1,9 @@
 //! Common types and traits for use in implementing query functionality for a Bindle server. Note
 //! that this functionality is quite likely to change
+use std::str::FromStr;
+
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 mod noop;
 mod strict;
@@ -19,6 +22,10 @@ pub struct SearchOptions {
     pub strict: bool,
     /// Whether to return yanked bindles
     pub yanked: bool,
+    /// If set, only invoices with a matching annotation are returned
+    pub annotation: Option<AnnotationFilter>,
+    /// The order the matching invoices are returned in, before the offset and limit are applied
+    pub sort: Sort,
 }
 
 impl Default for SearchOptions {
@@ -28,10 +35,150 @@ impl Default for SearchOptions {
             limit: 50,
             strict: false,
             yanked: false,
+            annotation: None,
+            sort: Sort::default(),
+        }
+    }
+}
+
+/// The reasons the options of a query are invalid
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum QueryError {
+    /// The sort key is not one of `name`, `version`, or `annotation:KEY`
+    #[error("unknown sort key `{0}`, expected one of name, version, or annotation:KEY (prefix with - to reverse)")]
+    UnknownSortKey(String),
+    /// The annotation filter does not have a key
+    #[error("annotation filter `{0}` must be of the form KEY or KEY=VALUE")]
+    InvalidAnnotationFilter(String),
+}
+
+/// Matches invoices by one of their annotations. Parsed from `KEY`, which matches any invoice with
+/// the annotation, or `KEY=VALUE`, which only matches invoices where it has that exact value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnotationFilter {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl AnnotationFilter {
+    /// Returns true if the invoice matches this filter
+    pub fn matches(&self, inv: &crate::Invoice) -> bool {
+        match inv.annotations.as_ref().and_then(|a| a.get(&self.key)) {
+            Some(v) => self.value.as_ref().map(|want| want == v).unwrap_or(true),
+            None => false,
+        }
+    }
+}
+
+impl FromStr for AnnotationFilter {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = match s.split_once('=') {
+            Some((k, v)) => (k, Some(v.to_owned())),
+            None => (s, None),
+        };
+        if key.is_empty() {
+            return Err(QueryError::InvalidAnnotationFilter(s.to_owned()));
+        }
+        Ok(AnnotationFilter {
+            key: key.to_owned(),
+            value,
+        })
+    }
+}
+
+/// The field that query results are sorted by
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SortKey {
+    /// The bindle name, then the version. This is the default
+    Name,
+    /// The bindle version by SemVer precedence, then the name
+    Version,
+    /// The value of the given annotation. Invoices without the annotation come last in both
+    /// ascending and descending order
+    Annotation(String),
+}
+
+/// The order of query results. Parsed from `name`, `version`, or `annotation:KEY`, optionally
+/// prefixed with `-` to sort in descending order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Sort {
+            key: SortKey::Name,
+            descending: false,
         }
     }
 }
 
+impl Sort {
+    /// Sorts the invoices in place. The sort is stable, so invoices that compare equal keep the
+    /// order they were in
+    pub fn apply(&self, invoices: &mut [crate::Invoice]) {
+        let name_order = |a: &crate::Invoice, b: &crate::Invoice| {
+            a.bindle
+                .id
+                .name()
+                .cmp(b.bindle.id.name())
+                .then_with(|| a.bindle.id.version().cmp(b.bindle.id.version()))
+        };
+        let directed = |ord: std::cmp::Ordering| {
+            if self.descending {
+                ord.reverse()
+            } else {
+                ord
+            }
+        };
+        invoices.sort_by(|a, b| match &self.key {
+            SortKey::Name => directed(name_order(a, b)),
+            SortKey::Version => directed(
+                a.bindle
+                    .id
+                    .version()
+                    .cmp(b.bindle.id.version())
+                    .then_with(|| a.bindle.id.name().cmp(b.bindle.id.name())),
+            ),
+            SortKey::Annotation(key) => {
+                let value =
+                    |i: &crate::Invoice| i.annotations.as_ref().and_then(|a| a.get(key)).cloned();
+                // Only the present values are reversed so missing ones stay at the end
+                match (value(a), value(b)) {
+                    (Some(x), Some(y)) => directed(x.cmp(&y)),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                }
+            }
+        });
+    }
+}
+
+impl FromStr for Sort {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (descending, key) = match s.strip_prefix('-') {
+            Some(k) => (true, k),
+            None => (false, s),
+        };
+        let key = match key {
+            "name" => SortKey::Name,
+            "version" => SortKey::Version,
+            _ => match key.strip_prefix("annotation:") {
+                Some(k) if !k.is_empty() => SortKey::Annotation(k.to_owned()),
+                _ => return Err(QueryError::UnknownSortKey(s.to_owned())),
+            },
+        };
+        Ok(Sort { key, descending })
+    }
+}
+
 /// Describes the matches that are returned from a query
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Matches {
@@ -45,17 +45,24 @@ impl Search for StrictEngine {
                 // Per the spec:
                 // - if `term` is present, then it must be contained within the name field of the bindle.
                 // - if a version filter is present, then the version of the bindle must abide by the filter.
+                // - yanked bindles are only included if they were asked for
                 debug!(term, filter, "comparing term and filter");
                 i.bindle.id.name().contains(term)
                     && (filter.is_empty() || i.version_in_range(filter))
+                    && (options.yanked || !i.yanked.unwrap_or(false))
+                    && options
+                        .annotation
+                        .as_ref()
+                        .map(|a| a.matches(i))
+                        .unwrap_or(true)
             })
             .map(|(_, v)| (*v).clone())
             .collect();
+        options.sort.apply(&mut found);
 
         debug!(total_matches = found.len(), "Found matches");
         let mut matches = Matches::new(&options, term.to_owned());
         matches.strict = true;
-        matches.yanked = false;
         matches.total = found.len() as u64;
 
         if matches.offset >= matches.total {
@@ -137,8 +144,153 @@ mod test {
             .await
             .expect("found some matches");
         assert!(matches.invoices.is_empty());
+    }
+
+    #[tokio::test]
+    async fn strict_engine_should_hide_yanked() {
+        let inv = invoice_fixture("my/bindle".to_owned(), "1.2.3".to_owned());
+        let mut yanked = invoice_fixture("my/bindle".to_owned(), "1.3.0".to_owned());
+        yanked.yanked = Some(true);
+        let searcher = StrictEngine::default();
+        searcher.index(&inv).await.expect("indexed my/bindle/1.2.3");
+        searcher
+            .index(&yanked)
+            .await
+            .expect("indexed my/bindle/1.3.0");
+
+        let matches = searcher
+            .query("my/bindle", "", SearchOptions::default())
+            .await
+            .expect("found some matches");
+        assert!(!matches.yanked);
+        assert_eq!(1, matches.invoices.len());
+        assert_eq!(inv.bindle.id, matches.invoices[0].bindle.id);
+
+        let matches = searcher
+            .query(
+                "my/bindle",
+                "",
+                SearchOptions {
+                    yanked: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("found some matches");
+        assert!(matches.yanked);
+        assert_eq!(2, matches.invoices.len());
+        assert_eq!(Some(true), matches.invoices[1].yanked);
+    }
+
+    #[tokio::test]
+    async fn strict_engine_should_filter_and_sort_by_annotation() {
+        let searcher = StrictEngine::default();
+        for (name, version, tier) in &[
+            ("my/bindle", "1.10.0", Some("gold")),
+            ("my/bindle", "1.2.0", Some("bronze")),
+            ("my/other", "1.5.0", None),
+            ("my/another", "0.1.0", Some("gold")),
+        ] {
+            let mut inv = invoice_fixture(name.to_string(), version.to_string());
+            if let Some(tier) = tier {
+                inv.annotations = Some(
+                    vec![("tier".to_owned(), tier.to_string())]
+                        .into_iter()
+                        .collect(),
+                );
+            }
+            searcher.index(&inv).await.expect("indexed invoice");
+        }
 
-        // TODO: Need to test yanked bindles
+        let ids = |opts: SearchOptions| async {
+            searcher
+                .query("", "", opts)
+                .await
+                .expect("found some matches")
+                .invoices
+                .into_iter()
+                .map(|i| i.bindle.id.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![
+                "my/another/0.1.0",
+                "my/bindle/1.2.0",
+                "my/bindle/1.10.0",
+                "my/other/1.5.0"
+            ],
+            ids(SearchOptions::default()).await,
+            "Default order should be by name, then SemVer"
+        );
+        assert_eq!(
+            vec!["my/another/0.1.0", "my/bindle/1.10.0"],
+            ids(SearchOptions {
+                annotation: Some("tier=gold".parse().unwrap()),
+                ..Default::default()
+            })
+            .await
+        );
+        assert_eq!(
+            3,
+            ids(SearchOptions {
+                annotation: Some("tier".parse().unwrap()),
+                ..Default::default()
+            })
+            .await
+            .len()
+        );
+        assert_eq!(
+            vec![
+                "my/bindle/1.10.0",
+                "my/other/1.5.0",
+                "my/bindle/1.2.0",
+                "my/another/0.1.0"
+            ],
+            ids(SearchOptions {
+                sort: "-version".parse().unwrap(),
+                ..Default::default()
+            })
+            .await
+        );
+        assert_eq!(
+            vec![
+                "my/bindle/1.2.0",
+                "my/another/0.1.0",
+                "my/bindle/1.10.0",
+                "my/other/1.5.0"
+            ],
+            ids(SearchOptions {
+                sort: "annotation:tier".parse().unwrap(),
+                ..Default::default()
+            })
+            .await,
+            "Invoices without the annotation should come last"
+        );
+        assert_eq!(
+            vec![
+                "my/another/0.1.0",
+                "my/bindle/1.10.0",
+                "my/bindle/1.2.0",
+                "my/other/1.5.0"
+            ],
+            ids(SearchOptions {
+                sort: "-annotation:tier".parse().unwrap(),
+                ..Default::default()
+            })
+            .await,
+            "Invoices without the annotation should come last when sorting in descending order"
+        );
+
+        for invalid in &["size", "annotation:", "-", ""] {
+            assert_eq!(
+                Err(crate::search::QueryError::UnknownSortKey(
+                    invalid.to_string()
+                )),
+                invalid.parse::<crate::search::Sort>()
+            );
+        }
+        assert!("=gold".parse::<crate::search::AnnotationFilter>().is_err());
     }
 
     fn invoice_fixture(name: String, version: String) -> Invoice {
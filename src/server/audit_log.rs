@@ -0,0 +1,116 @@
+//! Recording the changes users make to the server in an audit log
+
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::{trace, warn};
+use warp::Reply;
+
+use super::filters::Identity;
+use crate::audit::{AuditEntry, AuditOperation, AuditOutcome, AuditQuery, AuditSink};
+
+/// Records every request that creates or yanks something to an [`AuditSink`], along with who made
+/// it and whether it succeeded. Entries can be queried from the `_audit` endpoint by identities in
+/// one of the admin groups, and by no one else. The default log is disabled and records nothing.
+///
+/// Failing to record an entry doesn't fail the request, as the change has already been made by
+/// then. The failure is logged instead
+#[derive(Clone, Default)]
+pub struct AuditLog {
+    sink: Option<Arc<dyn AuditSink + Send + Sync>>,
+    admin_groups: Vec<String>,
+}
+
+impl std::fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditLog")
+            .field("enabled", &self.sink.is_some())
+            .field("admin_groups", &self.admin_groups)
+            .finish()
+    }
+}
+
+impl AuditLog {
+    /// Returns a log that records nothing
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Returns a log that records entries to the given sink
+    pub fn new<S: AuditSink + Send + Sync + 'static>(sink: S) -> Self {
+        AuditLog {
+            sink: Some(Arc::new(sink)),
+            admin_groups: Vec::new(),
+        }
+    }
+
+    /// Allows any identity in one of the given groups to query the log
+    pub fn with_admin_groups(mut self, groups: Vec<String>) -> Self {
+        self.admin_groups = groups;
+        self
+    }
+
+    /// Returns whether the given identity may query the log
+    pub(crate) fn allows(&self, identity: &Identity) -> bool {
+        identity
+            .groups
+            .iter()
+            .any(|g| self.admin_groups.contains(g))
+    }
+
+    /// Returns whether entries are being recorded
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    /// Returns the entries matching the query. A disabled log has no entries
+    pub(crate) async fn query(&self, query: &AuditQuery) -> anyhow::Result<Vec<AuditEntry>> {
+        match self.sink.as_ref() {
+            Some(sink) => sink.query(query).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Records the outcome of a request made by the given identity, based on the status of the
+    /// reply to it, and returns the reply
+    pub(crate) async fn record<R: Reply>(
+        &self,
+        identity: &Identity,
+        operation: AuditOperation,
+        target: String,
+        parcel: Option<String>,
+        reply: Result<R, Infallible>,
+    ) -> Result<warp::reply::Response, Infallible> {
+        let resp = match reply {
+            Ok(r) => r.into_response(),
+            Err(e) => match e {},
+        };
+        let sink = match self.sink.as_ref() {
+            Some(s) => s,
+            None => return Ok(resp),
+        };
+        let status = resp.status();
+        let entry = AuditEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            principal: identity.principal.clone(),
+            operation,
+            target,
+            parcel,
+            outcome: if status.is_success() {
+                AuditOutcome::Success
+            } else {
+                AuditOutcome::Failure
+            },
+            status: status.as_u16(),
+        };
+        trace!(?entry, "Recording audit entry");
+        if let Err(e) = sink.record(entry).await {
+            warn!(error = %e, ?operation, principal = %identity.principal, "Unable to record audit entry");
+        }
+        Ok(resp)
+    }
+}
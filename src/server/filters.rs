@@ -11,7 +11,7 @@ use warp::Filter;
 
 use super::TOML_MIME_TYPE;
 use crate::authn::Authenticator;
-use crate::authz::Authorizer;
+use crate::authz::{Authorizable, Authorizer};
 
 pub(crate) const PARCEL_ID_SEPARATOR: char = '@';
 
@@ -19,6 +19,16 @@ pub(crate) const PARCEL_ID_SEPARATOR: char = '@';
 #[derive(Debug, Deserialize)]
 pub struct InvoiceQuery {
     pub yanked: Option<bool>,
+    /// Return the invoice exactly as stored, ignoring any configured
+    /// [`SignatureStripping`](crate::server::SignatureStripping)
+    pub verbatim: Option<bool>,
+}
+
+/// Query string options for the yank endpoint
+#[derive(Debug, Deserialize)]
+pub struct YankQuery {
+    /// Yank even if the [`YankGuard`](crate::server::YankGuard) limit has been reached
+    pub force: Option<bool>,
 }
 
 /// A warp filter that returns the invoice ID if the path is for an invoice and rejects it otherwise
@@ -146,6 +156,40 @@ pub(crate) fn authenticate_and_authorize<
     authn: Authn,
     authz: Authz,
 ) -> impl Filter<Extract = ((),), Error = Rejection> + Clone {
+    authorized_identity(authn, authz).map(|_| ())
+}
+
+/// The identity of an authorized user, for handlers that need to know who made the request
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub principal: String,
+    pub groups: Vec<String>,
+    /// Whether the user actually authenticated, as opposed to being anonymous
+    pub authenticated: bool,
+}
+
+impl Authorizable for Identity {
+    fn principal(&self) -> String {
+        self.principal.clone()
+    }
+
+    fn groups(&self) -> Vec<String> {
+        self.groups.clone()
+    }
+
+    fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+}
+
+/// The same as [`authenticate_and_authorize`], but returns the identity of the authorized user
+pub(crate) fn authorized_identity<
+    Authn: Authenticator + Clone + Send + Sync,
+    Authz: Authorizer + Clone + Send + Sync,
+>(
+    authn: Authn,
+    authz: Authz,
+) -> impl Filter<Extract = (Identity,), Error = Rejection> + Clone {
     authenticate(authn)
         .and(warp::path::full())
         .and(warp::method())
@@ -154,11 +198,20 @@ pub(crate) fn authenticate_and_authorize<
             |item: Authn::Item, path: warp::path::FullPath, method, authz: Authz| {
                 async move {
                     trace!(path = path.as_str(), %method, "Authorizing request");
-                    if let Err(e) = authz.authorize(item, path.as_str(), method) {
+                    // The item is consumed by the authorizer, so grab the identity first
+                    let identity = Identity {
+                        principal: item.principal(),
+                        groups: item.groups(),
+                        authenticated: item.is_authenticated(),
+                    };
+                    if let Err(e) = authz.authorize(item, path.as_str(), method).await {
                         debug!(error = %e, "Authorization error");
+                        if e.downcast_ref::<crate::authz::Unauthenticated>().is_some() {
+                            return Err(warp::reject::custom(AuthnFail));
+                        }
                         return Err(warp::reject::custom(AuthzFail));
                     }
-                    Ok(())
+                    Ok(identity)
                 }
                 .instrument(tracing::trace_span!("authorization"))
             },
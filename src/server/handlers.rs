@@ -3,16 +3,23 @@ use std::convert::Infallible;
 use tracing::{debug, instrument, trace, trace_span};
 use warp::Reply;
 
-use super::filters::InvoiceQuery;
+use super::filters::{Identity, InvoiceQuery, YankQuery};
 use super::reply;
+use super::{AuditLog, CrawlerPolicy, SignatureStripping, YankGuard, YankedListing};
+use crate::audit::{AuditLogResponse, AuditOperation, AuditQuery};
 use crate::invoice::{SignatureRole, VerificationStrategy};
 use crate::provider::{Provider, ProviderError};
-use crate::search::Search;
+use crate::search::{Search, SearchOptions};
 
 pub mod v1 {
     use super::*;
 
+    use std::collections::HashSet;
+    use std::convert::TryFrom;
+
     use crate::{
+        authz::Authorizer,
+        interceptor::{InvoiceInterceptor, RejectReason},
         signature::{KeyRing, SecretKeyStorage},
         QueryOptions, SignatureError,
     };
@@ -22,18 +29,37 @@ pub mod v1 {
     //////////// Invoice Functions ////////////
     #[instrument(level = "trace", skip(index))]
     pub async fn query_invoices<S: Search>(
+        identity: Identity,
         options: QueryOptions,
         index: S,
+        yanked_listing: YankedListing,
         accept_header: Option<String>,
     ) -> Result<impl warp::Reply, Infallible> {
+        if options.yanked.unwrap_or_default() && !yanked_listing.allows(&identity) {
+            debug!(principal = %identity.principal, "Rejecting query for yanked invoices");
+            return Ok(reply::reply_from_error(
+                "not allowed to list yanked invoices",
+                warp::http::StatusCode::FORBIDDEN,
+            ));
+        }
         let term = options.query.clone().unwrap_or_default();
         let version = options.version.clone().unwrap_or_default();
+        let search_options = match SearchOptions::try_from(options) {
+            Ok(o) => o,
+            Err(e) => {
+                debug!(error = %e, "Got invalid query options");
+                return Ok(reply::reply_from_error(
+                    e,
+                    warp::http::StatusCode::BAD_REQUEST,
+                ));
+            }
+        };
         debug!(
             %term,
             %version,
             "Querying invoice index",
         );
-        let matches = match index.query(&term, &version, options.into()).await {
+        let matches = match index.query(&term, &version, search_options).await {
             Ok(m) => m,
             Err(e) => {
                 debug!(error = %e, "Got bad query request");
@@ -52,18 +78,61 @@ pub mod v1 {
         ))
     }
 
-    #[instrument(level = "trace", skip(store, secret_store))]
-    pub async fn create_invoice<P: Provider, S: SecretKeyStorage>(
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(level = "trace", skip(audit_log, store, secret_store, interceptor))]
+    pub async fn create_invoice<P: Provider, S: SecretKeyStorage, II: InvoiceInterceptor>(
+        identity: Identity,
+        audit_log: AuditLog,
+        store: P,
+        secret_store: S,
+        strategy: VerificationStrategy,
+        keyring: std::sync::Arc<KeyRing>,
+        interceptor: II,
+        inv: crate::Invoice,
+        accept_header: Option<String>,
+    ) -> Result<impl warp::Reply, Infallible> {
+        let target = inv.bindle.id.to_string();
+        let res = store_invoice(
+            store,
+            secret_store,
+            strategy,
+            keyring,
+            interceptor,
+            inv,
+            accept_header,
+        )
+        .await;
+        audit_log
+            .record(&identity, AuditOperation::CreateInvoice, target, None, res)
+            .await
+    }
+
+    async fn store_invoice<P: Provider, S: SecretKeyStorage, II: InvoiceInterceptor>(
         store: P,
         secret_store: S,
         strategy: VerificationStrategy,
         keyring: std::sync::Arc<KeyRing>,
+        interceptor: II,
         inv: crate::Invoice,
         accept_header: Option<String>,
     ) -> Result<impl warp::Reply, Infallible> {
         let accept = accept_header.unwrap_or_default();
         trace!("Create invoice request with invoice: {:?}", inv);
 
+        // Give any configured interceptors the chance to modify or reject the invoice before
+        // anything else happens to it
+        let inv = match interceptor.before_store(inv) {
+            Ok(i) => i,
+            Err(e) => {
+                debug!(error = %e, "Invoice rejected by interceptor");
+                let status_code = match e {
+                    RejectReason::Forbidden(_) => warp::http::StatusCode::FORBIDDEN,
+                    RejectReason::Invalid(_) => warp::http::StatusCode::BAD_REQUEST,
+                };
+                return Ok(reply::reply_from_error(e, status_code));
+            }
+        };
+
         // Right here, I need to load one secret key and a ring of public keys.
         // Then I need to validate the invoice against the public keys, sign the invoice
         // with my private key, and THEN go on to store.create_invoice()
@@ -102,13 +171,7 @@ pub mod v1 {
                 "Newly created invoice is missing parcels",
             );
             Ok(warp::reply::with_status(
-                reply::serialized_data(
-                    &crate::InvoiceCreateResponse {
-                        invoice,
-                        missing: Some(labels),
-                    },
-                    accept,
-                ),
+                reply::serialized_data(&crate::InvoiceCreateResponse::new(invoice, labels), accept),
                 warp::http::StatusCode::ACCEPTED,
             ))
         } else {
@@ -117,13 +180,7 @@ pub mod v1 {
                 "Newly created invoice has all existing parcels",
             );
             Ok(warp::reply::with_status(
-                reply::serialized_data(
-                    &crate::InvoiceCreateResponse {
-                        invoice,
-                        missing: None,
-                    },
-                    accept,
-                ),
+                reply::serialized_data(&crate::InvoiceCreateResponse::new(invoice, labels), accept),
                 warp::http::StatusCode::CREATED,
             ))
         }
@@ -134,6 +191,8 @@ pub mod v1 {
         id: String,
         query: InvoiceQuery,
         store: P,
+        stripping: SignatureStripping,
+        crawler_policy: CrawlerPolicy,
         accept_header: Option<String>,
     ) -> Result<Box<dyn warp::Reply>, Infallible> {
         let accept = accept_header.unwrap_or_default();
@@ -143,27 +202,63 @@ pub mod v1 {
         } else {
             store.get_invoice(id)
         };
-        let inv = match res.await {
+        let mut inv = match res.await {
             Ok(i) => i,
             Err(e) => {
                 debug!(error = %e, "Got error during get invoice request");
                 return Ok::<Box<dyn warp::Reply>, Infallible>(Box::new(reply::into_reply(e)));
             }
         };
-        let res = Box::new(warp::reply::with_status(
+        if !query.verbatim.unwrap_or_default() {
+            stripping.apply(&mut inv);
+        }
+        let res = warp::reply::with_status(
             reply::serialized_data(&inv, accept),
             warp::http::StatusCode::OK,
-        ));
-        Ok::<Box<dyn warp::Reply>, Infallible>(res)
+        );
+        if let Some(tag) = crawler_policy.robots_tag(&inv) {
+            return Ok(Box::new(warp::reply::with_header(res, "X-Robots-Tag", tag)));
+        }
+        Ok::<Box<dyn warp::Reply>, Infallible>(Box::new(res))
     }
 
-    #[instrument(level = "trace", skip(store), fields(id = tail.as_str()))]
+    #[instrument(level = "trace", skip(store, yank_guard, audit_log), fields(id = tail.as_str()))]
     pub async fn yank_invoice<P: Provider>(
         tail: warp::path::Tail,
+        identity: Identity,
+        query: YankQuery,
         store: P,
+        yank_guard: YankGuard,
+        audit_log: AuditLog,
         accept_header: Option<String>,
     ) -> Result<impl warp::Reply, Infallible> {
         let id = tail.as_str();
+        let res = yank(id, &identity, query, store, yank_guard, accept_header).await;
+        audit_log
+            .record(
+                &identity,
+                AuditOperation::YankInvoice,
+                id.to_owned(),
+                None,
+                res,
+            )
+            .await
+    }
+
+    async fn yank<P: Provider>(
+        id: &str,
+        identity: &Identity,
+        query: YankQuery,
+        store: P,
+        yank_guard: YankGuard,
+        accept_header: Option<String>,
+    ) -> Result<impl warp::Reply, Infallible> {
+        if let Err(e) = yank_guard.check(identity, query.force.unwrap_or_default()) {
+            return Ok(reply::reply_from_error(
+                e,
+                warp::http::StatusCode::TOO_MANY_REQUESTS,
+            ));
+        }
         if let Err(e) = store.yank_invoice(id).await {
             debug!(error = %e, "Got error during yank invoice request");
             return Ok(reply::into_reply(e));
@@ -182,25 +277,54 @@ pub mod v1 {
         id: String,
         query: InvoiceQuery,
         store: P,
+        stripping: SignatureStripping,
+        crawler_policy: CrawlerPolicy,
         accept_header: Option<String>,
     ) -> Result<Box<dyn warp::Reply>, Infallible> {
         trace!("Getting invoice data");
-        let inv = get_invoice(id, query, store, accept_header).await?;
+        let inv = get_invoice(id, query, store, stripping, crawler_policy, accept_header).await?;
 
         // Consume the response to we can take the headers
         let (parts, _) = inv.into_response().into_parts();
 
         Ok::<Box<dyn warp::Reply>, Infallible>(Box::new(super::HeadResponse {
+            status: parts.status,
             headers: parts.headers,
         }))
     }
 
     //////////// Parcel Functions ////////////
-    #[instrument(level = "trace", skip(store, body))]
+    #[instrument(level = "trace", skip(store, body, audit_log))]
     pub async fn create_parcel<P, B, D>(
+        identity: Identity,
         (bindle_id, sha): (String, String),
         body: B,
         store: P,
+        audit_log: AuditLog,
+        accept_header: Option<String>,
+    ) -> Result<impl warp::Reply, Infallible>
+    where
+        P: Provider + Sync,
+        B: stream::Stream<Item = Result<D, warp::Error>> + Send + Sync + Unpin + 'static,
+        D: bytes::Buf + Send,
+    {
+        let res = store_parcel(bindle_id.clone(), sha.clone(), body, store, accept_header).await;
+        audit_log
+            .record(
+                &identity,
+                AuditOperation::CreateParcel,
+                bindle_id,
+                Some(sha),
+                res,
+            )
+            .await
+    }
+
+    async fn store_parcel<P, B, D>(
+        bindle_id: String,
+        sha: String,
+        body: B,
+        store: P,
         accept_header: Option<String>,
     ) -> Result<impl warp::Reply, Infallible>
     where
@@ -237,6 +361,101 @@ pub mod v1 {
         ))
     }
 
+    /// Stores every parcel in a multipart form, where the name of each part is the SHA of the
+    /// parcel it contains. Each parcel is stored independently, so the response reports the result
+    /// of every parcel instead of failing the whole request
+    #[instrument(level = "trace", skip(form, store, audit_log), fields(id = tail.as_str()))]
+    pub async fn create_parcels<P: Provider + Sync>(
+        tail: warp::path::Tail,
+        identity: Identity,
+        form: warp::multipart::FormData,
+        store: P,
+        audit_log: AuditLog,
+        accept_header: Option<String>,
+    ) -> Result<impl warp::Reply, Infallible> {
+        let bindle_id = tail.as_str();
+        let res = store_parcels(bindle_id, form, store, accept_header).await;
+        audit_log
+            .record(
+                &identity,
+                AuditOperation::CreateParcels,
+                bindle_id.to_owned(),
+                None,
+                res,
+            )
+            .await
+    }
+
+    async fn store_parcels<P: Provider + Sync>(
+        bindle_id: &str,
+        mut form: warp::multipart::FormData,
+        store: P,
+        accept_header: Option<String>,
+    ) -> Result<impl warp::Reply, Infallible> {
+        let inv = match store.get_invoice(bindle_id).await {
+            Ok(i) => i,
+            Err(e) => {
+                trace!("Got error during batch parcel request: {:?}", e);
+                return Ok(reply::into_reply(e));
+            }
+        };
+
+        let mut results = Vec::new();
+        while let Some(part) = form.next().await {
+            let mut part = match part {
+                Ok(p) => p,
+                Err(e) => {
+                    debug!(error = %e, "Got invalid multipart body");
+                    return Ok(reply::reply_from_error(
+                        format!("invalid multipart body: {}", e),
+                        warp::http::StatusCode::BAD_REQUEST,
+                    ));
+                }
+            };
+            let sha = part.name().to_owned();
+            let in_bindle = inv.parcel.iter().flatten().any(|p| p.label.sha256 == sha);
+            let result = if !in_bindle {
+                Err(format!(
+                    "Parcel SHA {} does not exist in invoice {}",
+                    sha, bindle_id
+                ))
+            } else {
+                match read_part(&mut part).await {
+                    Ok(data) => match store
+                        .create_parcel(bindle_id, &sha, stream::iter(data.into_iter().map(Ok)))
+                        .await
+                    {
+                        Ok(_) => Ok(crate::BatchParcelStatus::Created),
+                        Err(ProviderError::Exists) => Ok(crate::BatchParcelStatus::AlreadyExists),
+                        Err(e) => Err(e.to_string()),
+                    },
+                    Err(e) => Err(e.to_string()),
+                }
+            };
+            trace!(%sha, ?result, "Stored parcel from batch");
+            results.push(match result {
+                Ok(status) => crate::BatchParcelResult {
+                    sha256: sha,
+                    status,
+                    error: None,
+                },
+                Err(e) => crate::BatchParcelResult {
+                    sha256: sha,
+                    status: crate::BatchParcelStatus::Failed,
+                    error: Some(e),
+                },
+            });
+        }
+
+        Ok(warp::reply::with_status(
+            reply::serialized_data(
+                &crate::BatchParcelResponse { parcel: results },
+                accept_header.unwrap_or_default(),
+            ),
+            warp::http::StatusCode::OK,
+        ))
+    }
+
     #[instrument(level = "trace", skip(store))]
     pub async fn get_parcel<P: Provider + Sync>(
         (bindle_id, id): (String, String),
@@ -284,10 +503,97 @@ pub mod v1 {
         let (parts, _) = inv.into_response().into_parts();
 
         Ok::<Box<dyn warp::Reply>, Infallible>(Box::new(super::HeadResponse {
+            status: parts.status,
             headers: parts.headers,
         }))
     }
 
+    /// The shortest SHA prefix that can be looked up. This avoids listing large portions of the
+    /// store with a single request
+    const MIN_SHA_PREFIX_LENGTH: usize = 4;
+
+    /// Reads all of the data in a multipart form part. The data may arrive in any number of chunks
+    async fn read_part(part: &mut warp::multipart::Part) -> Result<Vec<bytes::Bytes>, warp::Error> {
+        let mut chunks = Vec::new();
+        while let Some(chunk) = part.data().await {
+            let mut buf = chunk?;
+            let len = bytes::Buf::remaining(&buf);
+            chunks.push(bytes::Buf::copy_to_bytes(&mut buf, len));
+        }
+        Ok(chunks)
+    }
+
+    /// Looks up the parcels whose SHA starts with the given prefix. Parcels aren't owned by a single
+    /// bindle, so a parcel is only returned if the caller can read at least one bindle that contains
+    /// it. Otherwise the lookup could be used to discover parcels from private bindles
+    #[instrument(level = "trace", skip(store, index, authz))]
+    pub async fn get_parcels_with_prefix<P, S, Authz>(
+        prefix: String,
+        identity: Identity,
+        store: P,
+        index: S,
+        authz: Authz,
+        accept_header: Option<String>,
+    ) -> Result<impl warp::Reply, Infallible>
+    where
+        P: Provider + Sync,
+        S: Search,
+        Authz: Authorizer + Sync,
+    {
+        if prefix.len() < MIN_SHA_PREFIX_LENGTH || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(reply::reply_from_error(
+                format!(
+                    "SHA prefix must be at least {} hex characters",
+                    MIN_SHA_PREFIX_LENGTH
+                ),
+                warp::http::StatusCode::BAD_REQUEST,
+            ));
+        }
+
+        let mut matches = match store.parcels_with_prefix(&prefix.to_lowercase()).await {
+            Ok(m) => m,
+            Err(e) => {
+                debug!(error = %e, "Got error during parcel prefix request");
+                return Ok(reply::into_reply(e));
+            }
+        };
+
+        if !matches.is_empty() {
+            let invoices = match all_invoices(&index).await {
+                Ok(i) => i,
+                Err(e) => {
+                    debug!(error = %e, "Got error while listing invoices for parcel prefix");
+                    return Ok(reply::reply_from_error(
+                        e,
+                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    ));
+                }
+            };
+            let mut readable = HashSet::new();
+            for inv in invoices.iter() {
+                let contained: Vec<&str> = inv
+                    .parcel
+                    .iter()
+                    .flatten()
+                    .map(|p| p.label.sha256.as_str())
+                    .filter(|sha| matches.iter().any(|m| m == sha) && !readable.contains(*sha))
+                    .collect();
+                if !contained.is_empty() && can_read(&authz, &identity, &inv.bindle.id).await {
+                    readable.extend(contained.into_iter().map(str::to_owned));
+                }
+            }
+            matches.retain(|m| readable.contains(m));
+        }
+
+        Ok(warp::reply::with_status(
+            reply::serialized_data(
+                &crate::ParcelPrefixResponse { matches },
+                accept_header.unwrap_or_default(),
+            ),
+            warp::http::StatusCode::OK,
+        ))
+    }
+
     //////////// Relationship Functions ////////////
     #[instrument(level = "trace", skip(store), fields(id = tail.as_str()))]
     pub async fn get_missing<P: Provider + Sync + Clone>(
@@ -348,6 +654,149 @@ pub mod v1 {
         ))
     }
 
+    //////////// Health Functions ////////////
+    #[instrument(level = "trace", skip(store, started))]
+    pub async fn get_health<P: Provider + Sync>(
+        store: P,
+        started: std::time::Instant,
+        accept_header: Option<String>,
+    ) -> Result<impl warp::Reply, Infallible> {
+        let storage_ready = match store.ready().await {
+            Ok(_) => true,
+            Err(e) => {
+                debug!(error = %e, "Storage is not ready");
+                false
+            }
+        };
+        let health = crate::Health {
+            alive: true,
+            storage_ready,
+            spec_version: crate::BINDLE_VERSION_1.to_owned(),
+            impl_version: env!("CARGO_PKG_VERSION").to_owned(),
+            uptime_seconds: started.elapsed().as_secs(),
+        };
+        let status = if storage_ready {
+            warp::http::StatusCode::OK
+        } else {
+            warp::http::StatusCode::SERVICE_UNAVAILABLE
+        };
+        Ok(warp::reply::with_status(
+            reply::serialized_data(&health, accept_header.unwrap_or_default()),
+            status,
+        ))
+    }
+
+    //////////// Usage Functions ////////////
+    /// Returns the storage used by each namespace. Only bindles the caller can read are counted, so
+    /// private namespaces and bindles are not revealed to other users
+    #[instrument(level = "trace", skip(index, authz))]
+    pub async fn get_usage<S: Search, Authz: Authorizer + Sync>(
+        identity: Identity,
+        index: S,
+        authz: Authz,
+        accept_header: Option<String>,
+    ) -> Result<impl warp::Reply, Infallible> {
+        // Yanked invoices are included as their parcels still use storage
+        let all = match all_invoices(&index).await {
+            Ok(i) => i,
+            Err(e) => {
+                debug!(error = %e, "Got error while listing invoices for usage");
+                return Ok(reply::reply_from_error(
+                    e,
+                    warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                ));
+            }
+        };
+        let mut invoices = Vec::with_capacity(all.len());
+        for inv in all {
+            if can_read(&authz, &identity, &inv.bindle.id).await {
+                invoices.push(inv);
+            }
+        }
+        trace!(invoices = invoices.len(), "Computing usage");
+
+        let usage = crate::UsageResponse::new(crate::usage_by_namespace(&invoices));
+        Ok(warp::reply::with_status(
+            reply::serialized_data(&usage, accept_header.unwrap_or_default()),
+            warp::http::StatusCode::OK,
+        ))
+    }
+
+    /// Pages through every invoice in the index, including yanked ones
+    async fn all_invoices<S: Search>(index: &S) -> anyhow::Result<Vec<crate::Invoice>> {
+        let mut invoices = Vec::new();
+        loop {
+            let options = SearchOptions {
+                offset: invoices.len() as u64,
+                limit: u8::MAX,
+                strict: true,
+                yanked: true,
+                ..Default::default()
+            };
+            let matches = index.query("", "", options).await?;
+            let more = matches.more && !matches.invoices.is_empty();
+            invoices.extend(matches.invoices);
+            if !more {
+                return Ok(invoices);
+            }
+        }
+    }
+
+    /// Returns whether the identity is authorized to fetch the invoice with the given ID. Endpoints
+    /// that report on the whole store use this to leave out bindles the caller can't read
+    async fn can_read<Authz: Authorizer + Sync>(
+        authz: &Authz,
+        identity: &Identity,
+        id: &crate::Id,
+    ) -> bool {
+        let path = format!("/v1/_i/{}", id);
+        authz
+            .authorize(identity.clone(), &path, warp::http::Method::GET)
+            .await
+            .is_ok()
+    }
+
+    //////////// Audit Functions ////////////
+    #[instrument(level = "trace", skip(audit_log))]
+    pub async fn get_audit_log(
+        identity: Identity,
+        query: AuditQuery,
+        audit_log: AuditLog,
+        accept_header: Option<String>,
+    ) -> Result<impl warp::Reply, Infallible> {
+        if !audit_log.is_enabled() {
+            return Ok(reply::reply_from_error(
+                "audit logging is not enabled",
+                warp::http::StatusCode::NOT_FOUND,
+            ));
+        }
+        if !audit_log.allows(&identity) {
+            debug!(principal = %identity.principal, "Rejecting audit log query");
+            return Ok(reply::reply_from_error(
+                "not allowed to query the audit log",
+                warp::http::StatusCode::FORBIDDEN,
+            ));
+        }
+        let entries = match audit_log.query(&query).await {
+            Ok(e) => e,
+            Err(e) => {
+                debug!(error = %e, "Got error while querying audit log");
+                return Ok(reply::reply_from_error(
+                    e,
+                    warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                ));
+            }
+        };
+        trace!(entries = entries.len(), "Audit log query successful");
+        Ok(warp::reply::with_status(
+            reply::serialized_data(
+                &AuditLogResponse { entries },
+                accept_header.unwrap_or_default(),
+            ),
+            warp::http::StatusCode::OK,
+        ))
+    }
+
     //////////// Helper Functions ////////////
 
     /// Fetches an invoice from the given store and checks that the given SHA exists within that
@@ -384,17 +833,57 @@ pub mod v1 {
     }
 }
 
+/// Unversioned handlers for liveness and readiness probes, such as those used by Kubernetes. These
+/// only return a status code and a short plain text body
+pub mod probes {
+    use super::*;
+
+    pub async fn healthz() -> Result<impl warp::Reply, Infallible> {
+        Ok(warp::reply::with_status("ok", warp::http::StatusCode::OK))
+    }
+
+    #[instrument(level = "trace", skip(store))]
+    pub async fn readyz<P: Provider + Sync>(store: P) -> Result<impl warp::Reply, Infallible> {
+        match store.ready().await {
+            Ok(_) => Ok(warp::reply::with_status(
+                "ok".to_owned(),
+                warp::http::StatusCode::OK,
+            )),
+            Err(e) => {
+                debug!(error = %e, "Storage is not ready");
+                Ok(warp::reply::with_status(
+                    format!("storage not ready: {}", e),
+                    warp::http::StatusCode::SERVICE_UNAVAILABLE,
+                ))
+            }
+        }
+    }
+}
+
+/// Returns the configured `robots.txt`, or rejects the request as not found if there isn't one
+pub async fn robots_txt(policy: CrawlerPolicy) -> Result<impl warp::Reply, warp::Rejection> {
+    match policy.robots_txt {
+        Some(robots) => Ok(warp::reply::with_header(
+            robots.to_string(),
+            "Content-Type",
+            "text/plain; charset=utf-8",
+        )),
+        None => Err(warp::reject::not_found()),
+    }
+}
+
 // A helper struct for HEAD responses that takes the raw headers from a GET request and puts them
 // onto an empty body
 struct HeadResponse {
+    status: warp::http::StatusCode,
     headers: warp::http::HeaderMap,
 }
 
 impl Reply for HeadResponse {
     fn into_response(self) -> warp::reply::Response {
         let mut resp = warp::http::Response::new(warp::hyper::Body::empty());
-        let headers = resp.headers_mut();
-        *headers = self.headers;
+        *resp.status_mut() = self.status;
+        *resp.headers_mut() = self.headers;
         resp
     }
 }
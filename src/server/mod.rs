@@ -2,14 +2,17 @@
 //! Spec](https://github.com/deislabs/bindle/blob/master/docs/protocol-spec.md), with associated
 //! HTTP handlers and functions
 
+mod audit_log;
 pub(crate) mod filters;
 mod handlers;
 pub(crate) mod reply;
 
 mod routes;
+mod yank_guard;
 
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use tracing::debug;
 
@@ -17,8 +20,14 @@ use super::provider::Provider;
 use crate::signature::KeyRing;
 use crate::{search::Search, signature::SecretKeyStorage};
 
+pub use audit_log::AuditLog;
+pub use yank_guard::{YankGuard, YankThrottled};
+
 pub(crate) const TOML_MIME_TYPE: &str = "application/toml";
 pub(crate) const JSON_MIME_TYPE: &str = "application/json";
+/// The largest request body accepted by the batch parcel upload endpoint. Each parcel in the
+/// request is held in memory before it is stored, so larger parcels should be uploaded individually
+pub const MAX_BATCH_UPLOAD_SIZE: u64 = 64 * 1024 * 1024;
 
 /// The configuration required for running with TLS enabled
 pub struct TlsConfig {
@@ -26,6 +35,99 @@ pub struct TlsConfig {
     pub key_path: PathBuf,
 }
 
+/// Controls whether any signatures are removed from an invoice before the server returns it.
+///
+/// When an invoice is created, the server verifies it and then adds a signature with the `host`
+/// role. Every other field, including the signatures added by clients, is stored and returned
+/// verbatim. Some deployments consider the host signature internal to the server and prefer not
+/// to return it, which can be configured here. Stripping only changes what is returned, the stored
+/// invoice always keeps every signature
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SignatureStripping {
+    /// Return every signature exactly as it is stored. This is the default
+    #[default]
+    Disabled,
+    /// Remove all signatures with the `host` role, unless the request sets the `verbatim=true`
+    /// query parameter. The [`Client`](crate::client::Client) always sets this parameter so that
+    /// verification has access to every signature
+    Host,
+}
+
+impl SignatureStripping {
+    /// Removes any signatures from the invoice that should not be returned
+    pub(crate) fn apply(&self, inv: &mut crate::Invoice) {
+        if let SignatureStripping::Host = self {
+            if let Some(sigs) = inv.signature.as_mut() {
+                sigs.retain(|s| s.role != crate::SignatureRole::Host);
+                if sigs.is_empty() {
+                    inv.signature = None;
+                }
+            }
+        }
+    }
+}
+
+/// Controls who can include yanked invoices in query results with the `yanked=true` query
+/// parameter, such as for audit tooling that needs the full history of a bindle. Queries that do
+/// not ask for yanked invoices never return them
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum YankedListing {
+    /// Any user who is authorized to query can list yanked invoices. This is the default
+    #[default]
+    Allowed,
+    /// Only users in one of the given groups can list yanked invoices. Queries for yanked invoices
+    /// from anyone else are rejected as forbidden
+    Groups(Vec<String>),
+}
+
+impl YankedListing {
+    /// Returns whether the given identity may list yanked invoices
+    pub(crate) fn allows(&self, identity: &filters::Identity) -> bool {
+        match self {
+            YankedListing::Allowed => true,
+            YankedListing::Groups(groups) => identity.groups.iter().any(|g| groups.contains(g)),
+        }
+    }
+}
+
+/// Controls how the server discourages web crawlers from indexing a public registry. Everything is
+/// disabled by default, so no `robots.txt` is served and invoices are returned without any
+/// `X-Robots-Tag` header
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CrawlerPolicy {
+    /// The contents served at `/robots.txt`. If not set, requests for it return a 404
+    pub robots_txt: Option<Arc<str>>,
+    /// Whether invoices with the [`NOINDEX_ANNOTATION`](crate::NOINDEX_ANNOTATION) set to `true`
+    /// are returned with an `X-Robots-Tag: noindex` header
+    pub honor_noindex: bool,
+}
+
+impl CrawlerPolicy {
+    /// Returns the `X-Robots-Tag` value to return with the given invoice, if any
+    pub(crate) fn robots_tag(&self, inv: &crate::Invoice) -> Option<&'static str> {
+        (self.honor_noindex && inv.is_noindex()).then_some("noindex")
+    }
+}
+
+/// Optional server behavior that is off or permissive by default. Set only the fields you need and
+/// fill in the rest with `..Default::default()`
+#[derive(Clone, Default)]
+pub struct ServerOptions {
+    /// Transforms or rejects every invoice before it is stored. The default chain is empty and
+    /// stores invoices unmodified
+    pub interceptor: crate::interceptor::InterceptorChain,
+    /// Limits how quickly each user can yank bindles
+    pub yank_guard: YankGuard,
+    /// Controls whether host signatures are removed from returned invoices
+    pub signature_stripping: SignatureStripping,
+    /// Controls who can include yanked invoices in query results
+    pub yanked_listing: YankedListing,
+    /// Controls how web crawlers are discouraged from indexing the server
+    pub crawler_policy: CrawlerPolicy,
+    /// Records creates and yanks
+    pub audit_log: AuditLog,
+}
+
 /// Returns a future that runs a server until it receives a SIGINT to stop. If optional TLS
 /// configuration is given, the server will be configured to use TLS. Otherwise it will use plain
 /// HTTP
@@ -40,6 +142,7 @@ pub async fn server<P, I, Authn, Authz, S>(
     keystore: S,
     verification_strategy: crate::VerificationStrategy,
     keyring: KeyRing,
+    options: ServerOptions,
 ) -> anyhow::Result<()>
 where
     P: Provider + Clone + Send + Sync + 'static,
@@ -57,6 +160,7 @@ where
         keystore,
         verification_strategy,
         keyring,
+        options,
     );
 
     let server = warp::serve(api);
@@ -98,6 +202,7 @@ mod test {
 
     use crate::authn::always::AlwaysAuthenticate;
     use crate::authz::always::AlwaysAuthorize;
+    use crate::interceptor::noop::NoopInterceptor;
     use crate::invoice::{
         signature::{KeyRing, SecretKeyEntry},
         SignatureRole, VerificationStrategy,
@@ -130,6 +235,7 @@ mod test {
             ks,
             VerificationStrategy::default(),
             KeyRing::default(),
+            super::ServerOptions::default(),
         );
 
         // Now that we can't upload parcels before invoices exist, we need to create a bindle that shares some parcels
@@ -157,6 +263,7 @@ mod test {
             create_res.missing.is_some(),
             "Invoice should have missing parcels"
         );
+        assert!(!create_res.complete, "Invoice should not be complete");
 
         // Upload the parcels for one of the invoices
 
@@ -206,6 +313,7 @@ mod test {
             create_res.missing.is_none(),
             "Invoice should not have missing parcels"
         );
+        assert!(create_res.complete, "Invoice should be complete");
 
         // Create a second version of the same invoice with some missing and already existing
         // parcels and make sure the correct response is returned
@@ -300,6 +408,7 @@ mod test {
             ks,
             VerificationStrategy::default(),
             KeyRing::default(),
+            super::ServerOptions::default(),
         );
 
         let sk = SecretKeyEntry::new("test".to_owned(), vec![SignatureRole::Host]);
@@ -355,6 +464,354 @@ mod test {
         toml::from_slice::<crate::Invoice>(res.body()).expect("should be valid invoice TOML");
     }
 
+    #[rstest]
+    #[tokio::test]
+    async fn test_yanked_listing<T>(
+        #[values(testing::setup(), testing::setup_embedded())]
+        #[future]
+        provider_setup: (T, StrictEngine, MockKeyStore),
+    ) where
+        T: Provider + Clone + Send + Sync + 'static,
+    {
+        let (store, index, ks) = provider_setup.await;
+
+        let sk = SecretKeyEntry::new("test".to_owned(), vec![SignatureRole::Host]);
+        let mut scaffold = testing::Scaffold::load("incomplete").await;
+        for version in &["1.0.0", "2.0.0"] {
+            scaffold.invoice.bindle.id =
+                format!("{}/{}", scaffold.invoice.bindle.id.name(), version)
+                    .parse()
+                    .unwrap();
+            let verified = VerificationStrategy::MultipleAttestation(vec![])
+                .verify(scaffold.invoice.clone(), &KeyRing::default())
+                .unwrap();
+            let signed = crate::sign(verified, vec![(SignatureRole::Host, &sk)]).unwrap();
+            store
+                .create_invoice(signed)
+                .await
+                .expect("Should be able to insert invoice");
+        }
+        store
+            .yank_invoice(&scaffold.invoice.bindle.id)
+            .await
+            .expect("Should be able to yank invoice");
+
+        let query = |api, path: &'static str| async move {
+            let res = warp::test::request().path(path).reply(api).await;
+            let status = res.status();
+            let matches = toml::from_slice::<crate::search::Matches>(res.body()).ok();
+            (status, matches)
+        };
+
+        let api = super::routes::api(
+            store.clone(),
+            index.clone(),
+            AlwaysAuthenticate,
+            AlwaysAuthorize,
+            ks.clone(),
+            VerificationStrategy::default(),
+            KeyRing::default(),
+            super::ServerOptions::default(),
+        );
+
+        let (status, matches) = query(&api, "/v1/_q").await;
+        assert_eq!(status, warp::http::StatusCode::OK);
+        let matches = matches.expect("should be valid matches TOML");
+        assert_eq!(
+            1,
+            matches.invoices.len(),
+            "Yanked invoices should be hidden"
+        );
+
+        let (status, matches) = query(&api, "/v1/_q?include_yanked=true").await;
+        assert_eq!(status, warp::http::StatusCode::OK);
+        let matches = matches.expect("should be valid matches TOML");
+        assert_eq!(
+            2,
+            matches.invoices.len(),
+            "Yanked invoices should be listed"
+        );
+        assert!(
+            matches
+                .invoices
+                .iter()
+                .any(|inv| inv.yanked.unwrap_or_default()),
+            "Yanked invoice should be marked as yanked"
+        );
+
+        let api = super::routes::api(
+            store,
+            index,
+            AlwaysAuthenticate,
+            AlwaysAuthorize,
+            ks,
+            VerificationStrategy::default(),
+            KeyRing::default(),
+            super::ServerOptions {
+                yanked_listing: super::YankedListing::Groups(vec!["auditors".to_owned()]),
+                ..Default::default()
+            },
+        );
+        let (status, _) = query(&api, "/v1/_q?yanked=true").await;
+        assert_eq!(
+            status,
+            warp::http::StatusCode::FORBIDDEN,
+            "Users outside of the allowed groups should not list yanked invoices"
+        );
+        let (status, _) = query(&api, "/v1/_q").await;
+        assert_eq!(status, warp::http::StatusCode::OK);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_query_annotation_and_sort<T>(
+        #[values(testing::setup(), testing::setup_embedded())]
+        #[future]
+        provider_setup: (T, StrictEngine, MockKeyStore),
+    ) where
+        T: Provider + Clone + Send + Sync + 'static,
+    {
+        let (store, index, ks) = provider_setup.await;
+
+        let sk = SecretKeyEntry::new("test".to_owned(), vec![SignatureRole::Host]);
+        let mut scaffold = testing::Scaffold::load("incomplete").await;
+        for (version, channel) in &[("1.0.0", "stable"), ("2.0.0", "beta"), ("3.0.0", "stable")] {
+            scaffold.invoice.bindle.id =
+                format!("{}/{}", scaffold.invoice.bindle.id.name(), version)
+                    .parse()
+                    .unwrap();
+            let mut inv = scaffold.invoice.clone();
+            inv.annotations
+                .get_or_insert_with(Default::default)
+                .insert("channel".to_owned(), channel.to_string());
+            let verified = VerificationStrategy::MultipleAttestation(vec![])
+                .verify(inv, &KeyRing::default())
+                .unwrap();
+            let signed = crate::sign(verified, vec![(SignatureRole::Host, &sk)]).unwrap();
+            store
+                .create_invoice(signed)
+                .await
+                .expect("Should be able to insert invoice");
+        }
+
+        let api = super::routes::api(
+            store,
+            index,
+            AlwaysAuthenticate,
+            AlwaysAuthorize,
+            ks,
+            VerificationStrategy::default(),
+            KeyRing::default(),
+            super::ServerOptions::default(),
+        );
+
+        let res = warp::test::request()
+            .path("/v1/_q?annotation=channel%3Dstable&sort=-version")
+            .reply(&api)
+            .await;
+        assert_eq!(
+            res.status(),
+            warp::http::StatusCode::OK,
+            "Body: {}",
+            String::from_utf8_lossy(res.body())
+        );
+        let matches: crate::search::Matches =
+            toml::from_slice(res.body()).expect("should be valid matches TOML");
+        assert_eq!(
+            vec!["3.0.0", "1.0.0"],
+            matches
+                .invoices
+                .iter()
+                .map(|i| i.bindle.id.version_string())
+                .collect::<Vec<_>>()
+        );
+
+        for path in &["/v1/_q?sort=size", "/v1/_q?annotation=%3Dstable"] {
+            let res = warp::test::request().path(path).reply(&api).await;
+            assert_eq!(
+                res.status(),
+                warp::http::StatusCode::BAD_REQUEST,
+                "Invalid query options should be rejected"
+            );
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_crawler_policy<T>(
+        #[values(testing::setup(), testing::setup_embedded())]
+        #[future]
+        provider_setup: (T, StrictEngine, MockKeyStore),
+    ) where
+        T: Provider + Clone + Send + Sync + 'static,
+    {
+        let (store, index, ks) = provider_setup.await;
+
+        let sk = SecretKeyEntry::new("test".to_owned(), vec![SignatureRole::Host]);
+        let mut scaffold = testing::Scaffold::load("incomplete").await;
+        let mut paths = Vec::new();
+        for (version, noindex) in &[("1.0.0", true), ("2.0.0", false)] {
+            scaffold.invoice.bindle.id =
+                format!("{}/{}", scaffold.invoice.bindle.id.name(), version)
+                    .parse()
+                    .unwrap();
+            let mut inv = scaffold.invoice.clone();
+            if *noindex {
+                inv.annotations
+                    .get_or_insert_with(Default::default)
+                    .insert(crate::NOINDEX_ANNOTATION.to_owned(), "true".to_owned());
+            }
+            let verified = VerificationStrategy::MultipleAttestation(vec![])
+                .verify(inv, &KeyRing::default())
+                .unwrap();
+            let signed = crate::sign(verified, vec![(SignatureRole::Host, &sk)]).unwrap();
+            store
+                .create_invoice(signed)
+                .await
+                .expect("Should be able to insert invoice");
+            paths.push(format!("/v1/_i/{}", scaffold.invoice.bindle.id));
+        }
+
+        let api_with = |policy| {
+            super::routes::api(
+                store.clone(),
+                index.clone(),
+                AlwaysAuthenticate,
+                AlwaysAuthorize,
+                ks.clone(),
+                VerificationStrategy::default(),
+                KeyRing::default(),
+                super::ServerOptions {
+                    crawler_policy: policy,
+                    ..Default::default()
+                },
+            )
+        };
+
+        // Everything is off by default
+        let api = api_with(super::CrawlerPolicy::default());
+        let res = warp::test::request().path("/robots.txt").reply(&api).await;
+        assert_eq!(res.status(), warp::http::StatusCode::NOT_FOUND);
+        let res = warp::test::request().path(&paths[0]).reply(&api).await;
+        assert_eq!(res.status(), warp::http::StatusCode::OK);
+        assert!(res.headers().get("X-Robots-Tag").is_none());
+
+        let api = api_with(super::CrawlerPolicy {
+            robots_txt: Some("User-agent: *\nDisallow: /v1/\n".into()),
+            honor_noindex: true,
+        });
+        let res = warp::test::request().path("/robots.txt").reply(&api).await;
+        assert_eq!(res.status(), warp::http::StatusCode::OK);
+        assert_eq!(res.body().as_ref(), b"User-agent: *\nDisallow: /v1/\n");
+
+        for method in &["GET", "HEAD"] {
+            let res = warp::test::request()
+                .method(method)
+                .path(&paths[0])
+                .reply(&api)
+                .await;
+            assert_eq!(res.status(), warp::http::StatusCode::OK);
+            assert_eq!(
+                res.headers()
+                    .get("X-Robots-Tag")
+                    .expect("noindex invoice should have a robots tag"),
+                "noindex"
+            );
+            let res = warp::test::request()
+                .method(method)
+                .path(&paths[1])
+                .reply(&api)
+                .await;
+            assert_eq!(res.status(), warp::http::StatusCode::OK);
+            assert!(res.headers().get("X-Robots-Tag").is_none());
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_yank_guard<T>(
+        #[values(testing::setup(), testing::setup_embedded())]
+        #[future]
+        provider_setup: (T, StrictEngine, MockKeyStore),
+    ) where
+        T: Provider + Clone + Send + Sync + 'static,
+    {
+        let (store, index, ks) = provider_setup.await;
+
+        let api = super::routes::api(
+            store.clone(),
+            index,
+            AlwaysAuthenticate,
+            AlwaysAuthorize,
+            ks,
+            VerificationStrategy::default(),
+            KeyRing::default(),
+            super::ServerOptions {
+                yank_guard: super::YankGuard::new(1, std::time::Duration::from_secs(60)),
+                ..Default::default()
+            },
+        );
+
+        let sk = SecretKeyEntry::new("test".to_owned(), vec![SignatureRole::Host]);
+        let mut scaffold = testing::Scaffold::load("incomplete").await;
+        let mut ids = Vec::new();
+        for version in &["1.0.0", "2.0.0", "3.0.0"] {
+            scaffold.invoice.bindle.id =
+                format!("{}/{}", scaffold.invoice.bindle.id.name(), version)
+                    .parse()
+                    .unwrap();
+            let verified = VerificationStrategy::MultipleAttestation(vec![])
+                .verify(scaffold.invoice.clone(), &KeyRing::default())
+                .unwrap();
+            let signed = crate::sign(verified, vec![(SignatureRole::Host, &sk)]).unwrap();
+            store
+                .create_invoice(signed)
+                .await
+                .expect("Should be able to insert invoice");
+            ids.push(scaffold.invoice.bindle.id.clone());
+        }
+
+        let res = warp::test::request()
+            .method("DELETE")
+            .path(&format!("/v1/_i/{}", ids[0]))
+            .reply(&api)
+            .await;
+        assert_eq!(
+            res.status(),
+            warp::http::StatusCode::OK,
+            "Body: {}",
+            String::from_utf8_lossy(res.body())
+        );
+
+        let res = warp::test::request()
+            .method("DELETE")
+            .path(&format!("/v1/_i/{}", ids[1]))
+            .reply(&api)
+            .await;
+        assert_eq!(
+            res.status(),
+            warp::http::StatusCode::TOO_MANY_REQUESTS,
+            "Yanks over the limit should be rejected. Body: {}",
+            String::from_utf8_lossy(res.body())
+        );
+        store
+            .get_invoice(&ids[1])
+            .await
+            .expect("Throttled yank should not have yanked the invoice");
+
+        let res = warp::test::request()
+            .method("DELETE")
+            .path(&format!("/v1/_i/{}?force=true", ids[2]))
+            .reply(&api)
+            .await;
+        assert_eq!(
+            res.status(),
+            warp::http::StatusCode::OK,
+            "Forced yanks should not be limited. Body: {}",
+            String::from_utf8_lossy(res.body())
+        );
+    }
+
     #[rstest]
     #[tokio::test]
     // This isn't meant to test all of the possible validation failures (that should be done in a unit
@@ -377,6 +834,7 @@ mod test {
             ks,
             VerificationStrategy::default(),
             KeyRing::default(),
+            super::ServerOptions::default(),
         );
         let valid_raw = bindles.get("valid_v1").expect("Missing scaffold");
         let valid = testing::Scaffold::from(valid_raw.clone());
@@ -427,6 +885,7 @@ mod test {
             keystore.clone(),
             VerificationStrategy::default(),
             KeyRing::default(),
+            super::ServerOptions::default(),
         );
         // Insert a parcel
         let scaffold = testing::Scaffold::load("valid_v1").await;
@@ -525,6 +984,7 @@ mod test {
             ks,
             VerificationStrategy::default(),
             KeyRing::default(),
+            super::ServerOptions::default(),
         );
         let bindles_to_insert = vec!["incomplete", "valid_v1", "valid_v2"];
 
@@ -639,6 +1099,7 @@ mod test {
             ks,
             VerificationStrategy::default(),
             KeyRing::default(),
+            super::ServerOptions::default(),
         );
 
         let scaffold = testing::Scaffold::load("lotsa_parcels").await;
@@ -719,6 +1180,7 @@ mod test {
             ks,
             VerificationStrategy::default(),
             KeyRing::default(),
+            super::ServerOptions::default(),
         );
 
         let scaffold = testing::RawScaffold::load("valid_v1").await;
@@ -750,4 +1212,711 @@ mod test {
             "Newly created invoice should be signed by the host"
         );
     }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_signature_stripping<T>(
+        #[values(testing::setup(), testing::setup_embedded())]
+        #[future]
+        provider_setup: (T, StrictEngine, MockKeyStore),
+    ) where
+        T: Provider + Clone + Send + Sync + 'static,
+    {
+        let (store, index, ks) = provider_setup.await;
+
+        let api = super::routes::api(
+            store,
+            index,
+            AlwaysAuthenticate,
+            AlwaysAuthorize,
+            ks,
+            VerificationStrategy::default(),
+            KeyRing::default(),
+            super::ServerOptions {
+                signature_stripping: super::SignatureStripping::Host,
+                ..Default::default()
+            },
+        );
+
+        let scaffold = testing::RawScaffold::load("valid_v1").await;
+        let res = warp::test::request()
+            .method("POST")
+            .header("Content-Type", "application/toml")
+            .path("/v1/_i")
+            .body(&scaffold.invoice)
+            .reply(&api)
+            .await;
+        assert_eq!(
+            res.status(),
+            warp::http::StatusCode::ACCEPTED,
+            "Body: {}",
+            String::from_utf8_lossy(res.body())
+        );
+        let create_res: crate::InvoiceCreateResponse =
+            toml::from_slice(res.body()).expect("should be valid invoice response TOML");
+        let inv_path = format!("/v1/_i/{}", create_res.invoice.bindle.id);
+
+        let has_host_signature = |body: &[u8]| {
+            let inv: crate::Invoice = toml::from_slice(body).expect("should be valid invoice TOML");
+            inv.signature
+                .unwrap_or_default()
+                .into_iter()
+                .any(|sig| matches!(sig.role, crate::SignatureRole::Host))
+        };
+
+        let res = warp::test::request().path(&inv_path).reply(&api).await;
+        assert_eq!(res.status(), warp::http::StatusCode::OK);
+        assert!(
+            !has_host_signature(res.body()),
+            "Host signatures should be stripped from the returned invoice"
+        );
+
+        let res = warp::test::request()
+            .path(&format!("{}?verbatim=true", inv_path))
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), warp::http::StatusCode::OK);
+        assert!(
+            has_host_signature(res.body()),
+            "Verbatim invoice should include the host signature"
+        );
+    }
+
+    #[derive(Clone)]
+    struct AnnotateAndReject;
+
+    impl crate::interceptor::InvoiceInterceptor for AnnotateAndReject {
+        fn before_store(
+            &self,
+            mut invoice: crate::Invoice,
+        ) -> Result<crate::Invoice, crate::interceptor::RejectReason> {
+            if invoice.bindle.id.name().starts_with("forbidden") {
+                return Err(crate::interceptor::RejectReason::Forbidden(
+                    "forbidden bindles are forbidden".to_owned(),
+                ));
+            }
+            invoice
+                .annotations
+                .get_or_insert_with(Default::default)
+                .insert("intercepted".to_owned(), "true".to_owned());
+            Ok(invoice)
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_invoice_interceptor<T>(
+        #[values(testing::setup(), testing::setup_embedded())]
+        #[future]
+        provider_setup: (T, StrictEngine, MockKeyStore),
+    ) where
+        T: Provider + Clone + Send + Sync + 'static,
+    {
+        let (store, index, ks) = provider_setup.await;
+
+        let api = super::routes::api(
+            store,
+            index,
+            AlwaysAuthenticate,
+            AlwaysAuthorize,
+            ks,
+            VerificationStrategy::default(),
+            KeyRing::default(),
+            super::ServerOptions {
+                interceptor: crate::interceptor::InterceptorChain::new()
+                    .with(NoopInterceptor)
+                    .with(AnnotateAndReject),
+                ..Default::default()
+            },
+        );
+
+        let mut scaffold = testing::Scaffold::load("valid_v1").await;
+        // The stored invoice should contain the modifications from the interceptor
+        let res = warp::test::request()
+            .method("POST")
+            .header("Content-Type", "application/toml")
+            .path("/v1/_i")
+            .body(toml::to_vec(&scaffold.invoice).unwrap())
+            .reply(&api)
+            .await;
+        assert_eq!(
+            res.status(),
+            warp::http::StatusCode::ACCEPTED,
+            "Body: {}",
+            String::from_utf8_lossy(res.body())
+        );
+        let create_res: crate::InvoiceCreateResponse =
+            toml::from_slice(res.body()).expect("should be valid invoice response TOML");
+        assert_eq!(
+            Some("true"),
+            create_res
+                .invoice
+                .annotations
+                .unwrap_or_default()
+                .get("intercepted")
+                .map(|s| s.as_str()),
+            "Created invoice should have been modified by the interceptor"
+        );
+
+        // A rejected invoice should return the rejection status and never be stored
+        scaffold.invoice.bindle.id = "forbidden/1.0.0".parse().unwrap();
+        let res = warp::test::request()
+            .method("POST")
+            .header("Content-Type", "application/toml")
+            .path("/v1/_i")
+            .body(toml::to_vec(&scaffold.invoice).unwrap())
+            .reply(&api)
+            .await;
+        assert_eq!(
+            res.status(),
+            warp::http::StatusCode::FORBIDDEN,
+            "Body: {}",
+            String::from_utf8_lossy(res.body())
+        );
+        let res = warp::test::request()
+            .path("/v1/_i/forbidden/1.0.0")
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), warp::http::StatusCode::NOT_FOUND);
+    }
+
+    /// An authorizer that denies reading a single bindle and allows everything else
+    #[derive(Clone)]
+    struct DenyBindle(String);
+
+    #[async_trait::async_trait]
+    impl crate::authz::Authorizer for DenyBindle {
+        async fn authorize<A>(
+            &self,
+            _item: A,
+            path: &str,
+            _method: warp::http::Method,
+        ) -> anyhow::Result<()>
+        where
+            A: crate::authz::Authorizable + Send,
+        {
+            if path.starts_with(&format!("/v1/_i/{}", self.0)) {
+                anyhow::bail!("not allowed to read {}", self.0);
+            }
+            Ok(())
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_parcels_with_prefix<T>(
+        #[values(testing::setup(), testing::setup_embedded())]
+        #[future]
+        provider_setup: (T, StrictEngine, MockKeyStore),
+    ) where
+        T: Provider + Clone + Send + Sync + 'static,
+    {
+        let (store, index, ks) = provider_setup.await;
+
+        let api = super::routes::api(
+            store.clone(),
+            index.clone(),
+            AlwaysAuthenticate,
+            AlwaysAuthorize,
+            ks.clone(),
+            VerificationStrategy::default(),
+            KeyRing::default(),
+            super::ServerOptions::default(),
+        );
+
+        let scaffold = testing::Scaffold::load("valid_v2").await;
+        let res = warp::test::request()
+            .method("POST")
+            .header("Content-Type", "application/toml")
+            .path("/v1/_i")
+            .body(toml::to_vec(&scaffold.invoice).unwrap())
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), warp::http::StatusCode::ACCEPTED);
+        for parcel in scaffold.parcel_files.values() {
+            let res = warp::test::request()
+                .method("POST")
+                .path(&format!(
+                    "/v1/_i/{}@{}",
+                    scaffold.invoice.bindle.id, parcel.sha
+                ))
+                .body(parcel.data.clone())
+                .reply(&api)
+                .await;
+            assert_eq!(res.status(), warp::http::StatusCode::OK);
+        }
+
+        let sha = &scaffold.parcel_files.values().next().unwrap().sha;
+        let res = warp::test::request()
+            .path(&format!("/v1/_p/{}", &sha[..6]))
+            .reply(&api)
+            .await;
+        assert_eq!(
+            res.status(),
+            warp::http::StatusCode::OK,
+            "Body: {}",
+            String::from_utf8_lossy(res.body())
+        );
+        let resp: crate::ParcelPrefixResponse =
+            toml::from_slice(res.body()).expect("should be valid prefix response TOML");
+        assert_eq!(vec![sha.to_owned()], resp.matches);
+
+        // Prefixes that are too short or not hex should be rejected
+        for prefix in &["23f", "zzzz"] {
+            let res = warp::test::request()
+                .path(&format!("/v1/_p/{}", prefix))
+                .reply(&api)
+                .await;
+            assert_eq!(res.status(), warp::http::StatusCode::BAD_REQUEST);
+        }
+
+        // Parcels are only listed if the caller can read a bindle that contains them
+        let api = super::routes::api(
+            store,
+            index,
+            AlwaysAuthenticate,
+            DenyBindle(scaffold.invoice.bindle.id.to_string()),
+            ks,
+            VerificationStrategy::default(),
+            KeyRing::default(),
+            super::ServerOptions::default(),
+        );
+        let res = warp::test::request()
+            .path(&format!("/v1/_p/{}", &sha[..6]))
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), warp::http::StatusCode::OK);
+        let resp: crate::ParcelPrefixResponse =
+            toml::from_slice(res.body()).expect("should be valid prefix response TOML");
+        assert!(resp.matches.is_empty());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_usage_permissions<T>(
+        #[values(testing::setup(), testing::setup_embedded())]
+        #[future]
+        provider_setup: (T, StrictEngine, MockKeyStore),
+    ) where
+        T: Provider + Clone + Send + Sync + 'static,
+    {
+        let (store, index, ks) = provider_setup.await;
+        let api_with = |authz| {
+            super::routes::api(
+                store.clone(),
+                index.clone(),
+                AlwaysAuthenticate,
+                authz,
+                ks.clone(),
+                VerificationStrategy::default(),
+                KeyRing::default(),
+                super::ServerOptions::default(),
+            )
+        };
+
+        let hidden = testing::Scaffold::load("valid_v1").await.invoice;
+        let visible = testing::Scaffold::load("valid_v2").await.invoice;
+        // Nothing is denied to start with
+        let api = api_with(DenyBindle("example.com/nothing".to_owned()));
+        for inv in &[&hidden, &visible] {
+            let res = warp::test::request()
+                .method("POST")
+                .header("Content-Type", "application/toml")
+                .path("/v1/_i")
+                .body(toml::to_vec(inv).unwrap())
+                .reply(&api)
+                .await;
+            assert_eq!(res.status(), warp::http::StatusCode::ACCEPTED);
+        }
+
+        let invoice_count = |api| async move {
+            let res = warp::test::request().path("/v1/_usage").reply(&api).await;
+            assert_eq!(res.status(), warp::http::StatusCode::OK);
+            let resp: crate::UsageResponse =
+                toml::from_slice(res.body()).expect("should be valid usage response TOML");
+            resp.namespaces
+                .iter()
+                .map(|n| n.usage.invoice_count)
+                .sum::<u64>()
+        };
+        assert_eq!(2, invoice_count(api).await);
+        // Bindles the caller can't read aren't counted
+        let api = api_with(DenyBindle(hidden.bindle.id.to_string()));
+        assert_eq!(1, invoice_count(api).await);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_public_read_authorizer<T>(
+        #[values(testing::setup(), testing::setup_embedded())]
+        #[future]
+        provider_setup: (T, StrictEngine, MockKeyStore),
+    ) where
+        T: Provider + Clone + Send + Sync + 'static,
+    {
+        let (store, index, ks) = provider_setup.await;
+
+        let api = super::routes::api(
+            store.clone(),
+            index,
+            AlwaysAuthenticate,
+            crate::authz::public::PublicReadAuthorizer::new(
+                AlwaysAuthorize,
+                store.clone(),
+                Vec::new(),
+            ),
+            ks,
+            VerificationStrategy::default(),
+            KeyRing::default(),
+            super::ServerOptions::default(),
+        );
+
+        let sk = SecretKeyEntry::new("test".to_owned(), vec![SignatureRole::Host]);
+        let mut scaffold = testing::Scaffold::load("incomplete").await;
+        let private_path = format!("/v1/_i/{}", scaffold.invoice.name());
+        let create = |inv: crate::Invoice| {
+            let verified = VerificationStrategy::MultipleAttestation(vec![])
+                .verify(inv, &KeyRing::default())
+                .unwrap();
+            crate::sign(verified, vec![(SignatureRole::Host, &sk)]).unwrap()
+        };
+        store
+            .create_invoice(create(scaffold.invoice.clone()))
+            .await
+            .expect("Should be able to insert invoice");
+
+        // Mark a second version as public with an annotation
+        scaffold.invoice.bindle.id = format!("{}/9.9.9", scaffold.invoice.bindle.id.name())
+            .parse()
+            .unwrap();
+        scaffold.invoice.annotations = Some(
+            vec![(
+                crate::authz::public::VISIBILITY_ANNOTATION.to_owned(),
+                crate::authz::public::PUBLIC_VISIBILITY.to_owned(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        let public_path = format!("/v1/_i/{}", scaffold.invoice.name());
+        store
+            .create_invoice(create(scaffold.invoice.clone()))
+            .await
+            .expect("Should be able to insert invoice");
+
+        let res = warp::test::request().path(&private_path).reply(&api).await;
+        assert_eq!(
+            res.status(),
+            warp::http::StatusCode::UNAUTHORIZED,
+            "Private bindle should require authentication"
+        );
+
+        let res = warp::test::request().path(&public_path).reply(&api).await;
+        assert_eq!(
+            res.status(),
+            warp::http::StatusCode::OK,
+            "Public bindle should be readable. Body: {}",
+            String::from_utf8_lossy(res.body())
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_read_only<T>(
+        #[values(testing::setup(), testing::setup_embedded())]
+        #[future]
+        provider_setup: (T, StrictEngine, MockKeyStore),
+    ) where
+        T: Provider + Clone + Send + Sync + 'static,
+    {
+        let bindles = testing::load_all_files().await;
+        let (store, index, ks) = provider_setup.await;
+
+        let api = super::routes::api(
+            crate::provider::read_only::ReadOnlyProvider::new(store),
+            index,
+            AlwaysAuthenticate,
+            AlwaysAuthorize,
+            ks,
+            VerificationStrategy::default(),
+            KeyRing::default(),
+            super::ServerOptions::default(),
+        );
+
+        let valid_v1 = bindles.get("valid_v1").expect("Missing scaffold");
+        let res = warp::test::request()
+            .method("POST")
+            .header("Content-Type", "application/toml")
+            .path("/v1/_i")
+            .body(&valid_v1.invoice)
+            .reply(&api)
+            .await;
+
+        assert_eq!(
+            res.status(),
+            warp::http::StatusCode::METHOD_NOT_ALLOWED,
+            "Writes to a read-only registry should be rejected. Body: {}",
+            String::from_utf8_lossy(res.body())
+        );
+        assert_eq!(
+            "true",
+            res.headers()
+                .get(crate::provider::read_only::READ_ONLY_HEADER)
+                .expect("Read-only rejections should be marked")
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_health<T>(
+        #[values(testing::setup(), testing::setup_embedded())]
+        #[future]
+        provider_setup: (T, StrictEngine, MockKeyStore),
+    ) where
+        T: Provider + Clone + Send + Sync + 'static,
+    {
+        let (store, index, ks) = provider_setup.await;
+
+        let api = super::routes::api(
+            store,
+            index,
+            AlwaysAuthenticate,
+            AlwaysAuthorize,
+            ks,
+            VerificationStrategy::default(),
+            KeyRing::default(),
+            super::ServerOptions::default(),
+        );
+
+        for path in &["/healthz", "/readyz"] {
+            let res = warp::test::request().path(path).reply(&api).await;
+            assert_eq!(
+                res.status(),
+                warp::http::StatusCode::OK,
+                "Probe {} should succeed",
+                path
+            );
+        }
+
+        let res = warp::test::request().path("/v1/_health").reply(&api).await;
+        assert_eq!(
+            res.status(),
+            warp::http::StatusCode::OK,
+            "Body: {}",
+            String::from_utf8_lossy(res.body())
+        );
+        let health: crate::Health =
+            toml::from_slice(res.body()).expect("should be valid health TOML");
+        assert!(health.is_healthy(), "Server should be healthy");
+        assert_eq!(crate::BINDLE_VERSION_1, health.spec_version);
+        assert_eq!(env!("CARGO_PKG_VERSION"), health.impl_version);
+    }
+
+    #[tokio::test]
+    async fn test_health_storage_not_ready() {
+        // A file where the storage directory should be means the storage can never be created
+        let file = tempfile::NamedTempFile::new().expect("unable to create tempfile");
+        let index = StrictEngine::default();
+        let store =
+            crate::provider::file::FileProvider::new(file.path().join("storage"), index.clone())
+                .await;
+
+        let api = super::routes::api(
+            store,
+            index,
+            AlwaysAuthenticate,
+            AlwaysAuthorize,
+            MockKeyStore::new(),
+            VerificationStrategy::default(),
+            KeyRing::default(),
+            super::ServerOptions::default(),
+        );
+
+        let res = warp::test::request().path("/healthz").reply(&api).await;
+        assert_eq!(res.status(), warp::http::StatusCode::OK);
+
+        let res = warp::test::request().path("/readyz").reply(&api).await;
+        assert_eq!(res.status(), warp::http::StatusCode::SERVICE_UNAVAILABLE);
+
+        let res = warp::test::request().path("/v1/_health").reply(&api).await;
+        assert_eq!(res.status(), warp::http::StatusCode::SERVICE_UNAVAILABLE);
+        let health: crate::Health =
+            toml::from_slice(res.body()).expect("should be valid health TOML");
+        assert!(health.alive);
+        assert!(!health.storage_ready);
+    }
+
+    #[derive(Clone)]
+    struct HeaderAuthenticate;
+
+    /// A user named by the authorization header, who is an auditor if the name says so
+    struct HeaderUser(String);
+
+    impl crate::authz::Authorizable for HeaderUser {
+        fn principal(&self) -> String {
+            self.0.clone()
+        }
+
+        fn groups(&self) -> Vec<String> {
+            if self.0 == "auditor" {
+                vec!["auditors".to_owned()]
+            } else {
+                Vec::new()
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl crate::authn::Authenticator for HeaderAuthenticate {
+        type Item = HeaderUser;
+
+        async fn authenticate(&self, auth_data: &str) -> anyhow::Result<Self::Item> {
+            Ok(HeaderUser(auth_data.to_owned()))
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_audit_log<T>(
+        #[values(testing::setup(), testing::setup_embedded())]
+        #[future]
+        provider_setup: (T, StrictEngine, MockKeyStore),
+    ) where
+        T: Provider + Clone + Send + Sync + 'static,
+    {
+        use crate::audit::{AuditLogResponse, AuditOperation, AuditOutcome, FileAuditSink};
+
+        let (store, index, ks) = provider_setup.await;
+        let dir = tempfile::tempdir().unwrap();
+        let api_with = |audit_log| {
+            super::routes::api(
+                store.clone(),
+                index.clone(),
+                HeaderAuthenticate,
+                AlwaysAuthorize,
+                ks.clone(),
+                VerificationStrategy::default(),
+                KeyRing::default(),
+                super::ServerOptions {
+                    audit_log,
+                    ..Default::default()
+                },
+            )
+        };
+        let api = api_with(
+            super::AuditLog::new(FileAuditSink::new(dir.path().join("audit.jsonl")))
+                .with_admin_groups(vec!["auditors".to_owned()]),
+        );
+
+        let scaffold = Scaffold::load("valid_v1").await;
+        let id = scaffold.invoice.bindle.id.to_string();
+        let inv = toml::to_vec(&scaffold.invoice).unwrap();
+        for expected in &[
+            warp::http::StatusCode::ACCEPTED,
+            warp::http::StatusCode::CONFLICT,
+        ] {
+            let res = warp::test::request()
+                .method("POST")
+                .header("Authorization", "alice")
+                .header("Content-Type", "application/toml")
+                .path("/v1/_i")
+                .body(&inv)
+                .reply(&api)
+                .await;
+            assert_eq!(res.status(), *expected);
+        }
+        let parcel = scaffold.parcel_files.values().next().unwrap();
+        let res = warp::test::request()
+            .method("POST")
+            .header("Authorization", "alice")
+            .path(&format!("/v1/_i/{}@{}", id, parcel.sha))
+            .body(parcel.data.clone())
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), warp::http::StatusCode::OK);
+        let res = warp::test::request()
+            .method("DELETE")
+            .header("Authorization", "bob")
+            .path(&format!("/v1/_i/{}", id))
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), warp::http::StatusCode::OK);
+
+        let query = |user: &'static str, path: &'static str| {
+            let api = &api;
+            async move {
+                let res = warp::test::request()
+                    .header("Authorization", user)
+                    .path(path)
+                    .reply(api)
+                    .await;
+                let status = res.status();
+                let entries = toml::from_slice::<AuditLogResponse>(res.body())
+                    .map(|r| r.entries)
+                    .unwrap_or_default();
+                (status, entries)
+            }
+        };
+
+        let (status, _) = query("alice", "/v1/_audit").await;
+        assert_eq!(
+            status,
+            warp::http::StatusCode::FORBIDDEN,
+            "Users outside of the admin groups should not query the audit log"
+        );
+
+        let (status, entries) = query("auditor", "/v1/_audit").await;
+        assert_eq!(status, warp::http::StatusCode::OK);
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| (e.principal.as_str(), e.operation, e.outcome, e.status))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    "alice",
+                    AuditOperation::CreateInvoice,
+                    AuditOutcome::Success,
+                    202
+                ),
+                (
+                    "alice",
+                    AuditOperation::CreateInvoice,
+                    AuditOutcome::Failure,
+                    409
+                ),
+                (
+                    "alice",
+                    AuditOperation::CreateParcel,
+                    AuditOutcome::Success,
+                    200
+                ),
+                (
+                    "bob",
+                    AuditOperation::YankInvoice,
+                    AuditOutcome::Success,
+                    200
+                ),
+            ],
+            summary
+        );
+        assert!(entries.iter().all(|e| e.target == id));
+        assert_eq!(Some(parcel.sha.as_str()), entries[2].parcel.as_deref());
+
+        let (_, entries) = query("auditor", "/v1/_audit?principal=bob").await;
+        assert_eq!(1, entries.len());
+        let (_, entries) = query("auditor", "/v1/_audit?operation=createInvoice&limit=1").await;
+        assert_eq!(1, entries.len());
+        assert_eq!(AuditOutcome::Failure, entries[0].outcome);
+        let (status, _) = query("auditor", "/v1/_audit?operation=nope").await;
+        assert_eq!(status, warp::http::StatusCode::BAD_REQUEST);
+
+        let api =
+            api_with(super::AuditLog::disabled().with_admin_groups(vec!["auditors".to_owned()]));
+        let res = warp::test::request()
+            .header("Authorization", "auditor")
+            .path("/v1/_audit")
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), warp::http::StatusCode::NOT_FOUND);
+    }
 }
@@ -1,5 +1,5 @@
 use serde::Serialize;
-use warp::http::header::HeaderValue;
+use warp::http::header::{HeaderMap, HeaderValue};
 use warp::http::status::StatusCode;
 use warp::reply::Response;
 use warp::Reply;
@@ -37,6 +37,7 @@ where
     SerializedData {
         inner,
         mime: best_fit.to_owned(),
+        headers: HeaderMap::new(),
     }
 }
 
@@ -90,6 +91,7 @@ fn parse_accept(header: &str) -> Vec<mime::Mime> {
 pub struct SerializedData {
     inner: Result<Vec<u8>, ()>,
     mime: String,
+    headers: HeaderMap,
 }
 
 impl Reply for SerializedData {
@@ -103,6 +105,7 @@ impl Reply for SerializedData {
                     HeaderValue::from_str(self.mime.as_str())
                         .unwrap_or_else(|_| HeaderValue::from_static(TOML_MIME_TYPE)),
                 );
+                res.headers_mut().extend(self.headers);
                 res
             }
             Err(()) => warp::http::StatusCode::INTERNAL_SERVER_ERROR.into_response(),
@@ -118,7 +121,7 @@ impl Reply for SerializedData {
 pub fn into_reply(error: ProviderError) -> warp::reply::WithStatus<SerializedData> {
     let status_code = match &error {
         ProviderError::CreateYanked => StatusCode::UNPROCESSABLE_ENTITY,
-        ProviderError::NotFound => StatusCode::NOT_FOUND,
+        ProviderError::NotFound | ProviderError::NotCached => StatusCode::NOT_FOUND,
         ProviderError::Io(e) if e.kind() == std::io::ErrorKind::NotFound => {
             // Remap the error in the case this is a not found error
             return reply_from_error(ProviderError::NotFound, StatusCode::NOT_FOUND);
@@ -130,6 +133,17 @@ pub fn into_reply(error: ProviderError) -> warp::reply::WithStatus<SerializedDat
         | ProviderError::InvalidId(_)
         | ProviderError::SizeMismatch => StatusCode::BAD_REQUEST,
         ProviderError::Yanked => StatusCode::FORBIDDEN,
+        ProviderError::ReadOnly => {
+            // Mark the rejection explicitly so clients don't mistake any other 405 for a read-only
+            // registry
+            let mut reply = error_data(error);
+            reply.headers.insert(
+                crate::provider::read_only::READ_ONLY_HEADER,
+                HeaderValue::from_static("true"),
+            );
+            return warp::reply::with_status(reply, StatusCode::METHOD_NOT_ALLOWED);
+        }
+        ProviderError::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
         #[cfg(feature = "client")]
         ProviderError::ProxyError(e) => {
             // Unwrap the inner error so as to provide better details to the client
@@ -151,14 +165,15 @@ pub fn reply_from_error(
     error: impl std::string::ToString,
     status_code: warp::http::StatusCode,
 ) -> warp::reply::WithStatus<SerializedData> {
-    warp::reply::with_status(
-        serialized_data(
-            &crate::ErrorResponse {
-                error: error.to_string(),
-            },
-            TOML_MIME_TYPE.to_owned(),
-        ),
-        status_code,
+    warp::reply::with_status(error_data(error), status_code)
+}
+
+fn error_data(error: impl std::string::ToString) -> SerializedData {
+    serialized_data(
+        &crate::ErrorResponse {
+            error: error.to_string(),
+        },
+        TOML_MIME_TYPE.to_owned(),
     )
 }
 
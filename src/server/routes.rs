@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Instant;
 
 use warp::Filter;
 
@@ -6,6 +7,7 @@ use crate::{server::filters, signature::KeyRing};
 
 /// A helper function that aggregates all routes into a complete API filter. If you only wish to
 /// serve specific endpoints or versions, you can assemble them with the individual submodules
+#[allow(clippy::too_many_arguments)]
 pub fn api<P, I, Authn, Authz, S>(
     store: P,
     index: I,
@@ -14,6 +16,7 @@ pub fn api<P, I, Authn, Authz, S>(
     secret_store: S,
     verification_strategy: crate::VerificationStrategy,
     keyring: KeyRing,
+    options: crate::server::ServerOptions,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
 where
     P: crate::provider::Provider + Clone + Send + Sync + 'static,
@@ -24,31 +27,99 @@ where
 {
     // Use an Arc to avoid a possibly expensive clone of the keyring on every API call
     let wrapped_keyring = Arc::new(keyring);
-    warp::path("v1")
-        .and(filters::authenticate_and_authorize(authn, authz))
-        .untuple_one()
-        .and(
-            v1::invoice::query(index)
+    let crate::server::ServerOptions {
+        interceptor,
+        yank_guard,
+        signature_stripping,
+        yanked_listing,
+        crawler_policy,
+        audit_log,
+    } = options;
+    let started = Instant::now();
+    // The probes and health endpoint are not authenticated so they can be used by orchestrators
+    // and monitoring systems
+    probes::healthz()
+        .or(probes::readyz(store.clone()))
+        .or(robots_txt(crawler_policy.clone()))
+        .or(warp::path("v1").and(
+            v1::health::get(store.clone(), started)
+                // Writes, yanks, and queries are routed separately because the handlers need the
+                // identity of the user
+                .or(v1::invoice::yank(
+                    store.clone(),
+                    authn.clone(),
+                    authz.clone(),
+                    yank_guard,
+                    audit_log.clone(),
+                ))
+                .or(v1::invoice::query(
+                    index.clone(),
+                    authn.clone(),
+                    authz.clone(),
+                    yanked_listing,
+                ))
+                .or(v1::audit::get(
+                    authn.clone(),
+                    authz.clone(),
+                    audit_log.clone(),
+                ))
                 .or(v1::invoice::create_toml(
                     store.clone(),
                     secret_store.clone(),
                     verification_strategy.clone(),
                     wrapped_keyring.clone(),
+                    interceptor.clone(),
+                    authn.clone(),
+                    authz.clone(),
+                    audit_log.clone(),
                 ))
                 .or(v1::invoice::create_json(
                     store.clone(),
                     secret_store,
                     verification_strategy,
                     wrapped_keyring,
+                    interceptor,
+                    authn.clone(),
+                    authz.clone(),
+                    audit_log.clone(),
+                ))
+                .or(v1::parcel::prefix(
+                    store.clone(),
+                    index.clone(),
+                    authn.clone(),
+                    authz.clone(),
                 ))
-                .or(v1::invoice::get(store.clone()))
-                .or(v1::invoice::head(store.clone()))
-                .or(v1::invoice::yank(store.clone()))
-                .or(v1::parcel::create(store.clone()))
-                .or(v1::parcel::get(store.clone()))
-                .or(v1::parcel::head(store.clone()))
-                .or(v1::relationships::get_missing_parcels(store)),
-        )
+                .or(v1::usage::get(index.clone(), authn.clone(), authz.clone()))
+                .or(v1::parcel::create_batch(
+                    store.clone(),
+                    authn.clone(),
+                    authz.clone(),
+                    audit_log.clone(),
+                ))
+                .or(v1::parcel::create(
+                    store.clone(),
+                    authn.clone(),
+                    authz.clone(),
+                    audit_log,
+                ))
+                .or(filters::authenticate_and_authorize(authn, authz)
+                    .untuple_one()
+                    .and(
+                        v1::invoice::get(
+                            store.clone(),
+                            signature_stripping,
+                            crawler_policy.clone(),
+                        )
+                        .or(v1::invoice::head(
+                            store.clone(),
+                            signature_stripping,
+                            crawler_policy,
+                        ))
+                        .or(v1::parcel::get(store.clone()))
+                        .or(v1::parcel::head(store.clone()))
+                        .or(v1::relationships::get_missing_parcels(store)),
+                    )),
+        ))
         .recover(filters::handle_invalid_request_path)
         .recover(filters::handle_authn_rejection)
         .recover(filters::handle_authz_rejection)
@@ -65,7 +136,11 @@ pub mod v1 {
 
     pub mod invoice {
         use crate::{
-            server::routes::with_secret_store,
+            interceptor::InvoiceInterceptor,
+            server::{
+                routes::with_secret_store, AuditLog, CrawlerPolicy, SignatureStripping, YankGuard,
+                YankedListing,
+            },
             signature::{KeyRing, SecretKeyStorage},
         };
 
@@ -73,59 +148,94 @@ pub mod v1 {
 
         use std::sync::Arc;
 
-        pub fn query<S>(
+        pub fn query<S, Authn, Authz>(
             index: S,
+            authn: Authn,
+            authz: Authz,
+            yanked_listing: YankedListing,
         ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
         where
             S: Search + Clone + Send + Sync,
+            Authn: crate::authn::Authenticator + Clone + Send + Sync,
+            Authz: crate::authz::Authorizer + Clone + Send + Sync,
         {
+            // The path and method are checked before authenticating so that other requests don't
+            // get authenticated twice
             warp::path("_q")
                 .and(warp::get())
+                .and(filters::authorized_identity(authn, authz))
                 .and(warp::query::<crate::QueryOptions>())
                 .and(warp::any().map(move || index.clone()))
+                .and(warp::any().map(move || yanked_listing.clone()))
                 .and(warp::header::optional::<String>("accept"))
                 .and_then(query_invoices)
         }
 
-        pub fn create_toml<P, S>(
+        #[allow(clippy::too_many_arguments)]
+        pub fn create_toml<P, S, II, Authn, Authz>(
             store: P,
             secret_store: S,
             verification_strategy: crate::VerificationStrategy,
             keyring: Arc<KeyRing>,
+            interceptor: II,
+            authn: Authn,
+            authz: Authz,
+            audit_log: AuditLog,
         ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
         where
             P: Provider + Clone + Send + Sync,
             S: SecretKeyStorage + Clone + Send + Sync,
+            II: InvoiceInterceptor + Clone + Send + Sync,
+            Authn: crate::authn::Authenticator + Clone + Send + Sync,
+            Authz: crate::authz::Authorizer + Clone + Send + Sync,
         {
+            // The path and method are checked before authenticating so that other requests don't
+            // get authenticated twice
             warp::path("_i")
                 .and(warp::path::end())
                 .and(warp::post())
+                .and(filters::authorized_identity(authn, authz))
+                .and(warp::any().map(move || audit_log.clone()))
                 .and(with_store(store))
                 .and(with_secret_store(secret_store))
                 .and(warp::any().map(move || verification_strategy.clone()))
                 .and(warp::any().map(move || keyring.clone()))
+                .and(warp::any().map(move || interceptor.clone()))
                 .and(filters::toml())
                 .and(warp::header::optional::<String>("accept"))
                 .and_then(create_invoice)
                 .recover(filters::handle_deserialize_rejection)
         }
-        pub fn create_json<P, S>(
+        #[allow(clippy::too_many_arguments)]
+        pub fn create_json<P, S, II, Authn, Authz>(
             store: P,
             secret_store: S,
             verification_strategy: crate::VerificationStrategy,
             keyring: Arc<KeyRing>,
+            interceptor: II,
+            authn: Authn,
+            authz: Authz,
+            audit_log: AuditLog,
         ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
         where
             P: Provider + Clone + Send + Sync,
             S: SecretKeyStorage + Clone + Send + Sync,
+            II: InvoiceInterceptor + Clone + Send + Sync,
+            Authn: crate::authn::Authenticator + Clone + Send + Sync,
+            Authz: crate::authz::Authorizer + Clone + Send + Sync,
         {
+            // The path and method are checked before authenticating so that other requests don't
+            // get authenticated twice
             warp::path("_i")
                 .and(warp::path::end())
                 .and(warp::post())
+                .and(filters::authorized_identity(authn, authz))
+                .and(warp::any().map(move || audit_log.clone()))
                 .and(with_store(store))
                 .and(with_secret_store(secret_store))
                 .and(warp::any().map(move || verification_strategy.clone()))
                 .and(warp::any().map(move || keyring.clone()))
+                .and(warp::any().map(move || interceptor.clone()))
                 .and(warp::body::json())
                 .and(warp::header::optional::<String>("accept"))
                 .and_then(create_invoice)
@@ -135,6 +245,8 @@ pub mod v1 {
         // The GET and HEAD endpoints handle both parcels and invoices through the request router function
         pub fn get<P>(
             store: P,
+            stripping: SignatureStripping,
+            crawler_policy: CrawlerPolicy,
         ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
         where
             P: Provider + Clone + Send + Sync,
@@ -143,12 +255,16 @@ pub mod v1 {
                 .and(warp::get())
                 .and(warp::query::<filters::InvoiceQuery>())
                 .and(with_store(store))
+                .and(warp::any().map(move || stripping))
+                .and(warp::any().map(move || crawler_policy.clone()))
                 .and(warp::header::optional::<String>("accept"))
                 .and_then(get_invoice)
         }
 
         pub fn head<P>(
             store: P,
+            stripping: SignatureStripping,
+            crawler_policy: CrawlerPolicy,
         ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
         where
             P: Provider + Clone + Send + Sync,
@@ -157,42 +273,89 @@ pub mod v1 {
                 .and(warp::head())
                 .and(warp::query::<filters::InvoiceQuery>())
                 .and(with_store(store))
+                .and(warp::any().map(move || stripping))
+                .and(warp::any().map(move || crawler_policy.clone()))
                 .and(warp::header::optional::<String>("accept"))
                 .and_then(head_invoice)
         }
 
-        pub fn yank<P>(
+        pub fn yank<P, Authn, Authz>(
             store: P,
+            authn: Authn,
+            authz: Authz,
+            yank_guard: YankGuard,
+            audit_log: AuditLog,
         ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
         where
             P: Provider + Clone + Send + Sync,
+            Authn: crate::authn::Authenticator + Clone + Send + Sync,
+            Authz: crate::authz::Authorizer + Clone + Send + Sync,
         {
+            // The path and method are checked before authenticating so that other requests don't
+            // get authenticated twice
             warp::path("_i")
                 .and(warp::path::tail())
                 .and(warp::delete())
+                .and(filters::authorized_identity(authn, authz))
+                .and(warp::query::<filters::YankQuery>())
                 .and(with_store(store))
+                .and(warp::any().map(move || yank_guard.clone()))
+                .and(warp::any().map(move || audit_log.clone()))
                 .and(warp::header::optional::<String>("accept"))
                 .and_then(yank_invoice)
         }
     }
 
     pub mod parcel {
+        use crate::server::AuditLog;
+
         use super::*;
 
-        pub fn create<P>(
+        pub fn create<P, Authn, Authz>(
             store: P,
+            authn: Authn,
+            authz: Authz,
+            audit_log: AuditLog,
         ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
         where
             P: Provider + Clone + Send + Sync,
+            Authn: crate::authn::Authenticator + Clone + Send + Sync,
+            Authz: crate::authz::Authorizer + Clone + Send + Sync,
         {
-            filters::parcel()
-                .and(warp::post())
+            // The parcel path is checked after authenticating, as an invalid path would otherwise
+            // be reported instead of the authentication failure for other POST requests
+            warp::post()
+                .and(filters::authorized_identity(authn, authz))
+                .and(filters::parcel())
                 .and(warp::body::stream())
                 .and(with_store(store))
+                .and(warp::any().map(move || audit_log.clone()))
                 .and(warp::header::optional::<String>("accept"))
                 .and_then(create_parcel)
         }
 
+        pub fn create_batch<P, Authn, Authz>(
+            store: P,
+            authn: Authn,
+            authz: Authz,
+            audit_log: AuditLog,
+        ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+        where
+            P: Provider + Clone + Send + Sync,
+            Authn: crate::authn::Authenticator + Clone + Send + Sync,
+            Authz: crate::authz::Authorizer + Clone + Send + Sync,
+        {
+            warp::path("_batch")
+                .and(warp::path::tail())
+                .and(warp::post())
+                .and(filters::authorized_identity(authn, authz))
+                .and(warp::multipart::form().max_length(crate::server::MAX_BATCH_UPLOAD_SIZE))
+                .and(with_store(store))
+                .and(warp::any().map(move || audit_log.clone()))
+                .and(warp::header::optional::<String>("accept"))
+                .and_then(create_parcels)
+        }
+
         pub fn get<P>(
             store: P,
         ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
@@ -216,6 +379,107 @@ pub mod v1 {
                 .and(with_store(store))
                 .and_then(head_parcel)
         }
+
+        pub fn prefix<P, S, Authn, Authz>(
+            store: P,
+            index: S,
+            authn: Authn,
+            authz: Authz,
+        ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+        where
+            P: Provider + Clone + Send + Sync,
+            S: Search + Clone + Send + Sync,
+            Authn: crate::authn::Authenticator + Clone + Send + Sync,
+            Authz: crate::authz::Authorizer + Clone + Send + Sync,
+        {
+            // The handler needs the authorizer to check which bindles the caller can read
+            let handler_authz = authz.clone();
+            warp::path("_p")
+                .and(warp::path::param::<String>())
+                .and(warp::path::end())
+                .and(warp::get())
+                .and(filters::authorized_identity(authn, authz))
+                .and(with_store(store))
+                .and(warp::any().map(move || index.clone()))
+                .and(warp::any().map(move || handler_authz.clone()))
+                .and(warp::header::optional::<String>("accept"))
+                .and_then(get_parcels_with_prefix)
+        }
+    }
+
+    pub mod health {
+        use super::*;
+
+        use std::time::Instant;
+
+        pub fn get<P>(
+            store: P,
+            started: Instant,
+        ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+        where
+            P: Provider + Clone + Send + Sync,
+        {
+            warp::path("_health")
+                .and(warp::path::end())
+                .and(warp::get())
+                .and(with_store(store))
+                .and(warp::any().map(move || started))
+                .and(warp::header::optional::<String>("accept"))
+                .and_then(get_health)
+        }
+    }
+
+    pub mod usage {
+        use super::*;
+
+        pub fn get<S, Authn, Authz>(
+            index: S,
+            authn: Authn,
+            authz: Authz,
+        ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+        where
+            S: Search + Clone + Send + Sync,
+            Authn: crate::authn::Authenticator + Clone + Send + Sync,
+            Authz: crate::authz::Authorizer + Clone + Send + Sync,
+        {
+            // The handler needs the authorizer to check which bindles the caller can read
+            let handler_authz = authz.clone();
+            warp::path("_usage")
+                .and(warp::path::end())
+                .and(warp::get())
+                .and(filters::authorized_identity(authn, authz))
+                .and(warp::any().map(move || index.clone()))
+                .and(warp::any().map(move || handler_authz.clone()))
+                .and(warp::header::optional::<String>("accept"))
+                .and_then(get_usage)
+        }
+    }
+
+    pub mod audit {
+        use crate::server::AuditLog;
+
+        use super::*;
+
+        pub fn get<Authn, Authz>(
+            authn: Authn,
+            authz: Authz,
+            audit_log: AuditLog,
+        ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+        where
+            Authn: crate::authn::Authenticator + Clone + Send + Sync,
+            Authz: crate::authz::Authorizer + Clone + Send + Sync,
+        {
+            // The path and method are checked before authenticating so that other requests don't
+            // get authenticated twice
+            warp::path("_audit")
+                .and(warp::path::end())
+                .and(warp::get())
+                .and(filters::authorized_identity(authn, authz))
+                .and(warp::query::<crate::audit::AuditQuery>())
+                .and(warp::any().map(move || audit_log.clone()))
+                .and(warp::header::optional::<String>("accept"))
+                .and_then(get_audit_log)
+        }
     }
 
     pub mod relationships {
@@ -239,6 +503,45 @@ pub mod v1 {
     }
 }
 
+pub mod probes {
+    use crate::provider::Provider;
+    use crate::server::handlers::probes;
+    use crate::server::routes::with_store;
+
+    use warp::Filter;
+
+    pub fn healthz() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path("healthz")
+            .and(warp::path::end())
+            .and(warp::get())
+            .and_then(probes::healthz)
+    }
+
+    pub fn readyz<P>(
+        store: P,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+    where
+        P: Provider + Clone + Send + Sync,
+    {
+        warp::path("readyz")
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(with_store(store))
+            .and_then(probes::readyz)
+    }
+}
+
+/// Serves the `robots.txt` from the given crawler policy at the root of the server
+pub fn robots_txt(
+    policy: crate::server::CrawlerPolicy,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("robots.txt")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::any().map(move || policy.clone()))
+        .and_then(crate::server::handlers::robots_txt)
+}
+
 pub(crate) fn with_store<P>(
     store: P,
 ) -> impl Filter<Extract = (P,), Error = std::convert::Infallible> + Clone
@@ -0,0 +1,155 @@
+//! A safety limit on how quickly a single user can yank bindles
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+use tracing::{debug, trace};
+
+use super::filters::Identity;
+
+/// A guard that limits how many bindles a single identity can yank within a window of time. This
+/// protects against runaway automation (such as a buggy script) yanking an entire namespace.
+///
+/// Once an identity hits the limit, further yanks are rejected until enough time has passed,
+/// unless the request explicitly sets the `force` override. Identities in one of the admin groups
+/// are never limited. The default guard is disabled and never rejects a yank
+#[derive(Clone, Debug, Default)]
+pub struct YankGuard {
+    limit: Option<(usize, Duration)>,
+    admin_groups: Vec<String>,
+    history: Arc<Mutex<HashMap<String, VecDeque<Instant>>>>,
+}
+
+/// The error returned when an identity has yanked too many bindles within the window
+#[derive(Error, Debug)]
+#[error(
+    "at most {limit} yanks are allowed within {window:?}, use the force override to yank anyway"
+)]
+pub struct YankThrottled {
+    pub limit: usize,
+    pub window: Duration,
+}
+
+impl YankGuard {
+    /// Returns a guard that never rejects a yank
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Returns a guard that allows each identity at most `limit` yanks within the given window
+    pub fn new(limit: usize, window: Duration) -> Self {
+        YankGuard {
+            limit: Some((limit, window)),
+            ..Default::default()
+        }
+    }
+
+    /// Exempts any identity in one of the given groups from the limit
+    pub fn with_admin_groups(mut self, groups: Vec<String>) -> Self {
+        self.admin_groups = groups;
+        self
+    }
+
+    /// Checks whether the given identity is allowed to yank, recording the yank if it is
+    pub(crate) fn check(&self, identity: &Identity, force: bool) -> Result<(), YankThrottled> {
+        let (limit, window) = match self.limit {
+            Some(l) => l,
+            None => return Ok(()),
+        };
+        if force {
+            debug!(principal = %identity.principal, "Yank limit overridden by request");
+            return Ok(());
+        }
+        if identity
+            .groups
+            .iter()
+            .any(|g| self.admin_groups.contains(g))
+        {
+            trace!(principal = %identity.principal, "Identity is an admin, skipping yank limit");
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        // The lock is never held across an await and nothing in here panics, so poisoning can't
+        // happen in practice
+        let mut history = self.history.lock().unwrap();
+        let yanks = history.entry(identity.principal.clone()).or_default();
+        while yanks
+            .front()
+            .map(|t| now.duration_since(*t) >= window)
+            .unwrap_or(false)
+        {
+            yanks.pop_front();
+        }
+        if yanks.len() >= limit {
+            debug!(principal = %identity.principal, limit, ?window, "Rejecting yank over limit");
+            return Err(YankThrottled { limit, window });
+        }
+        yanks.push_back(now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn identity(principal: &str, groups: &[&str]) -> Identity {
+        Identity {
+            principal: principal.to_owned(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            authenticated: true,
+        }
+    }
+
+    #[test]
+    fn test_yank_limit() {
+        let guard =
+            YankGuard::new(2, Duration::from_secs(60)).with_admin_groups(vec!["admins".to_owned()]);
+        let user = identity("user", &["users"]);
+
+        guard.check(&user, false).expect("First yank should pass");
+        guard.check(&user, false).expect("Second yank should pass");
+        assert!(
+            guard.check(&user, false).is_err(),
+            "Third yank should be throttled"
+        );
+        guard
+            .check(&user, true)
+            .expect("Forced yank should pass over the limit");
+
+        // Limits are tracked per identity
+        guard
+            .check(&identity("other", &[]), false)
+            .expect("Another identity should not be throttled");
+
+        let admin = identity("admin", &["admins"]);
+        for _ in 0..5 {
+            guard
+                .check(&admin, false)
+                .expect("Admins should not be throttled");
+        }
+
+        let disabled = YankGuard::disabled();
+        for _ in 0..5 {
+            disabled
+                .check(&user, false)
+                .expect("Disabled guard should not throttle");
+        }
+    }
+
+    #[test]
+    fn test_yank_window() {
+        let guard = YankGuard::new(1, Duration::from_millis(50));
+        let user = identity("user", &[]);
+
+        guard.check(&user, false).expect("First yank should pass");
+        assert!(guard.check(&user, false).is_err());
+        std::thread::sleep(Duration::from_millis(60));
+        guard
+            .check(&user, false)
+            .expect("Yank should pass once the window has passed");
+    }
+}
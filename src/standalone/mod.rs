@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use std::convert::TryInto;
 use std::path::{Path, PathBuf};
 
+use serde::{Deserialize, Serialize};
 use tokio::fs::File;
 use tokio::io::{AsyncRead, AsyncWriteExt};
 use tokio_stream::{Stream, StreamExt};
@@ -10,13 +11,27 @@ use tokio_util::codec::{BytesCodec, FramedRead};
 use tracing::{debug, info, instrument, trace};
 
 use crate::client::{Client, ClientError, Result};
+use crate::invoice::signature::{
+    KeyRing, SecretKeyEntry, Signature, SignatureError, SignatureRole,
+};
 use crate::Id;
 
 /// The name of the invoice file
 pub const INVOICE_FILE: &str = "invoice.toml";
+/// The name of the file containing detached signatures of the invoice file
+pub const SIGNATURE_FILE: &str = "invoice.toml.sig";
 /// The name of the parcels directory
 pub use crate::provider::file::{PARCEL_DAT, PARCEL_DIRECTORY as PARCEL_DIR};
 
+/// The contents of a detached signature file. Each signature signs the exact bytes of the invoice
+/// file, so the invoice can be verified without parsing it
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DetachedSignatures {
+    #[serde(default)]
+    pub signature: Vec<Signature>,
+}
+
 /// A struct containing paths to all of the key components of a standalone bundle
 pub struct StandaloneRead {
     pub invoice_file: PathBuf,
@@ -53,11 +68,17 @@ impl StandaloneRead {
         trace!(path = %invoice_file.display(), "Computed invoice file path");
         let parcel_dir = base.join(PARCEL_DIR);
         trace!(path = %parcel_dir.display(), "Listing parcels in parcels directory");
-        let stream = tokio::fs::read_dir(&parcel_dir).await?;
-        let parcels = tokio_stream::wrappers::ReadDirStream::new(stream)
-            .map(|res| res.map(|entry| entry.path()).map_err(|e| e.into()))
-            .collect::<Result<_>>()
-            .await?;
+        let parcels = match tokio::fs::read_dir(&parcel_dir).await {
+            Ok(stream) => {
+                tokio_stream::wrappers::ReadDirStream::new(stream)
+                    .map(|res| res.map(|entry| entry.path()).map_err(|e| e.into()))
+                    .collect::<Result<_>>()
+                    .await?
+            }
+            // A bindle without any parcels doesn't need a parcels directory
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
         Ok(StandaloneRead {
             invoice_file,
             parcel_dir,
@@ -131,6 +152,46 @@ impl StandaloneRead {
         Ok(inv)
     }
 
+    /// Returns the path to the detached signature file for the invoice. The file is optional and
+    /// may not exist
+    pub fn signature_file(&self) -> PathBuf {
+        self.invoice_file.with_file_name(SIGNATURE_FILE)
+    }
+
+    /// Verifies the detached signatures of the invoice file against the given keyring, returning
+    /// the verified signatures. Every signature must be valid and at least one of them must be made
+    /// with a key in the keyring, so a missing or empty signature file fails with a
+    /// [`SignatureError::NoKnownKey`].
+    ///
+    /// This only checks the invoice file. Parcels should still be checked against the SHAs in the
+    /// invoice
+    #[instrument(level = "trace", skip(self, keyring), fields(path = %self.invoice_file.display()))]
+    pub async fn verify_detached_signatures(&self, keyring: &KeyRing) -> Result<Vec<Signature>> {
+        let signatures =
+            match crate::client::load::toml::<DetachedSignatures>(self.signature_file()).await {
+                Ok(s) => s.signature,
+                Err(ClientError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                    return Err(SignatureError::NoKnownKey.into())
+                }
+                Err(e) => return Err(e),
+            };
+        let data = tokio::fs::read(&self.invoice_file).await?;
+
+        let mut known_key = false;
+        for s in signatures.iter() {
+            debug!(by = %s.by, role = %s.role, "Checking detached signature");
+            let pk = s.public_key()?;
+            s.verify_detached(&data, &pk)?;
+            if keyring.contains(&pk) {
+                known_key = true;
+            }
+        }
+        if !known_key {
+            return Err(SignatureError::NoKnownKey.into());
+        }
+        Ok(signatures)
+    }
+
     /// Get the path to a parcel in a standalone bindle
     pub fn parcel_data_path(&self, parcel_id: &str) -> PathBuf {
         self.parcel_dir.join(format!("{}.dat", parcel_id))
@@ -182,12 +243,7 @@ async fn create_or_get_invoice(
             info!(invoice_id = %id, "Invoice already exists on the bindle server. Fetching existing invoice and missing parcels list");
             let invoice = client.get_invoice(&id).await?;
             let missing = client.get_missing_parcels(id).await?;
-            let missing = if missing.is_empty() {
-                None
-            } else {
-                Some(missing)
-            };
-            Ok(crate::InvoiceCreateResponse { invoice, missing })
+            Ok(crate::InvoiceCreateResponse::new(invoice, missing))
         }
         Err(e) => Err(e),
     }
@@ -229,6 +285,28 @@ impl StandaloneWrite {
         self.base_path.as_ref()
     }
 
+    /// Signs the invoice file that was written to this standalone bindle with each of the given
+    /// keys and roles, then writes the signatures to a detached signature file next to it. This
+    /// must be called after the invoice is written and will overwrite any existing signature file
+    #[instrument(level = "trace", skip(self, keys), fields(base_dir = %self.base_path.display()))]
+    pub async fn write_detached_signatures(
+        &self,
+        keys: &[(SignatureRole, &SecretKeyEntry)],
+    ) -> Result<()> {
+        let data = tokio::fs::read(self.base_path.join(INVOICE_FILE)).await?;
+        let signature = keys
+            .iter()
+            .map(|(role, key)| Signature::create_detached(&data, key, role.clone()))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        debug!(count = signature.len(), "Writing detached signature file");
+        tokio::fs::write(
+            self.base_path.join(SIGNATURE_FILE),
+            toml::to_vec(&DetachedSignatures { signature })?,
+        )
+        .await?;
+        Ok(())
+    }
+
     // TODO: From a tarball
 
     /// Writes the given invoice and `HashMap` of parcels (as readers). The key of the `HashMap`
@@ -377,6 +455,7 @@ mod test {
     use tokio_stream::StreamExt;
 
     use crate::{
+        signature::{KeyEntry, KeyRing, SecretKeyEntry, SignatureError, SignatureRole},
         standalone::{StandaloneRead, StandaloneWrite},
         BindleSpec, Id, Invoice, Label, Parcel,
     };
@@ -409,6 +488,7 @@ mod test {
                 sha256: sha_string.clone(),
                 annotations: None,
                 origin: None,
+                signatures: None,
                 feature: None,
             },
             conditions: None,
@@ -482,4 +562,81 @@ mod test {
         // Otherwise, tmpfile will clean up the tmpdir too soon.
         dir.close().expect("deleted temp dir");
     }
+
+    #[tokio::test]
+    async fn test_detached_signatures() {
+        use std::convert::TryInto;
+
+        let dir = tempdir().expect("create a temp dir");
+        let id: Id = "standalone/signed/1.0.0".parse().expect("expect valid ID");
+        let writer = StandaloneWrite::new(dir.path(), &id).expect("Create a writer");
+        writer
+            .write(
+                Invoice::new(BindleSpec {
+                    id: id.clone(),
+                    description: None,
+                    authors: None,
+                }),
+                HashMap::<String, &[u8]>::new(),
+            )
+            .await
+            .expect("Write invoice to disk");
+
+        let creator = SecretKeyEntry::new("creator".to_owned(), vec![SignatureRole::Creator]);
+        let approver = SecretKeyEntry::new("approver".to_owned(), vec![SignatureRole::Approver]);
+        writer
+            .write_detached_signatures(&[
+                (SignatureRole::Creator, &creator),
+                (SignatureRole::Approver, &approver),
+            ])
+            .await
+            .expect("Should be able to write detached signatures");
+
+        let reader = StandaloneRead::new(dir.path(), &id)
+            .await
+            .expect("construct a reader");
+        let keyring = KeyRing::new(vec![(&approver).try_into().unwrap()]);
+        let signatures = reader
+            .verify_detached_signatures(&keyring)
+            .await
+            .expect("Signatures should be valid");
+        assert_eq!(2, signatures.len());
+
+        // None of the keys are known
+        let unknown: KeyEntry =
+            (&SecretKeyEntry::new("unknown".to_owned(), vec![SignatureRole::Creator]))
+                .try_into()
+                .unwrap();
+        assert!(matches!(
+            reader
+                .verify_detached_signatures(&KeyRing::new(vec![unknown]))
+                .await,
+            Err(crate::client::ClientError::SignatureError(
+                SignatureError::NoKnownKey
+            ))
+        ));
+
+        // Any change to the invoice file should invalidate the signatures, even if it doesn't
+        // change the parsed invoice
+        let mut data = tokio::fs::read(&reader.invoice_file).await.unwrap();
+        data.extend_from_slice(b"# tampered\n");
+        tokio::fs::write(&reader.invoice_file, data).await.unwrap();
+        assert!(matches!(
+            reader.verify_detached_signatures(&keyring).await,
+            Err(crate::client::ClientError::SignatureError(
+                SignatureError::Unverified(_)
+            ))
+        ));
+
+        // A missing signature file cannot be verified
+        tokio::fs::remove_file(reader.signature_file())
+            .await
+            .unwrap();
+        assert!(matches!(
+            reader.verify_detached_signatures(&keyring).await,
+            Err(crate::client::ClientError::SignatureError(
+                SignatureError::NoKnownKey
+            ))
+        ));
+    }
 }
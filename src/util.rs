@@ -0,0 +1,81 @@
+//! Small adapters for moving between buffered and streaming parcel data, such as when mixing
+//! [`Client::get_parcel`](crate::client::Client::get_parcel) and
+//! [`Client::get_parcel_stream`](crate::client::Client::get_parcel_stream)
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+
+use crate::client::ClientError;
+
+/// Reads the whole stream into a vector of bytes. If a limit is given, the stream is abandoned
+/// with a [`ClientError::StreamTooLarge`] error as soon as more than `limit` bytes have been read,
+/// so a hostile or misbehaving source cannot make the caller buffer an unbounded amount of data
+pub async fn collect_stream<S, B, E>(
+    stream: S,
+    limit: Option<usize>,
+) -> Result<Vec<u8>, ClientError>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+    E: Into<ClientError>,
+{
+    futures::pin_mut!(stream);
+    let mut data = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| e.into())?;
+        let chunk = chunk.as_ref();
+        if let Some(limit) = limit {
+            if data.len() + chunk.len() > limit {
+                return Err(ClientError::StreamTooLarge(limit));
+            }
+        }
+        data.extend_from_slice(chunk);
+    }
+    Ok(data)
+}
+
+/// Wraps the given bytes in a stream with the same item type as
+/// [`Client::get_parcel_stream`](crate::client::Client::get_parcel_stream), so buffered data can be
+/// passed to code that expects a stream
+pub fn bytes_to_stream<T: Into<Bytes>>(data: T) -> impl Stream<Item = Result<Bytes, ClientError>> {
+    futures::stream::once(futures::future::ready(Ok(data.into())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_collect_stream() {
+        let chunks = || {
+            futures::stream::iter(vec![
+                Ok::<_, ClientError>(Bytes::from_static(b"hello ")),
+                Ok(Bytes::from_static(b"world")),
+            ])
+        };
+
+        let data = collect_stream(chunks(), None)
+            .await
+            .expect("Stream should be collected");
+        assert_eq!(data, b"hello world");
+
+        // A limit equal to the size of the data should still pass
+        collect_stream(chunks(), Some(11))
+            .await
+            .expect("Stream at the limit should be collected");
+
+        match collect_stream(chunks(), Some(10)).await {
+            Err(ClientError::StreamTooLarge(10)) => {}
+            res => panic!("Expected a StreamTooLarge error, got {:?}", res),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bytes_to_stream_round_trip() {
+        let data = b"some parcel data".to_vec();
+        let collected = collect_stream(bytes_to_stream(data.clone()), None)
+            .await
+            .expect("Stream should be collected");
+        assert_eq!(collected, data);
+    }
+}
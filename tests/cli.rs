@@ -176,7 +176,222 @@ async fn test_get() {
         .expect("Unable to read exported bindle")
         .is_dir(),
         "Expected exported bindle directory"
+    );
+
+    // Extracting with the default layout should place the parcel at its name
+    let extractdir = tempfile::tempdir().expect("Unable to set up tempdir");
+    let output = std::process::Command::new("cargo")
+        .args([
+            "run",
+            "--features",
+            "cli",
+            "--bin",
+            "bindle",
+            "--",
+            "-d",
+            cachedir.path().to_str().unwrap(),
+            "get",
+            "-x",
+            extractdir.path().to_str().unwrap(),
+            "enterprise.com/warpcore/1.0.0",
+        ])
+        .env(ENV_BINDLE_URL, &controller.base_url)
+        .output()
+        .expect("Should be able to run command");
+
+    assert_status(output, "Should be able to extract a bindle");
+    assert!(
+        tokio::fs::metadata(extractdir.path().join("isolinear_chip.txt"))
+            .await
+            .expect("Unable to read extracted parcel")
+            .is_file(),
+        "Expected extracted parcel"
+    )
+}
+
+#[tokio::test]
+async fn test_get_lazy() {
+    let controller = TestController::new(BINARY_NAME).await;
+    let mut scaffold = testing::Scaffold::load("valid_v1").await;
+    scaffold.invoice.bindle.id = "enterprise.com/lazy/1.0.0".parse().unwrap();
+    let lazy = &mut scaffold.invoice.parcel.as_mut().unwrap()[0];
+    lazy.conditions
+        .get_or_insert(bindle::Condition {
+            member_of: None,
+            requires: None,
+            lazy: None,
+        })
+        .lazy = Some(true);
+    let lazy_name = lazy.label.name.clone();
+    controller
+        .client
+        .create_invoice(scaffold.invoice.clone())
+        .await
+        .expect("Unable to insert invoice");
+    for parcel in scaffold.parcel_files.values() {
+        controller
+            .client
+            .create_parcel(
+                &scaffold.invoice.bindle.id,
+                &parcel.sha,
+                parcel.data.clone(),
+            )
+            .await
+            .expect("Unable to insert parcel");
+    }
+
+    // Lazy parcels should be skipped and recorded
+    let cachedir = tempfile::tempdir().expect("unable to set up tempdir");
+    let extractdir = tempfile::tempdir().expect("Unable to set up tempdir");
+    let output = std::process::Command::new("cargo")
+        .args([
+            "run",
+            "--features",
+            "cli",
+            "--bin",
+            "bindle",
+            "--",
+            "-d",
+            cachedir.path().to_str().unwrap(),
+            "get",
+            "-x",
+            extractdir.path().to_str().unwrap(),
+            "enterprise.com/lazy/1.0.0",
+        ])
+        .env(ENV_BINDLE_URL, &controller.base_url)
+        .output()
+        .expect("Should be able to run command");
+    assert_status(output, "Should be able to extract a bindle");
+    assert!(
+        !extractdir.path().join(&lazy_name).exists(),
+        "Lazy parcel should not be extracted"
+    );
+    assert!(
+        extractdir.path().join("deferred.toml").is_file(),
+        "Lazy parcel should be recorded"
+    );
+
+    let output = std::process::Command::new("cargo")
+        .args([
+            "run",
+            "--features",
+            "cli",
+            "--bin",
+            "bindle",
+            "--",
+            "-d",
+            cachedir.path().to_str().unwrap(),
+            "fetch-deferred",
+            extractdir.path().to_str().unwrap(),
+        ])
+        .env(ENV_BINDLE_URL, &controller.base_url)
+        .output()
+        .expect("Should be able to run command");
+    assert_status(output, "Should be able to fetch deferred parcels");
+    assert!(
+        extractdir.path().join(&lazy_name).is_file(),
+        "Lazy parcel should be fetched"
+    );
+    assert!(
+        !extractdir.path().join("deferred.toml").exists(),
+        "Deferred file should be removed once every parcel is fetched"
+    );
+}
+
+#[tokio::test]
+async fn test_export_detached_signature() {
+    use std::convert::TryInto;
+
+    use bindle::signature::{KeyRing, SecretKeyEntry, SecretKeyFile, SignatureRole};
+
+    let controller = TestController::new(BINARY_NAME).await;
+    setup_data(&controller.client).await;
+    let cachedir = tempfile::tempdir().expect("unable to set up tempdir");
+    let keydir = tempfile::tempdir().expect("unable to set up tempdir");
+    let exportdir = tempfile::tempdir().expect("unable to set up tempdir");
+
+    // Create a secret key file and a keyring that trusts the key
+    let key = SecretKeyEntry::new(
+        "test <test@example.com>".to_owned(),
+        vec![SignatureRole::Host],
+    );
+    let keyring = KeyRing::new(vec![(&key).try_into().unwrap()]);
+    let mut keyfile = SecretKeyFile::default();
+    keyfile.key.push(key);
+    let keyfile_path = keydir.path().join("secret_keys.toml");
+    keyfile
+        .save_file(&keyfile_path)
+        .await
+        .expect("Unable to save secret keys");
+    tokio::fs::write(
+        keydir.path().join("keyring.toml"),
+        toml::to_vec(&keyring).unwrap(),
     )
+    .await
+    .expect("Unable to save keyring");
+
+    let output = std::process::Command::new("cargo")
+        .args([
+            "run",
+            "--features",
+            "cli",
+            "--bin",
+            "bindle",
+            "--",
+            "-d",
+            cachedir.path().to_str().unwrap(),
+            "get",
+            "-e",
+            exportdir.path().to_str().unwrap(),
+            "--sign-detached",
+            "host",
+            "-f",
+            keyfile_path.to_str().unwrap(),
+            "enterprise.com/warpcore/1.0.0",
+        ])
+        .env(ENV_BINDLE_URL, &controller.base_url)
+        .output()
+        .expect("Should be able to run command");
+    assert_status(output, "Should be able to export a signed bindle");
+
+    let verify = || {
+        std::process::Command::new("cargo")
+            .args([
+                "run",
+                "--features",
+                "cli",
+                "--bin",
+                "bindle",
+                "--",
+                "-r",
+                keydir.path().to_str().unwrap(),
+                "verify-standalone",
+                "-p",
+                exportdir.path().to_str().unwrap(),
+                "enterprise.com/warpcore/1.0.0",
+            ])
+            .env(ENV_BINDLE_URL, &controller.base_url)
+            .output()
+            .expect("Should be able to run command")
+    };
+    assert_status(verify(), "Should be able to verify the exported bindle");
+
+    // Tampering with the invoice file should cause verification to fail
+    let invoice_path = exportdir
+        .path()
+        .join("1927aefa8fdc8327499e918300e2e49ecb271321530cc5881fcd069ca8372dcd")
+        .join(bindle::standalone::INVOICE_FILE);
+    let mut data = tokio::fs::read(&invoice_path)
+        .await
+        .expect("Unable to read exported invoice");
+    data.extend_from_slice(b"# tampered\n");
+    tokio::fs::write(&invoice_path, data)
+        .await
+        .expect("Unable to write exported invoice");
+    assert!(
+        !verify().status.success(),
+        "Verification of a tampered invoice should fail"
+    );
 }
 
 #[tokio::test]
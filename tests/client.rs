@@ -6,6 +6,7 @@ use test_util::TestController;
 
 use std::convert::TryInto;
 
+use bindle::provider::Provider;
 use bindle::testing;
 
 use tokio_stream::StreamExt;
@@ -158,6 +159,47 @@ async fn test_already_created() {
             .expect("Unable to create parcel");
     }
 
+    // Existing parcels should be skipped without ever reading the data, so a nonexistent file
+    // should not cause an error
+    let parcel = scaffold.parcel_files.values().next().unwrap();
+    let err = controller
+        .client
+        .create_parcel_from_file(
+            &scaffold.invoice.bindle.id,
+            &parcel.sha,
+            "/nonexistent/parcel.dat",
+        )
+        .await
+        .expect_err("Creating an existing parcel should error");
+    assert!(
+        matches!(err, bindle::client::ClientError::ParcelAlreadyExists),
+        "Expected a parcel already exists error, got {:?}",
+        err
+    );
+
+    // With the check disabled, the data should be sent to the server
+    let client = bindle::client::Client::new_with_options(
+        &controller.base_url,
+        bindle::client::ClientOptions {
+            skip_existing_parcels: false,
+            ..Default::default()
+        },
+    )
+    .expect("unable to create client");
+    let err = client
+        .create_parcel(
+            &scaffold.invoice.bindle.id,
+            &parcel.sha,
+            parcel.data.clone(),
+        )
+        .await
+        .expect_err("Creating an existing parcel should error");
+    assert!(
+        matches!(err, bindle::client::ClientError::ParcelAlreadyExists),
+        "Expected a parcel already exists error, got {:?}",
+        err
+    );
+
     // Make sure we can create an invoice where all parcels already exist
     let mut other_inv = scaffold.invoice.clone();
     other_inv.bindle.id = "another.com/bindle/1.0.0".try_into().unwrap();
@@ -168,6 +210,75 @@ async fn test_already_created() {
         .expect("invoice creation should not error");
 }
 
+#[tokio::test]
+async fn test_create_parcel_idempotent() {
+    let controller = TestController::new(BINARY_NAME).await;
+    // Skip the existence check so every upload actually reaches the server
+    let client = bindle::client::Client::new_with_options(
+        &controller.base_url,
+        bindle::client::ClientOptions {
+            skip_existing_parcels: false,
+            ..Default::default()
+        },
+    )
+    .expect("unable to create client");
+
+    let scaffold = testing::Scaffold::load("valid_v1").await;
+    let id = scaffold.invoice.bindle.id.clone();
+    client
+        .create_invoice(scaffold.invoice)
+        .await
+        .expect("Invoice creation should not error");
+    let parcel = scaffold.parcel_files.get("parcel").unwrap();
+
+    // Data that doesn't match the SHA must be rejected without blocking a later upload
+    let corrupt: Vec<u8> = parcel.data.iter().map(|b| b.wrapping_add(1)).collect();
+    let err = client
+        .create_parcel(&id, &parcel.sha, corrupt.clone())
+        .await
+        .expect_err("Creating a parcel with the wrong data should error");
+    assert!(
+        matches!(err, bindle::client::ClientError::InvalidRequest { .. }),
+        "Expected an invalid request error, got {:?}",
+        err
+    );
+
+    // Concurrent uploads of the same data should all either succeed or report that the parcel
+    // already exists
+    let results = futures::future::join_all(
+        (0..5).map(|_| client.create_parcel(&id, &parcel.sha, parcel.data.clone())),
+    )
+    .await;
+    for res in results.iter() {
+        assert!(
+            matches!(
+                res,
+                Ok(_) | Err(bindle::client::ClientError::ParcelAlreadyExists)
+            ),
+            "Expected success or an already exists error, got {:?}",
+            res
+        );
+    }
+    assert!(
+        results.iter().any(|r| r.is_ok()),
+        "At least one upload should succeed"
+    );
+
+    // Repeating the upload, or sending different data under the same SHA, must not change the
+    // stored parcel
+    for data in [parcel.data.clone(), corrupt] {
+        match client.create_parcel(&id, &parcel.sha, data).await {
+            Err(bindle::client::ClientError::ParcelAlreadyExists) => {}
+            res => panic!("Expected an already exists error, got {:?}", res),
+        }
+    }
+    let stored = client
+        .get_parcel(&id, &parcel.sha)
+        .await
+        .expect("Unable to get parcel");
+    assert_eq!(parcel.data, stored, "Stored parcel should not be modified");
+}
+
 #[tokio::test]
 async fn test_missing() {
     let controller = TestController::new(BINARY_NAME).await;
@@ -175,12 +286,18 @@ async fn test_missing() {
     // Create a bindle with missing invoices
     let scaffold = testing::Scaffold::load("lotsa_parcels").await;
 
-    let inv = controller
+    let resp = controller
         .client
         .create_invoice(scaffold.invoice)
         .await
-        .expect("unable to create invoice")
-        .invoice;
+        .expect("unable to create invoice");
+    assert!(!resp.complete, "Bindle should not be complete");
+    assert_eq!(
+        resp.missing_parcels().len(),
+        scaffold.parcel_files.len(),
+        "Create response should list all missing parcels"
+    );
+    let inv = resp.invoice;
 
     // Check we get the right amount of missing parcels
     let missing = controller
@@ -214,6 +331,177 @@ async fn test_missing() {
     }
 }
 
+#[tokio::test]
+async fn test_empty_invoice() {
+    let controller = TestController::new(BINARY_NAME).await;
+
+    let mut scaffold = testing::Scaffold::load("valid_v1").await;
+    scaffold.invoice.parcel = None;
+    let mut empty_list = scaffold.invoice.clone();
+    empty_list.bindle.id = "enterprise.com/warpcore/1.0.1".parse().unwrap();
+    empty_list.parcel = Some(Vec::new());
+
+    for inv in [scaffold.invoice, empty_list] {
+        let resp = controller
+            .client
+            .create_invoice(inv.clone())
+            .await
+            .expect("An invoice without parcels should be valid");
+        assert!(resp.complete, "Bindle without parcels should be complete");
+        assert!(resp.missing_parcels().is_empty());
+
+        let missing = controller
+            .client
+            .get_missing_parcels(&inv.bindle.id)
+            .await
+            .expect("Should be able to fetch list of missing parcels");
+        assert!(missing.is_empty(), "Expected no missing parcels");
+
+        let fetched = controller
+            .client
+            .get_invoice(&inv.bindle.id)
+            .await
+            .expect("Should be able to fetch invoice");
+        assert!(bindle::filters::BindleFilter::new(&fetched)
+            .filter()
+            .is_empty());
+        assert!(fetched
+            .resolve_platform("linux/amd64")
+            .expect("Resolving an empty bindle should not error")
+            .is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_create_parcels() {
+    let controller = TestController::new(BINARY_NAME).await;
+    let scaffold = testing::Scaffold::load("lotsa_parcels").await;
+    let id = scaffold.invoice.bindle.id.clone();
+    controller
+        .client
+        .create_invoice(scaffold.invoice.clone())
+        .await
+        .expect("unable to create invoice");
+
+    let mut parcels = scaffold.parcel_files.values();
+    let left_out = parcels.next().unwrap();
+    let corrupted = parcels.next().unwrap();
+    let mut batch: std::collections::HashMap<String, Vec<u8>> =
+        parcels.map(|p| (p.sha.clone(), p.data.clone())).collect();
+    let expected_created = batch.len();
+    batch.insert(corrupted.sha.clone(), b"not the right data".to_vec());
+    batch.insert("abc123".to_owned(), b"not in the bindle".to_vec());
+
+    let resp = controller
+        .client
+        .create_parcels(&id, batch)
+        .await
+        .expect("batch upload should succeed even if some parcels fail");
+    assert_eq!(expected_created + 2, resp.parcel.len());
+    let mut failed: Vec<&str> = resp.failed().map(|p| p.sha256.as_str()).collect();
+    failed.sort_unstable();
+    let mut expected_failed = vec!["abc123", corrupted.sha.as_str()];
+    expected_failed.sort_unstable();
+    assert_eq!(expected_failed, failed);
+    assert!(resp.failed().all(|p| p.error.is_some()));
+    assert_eq!(
+        expected_created,
+        resp.parcel
+            .iter()
+            .filter(|p| p.status == bindle::BatchParcelStatus::Created)
+            .count()
+    );
+
+    let mut missing: Vec<String> = controller
+        .client
+        .get_missing_parcels(&id)
+        .await
+        .expect("unable to get missing parcels")
+        .into_iter()
+        .map(|l| l.sha256)
+        .collect();
+    missing.sort_unstable();
+    let mut expected_missing = vec![left_out.sha.clone(), corrupted.sha.clone()];
+    expected_missing.sort_unstable();
+    assert_eq!(expected_missing, missing);
+
+    // Uploading the remaining parcels along with one that already exists completes the bindle
+    let existing = resp
+        .parcel
+        .iter()
+        .find(|p| p.status == bindle::BatchParcelStatus::Created)
+        .unwrap();
+    let batch = scaffold
+        .parcel_files
+        .values()
+        .filter(|p| p.sha == left_out.sha || p.sha == corrupted.sha || p.sha == existing.sha256)
+        .map(|p| (p.sha.clone(), p.data.clone()))
+        .collect();
+    let resp = controller
+        .client
+        .create_parcels(&id, batch)
+        .await
+        .expect("batch upload should succeed");
+    assert_eq!(0, resp.failed().count());
+    assert_eq!(
+        bindle::BatchParcelStatus::AlreadyExists,
+        resp.parcel
+            .iter()
+            .find(|p| p.sha256 == existing.sha256)
+            .unwrap()
+            .status
+    );
+    assert!(controller
+        .client
+        .get_missing_parcels(&id)
+        .await
+        .expect("unable to get missing parcels")
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_create_large_parcels() {
+    use sha2::{Digest, Sha256};
+
+    let controller = TestController::new(BINARY_NAME).await;
+
+    // Large parcels arrive at the server in many chunks, all of which should be stored
+    let data: Vec<u8> = (0..4 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+    let sha = format!("{:x}", Sha256::digest(&data));
+    let mut label = bindle::Label::new("large.dat".to_owned(), sha.clone());
+    label.size = data.len() as u64;
+    let mut inv = bindle::Invoice::new(bindle::BindleSpec {
+        id: "example.com/large/1.0.0".parse().unwrap(),
+        description: None,
+        authors: None,
+    });
+    inv.parcel = Some(vec![bindle::Parcel {
+        label,
+        conditions: None,
+    }]);
+    controller
+        .client
+        .create_invoice(inv.clone())
+        .await
+        .expect("unable to create invoice");
+
+    let resp = controller
+        .client
+        .create_parcels(
+            &inv.bindle.id,
+            vec![(sha.clone(), data.clone())].into_iter().collect(),
+        )
+        .await
+        .expect("batch upload should succeed");
+    assert_eq!(0, resp.failed().count(), "{:?}", resp.parcel);
+    let fetched = controller
+        .client
+        .get_parcel(&inv.bindle.id, &sha)
+        .await
+        .expect("unable to fetch parcel");
+    assert!(data == fetched, "Parcel data should not be truncated");
+}
+
 #[tokio::test]
 async fn test_charset() {
     let controller = TestController::new(BINARY_NAME).await;
@@ -235,3 +523,1580 @@ async fn test_charset() {
         .await
         .expect("Content-Type with charset shouldn't fail");
 }
+
+#[tokio::test]
+async fn test_get_invoices() {
+    let controller = TestController::new(BINARY_NAME).await;
+
+    let mut ids = Vec::new();
+    for name in &["valid_v1", "valid_v2", "lotsa_parcels"] {
+        let scaffold = testing::Scaffold::load(name).await;
+        let inv = controller
+            .client
+            .create_invoice(scaffold.invoice)
+            .await
+            .expect("unable to create invoice")
+            .invoice;
+        ids.push(inv.bindle.id);
+    }
+    let missing: bindle::Id = "does/not/exist/1.0.0".parse().unwrap();
+    ids.insert(1, missing.clone());
+
+    // Use a limit lower than the number of invoices so some requests have to wait
+    let client = bindle::client::Client::new_with_options(
+        &controller.base_url,
+        bindle::client::ClientOptions {
+            max_concurrency: 2,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let results = client.get_invoices(&ids).await;
+
+    assert_eq!(ids.len(), results.len());
+    for (id, (result_id, res)) in ids.iter().zip(results) {
+        assert_eq!(
+            *id, result_id,
+            "Results should be in the same order as the IDs"
+        );
+        if result_id == missing {
+            assert!(
+                matches!(res, Err(bindle::client::ClientError::InvoiceNotFound)),
+                "Missing invoice should return not found, got {:?}",
+                res
+            );
+        } else {
+            assert_eq!(result_id, res.expect("invoice should be fetched").bindle.id);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_tofu_host_key() {
+    let controller = TestController::new(BINARY_NAME).await;
+    let dir = tempfile::tempdir().expect("unable to create tempdir");
+    let client = bindle::client::Client::new_with_options(
+        &controller.base_url,
+        bindle::client::ClientOptions::default()
+            .with_tofu_store(dir.path().join("known_hosts.toml")),
+    )
+    .unwrap();
+
+    // The server's key is pinned from the invoice returned when it is created
+    let scaffold = testing::Scaffold::load("valid_v1").await;
+    let inv = client
+        .create_invoice(scaffold.invoice)
+        .await
+        .expect("unable to create invoice")
+        .invoice;
+    client
+        .get_invoice(&inv.bindle.id)
+        .await
+        .expect("Invoice signed with the pinned key should be accepted");
+
+    // Pretend the server used to have a different key
+    let other = bindle::SecretKeyEntry::new("other".to_owned(), vec![bindle::SignatureRole::Host]);
+    let mut signed = inv.clone();
+    signed.signature = None;
+    signed.sign(bindle::SignatureRole::Host, &other).unwrap();
+    let other_key = signed.signature.unwrap().remove(0).key;
+    client.accept_host_key(&other_key).await.unwrap();
+    match client.get_invoice(&inv.bindle.id).await {
+        Err(bindle::client::ClientError::HostKeyChanged { pinned, .. }) => {
+            assert_eq!(other_key, pinned)
+        }
+        res => panic!("Expected a changed host key, got {:?}", res),
+    }
+
+    // Clients without a store don't check anything
+    assert!(controller.client.accept_host_key(&other_key).await.is_err());
+    controller
+        .client
+        .get_invoice(&inv.bindle.id)
+        .await
+        .expect("Client without a TOFU store should not check host keys");
+}
+
+#[tokio::test]
+async fn test_export_many_to_tar() {
+    let controller = TestController::new(BINARY_NAME).await;
+
+    // Both of these scaffolds contain the same isolinear_chip.txt parcel
+    let mut ids = Vec::new();
+    for name in &["valid_v1", "valid_v2"] {
+        let scaffold = testing::Scaffold::load(name).await;
+        let inv = controller
+            .client
+            .create_invoice(scaffold.invoice)
+            .await
+            .expect("unable to create invoice")
+            .invoice;
+        for parcel in scaffold.parcel_files.values() {
+            match controller
+                .client
+                .create_parcel(&inv.bindle.id, &parcel.sha, parcel.data.clone())
+                .await
+            {
+                Ok(_) | Err(bindle::client::ClientError::ParcelAlreadyExists) => (),
+                Err(e) => panic!("Unable to create parcel: {:?}", e),
+            }
+        }
+        ids.push(inv.bindle.id);
+    }
+
+    let archive = controller
+        .client
+        .export_many_to_tar(&ids, Vec::new())
+        .await
+        .expect("Should be able to export invoices");
+    assert!(
+        archive.ends_with(&[0; 1024]),
+        "Archive should end with the tar termination blocks"
+    );
+
+    // Make sure the shared parcel was only stored once
+    let mut entries = tokio_tar::Archive::new(archive.as_slice())
+        .entries()
+        .expect("Should be able to read archive entries");
+    let mut paths = Vec::new();
+    while let Some(entry) = entries.next().await {
+        let entry = entry.expect("Archive entry should be valid");
+        paths.push(entry.path().unwrap().into_owned());
+    }
+    assert_eq!(
+        4,
+        paths.len(),
+        "Expected 2 invoices and 2 parcels in the archive, got {:?}",
+        paths
+    );
+
+    // Unpack the archive and make sure it can be read back for all invoices
+    let tempdir = tempfile::tempdir().expect("unable to create tempdir");
+    tokio_tar::Archive::new(archive.as_slice())
+        .unpack(tempdir.path())
+        .await
+        .expect("Should be able to unpack archive");
+    let store = bindle::provider::file::FileProvider::new(
+        tempdir.path(),
+        bindle::search::NoopEngine::default(),
+    )
+    .await;
+    for id in ids {
+        let inv = store
+            .get_invoice(&id)
+            .await
+            .expect("Exported invoice should be readable");
+        for parcel in inv.parcel.expect("Invoice should have parcels") {
+            assert!(
+                store
+                    .parcel_exists(&id, &parcel.label.sha256)
+                    .await
+                    .expect("Unable to check parcel existence"),
+                "Parcel {} should exist for bindle {}",
+                parcel.label.sha256,
+                id
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_export_chunked_tar() {
+    let controller = TestController::new(BINARY_NAME).await;
+
+    let scaffold = testing::Scaffold::load("valid_v2").await;
+    let inv = controller
+        .client
+        .create_invoice(scaffold.invoice)
+        .await
+        .expect("unable to create invoice")
+        .invoice;
+    for parcel in scaffold.parcel_files.values() {
+        controller
+            .client
+            .create_parcel(&inv.bindle.id, &parcel.sha, parcel.data.clone())
+            .await
+            .expect("Unable to create parcel");
+    }
+
+    // Only the 11 byte parcel is larger than the chunk size, so it should be split in 2
+    let archive = controller
+        .client
+        .export_many_to_tar_chunked(std::slice::from_ref(&inv.bindle.id), Vec::new(), 10)
+        .await
+        .expect("Should be able to export invoices");
+    let mut entries = tokio_tar::Archive::new(archive.as_slice())
+        .entries()
+        .expect("Should be able to read archive entries");
+    let mut paths = Vec::new();
+    while let Some(entry) = entries.next().await {
+        let entry = entry.expect("Archive entry should be valid");
+        paths.push(entry.path().unwrap().to_string_lossy().into_owned());
+    }
+    assert_eq!(bindle::client::archive::CHUNK_MANIFEST, paths[0]);
+    assert_eq!(
+        2,
+        paths
+            .iter()
+            .filter(|p| p.starts_with(&format!("{}/", bindle::client::archive::CHUNK_DIRECTORY)))
+            .count(),
+        "Expected 2 chunks in the archive, got {:?}",
+        paths
+    );
+
+    let tempdir = tempfile::tempdir().expect("unable to create tempdir");
+    bindle::client::archive::unpack(archive.as_slice(), tempdir.path())
+        .await
+        .expect("Should be able to unpack archive");
+    assert!(
+        !tempdir
+            .path()
+            .join(bindle::client::archive::CHUNK_DIRECTORY)
+            .read_dir()
+            .unwrap()
+            .any(|_| true),
+        "Chunks should be removed once reassembled"
+    );
+    let store = bindle::provider::file::FileProvider::new(
+        tempdir.path(),
+        bindle::search::NoopEngine::default(),
+    )
+    .await;
+    for parcel in scaffold.parcel_files.values() {
+        let mut stream = store
+            .get_parcel(&inv.bindle.id, &parcel.sha)
+            .await
+            .expect("Exported parcel should be readable");
+        let mut data = Vec::new();
+        while let Some(bytes) = stream.next().await {
+            data.extend(bytes.expect("Shouldn't get an error in stream"));
+        }
+        assert_eq!(parcel.data, data);
+    }
+
+    // A corrupted chunk should be caught when reassembling
+    let mut builder = tokio_tar::Builder::new(Vec::new());
+    let mut entries = tokio_tar::Archive::new(archive.as_slice())
+        .entries()
+        .expect("Should be able to read archive entries");
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry.expect("Archive entry should be valid");
+        let header = entry.header().clone();
+        let mut data = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(&mut entry, &mut data)
+            .await
+            .unwrap();
+        if entry
+            .path()
+            .unwrap()
+            .starts_with(bindle::client::archive::CHUNK_DIRECTORY)
+        {
+            data.iter_mut().for_each(|b| *b = b.wrapping_add(1));
+        }
+        builder.append(&header, data.as_slice()).await.unwrap();
+    }
+    let corrupt = builder.into_inner().await.unwrap();
+    let tempdir = tempfile::tempdir().expect("unable to create tempdir");
+    match bindle::client::archive::unpack(corrupt.as_slice(), tempdir.path()).await {
+        Err(bindle::client::ClientError::ParcelShaMismatch(_)) => {}
+        res => panic!("Expected a SHA mismatch, got {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn test_metadata_timeout() {
+    // A server that accepts connections but never responds
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("unable to bind listener");
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut conns = Vec::new();
+        while let Ok((conn, _)) = listener.accept().await {
+            conns.push(conn);
+        }
+    });
+
+    let client = bindle::client::Client::new_with_options(
+        &format!("http://{}/v1/", addr),
+        bindle::client::ClientOptions {
+            metadata_timeout: Some(std::time::Duration::from_millis(100)),
+            ..Default::default()
+        },
+    )
+    .expect("unable to create client");
+
+    match client.get_invoice("enterprise.com/warpcore/1.0.0").await {
+        Err(bindle::client::ClientError::Timeout { operation }) => {
+            assert_eq!("get invoice", operation)
+        }
+        res => panic!("Expected a timeout error, got: {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn test_cancellation() {
+    let controller = TestController::new(BINARY_NAME).await;
+    let scaffold = testing::Scaffold::load("valid_v1").await;
+    let inv = controller
+        .client
+        .create_invoice(scaffold.invoice)
+        .await
+        .expect("unable to create invoice")
+        .invoice;
+    let parcel = scaffold.parcel_files.values().next().unwrap();
+
+    // An upload that stalls after sending part of the parcel
+    let token = bindle::client::CancellationToken::new();
+    let client = controller.client.with_cancellation(token.clone());
+    let partial = parcel.data[..parcel.data.len() / 2].to_vec();
+    let stalled = tokio_stream::iter(vec![Ok::<_, std::io::Error>(bytes::Bytes::from(partial))])
+        .chain(tokio_stream::pending());
+    let canceller = token.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        canceller.cancel();
+    });
+    match client
+        .create_parcel_from_stream(&inv.bindle.id, &parcel.sha, stalled)
+        .await
+    {
+        Err(bindle::client::ClientError::Cancelled) => {}
+        res => panic!("Expected a cancelled error, got: {:?}", res),
+    }
+
+    // Operations started after cancelling fail straight away
+    match client.get_invoice(&inv.bindle.id).await {
+        Err(bindle::client::ClientError::Cancelled) => {}
+        res => panic!("Expected a cancelled error, got: {:?}", res),
+    }
+
+    // The server discards the partial upload, so it can be retried once it notices the connection
+    // is gone
+    let mut attempts = 0;
+    while let Err(e) = controller
+        .client
+        .create_parcel(&inv.bindle.id, &parcel.sha, parcel.data.clone())
+        .await
+    {
+        attempts += 1;
+        assert!(attempts < 20, "Unable to retry cancelled upload: {:?}", e);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    // Cancelling a download ends its stream with an error
+    let token = bindle::client::CancellationToken::new();
+    let mut stream = controller
+        .client
+        .with_cancellation(token.clone())
+        .get_parcel_stream(&inv.bindle.id, &parcel.sha)
+        .await
+        .expect("unable to get parcel stream");
+    token.cancel();
+    match stream.next().await {
+        Some(Err(bindle::client::ClientError::Cancelled)) => {}
+        res => panic!("Expected a cancelled error, got: {:?}", res),
+    }
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_invalid_invoice_response() {
+    // A server that responds to everything with a body that isn't an invoice
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("unable to bind listener");
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let body = "[bindle]\nname = \"enterprise.com/warpcore\"\nflavor = 1\n";
+        while let Ok((mut conn, _)) = listener.accept().await {
+            let mut buf = [0u8; 4096];
+            let _ = conn.read(&mut buf).await;
+            let resp = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/toml\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = conn.write_all(resp.as_bytes()).await;
+        }
+    });
+
+    let client = bindle::client::Client::new(&format!("http://{}/v1/", addr))
+        .expect("unable to create client");
+    match client.get_invoice("enterprise.com/warpcore/1.0.0").await {
+        Err(bindle::client::ClientError::InvalidInvoiceResponse { body, .. }) => {
+            assert!(body.contains("flavor = 1"))
+        }
+        res => panic!("Expected an invalid invoice response error, got: {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn test_max_invoice_size() {
+    let controller = TestController::new(BINARY_NAME).await;
+    let inv = testing::Scaffold::load("valid_v1").await.invoice;
+    controller
+        .client
+        .create_invoice(inv.clone())
+        .await
+        .expect("unable to create invoice");
+
+    let small_client = |base_url: &str| {
+        bindle::client::Client::new_with_options(
+            base_url,
+            bindle::client::ClientOptions {
+                max_invoice_size: 100,
+                ..Default::default()
+            },
+        )
+        .expect("unable to create client")
+    };
+    match small_client(&controller.base_url)
+        .get_invoice(&inv.bindle.id)
+        .await
+    {
+        Err(bindle::client::ClientError::ResponseTooLarge(100)) => {}
+        res => panic!("Expected a response too large error, got: {:?}", res),
+    }
+
+    // A server that doesn't send a content length should be cut off while streaming
+    use warp::Filter;
+    let route = warp::any().map(|| {
+        let chunks = (0..1000).map(|_| Ok::<_, std::io::Error>("# padding\n"));
+        warp::http::Response::new(warp::hyper::Body::wrap_stream(futures::stream::iter(
+            chunks,
+        )))
+    });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    match small_client(&format!("http://{}/v1/", addr))
+        .get_invoice(&inv.bindle.id)
+        .await
+    {
+        Err(bindle::client::ClientError::ResponseTooLarge(100)) => {}
+        res => panic!("Expected a response too large error, got: {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn test_unique_parcel_names() {
+    let controller = TestController::new(BINARY_NAME).await;
+
+    let mut inv = testing::Scaffold::load("valid_v1").await.invoice;
+    inv.bindle.id = "enterprise.com/duplicates/1.0.0".try_into().unwrap();
+    let mut duplicate = inv.parcel.as_ref().unwrap()[0].clone();
+    duplicate.label.sha256 = "abc123".to_owned();
+    inv.parcel.as_mut().unwrap().push(duplicate.clone());
+
+    let client = bindle::client::Client::new_with_options(
+        &controller.base_url,
+        bindle::client::ClientOptions {
+            require_unique_parcel_names: true,
+            ..Default::default()
+        },
+    )
+    .expect("unable to create client");
+    match client.create_invoice(inv.clone()).await {
+        Err(bindle::client::ClientError::DuplicateParcelName(name)) => {
+            assert_eq!(duplicate.label.name, name)
+        }
+        res => panic!("Expected a duplicate parcel name error, got: {:?}", res),
+    }
+
+    // Duplicate names are still allowed by default
+    controller
+        .client
+        .create_invoice(inv)
+        .await
+        .expect("Invoice with duplicate names should be created");
+}
+
+#[tokio::test]
+async fn test_define_missing_groups() {
+    let controller = TestController::new(BINARY_NAME).await;
+
+    let mut inv = testing::Scaffold::load("valid_v1").await.invoice;
+    inv.bindle.id = "enterprise.com/groups/1.0.0".try_into().unwrap();
+    inv.group = None;
+    inv.parcel.as_mut().unwrap()[0].conditions = Some(bindle::Condition {
+        member_of: Some(vec!["undefined".to_owned()]),
+        requires: None,
+        lazy: None,
+    });
+
+    // Undefined groups are sent as is by default
+    let res = controller
+        .client
+        .create_invoice(inv.clone())
+        .await
+        .expect("Invoice should be created");
+    assert!(res.invoice.group.is_none());
+
+    let client = bindle::client::Client::new_with_options(
+        &controller.base_url,
+        bindle::client::ClientOptions {
+            define_missing_groups: true,
+            ..Default::default()
+        },
+    )
+    .expect("unable to create client");
+    inv.bindle.id = "enterprise.com/groups/2.0.0".try_into().unwrap();
+    let res = client
+        .create_invoice(inv.clone())
+        .await
+        .expect("Invoice should be created");
+    assert!(res.invoice.undefined_groups().is_empty());
+    assert_eq!(1, res.invoice.group_members("undefined").len());
+
+    // Invoices uploaded from a file get the same treatment
+    let tempdir = tempfile::tempdir().expect("unable to create tempdir");
+    let path = tempdir.path().join("invoice.toml");
+    inv.bindle.id = "enterprise.com/groups/3.0.0".try_into().unwrap();
+    tokio::fs::write(&path, toml::to_vec(&inv).unwrap())
+        .await
+        .unwrap();
+    let res = client
+        .create_invoice_from_file(&path)
+        .await
+        .expect("Invoice should be created");
+    assert!(res.invoice.undefined_groups().is_empty());
+    assert_eq!(1, res.invoice.group_members("undefined").len());
+}
+
+#[tokio::test]
+async fn test_audit_log() {
+    use bindle::audit::{AuditOperation, AuditQuery, AuditSink, FileAuditSink};
+
+    let tempdir = tempfile::tempdir().expect("unable to create tempdir");
+    let log_path = tempdir.path().join("audit.jsonl");
+    let controller =
+        TestController::new_with_args(BINARY_NAME, &["--audit-log", log_path.to_str().unwrap()])
+            .await;
+
+    let mut inv = testing::Scaffold::load("valid_v1").await.invoice;
+    inv.bindle.id = "enterprise.com/audited/1.0.0".try_into().unwrap();
+    controller
+        .client
+        .create_invoice(inv.clone())
+        .await
+        .expect("unable to create invoice");
+    controller
+        .client
+        .yank_invoice(&inv.bindle.id)
+        .await
+        .expect("unable to yank invoice");
+
+    // Anonymous users are not in any admin group
+    match controller.client.audit_log(&AuditQuery::default()).await {
+        Err(bindle::client::ClientError::InvalidRequest { status_code, .. }) => {
+            assert_eq!(reqwest::StatusCode::FORBIDDEN, status_code)
+        }
+        res => panic!("Expected a forbidden error, got: {:?}", res),
+    }
+
+    let entries = FileAuditSink::new(&log_path)
+        .query(&AuditQuery::default())
+        .await
+        .expect("unable to read audit log");
+    let ops: Vec<_> = entries
+        .iter()
+        .map(|e| (e.principal.as_str(), e.operation, e.target.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                "anonymous",
+                AuditOperation::CreateInvoice,
+                "enterprise.com/audited/1.0.0"
+            ),
+            (
+                "anonymous",
+                AuditOperation::YankInvoice,
+                "enterprise.com/audited/1.0.0"
+            ),
+        ],
+        ops
+    );
+}
+
+#[tokio::test]
+async fn test_resolve_sha_prefix() {
+    use sha2::{Digest, Sha256};
+
+    let controller = TestController::new(BINARY_NAME).await;
+
+    // These two parcels have SHAs that share the prefix "43cc"
+    let data: Vec<Vec<u8>> = vec![b"parcel 251".to_vec(), b"parcel 335".to_vec()];
+    let shas: Vec<String> = data
+        .iter()
+        .map(|d| format!("{:x}", Sha256::digest(d)))
+        .collect();
+
+    let mut inv = bindle::Invoice::new(bindle::BindleSpec {
+        id: "enterprise.com/prefixes/1.0.0".try_into().unwrap(),
+        description: None,
+        authors: None,
+    });
+    inv.parcel = Some(
+        data.iter()
+            .zip(shas.iter())
+            .enumerate()
+            .map(|(i, (d, sha))| bindle::Parcel {
+                label: bindle::Label {
+                    sha256: sha.clone(),
+                    name: format!("parcel{}.txt", i),
+                    media_type: "text/plain".to_owned(),
+                    size: d.len() as u64,
+                    ..Default::default()
+                },
+                conditions: None,
+            })
+            .collect(),
+    );
+    controller
+        .client
+        .create_invoice(inv.clone())
+        .await
+        .expect("unable to create invoice");
+    for (d, sha) in data.into_iter().zip(shas.iter()) {
+        controller
+            .client
+            .create_parcel(&inv.bindle.id, sha, d)
+            .await
+            .expect("Unable to create parcel");
+    }
+
+    match controller.client.resolve_sha_prefix("43cc").await {
+        Err(bindle::client::ClientError::AmbiguousSha {
+            prefix,
+            mut matches,
+        }) => {
+            assert_eq!("43cc", prefix);
+            matches.sort();
+            let mut expected = shas.clone();
+            expected.sort();
+            assert_eq!(expected, matches);
+        }
+        res => panic!("Expected an ambiguous SHA error, got: {:?}", res),
+    }
+
+    for sha in shas.iter() {
+        let resolved = controller
+            .client
+            .resolve_sha_prefix(&sha[..8])
+            .await
+            .expect("Unique prefix should resolve");
+        assert_eq!(sha, &resolved);
+    }
+
+    assert!(matches!(
+        controller.client.resolve_sha_prefix("ffff").await,
+        Err(bindle::client::ClientError::ParcelNotFound)
+    ));
+    assert!(matches!(
+        controller.client.resolve_sha_prefix("43").await,
+        Err(bindle::client::ClientError::InvalidRequest { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_get_overlay_manifest() {
+    let controller = TestController::new(BINARY_NAME).await;
+
+    // Give each parcel a different feature so only one is resolved
+    let mut scaffold = testing::Scaffold::load("valid_v2").await;
+    let mut parcels: Vec<_> = scaffold.invoice.parcel.take().unwrap();
+    for (parcel, animal) in parcels.iter_mut().zip(["narwhal", "unicorn"].iter()) {
+        parcel.label.feature = Some(
+            vec![(
+                "testing".to_owned(),
+                vec![("animal".to_owned(), animal.to_string())]
+                    .into_iter()
+                    .collect(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+    }
+    scaffold.invoice.parcel = Some(parcels);
+    let inv = controller
+        .client
+        .create_invoice(scaffold.invoice.clone())
+        .await
+        .expect("Invoice creation should not error")
+        .invoice;
+    for parcel in scaffold.parcel_files.values() {
+        controller
+            .client
+            .create_parcel(&inv.bindle.id, &parcel.sha, parcel.data.clone())
+            .await
+            .expect("Unable to create parcel");
+    }
+
+    let features = vec![(
+        "testing".to_owned(),
+        vec![("animal".to_owned(), "narwhal".to_owned())]
+            .into_iter()
+            .collect(),
+    )]
+    .into_iter()
+    .collect();
+    let manifest = controller
+        .client
+        .get_overlay_manifest(&inv.bindle.id, &features)
+        .await
+        .expect("Should be able to get overlay manifest");
+
+    assert_eq!(inv.bindle.id, manifest.id);
+    assert_eq!(1, manifest.entries.len(), "Only one parcel should resolve");
+    let expected = &inv.parcel.as_ref().unwrap()[0].label;
+    let entry = manifest
+        .entries
+        .get(&expected.name)
+        .expect("Manifest should contain the narwhal parcel");
+    assert_eq!(expected.sha256, entry.sha256);
+    assert_eq!(expected.size, entry.size);
+    assert_eq!(expected.media_type, entry.media_type);
+
+    // The URL should be directly fetchable
+    let data = reqwest::get(&entry.url)
+        .await
+        .expect("Should be able to fetch parcel URL")
+        .bytes()
+        .await
+        .expect("Should be able to read parcel data");
+    let parcel = scaffold
+        .parcel_files
+        .values()
+        .find(|p| p.sha == entry.sha256)
+        .unwrap();
+    assert_eq!(parcel.data, data.to_vec());
+}
+
+/// Sends every request to the given host and port, no matter which one the client was configured
+/// with, and counts the requests it sees
+struct RedirectInterceptor {
+    port: u16,
+    count: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl bindle::client::RequestInterceptor for RedirectInterceptor {
+    fn intercept(&self, request: &mut reqwest::Request) {
+        request
+            .url_mut()
+            .set_port(Some(self.port))
+            .expect("unable to set port");
+        self.count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[tokio::test]
+async fn test_request_interceptor() {
+    let controller = TestController::new(BINARY_NAME).await;
+    let scaffold = testing::Scaffold::load("valid_v1").await;
+    controller
+        .client
+        .create_invoice(scaffold.invoice.clone())
+        .await
+        .expect("unable to create invoice");
+
+    let port = url::Url::parse(&controller.base_url)
+        .unwrap()
+        .port()
+        .expect("test server should have a port");
+    let count = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    // Nothing is listening on port 1, so this only works if the interceptor rewrote the URL
+    let client = bindle::client::Client::new_with_options(
+        "http://127.0.0.1:1/v1/",
+        bindle::client::ClientOptions::default()
+            .with_interceptor(bindle::client::interceptor::LoggingInterceptor)
+            .with_interceptor(RedirectInterceptor {
+                port,
+                count: count.clone(),
+            }),
+    )
+    .expect("unable to create client");
+
+    client
+        .get_invoice(&scaffold.invoice.bindle.id)
+        .await
+        .expect("Request should have been sent to the rewritten URL");
+    assert_eq!(
+        1,
+        count.load(std::sync::atomic::Ordering::SeqCst),
+        "Interceptor should have seen exactly one request"
+    );
+}
+
+#[tokio::test]
+async fn test_get_verified_parcel() {
+    let controller = TestController::new(BINARY_NAME).await;
+    let mut scaffold = testing::Scaffold::load("valid_v1").await;
+
+    let author = bindle::SecretKeyEntry::new(
+        "Parcel Author <author@example.com>".to_owned(),
+        vec![bindle::SignatureRole::Creator],
+    );
+    let keyring =
+        bindle::signature::KeyRing::new(vec![(&author).try_into().expect("convert to public key")]);
+    let label = &mut scaffold.invoice.parcel.as_mut().unwrap()[0].label;
+    bindle::sign_parcel(label, bindle::SignatureRole::Creator, &author)
+        .expect("unable to sign parcel");
+    let label = label.clone();
+
+    controller
+        .client
+        .create_invoice(scaffold.invoice.clone())
+        .await
+        .expect("unable to create invoice");
+    let parcel = scaffold
+        .parcel_files
+        .values()
+        .find(|p| p.sha == label.sha256)
+        .expect("signed parcel should be in the scaffold");
+    controller
+        .client
+        .create_parcel(
+            &scaffold.invoice.bindle.id,
+            &parcel.sha,
+            parcel.data.clone(),
+        )
+        .await
+        .expect("unable to create parcel");
+
+    // The signature should be stored with the invoice
+    let inv = controller
+        .client
+        .get_invoice(&scaffold.invoice.bindle.id)
+        .await
+        .expect("unable to get invoice");
+    let fetched_label = &inv.parcel.unwrap()[0].label;
+    assert_eq!(label, *fetched_label);
+
+    let data = controller
+        .client
+        .get_verified_parcel(&scaffold.invoice.bindle.id, fetched_label, &keyring)
+        .await
+        .expect("parcel signed with a known key should be returned");
+    assert_eq!(parcel.data, data);
+
+    match controller
+        .client
+        .get_verified_parcel(
+            &scaffold.invoice.bindle.id,
+            fetched_label,
+            &bindle::signature::KeyRing::default(),
+        )
+        .await
+    {
+        Err(bindle::client::ClientError::SignatureError(bindle::SignatureError::NoKnownKey)) => {}
+        res => panic!(
+            "Expected an unknown key error, got: {:?}",
+            res.map(|d| d.len())
+        ),
+    }
+}
+
+#[tokio::test]
+async fn test_creator_signature_survives_server() {
+    let author = bindle::SecretKeyEntry::new(
+        "Invoice Author <author@example.com>".to_owned(),
+        vec![bindle::SignatureRole::Creator],
+    );
+    let tempdir = tempfile::tempdir().expect("unable to create tempdir");
+    let keyring_path = tempdir.path().join("keyring.toml");
+    let server_keyring =
+        bindle::signature::KeyRing::new(vec![(&author).try_into().expect("convert to public key")]);
+    tokio::fs::write(&keyring_path, toml::to_vec(&server_keyring).unwrap())
+        .await
+        .expect("unable to write keyring");
+    let controller =
+        TestController::new_with_args(BINARY_NAME, &["--keyring", keyring_path.to_str().unwrap()])
+            .await;
+
+    let mut inv = testing::Scaffold::load("valid_v1").await.invoice;
+    inv.sign(bindle::SignatureRole::Creator, &author)
+        .expect("unable to sign invoice");
+
+    controller
+        .client
+        .create_invoice(inv.clone())
+        .await
+        .expect("unable to create invoice");
+    let fetched = controller
+        .client
+        .get_invoice(&inv.bindle.id)
+        .await
+        .expect("unable to get invoice");
+
+    // The server sets the creation time and signs as the host without touching the creator
+    // signature
+    assert!(
+        fetched.created().is_some(),
+        "Server should set the creation time"
+    );
+    let sigs = fetched
+        .signature
+        .as_ref()
+        .expect("invoice should be signed");
+    let host = sigs
+        .iter()
+        .find(|s| s.role == bindle::SignatureRole::Host)
+        .expect("server should sign as the host");
+    let keyring = bindle::signature::KeyRing::new(vec![
+        (&author).try_into().expect("convert to public key"),
+        bindle::signature::KeyEntry {
+            label: host.by.clone(),
+            roles: vec![bindle::SignatureRole::Host],
+            key: host.key.clone(),
+            label_signature: None,
+        },
+    ]);
+    bindle::VerificationStrategy::GreedyVerification
+        .verify(fetched, &keyring)
+        .expect("creator and host signatures should both be valid");
+}
+
+#[tokio::test]
+async fn test_get_parcel_transformed() {
+    use sha2::Digest;
+    use std::io::Write;
+
+    let controller = TestController::new(BINARY_NAME).await;
+    let mut inv = testing::Scaffold::load("valid_v1").await.invoice;
+    let data = b"Compressed for your convenience".to_vec();
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&data).unwrap();
+    let compressed = encoder.finish().unwrap();
+    let label = bindle::Label {
+        sha256: format!("{:x}", sha2::Sha256::digest(&compressed)),
+        media_type: "application/gzip".to_owned(),
+        name: "convenience.txt.gz".to_owned(),
+        size: compressed.len() as u64,
+        ..Default::default()
+    };
+    inv.parcel = Some(vec![bindle::Parcel {
+        label: label.clone(),
+        conditions: None,
+    }]);
+
+    controller
+        .client
+        .create_invoice(inv.clone())
+        .await
+        .expect("unable to create invoice");
+    controller
+        .client
+        .create_parcel(&inv.bindle.id, &label.sha256, compressed)
+        .await
+        .expect("unable to create parcel");
+
+    let registry = bindle::client::transform::TransformRegistry::default();
+    let transform = registry
+        .get(&label.media_type)
+        .expect("gzip should have a transform");
+    let transformed = controller
+        .client
+        .get_parcel_transformed(&inv.bindle.id, &label.sha256, transform)
+        .await
+        .expect("unable to get transformed parcel");
+    assert_eq!(data, transformed);
+}
+
+#[tokio::test]
+async fn test_preflight_signature_check() {
+    let author = bindle::SecretKeyEntry::new(
+        "Invoice Author <author@example.com>".to_owned(),
+        vec![bindle::SignatureRole::Creator],
+    );
+    let keyring =
+        bindle::signature::KeyRing::new(vec![(&author).try_into().expect("convert to public key")]);
+    // Nothing listens on this address, so any invoice that passes the preflight check fails to
+    // send instead
+    let client_with = |keyring: bindle::signature::KeyRing| {
+        bindle::client::Client::new_with_options(
+            "http://127.0.0.1:1/v1/",
+            bindle::client::ClientOptions {
+                preflight_keyring: Some(std::sync::Arc::new(keyring)),
+                ..Default::default()
+            },
+        )
+        .expect("unable to create client")
+    };
+    let is_signature_error =
+        |res: &Result<_, _>| matches!(res, Err(bindle::client::ClientError::SignatureError(_)));
+
+    let unsigned = testing::Scaffold::load("valid_v1").await.invoice;
+    let mut signed = unsigned.clone();
+    signed
+        .sign(bindle::SignatureRole::Creator, &author)
+        .expect("unable to sign invoice");
+    let mut tampered = signed.clone();
+    tampered.bindle.id = "enterprise.com/warpcore/6.6.6".parse().unwrap();
+
+    let client = client_with(keyring);
+    assert!(!is_signature_error(
+        &client.create_invoice(signed.clone()).await
+    ));
+    assert!(!is_signature_error(&client.create_invoice(unsigned).await));
+    assert!(is_signature_error(&client.create_invoice(tampered).await));
+
+    let client = client_with(bindle::signature::KeyRing::default());
+    assert!(is_signature_error(
+        &client.create_invoice(signed.clone()).await
+    ));
+
+    // Files are checked too
+    let tempdir = tempfile::tempdir().expect("unable to create tempdir");
+    let path = tempdir.path().join("invoice.toml");
+    tokio::fs::write(&path, toml::to_vec(&signed).unwrap())
+        .await
+        .unwrap();
+    assert!(is_signature_error(
+        &client.create_invoice_from_file(&path).await
+    ));
+}
+
+/// Records the `Content-Length` header of every request it sees
+struct ContentLengthRecorder(std::sync::Arc<std::sync::Mutex<Vec<Option<u64>>>>);
+
+impl bindle::client::RequestInterceptor for ContentLengthRecorder {
+    fn intercept(&self, request: &mut reqwest::Request) {
+        let len = request
+            .headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .map(|v| v.to_str().unwrap().parse().unwrap());
+        self.0.lock().unwrap().push(len);
+    }
+}
+
+#[tokio::test]
+async fn test_invoice_upload_encodings() {
+    let controller = TestController::new(BINARY_NAME).await;
+    let root = std::env::var("CARGO_MANIFEST_DIR").expect("Unable to get project directory");
+    let scaffolds = std::path::PathBuf::from(root).join("tests/scaffolds");
+
+    // Uploading from a file should send an accurate content length
+    let lengths = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let client = bindle::client::Client::new_with_options(
+        &controller.base_url,
+        bindle::client::ClientOptions::default()
+            .with_interceptor(ContentLengthRecorder(lengths.clone())),
+    )
+    .expect("unable to create client");
+    let invoice_path = scaffolds.join("valid_v1/invoice.toml");
+    client
+        .create_invoice_from_file(&invoice_path)
+        .await
+        .expect("Invoice upload with a content length should be accepted");
+    let on_disk_len = tokio::fs::metadata(&invoice_path)
+        .await
+        .expect("Unable to get file info")
+        .len();
+    assert_eq!(vec![Some(on_disk_len)], *lengths.lock().unwrap());
+
+    // A stream without a known length is sent with chunked encoding
+    let stream = bindle::client::load::raw(scaffolds.join("lotsa_parcels/invoice.toml"))
+        .await
+        .expect("Unable to load invoice");
+    let resp = reqwest::Client::new()
+        .post(format!("{}_i", controller.base_url))
+        .header(reqwest::header::CONTENT_TYPE, "application/toml")
+        .body(reqwest::Body::wrap_stream(stream))
+        .send()
+        .await
+        .expect("Unable to send request");
+    assert!(
+        resp.status().is_success(),
+        "Chunked invoice upload should be accepted, got status {}",
+        resp.status()
+    );
+}
+
+#[tokio::test]
+async fn test_health() {
+    let controller = TestController::new(BINARY_NAME).await;
+
+    let health = controller
+        .client
+        .health()
+        .await
+        .expect("Should be able to get server health");
+    assert!(health.is_healthy(), "Server should be healthy");
+    assert_eq!(bindle::BINDLE_VERSION_1, health.spec_version);
+    assert_eq!(env!("CARGO_PKG_VERSION"), health.impl_version);
+}
+
+#[tokio::test]
+async fn test_warm_up() {
+    let controller = TestController::new(BINARY_NAME).await;
+    let server_addr = url::Url::parse(&controller.base_url)
+        .unwrap()
+        .socket_addrs(|| None)
+        .unwrap()[0];
+
+    // A proxy in front of the server that counts the connections made through it
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("unable to bind listener");
+    let addr = listener.local_addr().unwrap();
+    let connections = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = connections.clone();
+    tokio::spawn(async move {
+        while let Ok((mut conn, _)) = listener.accept().await {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut upstream = tokio::net::TcpStream::connect(server_addr).await.unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut conn, &mut upstream).await;
+            });
+        }
+    });
+
+    let client = bindle::client::Client::new_with_options(
+        &format!("http://{}/v1/", addr),
+        bindle::client::ClientOptions {
+            warm_up: true,
+            ..Default::default()
+        },
+    )
+    .expect("unable to create client");
+
+    // The first requests are made straight away, and should wait for the warm-up rather than open
+    // connections of their own
+    client
+        .get_invoice("enterprise.com/warpcore/1.0.0")
+        .await
+        .expect_err("Invoice shouldn't exist");
+    client.health().await.expect("Unable to get server health");
+    assert_eq!(
+        1,
+        connections.load(std::sync::atomic::Ordering::SeqCst),
+        "Requests should reuse the warmed up connection"
+    );
+
+    // A warm-up that fails shouldn't stop the client from being created or used
+    let unused = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let unused_addr = unused.local_addr().unwrap();
+    drop(unused);
+    let client = bindle::client::Client::new_with_options(
+        &format!("http://{}/v1/", unused_addr),
+        bindle::client::ClientOptions {
+            warm_up: true,
+            ..Default::default()
+        },
+    )
+    .expect("A failed warm-up shouldn't fail client creation");
+    match client.health().await {
+        Err(bindle::client::ClientError::HttpClientError(_)) => (),
+        res => panic!("Expected a connection error, got: {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn test_usage_by_namespace() {
+    let controller = TestController::new(BINARY_NAME).await;
+
+    let mut invoices = Vec::new();
+    for name in ["valid_v1", "valid_v2"] {
+        let scaffold = testing::Scaffold::load(name).await;
+        invoices.push(
+            controller
+                .client
+                .create_invoice(scaffold.invoice)
+                .await
+                .expect("unable to create invoice")
+                .invoice,
+        );
+    }
+    // A bindle without a namespace should be reported under the empty namespace
+    let unnamespaced = bindle::Invoice::new(bindle::BindleSpec {
+        id: "standalone/1.0.0".parse().unwrap(),
+        description: None,
+        authors: None,
+    });
+    controller
+        .client
+        .create_invoice(unnamespaced)
+        .await
+        .expect("unable to create invoice");
+
+    let usage = controller
+        .client
+        .usage_by_namespace()
+        .await
+        .expect("Should be able to get usage");
+    assert_eq!(2, usage.len());
+
+    let expected = bindle::usage_by_namespace(&invoices);
+    assert_eq!(expected["enterprise.com"], usage["enterprise.com"]);
+    assert_eq!(2, usage["enterprise.com"].invoice_count);
+    assert_eq!(1, usage[""].invoice_count);
+    assert_eq!(0, usage[""].bytes);
+}
+
+#[tokio::test]
+async fn test_get_parcel_parallel_fallback() {
+    // The bindle server doesn't support range requests, so this should fall back to a sequential
+    // download
+    let controller = TestController::new(BINARY_NAME).await;
+    let scaffold = testing::Scaffold::load("valid_v1").await;
+    let inv = controller
+        .client
+        .create_invoice(scaffold.invoice)
+        .await
+        .expect("unable to create invoice")
+        .invoice;
+    let parcel = scaffold
+        .parcel_files
+        .values()
+        .next()
+        .expect("scaffold should have parcels");
+    controller
+        .client
+        .create_parcel(&inv.bindle.id, &parcel.sha, parcel.data.clone())
+        .await
+        .expect("Unable to create parcel");
+
+    let tempdir = tempfile::tempdir().expect("unable to create tempdir");
+    let dest = tempdir.path().join("parcel.dat");
+    controller
+        .client
+        .get_parcel_parallel(&inv.bindle.id, &parcel.sha, &dest, 4)
+        .await
+        .expect("unable to download parcel");
+    assert_eq!(
+        parcel.data,
+        std::fs::read(&dest).expect("unable to read parcel")
+    );
+}
+
+#[tokio::test]
+async fn test_get_parcel_parallel() {
+    use sha2::{Digest, Sha256};
+    use warp::Filter;
+
+    // The bindle server doesn't support range requests, so serve a parcel from a minimal server
+    // that does, recording the ranges that were requested
+    let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    let sha = format!("{:x}", Sha256::digest(&data));
+    let ranges = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let served = data.clone();
+    let recorded = ranges.clone();
+    let route = warp::method()
+        .and(warp::header::optional::<String>("range"))
+        .map(move |method: warp::http::Method, range: Option<String>| {
+            let builder = warp::http::Response::builder().header("Accept-Ranges", "bytes");
+            if method == warp::http::Method::HEAD {
+                return builder
+                    .header("Content-Length", served.len())
+                    .body(Vec::new())
+                    .unwrap();
+            }
+            let range = range.expect("Every GET should be a range request");
+            let (start, end) = range
+                .trim_start_matches("bytes=")
+                .split_once('-')
+                .map(|(s, e)| (s.parse::<usize>().unwrap(), e.parse::<usize>().unwrap()))
+                .expect("Range should be of the form bytes=start-end");
+            recorded.lock().unwrap().push((start, end));
+            builder
+                .status(warp::http::StatusCode::PARTIAL_CONTENT)
+                .header(
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", start, end, served.len()),
+                )
+                .body(served[start..=end].to_vec())
+                .unwrap()
+        });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+
+    let client = bindle::client::Client::new(&format!("http://{}/v1/", addr))
+        .expect("unable to setup bindle client");
+    let tempdir = tempfile::tempdir().expect("unable to create tempdir");
+    let dest = tempdir.path().join("parcel.dat");
+    client
+        .get_parcel_parallel("mock/parcel/1.0.0", &sha, &dest, 4)
+        .await
+        .expect("unable to download parcel");
+    assert_eq!(data, std::fs::read(&dest).expect("unable to read parcel"));
+
+    let mut requested = ranges.lock().unwrap().clone();
+    requested.sort_unstable();
+    assert_eq!(
+        vec![
+            (0, 24_999),
+            (25_000, 49_999),
+            (50_000, 74_999),
+            (75_000, 99_999)
+        ],
+        requested
+    );
+
+    // Data that doesn't match the SHA should be rejected and removed
+    let bad_sha = format!("{:x}", Sha256::digest(b"not the data"));
+    match client
+        .get_parcel_parallel("mock/parcel/1.0.0", &bad_sha, &dest, 4)
+        .await
+    {
+        Err(bindle::client::ClientError::ParcelShaMismatch(_)) => {}
+        res => panic!("Expected a SHA mismatch, got {:?}", res),
+    }
+    assert_eq!(
+        data,
+        std::fs::read(&dest).expect("unable to read parcel"),
+        "A corrupt download should not replace the existing parcel"
+    );
+    assert_eq!(
+        1,
+        std::fs::read_dir(tempdir.path()).unwrap().count(),
+        "The corrupt download should have been removed"
+    );
+}
+
+#[tokio::test]
+async fn test_get_parcel_parallel_error() {
+    use warp::Filter;
+
+    // Advertise range support but fail every GET of the parcel, and report the missing bindle as
+    // not found
+    let route = warp::method().and(warp::path::tail()).map(
+        |method: warp::http::Method, tail: warp::path::Tail| {
+            if tail.as_str().contains("missing") {
+                return warp::http::Response::builder()
+                    .status(warp::http::StatusCode::NOT_FOUND)
+                    .body(Vec::new())
+                    .unwrap();
+            }
+            let builder = warp::http::Response::builder().header("Accept-Ranges", "bytes");
+            if method == warp::http::Method::HEAD {
+                return builder
+                    .header("Content-Length", 100_000)
+                    .body(Vec::new())
+                    .unwrap();
+            }
+            builder
+                .status(warp::http::StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Vec::new())
+                .unwrap()
+        },
+    );
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+
+    let client = bindle::client::Client::new(&format!("http://{}/v1/", addr))
+        .expect("unable to setup bindle client");
+    let tempdir = tempfile::tempdir().expect("unable to create tempdir");
+    let dest = tempdir.path().join("parcel.dat");
+    client
+        .get_parcel_parallel("mock/parcel/1.0.0", &"a".repeat(64), &dest, 4)
+        .await
+        .expect_err("Download should fail");
+    assert!(!dest.exists(), "Partial parcel should have been removed");
+
+    // A failed download must never remove a file it didn't write
+    std::fs::write(&dest, b"existing").unwrap();
+    for id in &["mock/parcel/1.0.0", "mock/missing/1.0.0"] {
+        client
+            .get_parcel_parallel(*id, &"a".repeat(64), &dest, 4)
+            .await
+            .expect_err("Download should fail");
+        assert_eq!(
+            b"existing".to_vec(),
+            std::fs::read(&dest).expect("Existing file should not be removed")
+        );
+    }
+    assert_eq!(
+        1,
+        std::fs::read_dir(tempdir.path()).unwrap().count(),
+        "No temporary files should be left behind"
+    );
+}
+
+#[tokio::test]
+async fn test_export_size_mismatch() {
+    use warp::Filter;
+
+    // Serve an invoice whose single parcel is 5 bytes, whatever size its label claims
+    let mut scaffold = testing::Scaffold::load("valid_v1").await;
+    scaffold.invoice.parcel.as_mut().unwrap().truncate(1);
+    for (size, desc) in [(10, "short"), (2, "long")] {
+        scaffold.invoice.parcel.as_mut().unwrap()[0].label.size = size;
+        let invoice = toml::to_string(&scaffold.invoice).unwrap();
+        let route = warp::path::tail().map(move |tail: warp::path::Tail| {
+            if tail.as_str().contains('@') {
+                warp::http::Response::new("12345".to_owned())
+            } else {
+                warp::http::Response::new(invoice.clone())
+            }
+        });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let client = bindle::client::Client::new(&format!("http://{}/v1/", addr))
+            .expect("unable to setup bindle client");
+        match client
+            .export_many_to_tar(
+                std::slice::from_ref(&scaffold.invoice.bindle.id),
+                Vec::new(),
+            )
+            .await
+        {
+            Err(bindle::client::ClientError::ParcelSizeMismatch(_)) => {}
+            res => panic!(
+                "Expected a size mismatch for {} parcel data, got {:?}",
+                desc,
+                res.map(|a| a.len())
+            ),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_prefetch_stream() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use warp::Filter;
+
+    // Serve every parcel with its SHA as the data, counting the requests that were made
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    let route = warp::path::tail().map(move |tail: warp::path::Tail| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        let sha = tail.as_str().rsplit('@').next().unwrap().to_owned();
+        warp::http::Response::new(sha)
+    });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+
+    let client = bindle::client::Client::new(&format!("http://{}/v1/", addr))
+        .expect("unable to setup bindle client");
+    let id: bindle::Id = "mock/parcel/1.0.0".parse().unwrap();
+    let labels: Vec<bindle::Label> = (0..10)
+        .map(|i| bindle::Label::new(format!("parcel{}", i), format!("sha{}", i)))
+        .collect();
+
+    let stream = client.prefetch_stream(&id, labels.clone(), 2);
+    futures::pin_mut!(stream);
+    let (label, data) = stream
+        .next()
+        .await
+        .expect("Stream should have a parcel")
+        .expect("Unable to fetch parcel");
+    assert_eq!(labels[0], label);
+    assert_eq!(b"sha0".to_vec(), data);
+
+    // Nothing more should be fetched until the consumer asks for it
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let fetched = requests.load(Ordering::SeqCst);
+    assert!(
+        fetched <= 3,
+        "Expected at most 3 parcels to be fetched ahead, got {}",
+        fetched
+    );
+
+    // The rest should arrive in order
+    let mut remaining = Vec::new();
+    while let Some(res) = stream.next().await {
+        let (label, data) = res.expect("Unable to fetch parcel");
+        assert_eq!(label.sha256.as_bytes(), data.as_slice());
+        remaining.push(label);
+    }
+    assert_eq!(labels[1..], remaining[..]);
+    assert_eq!(10, requests.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn test_get_parcel_cached_only() {
+    use sha2::Digest;
+
+    let data = b"cached parcel".to_vec();
+    let sha = format!("{:x}", sha2::Sha256::digest(&data));
+    let dir = tempfile::tempdir().expect("unable to create tempdir");
+
+    // Nothing is listening here, so any request to the server would fail
+    let base_url = "http://127.0.0.1:1/v1/";
+    let uncached = bindle::client::Client::new(base_url).expect("unable to setup bindle client");
+    assert!(matches!(
+        uncached.get_parcel_cached_only(&sha).await,
+        Err(bindle::client::ClientError::NotCached(s)) if s == sha
+    ));
+
+    let client = bindle::client::Client::new_with_options(
+        base_url,
+        bindle::client::ClientOptions::default().with_cache_dir(dir.path()),
+    )
+    .expect("unable to setup bindle client");
+    assert!(matches!(
+        client.get_parcel_cached_only(&sha).await,
+        Err(bindle::client::ClientError::NotCached(_))
+    ));
+
+    let parcel_dir = dir.path().join("parcels").join(&sha);
+    tokio::fs::create_dir_all(&parcel_dir).await.unwrap();
+    tokio::fs::write(parcel_dir.join("parcel.dat"), &data)
+        .await
+        .unwrap();
+    assert_eq!(
+        data,
+        client
+            .get_parcel_cached_only(&sha)
+            .await
+            .expect("Cached parcel should be returned")
+    );
+
+    tokio::fs::write(parcel_dir.join("parcel.dat"), b"corrupted")
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.get_parcel_cached_only(&sha).await,
+        Err(bindle::client::ClientError::ParcelShaMismatch(_))
+    ));
+
+    // Anything that isn't a SHA is never looked up on disk
+    for invalid in &["../parcels", "", &sha.to_uppercase()] {
+        assert!(matches!(
+            client.get_parcel_cached_only(invalid).await,
+            Err(bindle::client::ClientError::NotCached(_))
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_read_only_registry() {
+    use warp::Filter;
+
+    // Only rejections marked by the server mean the registry is read-only
+    let route = warp::path::tail().map(|tail: warp::path::Tail| {
+        let builder = warp::http::Response::builder()
+            .status(warp::http::StatusCode::METHOD_NOT_ALLOWED)
+            .header("Content-Type", "application/toml");
+        let builder = if tail.as_str().contains("readonly") {
+            builder.header(bindle::provider::read_only::READ_ONLY_HEADER, "true")
+        } else {
+            builder
+        };
+        builder.body("error = \"not allowed\"".to_owned()).unwrap()
+    });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+
+    let client = bindle::client::Client::new(&format!("http://{}/v1/", addr))
+        .expect("unable to setup bindle client");
+    assert!(matches!(
+        client.yank_invoice("readonly/1.0.0").await,
+        Err(bindle::client::ClientError::ReadOnlyRegistry)
+    ));
+    match client.yank_invoice("other/1.0.0").await {
+        Err(bindle::client::ClientError::InvalidRequest {
+            status_code,
+            message,
+        }) => {
+            assert_eq!(warp::http::StatusCode::METHOD_NOT_ALLOWED, status_code);
+            assert_eq!(Some("not allowed".to_owned()), message);
+        }
+        res => panic!("Expected a generic error, got {:?}", res),
+    }
+}
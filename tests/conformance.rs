@@ -0,0 +1,58 @@
+//! Runs the conformance suite against the reference server, so any change that breaks the API
+//! contract is caught here first
+
+use std::net::{Ipv4Addr, SocketAddrV4, TcpListener};
+
+use bindle::authn::always::AlwaysAuthenticate;
+use bindle::authz::always::AlwaysAuthorize;
+use bindle::invoice::signature::KeyRing;
+use bindle::testing;
+
+#[tokio::test]
+async fn test_conformance_in_process() {
+    let (store, index, keystore) = testing::setup().await;
+    let addr = SocketAddrV4::new(
+        Ipv4Addr::LOCALHOST,
+        TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+            .expect("Unable to bind to check for port")
+            .local_addr()
+            .unwrap()
+            .port(),
+    );
+    tokio::spawn(bindle::server::server(
+        store,
+        index,
+        AlwaysAuthenticate,
+        AlwaysAuthorize,
+        addr,
+        None,
+        keystore,
+        bindle::VerificationStrategy::default(),
+        KeyRing::default(),
+        bindle::server::ServerOptions::default(),
+    ));
+
+    // Wait until we can connect to the server so we know it is available
+    let mut connected = false;
+    for _ in 0..10 {
+        if tokio::net::TcpStream::connect(addr).await.is_ok() {
+            connected = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert!(connected, "Ran out of retries waiting for server to start");
+
+    let report = bindle::conformance::run_url(&format!("http://{}/v1/", addr))
+        .await
+        .expect("unable to create client");
+    assert!(
+        report.is_success(),
+        "Reference server should pass the conformance suite:\n{}",
+        report
+    );
+    assert!(
+        !report.results.is_empty(),
+        "Conformance suite should run at least one check"
+    );
+}
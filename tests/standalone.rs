@@ -143,6 +143,34 @@ async fn test_invalid_standalone_write() {
         .expect_err("write shouldn't succeed");
 }
 
+#[tokio::test]
+async fn test_read_without_parcels() {
+    let tempdir = tempfile::tempdir().expect("unable to create tempdir");
+
+    let mut scaffold = testing::Scaffold::load("valid_v1").await;
+    scaffold.invoice.parcel = None;
+    let id = scaffold.invoice.bindle.id.clone();
+
+    // Bindles without parcels don't need a parcels directory
+    let base = tempdir.path().join(id.sha());
+    std::fs::create_dir_all(&base).unwrap();
+    std::fs::write(
+        base.join(INVOICE_FILE),
+        toml::to_vec(&scaffold.invoice).unwrap(),
+    )
+    .unwrap();
+
+    let standalone = StandaloneRead::new(tempdir.path(), &id)
+        .await
+        .expect("Should be able to read a standalone bindle without a parcels directory");
+    assert!(standalone.parcels.is_empty());
+    let inv = standalone
+        .get_invoice()
+        .await
+        .expect("Should be able to load invoice");
+    assert!(inv.parcel.is_none());
+}
+
 #[tokio::test]
 async fn test_push() {
     let tempdir = tempfile::tempdir().expect("unable to create tempdir");
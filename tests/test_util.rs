@@ -15,6 +15,12 @@ impl TestController {
     /// project is called bindle-foo, then `bindle-foo` would be the argument to this function).
     /// Waits for up to 10 seconds for the server to run
     pub async fn new(server_binary_name: &str) -> TestController {
+        Self::new_with_args(server_binary_name, &[]).await
+    }
+
+    /// Same as [`new`](TestController::new), but passes the given extra arguments to the server
+    #[allow(dead_code)]
+    pub async fn new_with_args(server_binary_name: &str, args: &[&str]) -> TestController {
         let build_result = tokio::task::spawn_blocking(|| {
             std::process::Command::new("cargo")
                 .args(&["build", "--features", "cli"])
@@ -47,6 +53,7 @@ impl TestController {
             "-i",
             address.as_str(),
         ])
+        .args(args)
         .spawn()
         .expect("unable to start bindle server");
 